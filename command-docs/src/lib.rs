@@ -0,0 +1,404 @@
+//! Command metadata shared by the Redis modules.
+//!
+//! Each module describes its commands as a static table of [`CommandDoc`]s
+//! (summary, arity, key specs and arguments) and hands it to [`register`]
+//! at load time. The table is passed to `RedisModule_SetCommandInfo`, so
+//! `COMMAND DOCS`, `COMMAND INFO` and `COMMAND GETKEYS` describe module
+//! commands the same way they describe built-in ones, and client libraries
+//! can generate bindings from them.
+//!
+//! The structs below mirror `RedisModuleCommandInfo` and friends from
+//! `redismodule.h` (command info version 1). Redis copies everything it is
+//! given, so the FFI structs only live for the duration of the call.
+
+use std::ffi::CString;
+use std::os::raw::{c_char, c_int};
+use std::ptr;
+use std::time::Duration;
+
+use redis_module::{raw, Context};
+
+/// Key spec flags (`REDISMODULE_CMD_KEY_*`).
+pub const KEY_RO: u64 = 1 << 0;
+pub const KEY_RW: u64 = 1 << 1;
+pub const KEY_OW: u64 = 1 << 2;
+pub const KEY_RM: u64 = 1 << 3;
+pub const KEY_ACCESS: u64 = 1 << 4;
+pub const KEY_UPDATE: u64 = 1 << 5;
+pub const KEY_INSERT: u64 = 1 << 6;
+pub const KEY_DELETE: u64 = 1 << 7;
+/// The argument isn't a keyspace key, but should be routed in cluster mode
+/// as if it was one. Used for session ids and custom hashmap keys.
+pub const KEY_NOT_KEY: u64 = 1 << 8;
+
+// Argument flags (`REDISMODULE_CMD_ARG_*`)
+const ARG_OPTIONAL: c_int = 1 << 0;
+const ARG_MULTIPLE: c_int = 1 << 1;
+
+/// Argument types, in `RedisModuleCommandArgType` order.
+#[derive(Clone, Copy)]
+pub enum ArgType {
+    String = 0,
+    Integer,
+    Double,
+    Key,
+    Pattern,
+    UnixTime,
+    PureToken,
+    OneOf,
+    Block,
+}
+
+/// One command argument as shown by `COMMAND DOCS`.
+pub struct Arg {
+    name: &'static str,
+    kind: ArgType,
+    // Index into the command's key specs for `Key` arguments, -1 otherwise
+    key_spec: i32,
+    token: Option<&'static str>,
+    flags: c_int,
+    subargs: &'static [Arg],
+}
+
+impl Arg {
+    const fn new(name: &'static str, kind: ArgType) -> Self {
+        Arg { name, kind, key_spec: -1, token: None, flags: 0, subargs: &[] }
+    }
+
+    pub const fn string(name: &'static str) -> Self {
+        Arg::new(name, ArgType::String)
+    }
+
+    pub const fn integer(name: &'static str) -> Self {
+        Arg::new(name, ArgType::Integer)
+    }
+
+    pub const fn pattern(name: &'static str) -> Self {
+        Arg::new(name, ArgType::Pattern)
+    }
+
+    /// A key argument described by the command's `key_spec`th key spec.
+    pub const fn key(name: &'static str, key_spec: i32) -> Self {
+        let mut arg = Arg::new(name, ArgType::Key);
+        arg.key_spec = key_spec;
+        arg
+    }
+
+    /// A literal keyword such as `DELETE`.
+    pub const fn pure_token(name: &'static str, token: &'static str) -> Self {
+        Arg::new(name, ArgType::PureToken).with_token(token)
+    }
+
+    /// Exactly one of `choices`.
+    pub const fn one_of(name: &'static str, choices: &'static [Arg]) -> Self {
+        let mut arg = Arg::new(name, ArgType::OneOf);
+        arg.subargs = choices;
+        arg
+    }
+
+    /// `parts`, in order.
+    pub const fn block(name: &'static str, parts: &'static [Arg]) -> Self {
+        let mut arg = Arg::new(name, ArgType::Block);
+        arg.subargs = parts;
+        arg
+    }
+
+    /// Prefix the argument with a keyword, e.g. `TTL seconds`.
+    pub const fn with_token(mut self, token: &'static str) -> Self {
+        self.token = Some(token);
+        self
+    }
+
+    pub const fn optional(mut self) -> Self {
+        self.flags |= ARG_OPTIONAL;
+        self
+    }
+
+    pub const fn multiple(mut self) -> Self {
+        self.flags |= ARG_MULTIPLE;
+        self
+    }
+}
+
+/// Where the keys of a key spec start.
+pub enum BeginSearch {
+    /// At a fixed argument index.
+    Index(i32),
+    /// After a keyword, searched for from an argument index onwards.
+    Keyword(&'static str, i32),
+}
+
+/// Which arguments a command treats as keys.
+pub struct KeySpec {
+    notes: Option<&'static str>,
+    flags: u64,
+    begin: BeginSearch,
+    // Range from the first key: last key (relative), step and limit
+    last_key: i32,
+    key_step: i32,
+}
+
+impl KeySpec {
+    /// A single key at argument `index`.
+    pub const fn index(index: i32, flags: u64) -> Self {
+        KeySpec { notes: None, flags, begin: BeginSearch::Index(index), last_key: 0, key_step: 1 }
+    }
+
+    /// A single key right after `keyword`.
+    pub const fn keyword(keyword: &'static str, flags: u64) -> Self {
+        KeySpec { notes: None, flags, begin: BeginSearch::Keyword(keyword, 1), last_key: 0, key_step: 1 }
+    }
+
+    /// `count` consecutive keys from argument `index`.
+    pub const fn range(index: i32, count: i32, flags: u64) -> Self {
+        KeySpec { notes: None, flags, begin: BeginSearch::Index(index), last_key: count - 1, key_step: 1 }
+    }
+
+    pub const fn with_notes(mut self, notes: &'static str) -> Self {
+        self.notes = Some(notes);
+        self
+    }
+}
+
+/// Metadata for one command, as returned by `COMMAND DOCS` / `COMMAND INFO`.
+pub struct CommandDoc {
+    pub name: &'static str,
+    pub summary: &'static str,
+    pub complexity: Option<&'static str>,
+    pub since: &'static str,
+    /// Number of arguments including the command name; negative means "at least".
+    pub arity: i32,
+    pub key_specs: &'static [KeySpec],
+    pub args: &'static [Arg],
+}
+
+#[repr(C)]
+struct RawInfoVersion {
+    version: c_int,
+    sizeof_historyentry: usize,
+    sizeof_keyspec: usize,
+    sizeof_arg: usize,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct RawKeyword {
+    keyword: *const c_char,
+    startfrom: c_int,
+}
+
+#[repr(C)]
+union RawBeginSearch {
+    index: c_int,
+    keyword: RawKeyword,
+}
+
+// Only the range variant of the find-keys union is used; the keynum
+// variant has the same layout
+#[repr(C)]
+struct RawFindKeys {
+    lastkey: c_int,
+    keystep: c_int,
+    limit: c_int,
+}
+
+#[repr(C)]
+struct RawKeySpec {
+    notes: *const c_char,
+    flags: u64,
+    begin_search_type: c_int,
+    bs: RawBeginSearch,
+    find_keys_type: c_int,
+    fk: RawFindKeys,
+}
+
+#[repr(C)]
+struct RawArg {
+    name: *const c_char,
+    kind: c_int,
+    key_spec_index: c_int,
+    token: *const c_char,
+    summary: *const c_char,
+    since: *const c_char,
+    flags: c_int,
+    deprecated_since: *const c_char,
+    subargs: *mut RawArg,
+    display_text: *const c_char,
+}
+
+#[repr(C)]
+struct RawInfo {
+    version: *const RawInfoVersion,
+    summary: *const c_char,
+    complexity: *const c_char,
+    since: *const c_char,
+    history: *mut RawHistoryEntry,
+    tips: *const c_char,
+    arity: c_int,
+    key_specs: *mut RawKeySpec,
+    args: *mut RawArg,
+}
+
+// `since` and `changes`; no command history is published
+type RawHistoryEntry = [*const c_char; 2];
+
+// REDISMODULE_KSPEC_BS_* / REDISMODULE_KSPEC_FK_*
+const BS_INDEX: c_int = 2;
+const BS_KEYWORD: c_int = 3;
+const FK_RANGE: c_int = 2;
+
+// Owns everything the FFI structs point at until SetCommandInfo returns.
+// Moving a CString or Vec into these doesn't move its heap buffer, so the
+// pointers taken before stay valid.
+#[derive(Default)]
+struct Arena {
+    strings: Vec<CString>,
+    args: Vec<Vec<RawArg>>,
+}
+
+impl Arena {
+    fn str(&mut self, s: &str) -> *const c_char {
+        let s = CString::new(s).expect("command docs contain no NUL bytes");
+        let ptr = s.as_ptr();
+        self.strings.push(s);
+        ptr
+    }
+
+    fn opt_str(&mut self, s: Option<&str>) -> *const c_char {
+        s.map_or(ptr::null(), |s| self.str(s))
+    }
+
+    // A NULL-terminated argument array, or NULL when there are none
+    fn args(&mut self, args: &[Arg]) -> *mut RawArg {
+        if args.is_empty() {
+            return ptr::null_mut();
+        }
+
+        let mut raw = Vec::with_capacity(args.len() + 1);
+        for arg in args {
+            raw.push(RawArg {
+                name: self.str(arg.name),
+                kind: arg.kind as c_int,
+                key_spec_index: arg.key_spec,
+                token: self.opt_str(arg.token),
+                summary: ptr::null(),
+                since: ptr::null(),
+                flags: arg.flags,
+                deprecated_since: ptr::null(),
+                subargs: self.args(arg.subargs),
+                display_text: ptr::null(),
+            });
+        }
+        // SAFETY: an all-zero RawArg is the array terminator
+        raw.push(unsafe { std::mem::zeroed() });
+
+        let ptr = raw.as_mut_ptr();
+        self.args.push(raw);
+        ptr
+    }
+
+    // A key spec array terminated by a zeroed (BS_INVALID) entry
+    fn key_specs(&mut self, specs: &[KeySpec]) -> Vec<RawKeySpec> {
+        let mut raw: Vec<RawKeySpec> = specs.iter()
+            .map(|spec| {
+                let (begin_search_type, bs) = match spec.begin {
+                    BeginSearch::Index(index) => (BS_INDEX, RawBeginSearch { index }),
+                    BeginSearch::Keyword(keyword, startfrom) => (BS_KEYWORD, RawBeginSearch {
+                        keyword: RawKeyword { keyword: self.str(keyword), startfrom },
+                    }),
+                };
+                RawKeySpec {
+                    notes: self.opt_str(spec.notes),
+                    flags: spec.flags,
+                    begin_search_type,
+                    bs,
+                    find_keys_type: FK_RANGE,
+                    fk: RawFindKeys { lastkey: spec.last_key, keystep: spec.key_step, limit: 0 },
+                }
+            })
+            .collect();
+        // SAFETY: an all-zero RawKeySpec is the array terminator
+        raw.push(unsafe { std::mem::zeroed() });
+        raw
+    }
+}
+
+enum Outcome {
+    Set,
+    // The command isn't registered (yet)
+    Missing,
+    Failed(std::io::Error),
+}
+
+fn set_info(ctx: &Context, doc: &CommandDoc) -> Outcome {
+    let (get_command, set_command_info) = unsafe {
+        match (raw::RedisModule_GetCommand, raw::RedisModule_SetCommandInfo) {
+            (Some(get_command), Some(set_command_info)) => (get_command, set_command_info),
+            _ => return Outcome::Failed(std::io::Error::other("command info needs Redis 7.0 or later")),
+        }
+    };
+
+    let mut arena = Arena::default();
+    let command = unsafe { get_command(ctx.ctx, arena.str(doc.name)) };
+    if command.is_null() {
+        return Outcome::Missing;
+    }
+
+    let version = RawInfoVersion {
+        version: 1,
+        sizeof_historyentry: std::mem::size_of::<RawHistoryEntry>(),
+        sizeof_keyspec: std::mem::size_of::<RawKeySpec>(),
+        sizeof_arg: std::mem::size_of::<RawArg>(),
+    };
+    let mut key_specs = arena.key_specs(doc.key_specs);
+    let info = RawInfo {
+        version: &version,
+        summary: arena.str(doc.summary),
+        complexity: arena.opt_str(doc.complexity),
+        since: arena.str(doc.since),
+        history: ptr::null_mut(),
+        tips: ptr::null(),
+        arity: doc.arity,
+        key_specs: if doc.key_specs.is_empty() { ptr::null_mut() } else { key_specs.as_mut_ptr() },
+        args: arena.args(doc.args),
+    };
+
+    let res = unsafe { set_command_info(command, &info as *const RawInfo as *const raw::RedisModuleCommandInfo) };
+    if res == raw::REDISMODULE_OK as c_int {
+        Outcome::Set
+    } else {
+        Outcome::Failed(std::io::Error::last_os_error())
+    }
+}
+
+fn apply(ctx: &Context, docs: &[&'static CommandDoc], retry: bool) {
+    let mut missing = Vec::new();
+    for &doc in docs {
+        match set_info(ctx, doc) {
+            Outcome::Set => {},
+            Outcome::Missing if retry => missing.push(doc),
+            Outcome::Missing => {
+                ctx.log_warning(&format!("No command {} to attach docs to", doc.name));
+            },
+            Outcome::Failed(err) => {
+                ctx.log_warning(&format!("Failed to set command info for {}: {}", doc.name, err));
+            },
+        }
+    }
+
+    // The module's init can run before its commands are created; attach
+    // the rest once loading has finished
+    if !missing.is_empty() {
+        ctx.create_timer(Duration::ZERO, retry_missing, missing);
+    }
+}
+
+fn retry_missing(ctx: &Context, missing: Vec<&'static CommandDoc>) {
+    apply(ctx, &missing, false);
+}
+
+/// Attach `docs` to the module's commands. Call from the module's init.
+pub fn register(ctx: &Context, docs: &'static [CommandDoc]) {
+    let docs: Vec<&'static CommandDoc> = docs.iter().collect();
+    apply(ctx, &docs, true);
+}
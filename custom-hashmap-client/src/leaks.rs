@@ -0,0 +1,87 @@
+//! Counts of buffers that cross the C boundary and of those released again,
+//! per call site, so a missed free (say on an error path) shows up as an
+//! outstanding balance instead of slowly growing memory.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Where a buffer crosses the C boundary
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Site {
+    /// Values returned by [`Client::get`](crate::Client::get)
+    Get,
+    /// Values returned by [`Client::consume`](crate::Client::consume)
+    Consume,
+    /// Result structs filled by [`Client::mget`](crate::Client::mget)
+    Mget,
+    /// Result structs filled by [`Client::get_prefix`](crate::Client::get_prefix)
+    GetPrefix,
+    /// Values returned by [`Client::get_bytes`](crate::Client::get_bytes)
+    GetBytes,
+    /// Values returned in ring completions
+    RingGet,
+    /// Rings and their slots shared with the module by [`Client::ring`](crate::Client::ring)
+    Ring,
+}
+
+impl Site {
+    /// Every call site, in reporting order
+    pub const ALL: [Site; 7] = [Site::Get, Site::Consume, Site::Mget, Site::GetPrefix, Site::GetBytes, Site::RingGet, Site::Ring];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Site::Get => "get",
+            Site::Consume => "consume",
+            Site::Mget => "mget",
+            Site::GetPrefix => "getprefix",
+            Site::GetBytes => "get_bytes",
+            Site::RingGet => "ring_get",
+            Site::Ring => "ring",
+        }
+    }
+}
+
+struct Counter {
+    allocated: AtomicU64,
+    freed: AtomicU64,
+}
+
+static COUNTERS: [Counter; Site::ALL.len()] = [const { Counter { allocated: AtomicU64::new(0), freed: AtomicU64::new(0) } }; Site::ALL.len()];
+
+pub(crate) fn allocated(site: Site) {
+    COUNTERS[site as usize].allocated.fetch_add(1, Ordering::Relaxed);
+}
+
+pub(crate) fn freed(site: Site) {
+    COUNTERS[site as usize].freed.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Buffers of one call site received or shared, and released, since the
+/// process started
+#[derive(Debug, Clone, Copy)]
+pub struct Balance {
+    pub site: Site,
+    pub allocated: u64,
+    pub freed: u64,
+}
+
+impl Balance {
+    /// Buffers not yet released. Attached rings count until they are
+    /// dropped; anywhere else a balance that keeps growing is a leak.
+    pub fn outstanding(&self) -> u64 {
+        self.allocated.saturating_sub(self.freed)
+    }
+}
+
+/// The balance of every call site, across all clients
+pub fn balances() -> Vec<Balance> {
+    Site::ALL.iter()
+        .map(|site| {
+            let counter = &COUNTERS[*site as usize];
+            Balance {
+                site: *site,
+                allocated: counter.allocated.load(Ordering::Relaxed),
+                freed: counter.freed.load(Ordering::Relaxed),
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,365 @@
+//! Safe client for the custom hashmap module's C API.
+//!
+//! [`Client`] loads the module's shared library, resolves its exports and
+//! keeps the library mapped for as long as the client lives, so callers
+//! never handle raw symbols, C strings or returned buffers themselves.
+//!
+//! ```no_run
+//! let client = custom_hashmap_client::Client::load()?;
+//! client.set("user123", "session-id")?;
+//! assert_eq!(client.get("user123")?.as_deref(), Some("session-id"));
+//! # Ok::<(), custom_hashmap_client::Error>(())
+//! ```
+
+use std::ffi::{CStr, CString};
+use std::fmt;
+
+use custom_hashmap_sys as sys;
+use libloading::Library;
+
+mod leaks;
+mod ring;
+
+pub use leaks::{balances, Balance, Site};
+pub use ring::{Ring, RingOp, RingReply, RingStats};
+
+/// Errors returned by [`Client`]
+#[derive(Debug)]
+pub enum Error {
+    /// The shared library could not be loaded
+    Load(String),
+    /// The library does not export a required symbol
+    MissingSymbol(&'static str),
+    /// A key or value contains a NUL byte and can't cross the C API as a
+    /// C string; the `*_bytes` methods take any bytes
+    Nul,
+    /// A stored value is not valid UTF-8 and can't be returned as a `String`;
+    /// read it with [`Client::get_bytes`]
+    NotUtf8,
+    /// The module reported a failure
+    Failed(&'static str),
+    /// The key's prefix is protected against this caller
+    Protected(&'static str),
+    /// The module and this client were built against different layouts of
+    /// the C API's structs
+    WireMismatch(&'static str),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Load(e) => write!(f, "Failed to load custom hashmap library: {}", e),
+            Error::MissingSymbol(name) => write!(f, "Failed to load {}", name),
+            Error::Nul => write!(f, "Key or value contains a NUL byte"),
+            Error::NotUtf8 => write!(f, "Value is not valid UTF-8"),
+            Error::Failed(op) => write!(f, "{} failed", op),
+            Error::Protected(op) => write!(f, "{} refused: the key's prefix is protected", op),
+            Error::WireMismatch(op) => write!(f, "{} failed: module and client struct layouts differ, rebuild both against the same custom-hashmap-sys", op),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+// The binary-safe exports, present together or not at all
+#[derive(Clone, Copy)]
+struct BinFns {
+    set: sys::custom_hashmap_set_bin_fn,
+    get: sys::custom_hashmap_get_bin_fn,
+    del: sys::custom_hashmap_del_bin_fn,
+    free: sys::custom_hashmap_free_bin_fn,
+}
+
+/// A loaded custom hashmap library
+pub struct Client {
+    set_fn: sys::custom_hashmap_set_fn,
+    get_fn: sys::custom_hashmap_get_fn,
+    del_fn: sys::custom_hashmap_del_fn,
+    // Older builds of the module don't export a free function
+    free_fn: Option<sys::custom_hashmap_free_fn>,
+    // Nor a multi-get; `mget` then falls back to one get per key
+    mget_fns: Option<(sys::custom_hashmap_mget_fn, sys::custom_hashmap_mget_free_fn)>,
+    // Nor prefix fetches, which also need a module built with the ordered feature
+    getprefix_fn: Option<sys::custom_hashmap_getprefix_fn>,
+    // Nor an atomic get-and-delete, which has no safe fallback
+    consume_fn: Option<sys::custom_hashmap_consume_fn>,
+    // Nor caller identification, without which owned prefixes can't be written
+    act_as_fn: Option<sys::custom_hashmap_act_as_fn>,
+    // Nor the ring transport
+    ring_attach_fn: Option<sys::custom_hashmap_ring_attach_fn>,
+    // Nor the binary-safe set, get, del and free
+    bin_fns: Option<BinFns>,
+    // Nor their C API version
+    abi_version_fn: Option<sys::custom_hashmap_abi_version_fn>,
+    // Keeps the function pointers above valid; dropped last
+    _library: Library,
+}
+
+impl Client {
+    /// Load the library by its default file name, using the platform's search path
+    pub fn load() -> Result<Client, Error> {
+        Client::open(sys::LIBRARY_NAME)
+    }
+
+    /// Load the library from an explicit path
+    pub fn open(path: &str) -> Result<Client, Error> {
+        // Safety: loading runs the library's initializers; the custom hashmap
+        // module has none beyond what the Rust runtime sets up
+        let library = unsafe { Library::new(path) }.map_err(|e| Error::Load(e.to_string()))?;
+
+        // Safety: the symbol types match the module's exported signatures
+        unsafe {
+            let set_fn = *library.get::<sys::custom_hashmap_set_fn>(sys::SET_SYMBOL)
+                .map_err(|_| Error::MissingSymbol("custom_hashmap_set"))?;
+            let get_fn = *library.get::<sys::custom_hashmap_get_fn>(sys::GET_SYMBOL)
+                .map_err(|_| Error::MissingSymbol("custom_hashmap_get"))?;
+            let del_fn = *library.get::<sys::custom_hashmap_del_fn>(sys::DEL_SYMBOL)
+                .map_err(|_| Error::MissingSymbol("custom_hashmap_del"))?;
+            let free_fn = library.get::<sys::custom_hashmap_free_fn>(sys::FREE_SYMBOL)
+                .ok()
+                .map(|symbol| *symbol);
+            let mget_fns = library.get::<sys::custom_hashmap_mget_fn>(sys::MGET_SYMBOL)
+                .and_then(|mget| {
+                    library.get::<sys::custom_hashmap_mget_free_fn>(sys::MGET_FREE_SYMBOL)
+                        .map(|mget_free| (*mget, *mget_free))
+                })
+                .ok();
+            let getprefix_fn = library.get::<sys::custom_hashmap_getprefix_fn>(sys::GETPREFIX_SYMBOL)
+                .ok()
+                .map(|symbol| *symbol);
+            let consume_fn = library.get::<sys::custom_hashmap_consume_fn>(sys::CONSUME_SYMBOL)
+                .ok()
+                .map(|symbol| *symbol);
+            let act_as_fn = library.get::<sys::custom_hashmap_act_as_fn>(sys::ACT_AS_SYMBOL)
+                .ok()
+                .map(|symbol| *symbol);
+            let ring_attach_fn = library.get::<sys::custom_hashmap_ring_attach_fn>(sys::RING_ATTACH_SYMBOL)
+                .ok()
+                .map(|symbol| *symbol);
+            let bin_fns = (|| Ok::<_, libloading::Error>(BinFns {
+                set: *library.get::<sys::custom_hashmap_set_bin_fn>(sys::SET_BIN_SYMBOL)?,
+                get: *library.get::<sys::custom_hashmap_get_bin_fn>(sys::GET_BIN_SYMBOL)?,
+                del: *library.get::<sys::custom_hashmap_del_bin_fn>(sys::DEL_BIN_SYMBOL)?,
+                free: *library.get::<sys::custom_hashmap_free_bin_fn>(sys::FREE_BIN_SYMBOL)?,
+            }))().ok();
+            let abi_version_fn = library.get::<sys::custom_hashmap_abi_version_fn>(sys::ABI_VERSION_SYMBOL)
+                .ok()
+                .map(|symbol| *symbol);
+
+            Ok(Client {
+                set_fn, get_fn, del_fn, free_fn, mget_fns, getprefix_fn, consume_fn, act_as_fn, ring_attach_fn, bin_fns, abi_version_fn,
+                _library: library,
+            })
+        }
+    }
+
+    /// The C API version the loaded module was built with, `None` for
+    /// builds that predate versioning. Compare it with
+    /// [`custom_hashmap_sys::ABI_VERSION`] before relying on the module.
+    pub fn abi_version(&self) -> Option<u32> {
+        // Safety: the function takes no arguments and only returns a constant
+        self.abi_version_fn.map(|abi_version_fn| unsafe { abi_version_fn() })
+    }
+
+    /// Look up a key
+    pub fn get(&self, key: &str) -> Result<Option<String>, Error> {
+        let key = CString::new(key).map_err(|_| Error::Nul)?;
+
+        // Safety: `key` is a valid C string
+        unsafe { self.take_value((self.get_fn)(key.as_ptr()), Site::Get) }
+    }
+
+    /// Remove a key and return its value atomically; of several callers
+    /// consuming the same key, only one gets `Some`
+    pub fn consume(&self, key: &str) -> Result<Option<String>, Error> {
+        let consume_fn = self.consume_fn.ok_or(Error::MissingSymbol("custom_hashmap_consume"))?;
+        let key = CString::new(key).map_err(|_| Error::Nul)?;
+
+        // Safety: `key` is a valid C string
+        unsafe { self.take_value(consume_fn(key.as_ptr()), Site::Consume) }
+    }
+
+    // Copy a value returned by the module and hand the buffer back to it.
+    // Safety: `value_ptr` is NULL or an owned C string from the module
+    unsafe fn take_value(&self, value_ptr: *mut libc::c_char, site: Site) -> Result<Option<String>, Error> {
+        if value_ptr.is_null() {
+            return Ok(None);
+        }
+        leaks::allocated(site);
+
+        let value = CStr::from_ptr(value_ptr).to_bytes().to_vec();
+        match self.free_fn {
+            Some(free_fn) => free_fn(value_ptr),
+            None => libc::free(value_ptr as *mut libc::c_void),
+        }
+        leaks::freed(site);
+        String::from_utf8(value).map(Some).map_err(|_| Error::NotUtf8)
+    }
+
+    /// Look up several keys in one call, returning their values in order
+    pub fn mget(&self, keys: &[&str]) -> Result<Vec<Option<String>>, Error> {
+        let (mget_fn, mget_free_fn) = match self.mget_fns {
+            Some(fns) => fns,
+            None => return keys.iter().map(|key| self.get(key)).collect(),
+        };
+
+        let keys = keys.iter()
+            .map(|key| CString::new(*key).map_err(|_| Error::Nul))
+            .collect::<Result<Vec<CString>, Error>>()?;
+        let key_ptrs: Vec<*const libc::c_char> = keys.iter().map(|key| key.as_ptr()).collect();
+
+        // Safety: `key_ptrs` holds `keys.len()` valid C strings that outlive
+        // the call; on success the result is read and then released once
+        unsafe {
+            let mut result = sys::custom_hashmap_mget_result::default();
+            match mget_fn(key_ptrs.as_ptr(), key_ptrs.len(), &mut result) {
+                1 => leaks::allocated(Site::Mget),
+                sys::WIRE_MISMATCH => return Err(Error::WireMismatch("custom_hashmap_mget")),
+                _ => return Err(Error::Failed("custom_hashmap_mget")),
+            }
+            // Don't read (or free) a result laid out by a different build
+            if !result.is_valid() || result.count != key_ptrs.len() {
+                return Err(Error::WireMismatch("custom_hashmap_mget"));
+            }
+
+            let values = (0..result.count)
+                .map(|index| {
+                    let value_ptr = *result.values.add(index);
+                    if value_ptr.is_null() {
+                        Ok(None)
+                    } else {
+                        utf8(CStr::from_ptr(value_ptr)).map(Some)
+                    }
+                })
+                .collect();
+            mget_free_fn(&mut result);
+            leaks::freed(Site::Mget);
+            values
+        }
+    }
+
+    /// Fetch up to `limit` keys starting with `prefix` and their values, in
+    /// lexicographic order. Fails if the module was built without the
+    /// `ordered` feature.
+    pub fn get_prefix(&self, prefix: &str, limit: usize) -> Result<Vec<(String, String)>, Error> {
+        let getprefix_fn = self.getprefix_fn.ok_or(Error::MissingSymbol("custom_hashmap_getprefix"))?;
+        let (_, mget_free_fn) = self.mget_fns.ok_or(Error::MissingSymbol("custom_hashmap_mget_free"))?;
+        let prefix = CString::new(prefix).map_err(|_| Error::Nul)?;
+
+        // Safety: `prefix` is a valid C string; on success the result is
+        // read and then released once
+        unsafe {
+            let mut result = sys::custom_hashmap_mget_result::default();
+            match getprefix_fn(prefix.as_ptr(), limit, &mut result) {
+                1 => leaks::allocated(Site::GetPrefix),
+                sys::WIRE_MISMATCH => return Err(Error::WireMismatch("custom_hashmap_getprefix")),
+                _ => return Err(Error::Failed("custom_hashmap_getprefix")),
+            }
+            if !result.is_valid() || result.count % 2 != 0 {
+                return Err(Error::WireMismatch("custom_hashmap_getprefix"));
+            }
+
+            let string_at = |index: usize| {
+                let ptr = *result.values.add(index);
+                if ptr.is_null() { Ok(String::new()) } else { utf8(CStr::from_ptr(ptr)) }
+            };
+            let entries = (0..result.count / 2)
+                .map(|pair| Ok((string_at(2 * pair)?, string_at(2 * pair + 1)?)))
+                .collect();
+            mget_free_fn(&mut result);
+            leaks::freed(Site::GetPrefix);
+            entries
+        }
+    }
+
+    /// Store a key
+    pub fn set(&self, key: &str, value: &str) -> Result<(), Error> {
+        let key = CString::new(key).map_err(|_| Error::Nul)?;
+        let value = CString::new(value).map_err(|_| Error::Nul)?;
+
+        // Safety: both arguments are valid C strings that outlive the call
+        if unsafe { (self.set_fn)(key.as_ptr(), value.as_ptr()) } == 1 {
+            Ok(())
+        } else {
+            Err(Error::Failed("custom_hashmap_set"))
+        }
+    }
+
+    /// Remove a key, returning whether it existed
+    pub fn del(&self, key: &str) -> Result<bool, Error> {
+        let key = CString::new(key).map_err(|_| Error::Nul)?;
+
+        // Safety: `key` is a valid C string that outlives the call
+        match unsafe { (self.del_fn)(key.as_ptr()) } {
+            sys::PROTECTED => Err(Error::Protected("custom_hashmap_del")),
+            removed => Ok(removed == 1),
+        }
+    }
+
+    /// Store a key whose key or value may hold any bytes, NUL included
+    pub fn set_bytes(&self, key: &[u8], value: &[u8]) -> Result<(), Error> {
+        let bin_fns = self.bin_fns.ok_or(Error::MissingSymbol("custom_hashmap_set_bin"))?;
+
+        // Safety: both slices outlive the call and are passed with their lengths
+        if unsafe { (bin_fns.set)(key.as_ptr(), key.len(), value.as_ptr(), value.len()) } == 1 {
+            Ok(())
+        } else {
+            Err(Error::Failed("custom_hashmap_set_bin"))
+        }
+    }
+
+    /// Look up a key that may hold any bytes, returning the value as stored
+    pub fn get_bytes(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        let bin_fns = self.bin_fns.ok_or(Error::MissingSymbol("custom_hashmap_get_bin"))?;
+
+        // Safety: `key` outlives the call; a non-NULL result is a buffer of
+        // `value_len` bytes owned by us until handed back to the module
+        unsafe {
+            let mut value_len = 0;
+            let value_ptr = (bin_fns.get)(key.as_ptr(), key.len(), &mut value_len);
+            if value_ptr.is_null() {
+                return Ok(None);
+            }
+            leaks::allocated(Site::GetBytes);
+            let value = std::slice::from_raw_parts(value_ptr, value_len).to_vec();
+            (bin_fns.free)(value_ptr, value_len);
+            leaks::freed(Site::GetBytes);
+            Ok(Some(value))
+        }
+    }
+
+    /// Remove a key that may hold any bytes, returning whether it existed
+    pub fn del_bytes(&self, key: &[u8]) -> Result<bool, Error> {
+        let bin_fns = self.bin_fns.ok_or(Error::MissingSymbol("custom_hashmap_del_bin"))?;
+
+        // Safety: `key` outlives the call and is passed with its length
+        match unsafe { (bin_fns.del)(key.as_ptr(), key.len()) } {
+            sys::PROTECTED => Err(Error::Protected("custom_hashmap_del_bin")),
+            removed => Ok(removed == 1),
+        }
+    }
+
+    /// Run `f` with the current thread acting as `module`, so it may write
+    /// keys under prefixes owned by that module; this covers both calls
+    /// through this client and `custom.*` commands `f` runs on this thread.
+    /// Modules too old to protect prefixes just run `f`.
+    pub fn acting_as<T>(&self, module: &str, f: impl FnOnce() -> T) -> Result<T, Error> {
+        let act_as_fn = match self.act_as_fn {
+            Some(act_as_fn) => act_as_fn,
+            None => return Ok(f()),
+        };
+        let module = CString::new(module).map_err(|_| Error::Nul)?;
+
+        // Safety: `module` is a valid C string; the module copies it
+        unsafe { act_as_fn(module.as_ptr()) };
+        let result = f();
+        // Safety: NULL clears the identity
+        unsafe { act_as_fn(std::ptr::null()) };
+        Ok(result)
+    }
+}
+
+// A value returned by the module as a C string, refused rather than mangled if it isn't UTF-8
+fn utf8(value: &CStr) -> Result<String, Error> {
+    value.to_str().map(str::to_string).map_err(|_| Error::NotUtf8)
+}
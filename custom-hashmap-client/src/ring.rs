@@ -0,0 +1,244 @@
+//! Shared-memory ring transport, an alternative to one C call per operation.
+
+use std::ffi::CString;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use custom_hashmap_sys as sys;
+
+use crate::{leaks, Client, Error, Site};
+
+// Empty polls spent spinning, then yielding, before a waiting caller starts sleeping
+const SPIN_POLLS: u32 = 1024;
+const YIELD_POLLS: u32 = 1024;
+const IDLE_SLEEP: Duration = Duration::from_micros(50);
+
+/// An operation to send through a [`Ring`]
+#[derive(Debug, Clone, Copy)]
+pub enum RingOp<'a> {
+    Get(&'a str),
+    Set(&'a str, &'a str),
+    Del(&'a str),
+}
+
+/// The outcome of one [`RingOp`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum RingReply {
+    /// A GET's value, `None` for a missing key
+    Value(Option<String>),
+    /// A SET went through
+    Stored,
+    /// Whether a DEL removed the key
+    Removed(bool),
+}
+
+/// Counters of a [`Ring`]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RingStats {
+    /// Operations sent
+    pub ops: u64,
+    /// Batches published to the module; a batch is at most one ring's worth
+    pub batches: u64,
+    /// Batches that had to wait for the previous one because a single
+    /// submit held more operations than the ring has slots
+    pub full: u64,
+}
+
+/// A ring attached to the module, created with [`Client::ring`]
+///
+/// [`Ring::submit`] publishes a whole batch of operations with one atomic
+/// store, and the module's ring thread answers them in order without a C
+/// call per operation. Callers on different threads take turns: each queue
+/// has exactly one producer and one consumer.
+pub struct Ring<'a> {
+    client: &'a Client,
+    ring: *mut sys::custom_hashmap_ring,
+    // Each caller's turn, and the counters it updates
+    turn: Mutex<RingStats>,
+}
+
+// Safety: the slots are only written under `turn`, and the ring's own fields
+// are either immutable after attaching or atomics
+unsafe impl Send for Ring<'_> {}
+unsafe impl Sync for Ring<'_> {}
+
+fn wait(polls: u32) {
+    if polls < SPIN_POLLS {
+        std::hint::spin_loop();
+    } else if polls < SPIN_POLLS + YIELD_POLLS {
+        thread::yield_now();
+    } else {
+        thread::sleep(IDLE_SLEEP);
+    }
+}
+
+fn alloc_slots(capacity: u32) -> *mut sys::custom_hashmap_ring_slot {
+    let slots = vec![sys::custom_hashmap_ring_slot::default(); capacity as usize].into_boxed_slice();
+    Box::into_raw(slots) as *mut sys::custom_hashmap_ring_slot
+}
+
+// Safety: `slots` came from `alloc_slots(capacity)` and is no longer shared
+unsafe fn free_slots(slots: *mut sys::custom_hashmap_ring_slot, capacity: u32) {
+    drop(Box::from_raw(std::ptr::slice_from_raw_parts_mut(slots, capacity as usize)));
+}
+
+impl Client {
+    /// Attach a ring of `capacity` slots per direction. Writes sent through
+    /// it act as `module` (see [`Client::acting_as`]).
+    pub fn ring(&self, capacity: u32, module: Option<&str>) -> Result<Ring<'_>, Error> {
+        let ring_attach_fn = self.ring_attach_fn.ok_or(Error::MissingSymbol("custom_hashmap_ring_attach"))?;
+        if capacity == 0 {
+            return Err(Error::Failed("custom_hashmap_ring_attach"));
+        }
+        let module = module.map(CString::new).transpose().map_err(|_| Error::Nul)?;
+
+        leaks::allocated(Site::Ring);
+        let ring = Box::into_raw(Box::new(sys::custom_hashmap_ring {
+            header: sys::custom_hashmap_wire_header::new::<sys::custom_hashmap_ring>(),
+            capacity,
+            closed: Default::default(),
+            stopped: Default::default(),
+            requests: sys::custom_hashmap_ring_queue { slots: alloc_slots(capacity), head: Default::default(), tail: Default::default() },
+            completions: sys::custom_hashmap_ring_queue { slots: alloc_slots(capacity), head: Default::default(), tail: Default::default() },
+        }));
+
+        // Safety: the ring and its slots stay allocated until `Ring::drop`
+        // has seen the module stop serving them
+        let attached = unsafe { ring_attach_fn(ring, module.as_ref().map_or(std::ptr::null(), |module| module.as_ptr())) };
+        if attached != 1 {
+            // Safety: the module refused the ring, so nothing else refers to it
+            unsafe {
+                let ring = Box::from_raw(ring);
+                free_slots(ring.requests.slots, capacity);
+                free_slots(ring.completions.slots, capacity);
+            }
+            leaks::freed(Site::Ring);
+            return Err(if attached == sys::WIRE_MISMATCH {
+                Error::WireMismatch("custom_hashmap_ring_attach")
+            } else {
+                Error::Failed("custom_hashmap_ring_attach")
+            });
+        }
+
+        Ok(Ring { client: self, ring, turn: Mutex::new(RingStats::default()) })
+    }
+}
+
+impl Ring<'_> {
+    /// Run `ops` through the module, returning one outcome per operation in
+    /// order. Operations beyond the ring's capacity go in further batches,
+    /// each sent once the previous one has been answered.
+    pub fn submit(&self, ops: &[RingOp]) -> Result<Vec<Result<RingReply, Error>>, Error> {
+        // Keys and values must stay valid until their completions are read
+        let args = ops.iter()
+            .map(|op| {
+                let (key, value) = match *op {
+                    RingOp::Get(key) | RingOp::Del(key) => (key, None),
+                    RingOp::Set(key, value) => (key, Some(value)),
+                };
+                Ok((CString::new(key).map_err(|_| Error::Nul)?, value.map(CString::new).transpose().map_err(|_| Error::Nul)?))
+            })
+            .collect::<Result<Vec<(CString, Option<CString>)>, Error>>()?;
+
+        let mut stats = self.turn.lock().map_err(|_| Error::Failed("custom_hashmap_ring"))?;
+        // Safety: the ring is alive for as long as `self`
+        let ring = unsafe { &*self.ring };
+        let capacity = ring.capacity as u64;
+        let mut replies = Vec::with_capacity(ops.len());
+
+        for (batch, chunk) in ops.chunks(capacity as usize).enumerate() {
+            let first = batch * capacity as usize;
+            // The previous batch has been answered in full, so every slot is free
+            let head = ring.requests.head.load(Ordering::Relaxed);
+            for (offset, op) in chunk.iter().enumerate() {
+                let (key, value) = &args[first + offset];
+                let request = sys::custom_hashmap_ring_slot {
+                    tag: (first + offset) as u64,
+                    op: match op {
+                        RingOp::Get(_) => sys::RING_OP_GET,
+                        RingOp::Set(..) => sys::RING_OP_SET,
+                        RingOp::Del(_) => sys::RING_OP_DEL,
+                    },
+                    key: key.as_ptr(),
+                    value: value.as_ref().map_or(std::ptr::null(), |value| value.as_ptr()),
+                    ..Default::default()
+                };
+                // Safety: the module reads no slot past the published head
+                unsafe { *ring.requests.slots.add(((head + offset as u64) % capacity) as usize) = request };
+            }
+            ring.requests.head.store(head + chunk.len() as u64, Ordering::Release);
+            stats.ops += chunk.len() as u64;
+            stats.batches += 1;
+            if batch > 0 {
+                stats.full += 1;
+            }
+
+            let mut polls = 0;
+            let mut received = 0;
+            while received < chunk.len() {
+                let tail = ring.completions.tail.load(Ordering::Relaxed);
+                let available = ring.completions.head.load(Ordering::Acquire) - tail;
+                if available == 0 {
+                    if ring.stopped.load(Ordering::Acquire) != 0 {
+                        // The module may still hold pointers into `args`
+                        std::mem::forget(args);
+                        return Err(Error::Failed("custom_hashmap_ring"));
+                    }
+                    wait(polls);
+                    polls = polls.saturating_add(1);
+                    continue;
+                }
+                for index in tail..tail + available {
+                    // Safety: the module wrote this slot before publishing the head
+                    let completion = unsafe { *ring.completions.slots.add((index % capacity) as usize) };
+                    replies.push(self.reply(completion, (first + received) as u64));
+                    received += 1;
+                }
+                ring.completions.tail.store(tail + available, Ordering::Release);
+            }
+        }
+
+        Ok(replies)
+    }
+
+    // Turn a completion into an outcome, taking ownership of any value in it
+    fn reply(&self, completion: sys::custom_hashmap_ring_slot, tag: u64) -> Result<RingReply, Error> {
+        // Safety: `result` is NULL or a value the module handed over
+        let value = unsafe { self.client.take_value(completion.result, Site::RingGet) }?;
+        if completion.tag != tag {
+            return Err(Error::WireMismatch("custom_hashmap_ring"));
+        }
+        match (completion.op, completion.status) {
+            (sys::RING_OP_GET, _) => Ok(RingReply::Value(value)),
+            (sys::RING_OP_SET, 1) => Ok(RingReply::Stored),
+            (sys::RING_OP_SET, _) => Err(Error::Failed("custom_hashmap_set")),
+            (sys::RING_OP_DEL, sys::PROTECTED) => Err(Error::Protected("custom_hashmap_del")),
+            (sys::RING_OP_DEL, removed) => Ok(RingReply::Removed(removed == 1)),
+            _ => Err(Error::WireMismatch("custom_hashmap_ring")),
+        }
+    }
+
+    /// Counters since the ring was attached
+    pub fn stats(&self) -> RingStats {
+        self.turn.lock().map_or_else(|poisoned| *poisoned.into_inner(), |stats| *stats)
+    }
+}
+
+impl Drop for Ring<'_> {
+    fn drop(&mut self) {
+        // Safety: the ring stays allocated until the module has stopped
+        unsafe {
+            let ring = &*self.ring;
+            ring.closed.store(1, Ordering::Release);
+            while ring.stopped.load(Ordering::Acquire) == 0 {
+                thread::sleep(IDLE_SLEEP);
+            }
+            let ring = Box::from_raw(self.ring);
+            free_slots(ring.requests.slots, ring.capacity);
+            free_slots(ring.completions.slots, ring.capacity);
+        }
+        leaks::freed(Site::Ring);
+    }
+}
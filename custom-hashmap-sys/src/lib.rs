@@ -0,0 +1,421 @@
+//! Raw declarations for the C API exported by the custom hashmap module.
+//!
+//! The functions live in the module's shared library and are resolved at
+//! runtime, so this crate only describes their names and signatures. Use
+//! `custom-hashmap-client` for a safe interface.
+
+#![allow(non_camel_case_types)]
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicU32, AtomicU64};
+
+use libc::{c_char, c_int};
+
+/// File name of the custom hashmap module's shared library
+#[cfg(target_os = "macos")]
+pub const LIBRARY_NAME: &str = "libredis_custom_hashmap.dylib";
+
+/// File name of the custom hashmap module's shared library
+#[cfg(not(target_os = "macos"))]
+pub const LIBRARY_NAME: &str = "libredis_custom_hashmap.so";
+
+/// `int custom_hashmap_set(const char *key, const char *value)`
+///
+/// Returns 1 on success and 0 on failure. Both arguments must be valid
+/// NUL-terminated strings; they are copied and not retained.
+pub type custom_hashmap_set_fn = unsafe extern "C" fn(key: *const c_char, value: *const c_char) -> c_int;
+
+/// `char *custom_hashmap_get(const char *key)`
+///
+/// Returns NULL when the key is missing. Otherwise the returned string is
+/// owned by the caller and must be released with `custom_hashmap_free`. A
+/// value containing a NUL byte can't be returned as a C string and reads as
+/// missing; use `custom_hashmap_get_bin` for binary values.
+pub type custom_hashmap_get_fn = unsafe extern "C" fn(key: *const c_char) -> *mut c_char;
+
+/// `int custom_hashmap_del(const char *key)`
+///
+/// Returns 1 if the key existed and was removed, 0 otherwise, and
+/// `PROTECTED` if the key's prefix is protected against the caller.
+pub type custom_hashmap_del_fn = unsafe extern "C" fn(key: *const c_char) -> c_int;
+
+/// `char *custom_hashmap_consume(const char *key)`
+///
+/// Removes the key and returns its value atomically: when several callers
+/// consume the same key, exactly one of them gets the value. Returns NULL
+/// when the key is missing; otherwise the string is owned by the caller and
+/// must be released with `custom_hashmap_free`. A value containing a NUL
+/// byte is left in place and NULL returned.
+pub type custom_hashmap_consume_fn = unsafe extern "C" fn(key: *const c_char) -> *mut c_char;
+
+/// `int custom_hashmap_set_bin(const uint8_t *key, size_t key_len, const uint8_t *value, size_t value_len)`
+///
+/// Like `custom_hashmap_set`, with the key and value given as pointer and
+/// length so they may hold any bytes, including NUL and invalid UTF-8. A
+/// pointer may be NULL only when its length is 0.
+pub type custom_hashmap_set_bin_fn = unsafe extern "C" fn(
+    key: *const u8,
+    key_len: usize,
+    value: *const u8,
+    value_len: usize,
+) -> c_int;
+
+/// `uint8_t *custom_hashmap_get_bin(const uint8_t *key, size_t key_len, size_t *value_len)`
+///
+/// Like `custom_hashmap_get` for any bytes. Returns NULL when the key is
+/// missing; otherwise stores the value's length in `value_len` and returns
+/// a buffer owned by the caller, non-NULL even for an empty value, to be
+/// released with `custom_hashmap_free_bin`.
+pub type custom_hashmap_get_bin_fn = unsafe extern "C" fn(
+    key: *const u8,
+    key_len: usize,
+    value_len: *mut usize,
+) -> *mut u8;
+
+/// `int custom_hashmap_del_bin(const uint8_t *key, size_t key_len)`
+///
+/// Like `custom_hashmap_del` for a key given as pointer and length.
+pub type custom_hashmap_del_bin_fn = unsafe extern "C" fn(key: *const u8, key_len: usize) -> c_int;
+
+/// `void custom_hashmap_free_bin(uint8_t *value, size_t value_len)`
+///
+/// Releases a buffer returned by `custom_hashmap_get_bin`, given the length
+/// it came back with. Passing NULL is a no-op.
+pub type custom_hashmap_free_bin_fn = unsafe extern "C" fn(value: *mut u8, value_len: usize);
+
+/// `void custom_hashmap_act_as(const char *module)`
+///
+/// Names the module the calling thread acts for, until the next call; NULL
+/// clears it. Keys under a prefix protected with `owner:<module>` can only
+/// be written while the thread acts as that module, through this C API or
+/// through `custom.*` commands the thread runs with `RedisModule_Call`.
+/// Writes refused that way fail like any other failed write.
+pub type custom_hashmap_act_as_fn = unsafe extern "C" fn(module: *const c_char);
+
+/// Returned by `custom_hashmap_del` when the key's prefix is protected
+/// against the caller
+pub const PROTECTED: c_int = -2;
+
+/// `void custom_hashmap_free(char *value)`
+///
+/// Releases a string returned by `custom_hashmap_get` or
+/// `custom_hashmap_consume`. Passing NULL is a no-op.
+pub type custom_hashmap_free_fn = unsafe extern "C" fn(value: *mut c_char);
+
+/// Version of the C API as a whole, bumped whenever an existing export
+/// changes its signature or meaning. New exports don't bump it: callers
+/// probe for those and fall back when they are missing.
+pub const ABI_VERSION: u32 = 1;
+
+/// `uint32_t custom_hashmap_abi_version(void)`
+///
+/// The `ABI_VERSION` the module was built with. Builds older than the
+/// versioning don't export it.
+pub type custom_hashmap_abi_version_fn = unsafe extern "C" fn() -> u32;
+
+/// Magic number opening every struct passed across the batched C APIs ("CHMW")
+pub const WIRE_MAGIC: u32 = 0x4348_4D57;
+
+/// Layout version of the wire structs; bumped on any layout change
+pub const WIRE_VERSION: u16 = 1;
+
+/// Returned by batched C APIs when the caller's struct doesn't match the
+/// module's layout, i.e. the two were built against different versions of
+/// this crate
+pub const WIRE_MISMATCH: c_int = -1;
+
+/// Header opening every struct of the batched C APIs
+///
+/// The caller sets `magic`, `version` and `size` before the call and the
+/// module refuses to touch a struct whose header doesn't match its own
+/// layout. Whoever fills in the payload sets `crc` to the CRC-32 of the
+/// payload fields, which the other side checks before trusting them.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct custom_hashmap_wire_header {
+    pub magic: u32,
+    pub version: u16,
+    /// Size of the whole struct, header included
+    pub size: u16,
+    pub crc: u32,
+    pub reserved: u32,
+}
+
+impl custom_hashmap_wire_header {
+    /// Header for a `T` laid out by this build
+    pub fn new<T>() -> Self {
+        custom_hashmap_wire_header {
+            magic: WIRE_MAGIC,
+            version: WIRE_VERSION,
+            size: std::mem::size_of::<T>() as u16,
+            crc: 0,
+            reserved: 0,
+        }
+    }
+
+    /// Whether the header describes a `T` laid out by this build
+    pub fn matches<T>(&self) -> bool {
+        self.magic == WIRE_MAGIC
+            && self.version == WIRE_VERSION
+            && self.size as usize == std::mem::size_of::<T>()
+    }
+}
+
+/// Number of independently locked shards the module splits its keys over,
+/// unless configured otherwise with `CUSTOM.SHARDS`
+pub const SHARD_COUNT: usize = 64;
+
+/// Shard of the module that holds `key`, so callers can tell which keys
+/// contend for the same write lock. Both sides must be built with the same
+/// toolchain for the answer to match the module's, and the module must be
+/// running with the default shard count.
+pub fn shard_of(key: &[u8]) -> usize {
+    shard_in(key, SHARD_COUNT)
+}
+
+/// Shard holding `key` when keys are split over `shards` shards
+pub fn shard_in(key: &[u8], shards: usize) -> usize {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish() as usize % shards
+}
+
+/// CRC-32 (IEEE) of `bytes`
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+/// Output of `custom_hashmap_mget`
+///
+/// `values` points at `count` entries, one per requested key in order, each
+/// either a NUL-terminated value or NULL for a missing key (or a value
+/// containing a NUL byte). The table and
+/// all the strings share one allocation of `arena_size` bytes, released by
+/// `custom_hashmap_mget_free`. `Default` gives an empty result with its
+/// header ready for the call.
+#[repr(C)]
+#[derive(Debug)]
+pub struct custom_hashmap_mget_result {
+    pub header: custom_hashmap_wire_header,
+    pub values: *mut *mut c_char,
+    pub count: usize,
+    pub arena_size: usize,
+}
+
+impl custom_hashmap_mget_result {
+    // Checksum over the payload fields, independent of padding
+    fn payload_crc(&self) -> u32 {
+        let mut payload = [0u8; 24];
+        payload[..8].copy_from_slice(&(self.values as usize as u64).to_le_bytes());
+        payload[8..16].copy_from_slice(&(self.count as u64).to_le_bytes());
+        payload[16..].copy_from_slice(&(self.arena_size as u64).to_le_bytes());
+        crc32(&payload)
+    }
+
+    /// Stamp the checksum after filling in the payload
+    pub fn seal(&mut self) {
+        self.header.crc = self.payload_crc();
+    }
+
+    /// Whether the header matches this build and the payload its checksum
+    pub fn is_valid(&self) -> bool {
+        self.header.matches::<Self>() && self.header.crc == self.payload_crc()
+    }
+}
+
+impl Default for custom_hashmap_mget_result {
+    fn default() -> Self {
+        let mut result = custom_hashmap_mget_result {
+            header: custom_hashmap_wire_header::new::<Self>(),
+            values: std::ptr::null_mut(),
+            count: 0,
+            arena_size: 0,
+        };
+        result.seal();
+        result
+    }
+}
+
+/// `int custom_hashmap_mget(const char *const *keys, size_t count, custom_hashmap_mget_result *out)`
+///
+/// Looks up `count` keys in one call and fills `out`, whose header must be
+/// initialized (see `custom_hashmap_mget_result::default`). Returns 1 on
+/// success, 0 on failure and `WIRE_MISMATCH` if `out`'s header doesn't match
+/// the module's layout; `out` is left untouched unless the call succeeds.
+/// NULL entries in `keys` are treated as missing keys.
+pub type custom_hashmap_mget_fn = unsafe extern "C" fn(
+    keys: *const *const c_char,
+    count: usize,
+    out: *mut custom_hashmap_mget_result,
+) -> c_int;
+
+/// `void custom_hashmap_mget_free(custom_hashmap_mget_result *result)`
+///
+/// Releases the allocation behind a `custom_hashmap_mget` result and resets
+/// it. Passing NULL or an already released result is a no-op, and so is a
+/// result that fails its header or checksum check: leaking it is safer than
+/// freeing memory through a misread layout.
+pub type custom_hashmap_mget_free_fn = unsafe extern "C" fn(result: *mut custom_hashmap_mget_result);
+
+/// `int custom_hashmap_getprefix(const char *prefix, size_t limit, custom_hashmap_mget_result *out)`
+///
+/// Fetches up to `limit` live keys starting with `prefix`, in lexicographic
+/// order, together with their values. `out` is filled like a
+/// `custom_hashmap_mget` result except that its `count` entries alternate
+/// key and value, so it holds `count / 2` pairs; release it with
+/// `custom_hashmap_mget_free`. Pairs whose key or value contains a NUL byte
+/// are left out. Returns 1 on success, 0 on failure (including
+/// a module built without the `ordered` feature) and `WIRE_MISMATCH` if
+/// `out`'s header doesn't match the module's layout.
+pub type custom_hashmap_getprefix_fn = unsafe extern "C" fn(
+    prefix: *const c_char,
+    limit: usize,
+    out: *mut custom_hashmap_mget_result,
+) -> c_int;
+
+/// Ring request: look up `key`; the completion's `result` holds the value
+pub const RING_OP_GET: u32 = 1;
+
+/// Ring request: store `value` under `key`
+pub const RING_OP_SET: u32 = 2;
+
+/// Ring request: remove `key`
+pub const RING_OP_DEL: u32 = 3;
+
+/// One request or completion travelling through a `custom_hashmap_ring`
+///
+/// A request names an operation and its arguments; `key` and `value` stay
+/// owned by the caller and must remain valid until its completion has been
+/// read. The completion carries the same `tag` and `op`, a `status` equal
+/// to what the matching direct call returns (`custom_hashmap_set`: 1 or 0,
+/// `custom_hashmap_del`: 1, 0 or `PROTECTED`, GET: 1 if found, else 0) and,
+/// for a found GET, the value in `result`, owned by the caller and released
+/// with `custom_hashmap_free`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct custom_hashmap_ring_slot {
+    /// Chosen by the caller and copied into the completion
+    pub tag: u64,
+    pub op: u32,
+    pub status: c_int,
+    pub key: *const c_char,
+    pub value: *const c_char,
+    pub result: *mut c_char,
+}
+
+impl Default for custom_hashmap_ring_slot {
+    fn default() -> Self {
+        custom_hashmap_ring_slot {
+            tag: 0,
+            op: 0,
+            status: 0,
+            key: std::ptr::null(),
+            value: std::ptr::null(),
+            result: std::ptr::null_mut(),
+        }
+    }
+}
+
+/// A single-producer, single-consumer queue of `capacity` slots
+///
+/// `head` and `tail` count slots ever written and read; slot `n` lives at
+/// `slots[n % capacity]`. Only the producer stores `head`, with release
+/// ordering after filling the slots it publishes, and only the consumer
+/// stores `tail`, after it is done with the slots it releases. The queue is
+/// full when `head - tail == capacity`.
+#[repr(C)]
+#[derive(Debug)]
+pub struct custom_hashmap_ring_queue {
+    pub slots: *mut custom_hashmap_ring_slot,
+    pub head: AtomicU64,
+    pub tail: AtomicU64,
+}
+
+/// Shared-memory transport between a caller and the module
+///
+/// The caller allocates the ring and both queues' slots, then hands it to
+/// `custom_hashmap_ring_attach`. From then on the caller produces
+/// `requests` and consumes `completions`, while a thread of the module
+/// consumes `requests` and produces `completions`, several at a time. A
+/// caller with more than one thread must serialize its side itself.
+///
+/// To detach, the caller sets `closed` to 1 and must keep the ring and its
+/// slots alive until the module sets `stopped` to 1. The header's `crc` is
+/// unused: the payload is live and changes under both sides.
+#[repr(C)]
+#[derive(Debug)]
+pub struct custom_hashmap_ring {
+    pub header: custom_hashmap_wire_header,
+    /// Slots in each queue
+    pub capacity: u32,
+    /// Set by the caller to ask the module to stop serving the ring
+    pub closed: AtomicU32,
+    /// Set by the module once it will never touch the ring again
+    pub stopped: AtomicU32,
+    pub requests: custom_hashmap_ring_queue,
+    pub completions: custom_hashmap_ring_queue,
+}
+
+/// `int custom_hashmap_ring_attach(custom_hashmap_ring *ring, const char *module)`
+///
+/// Starts a module thread serving `ring`. Writes made through the ring act
+/// as `module` (see `custom_hashmap_act_as`), or as no one if it is NULL.
+/// Returns 1 on success, 0 on failure (a zero capacity or NULL slots) and
+/// `WIRE_MISMATCH` if the ring's header doesn't match the module's layout.
+pub type custom_hashmap_ring_attach_fn = unsafe extern "C" fn(
+    ring: *mut custom_hashmap_ring,
+    module: *const c_char,
+) -> c_int;
+
+/// Symbol name of `custom_hashmap_set`
+pub const SET_SYMBOL: &[u8] = b"custom_hashmap_set\0";
+
+/// Symbol name of `custom_hashmap_get`
+pub const GET_SYMBOL: &[u8] = b"custom_hashmap_get\0";
+
+/// Symbol name of `custom_hashmap_del`
+pub const DEL_SYMBOL: &[u8] = b"custom_hashmap_del\0";
+
+/// Symbol name of `custom_hashmap_consume`
+pub const CONSUME_SYMBOL: &[u8] = b"custom_hashmap_consume\0";
+
+/// Symbol name of `custom_hashmap_set_bin`
+pub const SET_BIN_SYMBOL: &[u8] = b"custom_hashmap_set_bin\0";
+
+/// Symbol name of `custom_hashmap_get_bin`
+pub const GET_BIN_SYMBOL: &[u8] = b"custom_hashmap_get_bin\0";
+
+/// Symbol name of `custom_hashmap_del_bin`
+pub const DEL_BIN_SYMBOL: &[u8] = b"custom_hashmap_del_bin\0";
+
+/// Symbol name of `custom_hashmap_free_bin`
+pub const FREE_BIN_SYMBOL: &[u8] = b"custom_hashmap_free_bin\0";
+
+/// Symbol name of `custom_hashmap_act_as`
+pub const ACT_AS_SYMBOL: &[u8] = b"custom_hashmap_act_as\0";
+
+/// Symbol name of `custom_hashmap_free`
+pub const FREE_SYMBOL: &[u8] = b"custom_hashmap_free\0";
+
+/// Symbol name of `custom_hashmap_mget`
+pub const MGET_SYMBOL: &[u8] = b"custom_hashmap_mget\0";
+
+/// Symbol name of `custom_hashmap_mget_free`
+pub const MGET_FREE_SYMBOL: &[u8] = b"custom_hashmap_mget_free\0";
+
+/// Symbol name of `custom_hashmap_getprefix`
+pub const GETPREFIX_SYMBOL: &[u8] = b"custom_hashmap_getprefix\0";
+
+/// Symbol name of `custom_hashmap_ring_attach`
+pub const RING_ATTACH_SYMBOL: &[u8] = b"custom_hashmap_ring_attach\0";
+
+/// Symbol name of `custom_hashmap_abi_version`
+pub const ABI_VERSION_SYMBOL: &[u8] = b"custom_hashmap_abi_version\0";
@@ -0,0 +1,283 @@
+//! Span recording shared by the Redis modules.
+//!
+//! [`init`] installs a `tracing` subscriber that keeps the most recent
+//! closed spans in memory and can append them, one JSON object per line, to
+//! a file. Each module exposes [`trace_command`] as its own `*.TRACE`
+//! command so spans can be dumped and the exporter switched at runtime.
+//!
+//! Every span record carries its start time in microseconds since the Unix
+//! epoch and its duration, so it can be lined up with application traces.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{LockResult, Mutex, Once, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use redis_module::{NextArg, RedisError, RedisResult, RedisString, RedisValue};
+use serde_json::{Map, Value};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::subscriber::Interest;
+use tracing::{Level, Metadata, Subscriber};
+use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+use tracing_subscriber::registry::{LookupSpan, Registry};
+
+// Closed spans kept for `TRACE RECENT`
+const RECENT_CAPACITY: usize = 1024;
+
+// Spans returned by `TRACE RECENT` when no count is given
+const DEFAULT_RECENT_COUNT: usize = 100;
+
+// Most verbose level recorded: 0 = off, 1 = error ... 5 = trace
+static MAX_LEVEL: AtomicU8 = AtomicU8::new(3);
+
+static INIT: Once = Once::new();
+
+const LEVEL_NAMES: [&str; 6] = ["off", "error", "warn", "info", "debug", "trace"];
+
+fn level_rank(level: &Level) -> u8 {
+    match *level {
+        Level::ERROR => 1,
+        Level::WARN => 2,
+        Level::INFO => 3,
+        Level::DEBUG => 4,
+        Level::TRACE => 5,
+    }
+}
+
+fn now_micros() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_micros() as u64)
+}
+
+// Recent spans plus the optional file exporter
+struct Recorder {
+    recent: VecDeque<String>,
+    exporter: Option<(String, File)>,
+    recorded: u64,
+    export_errors: u64,
+}
+
+static RECORDER: Mutex<Recorder> = Mutex::new(Recorder {
+    recent: VecDeque::new(),
+    exporter: None,
+    recorded: 0,
+    export_errors: 0,
+});
+
+impl Recorder {
+    fn push(&mut self, line: String) {
+        if let Some((_, file)) = self.exporter.as_mut() {
+            if writeln!(file, "{}", line).is_err() {
+                self.export_errors += 1;
+            }
+        }
+        if self.recent.len() == RECENT_CAPACITY {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(line);
+        self.recorded += 1;
+    }
+}
+
+// Timing and fields of an open span, kept in the registry's span extensions
+struct OpenSpan {
+    started: Instant,
+    start_us: u64,
+    fields: Map<String, Value>,
+}
+
+struct FieldVisitor<'a>(&'a mut Map<String, Value>);
+
+impl Visit for FieldVisitor<'_> {
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0.insert(field.name().to_string(), Value::from(format!("{:?}", value)));
+    }
+}
+
+// Records every closed span at or below the configured level
+struct RecorderLayer;
+
+impl<S> Layer<S> for RecorderLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    // The level can change at runtime, so never let callsites cache a decision
+    fn register_callsite(&self, _metadata: &'static Metadata<'static>) -> Interest {
+        Interest::sometimes()
+    }
+
+    fn enabled(&self, metadata: &Metadata<'_>, _ctx: Context<'_, S>) -> bool {
+        level_rank(metadata.level()) <= MAX_LEVEL.load(Ordering::Relaxed)
+    }
+
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            let mut fields = Map::new();
+            attrs.record(&mut FieldVisitor(&mut fields));
+            span.extensions_mut().insert(OpenSpan {
+                started: Instant::now(),
+                start_us: now_micros(),
+                fields,
+            });
+        }
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            if let Some(open) = span.extensions_mut().get_mut::<OpenSpan>() {
+                values.record(&mut FieldVisitor(&mut open.fields));
+            }
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let span = match ctx.span(&id) {
+            Some(span) => span,
+            None => return,
+        };
+        let open = match span.extensions_mut().remove::<OpenSpan>() {
+            Some(open) => open,
+            None => return,
+        };
+
+        let record = serde_json::json!({
+            "name": span.name(),
+            "target": span.metadata().target(),
+            "level": span.metadata().level().as_str(),
+            "span_id": id.into_u64(),
+            "parent_id": span.parent().map(|parent| parent.id().into_u64()),
+            "start_us": open.start_us,
+            "duration_us": open.started.elapsed().as_micros() as u64,
+            "fields": open.fields,
+        });
+
+        if let Ok(mut recorder) = RECORDER.lock() {
+            recorder.push(record.to_string());
+        }
+    }
+}
+
+/// Install the span recorder as this module's global subscriber. Safe to
+/// call more than once; only the first call has an effect.
+pub fn init() {
+    INIT.call_once(|| {
+        let subscriber = Registry::default().with(RecorderLayer);
+        let _ = tracing::subscriber::set_global_default(subscriber);
+    });
+}
+
+/// An `RwLock` whose acquisitions are recorded as `lock_wait` spans
+pub struct TracedRwLock<T> {
+    name: &'static str,
+    inner: RwLock<T>,
+}
+
+impl<T> TracedRwLock<T> {
+    pub fn new(name: &'static str, value: T) -> Self {
+        TracedRwLock { name, inner: RwLock::new(value) }
+    }
+
+    pub fn read(&self) -> LockResult<RwLockReadGuard<'_, T>> {
+        let _span = tracing::debug_span!("lock_wait", lock = self.name, mode = "read").entered();
+        self.inner.read()
+    }
+
+    pub fn write(&self) -> LockResult<RwLockWriteGuard<'_, T>> {
+        let _span = tracing::debug_span!("lock_wait", lock = self.name, mode = "write").entered();
+        self.inner.write()
+    }
+}
+
+/// Handle a module's trace command (`command` is its name, for errors):
+///
+/// - `RECENT [count]` - the most recent spans as JSON, newest first
+/// - `EXPORT path` - also append every span to `path`, one JSON object per line
+/// - `EXPORT OFF` - stop exporting
+/// - `LEVEL off|error|warn|info|debug|trace` - most verbose level recorded
+/// - `STATUS` - level, buffered and recorded spans, exporter and its errors
+pub fn trace_command(command: &str, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let subcommand = args.next_string()?.to_uppercase();
+
+    let mut recorder = RECORDER.lock().map_err(|_| {
+        RedisError::String("Failed to acquire trace recorder lock".to_string())
+    })?;
+
+    match subcommand.as_str() {
+        "RECENT" => {
+            let count = match args.next() {
+                Some(count) => count.parse_integer()
+                    .map_err(|_| RedisError::String(format!("Invalid count: {}", count)))? as usize,
+                None => DEFAULT_RECENT_COUNT,
+            };
+            args.done()?;
+
+            let spans = recorder.recent.iter().rev()
+                .take(count)
+                .map(|span| RedisValue::BulkString(span.clone()))
+                .collect();
+            Ok(RedisValue::Array(spans))
+        },
+        "EXPORT" => {
+            let target = args.next_string()?;
+            args.done()?;
+
+            if target.eq_ignore_ascii_case("OFF") {
+                recorder.exporter = None;
+            } else {
+                let file = OpenOptions::new().create(true).append(true).open(&target).map_err(|e| {
+                    RedisError::String(format!("Failed to open {}: {}", target, e))
+                })?;
+                recorder.exporter = Some((target, file));
+                recorder.export_errors = 0;
+            }
+            Ok(RedisValue::SimpleStringStatic("OK"))
+        },
+        "LEVEL" => {
+            let name = args.next_string()?.to_lowercase();
+            args.done()?;
+
+            let rank = LEVEL_NAMES.iter().position(|level| *level == name)
+                .ok_or_else(|| RedisError::String(format!("Unknown level: {}", name)))?;
+            MAX_LEVEL.store(rank as u8, Ordering::Relaxed);
+            Ok(RedisValue::SimpleStringStatic("OK"))
+        },
+        "STATUS" => {
+            args.done()?;
+            let level = LEVEL_NAMES[MAX_LEVEL.load(Ordering::Relaxed) as usize];
+            let exporter = recorder.exporter.as_ref().map_or(String::new(), |(path, _)| path.clone());
+            Ok(RedisValue::Array(vec![
+                RedisValue::SimpleStringStatic("level"),
+                RedisValue::SimpleStringStatic(level),
+                RedisValue::SimpleStringStatic("buffered"),
+                RedisValue::Integer(recorder.recent.len() as i64),
+                RedisValue::SimpleStringStatic("recorded"),
+                RedisValue::Integer(recorder.recorded as i64),
+                RedisValue::SimpleStringStatic("exporter"),
+                RedisValue::BulkString(exporter),
+                RedisValue::SimpleStringStatic("export_errors"),
+                RedisValue::Integer(recorder.export_errors as i64),
+            ]))
+        },
+        _ => Err(RedisError::String(format!("Unknown {} subcommand: {}", command, subcommand))),
+    }
+}
@@ -0,0 +1,414 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+use redis_module::{Context, NextArg, RedisError, RedisResult, RedisString, RedisValue};
+
+use crate::init_hashmap;
+use crate::store::{now_millis, BulkEntry};
+
+// How often queued writes are applied to a persistent backend. The backend
+// lags the map by up to this plus the time a commit takes, and a crash loses
+// the writes in that window.
+const FLUSH_INTERVAL: Duration = Duration::from_millis(100);
+
+// Entries handed to the map per bulk load while a backend's contents are read back
+const LOAD_BATCH: usize = 10_000;
+
+// A write waiting for the backend: the value and expiry, or None for a delete
+type PendingWrite = Option<(Vec<u8>, Option<u64>)>;
+
+// Where the map's contents are persisted beyond the process. This is a sink,
+// not the map's storage: the sharded map always serves reads and takes every
+// write first. A backend is handed the writes in batches after they are
+// applied, on a thread of its own so a commit never stalls the event loop,
+// and its contents are read back once at module load.
+pub trait PersistenceSink: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    // Whether anything outlives the process; writes are only queued if so
+    fn persistent(&self) -> bool;
+
+    // Every stored entry, including ones that have expired since
+    fn load(&self) -> Result<Vec<BulkEntry>, String>;
+
+    // Apply a batch of writes in one transaction; None deletes the key
+    fn apply(&self, writes: &[(Vec<u8>, PendingWrite)]) -> Result<(), String>;
+}
+
+// The default: the map is the only copy, as it has always been
+pub struct MemorySink;
+
+impl PersistenceSink for MemorySink {
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+
+    fn persistent(&self) -> bool {
+        false
+    }
+
+    fn load(&self) -> Result<Vec<BulkEntry>, String> {
+        Ok(Vec::new())
+    }
+
+    fn apply(&self, _writes: &[(Vec<u8>, PendingWrite)]) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+// An embedded redb database file. Values are stored behind their expiry as
+// 8 big-endian bytes, 0 for none.
+#[cfg(feature = "redb")]
+pub struct RedbSink {
+    db: redb::Database,
+}
+
+#[cfg(feature = "redb")]
+const REDB_TABLE: redb::TableDefinition<&[u8], &[u8]> = redb::TableDefinition::new("custom_hashmap");
+
+#[cfg(feature = "redb")]
+impl RedbSink {
+    pub fn open(path: &str) -> Result<RedbSink, String> {
+        let db = redb::Database::create(path).map_err(|e| format!("Failed to open {}: {}", path, e))?;
+        Ok(RedbSink { db })
+    }
+}
+
+#[cfg(feature = "redb")]
+impl PersistenceSink for RedbSink {
+    fn name(&self) -> &'static str {
+        "redb"
+    }
+
+    fn persistent(&self) -> bool {
+        true
+    }
+
+    fn load(&self) -> Result<Vec<BulkEntry>, String> {
+        use redb::ReadableTable;
+
+        let txn = self.db.begin_read().map_err(|e| e.to_string())?;
+        let table = match txn.open_table(REDB_TABLE) {
+            Ok(table) => table,
+            Err(redb::TableError::TableDoesNotExist(_)) => return Ok(Vec::new()),
+            Err(err) => return Err(err.to_string()),
+        };
+        let mut entries = Vec::new();
+        for item in table.iter().map_err(|e| e.to_string())? {
+            let (key, stored) = item.map_err(|e| e.to_string())?;
+            let stored = stored.value();
+            if stored.len() < 8 {
+                return Err(format!("Corrupt entry for key {}", String::from_utf8_lossy(key.value())));
+            }
+            let (expiry, value) = stored.split_at(8);
+            let expires_at = u64::from_be_bytes(expiry.try_into().unwrap_or_default());
+            entries.push((key.value().to_vec(), value.to_vec(), (expires_at > 0).then_some(expires_at)));
+        }
+        Ok(entries)
+    }
+
+    fn apply(&self, writes: &[(Vec<u8>, PendingWrite)]) -> Result<(), String> {
+        let txn = self.db.begin_write().map_err(|e| e.to_string())?;
+        {
+            let mut table = txn.open_table(REDB_TABLE).map_err(|e| e.to_string())?;
+            for (key, write) in writes {
+                match write {
+                    Some((value, expires_at)) => {
+                        let stored = [&expires_at.unwrap_or(0).to_be_bytes()[..], value].concat();
+                        table.insert(key.as_slice(), stored.as_slice()).map_err(|e| e.to_string())?;
+                    },
+                    None => {
+                        table.remove(key.as_slice()).map_err(|e| e.to_string())?;
+                    },
+                }
+            }
+        }
+        txn.commit().map_err(|e| e.to_string())
+    }
+}
+
+// The backend chosen by the `backend=` and `backend_path=` module arguments
+#[derive(Default)]
+struct Selection {
+    name: Option<String>,
+    path: Option<String>,
+}
+
+static mut SELECTION: Option<Mutex<Selection>> = None;
+static mut BACKEND: Option<Box<dyn PersistenceSink>> = None;
+static mut PENDING: Option<Mutex<HashMap<Vec<u8>, PendingWrite>>> = None;
+
+// Set once the backend's contents are in the map; until then nothing is queued
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+static QUEUED: AtomicU64 = AtomicU64::new(0);
+static FLUSHED: AtomicU64 = AtomicU64::new(0);
+static FAILED_FLUSHES: AtomicU64 = AtomicU64::new(0);
+static LAST_FLUSH_MICROS: AtomicU64 = AtomicU64::new(0);
+
+// Held while a batch is taken and applied, so batches reach the backend in
+// the order they were taken
+static FLUSHING: Mutex<()> = Mutex::new(());
+static LAST_ERROR: Mutex<Option<String>> = Mutex::new(None);
+
+fn init_selection() -> &'static Mutex<Selection> {
+    unsafe {
+        if SELECTION.is_none() {
+            SELECTION = Some(Mutex::new(Selection::default()));
+        }
+        SELECTION.as_ref().unwrap()
+    }
+}
+
+// Initialize the queue of writes waiting for the backend; one entry per
+// key, so a key written many times between flushes is written once
+fn init_pending() -> &'static Mutex<HashMap<Vec<u8>, PendingWrite>> {
+    unsafe {
+        if PENDING.is_none() {
+            PENDING = Some(Mutex::new(HashMap::new()));
+        }
+        PENDING.as_ref().unwrap()
+    }
+}
+
+fn backend() -> &'static dyn PersistenceSink {
+    unsafe { BACKEND.as_deref().unwrap_or(&MemorySink) }
+}
+
+// Apply the `backend=memory|redb` module argument
+pub fn select(name: &str) -> Result<(), String> {
+    let name = name.to_lowercase();
+    match name.as_str() {
+        "memory" => {},
+        #[cfg(feature = "redb")]
+        "redb" => {},
+        #[cfg(not(feature = "redb"))]
+        "redb" => return Err("backend=redb needs the module built with the redb feature".to_string()),
+        _ => return Err(format!("Unknown backend: {}, expected memory or redb", name)),
+    }
+    let mut selection = init_selection().lock().map_err(|_| "Failed to acquire backend lock".to_string())?;
+    selection.name = Some(name);
+    Ok(())
+}
+
+// Apply the `backend_path=<file>` module argument
+pub fn set_path(path: &str) -> Result<(), String> {
+    if path.is_empty() {
+        return Err("backend_path must not be empty".to_string());
+    }
+    let mut selection = init_selection().lock().map_err(|_| "Failed to acquire backend lock".to_string())?;
+    selection.path = Some(path.to_string());
+    Ok(())
+}
+
+fn open_selected() -> Result<Box<dyn PersistenceSink>, String> {
+    let selection = init_selection().lock().map_err(|_| "Failed to acquire backend lock".to_string())?;
+    match selection.name.as_deref() {
+        None | Some("memory") => Ok(Box::new(MemorySink)),
+        #[cfg(feature = "redb")]
+        Some("redb") => {
+            let path = selection.path.as_deref().ok_or("backend=redb needs backend_path=<file>")?;
+            Ok(Box::new(RedbSink::open(path)?))
+        },
+        Some(name) => Err(format!("Unknown backend: {}", name)),
+    }
+}
+
+// Open the selected backend at module load, read its contents into the map
+// and start the thread flushing writes to it. Returns how many live keys were loaded.
+pub fn start() -> Result<usize, String> {
+    let opened = open_selected()?;
+    let persistent = opened.persistent();
+    unsafe {
+        BACKEND = Some(opened);
+    }
+    if !persistent {
+        return Ok(0);
+    }
+
+    let now = now_millis();
+    let mut entries = backend().load()?;
+    entries.retain(|(_, _, expires_at)| expires_at.is_none_or(|expires_at| expires_at > now));
+    let expected = entries.len();
+    let hashmap = init_hashmap();
+    let mut loaded = 0;
+    while !entries.is_empty() {
+        let batch = entries.split_off(entries.len().saturating_sub(LOAD_BATCH));
+        loaded += hashmap.load_bulk(batch, expected).map_err(|e| e.to_string())?;
+    }
+
+    thread::Builder::new()
+        .name("custom-hashmap-backend".to_string())
+        .spawn(flush_loop)
+        .map_err(|e| format!("Failed to start the backend thread: {}", e))?;
+    ENABLED.store(true, Ordering::Relaxed);
+    Ok(loaded)
+}
+
+// Queue a stored value for the backend
+pub fn queue_put(key: &[u8], value: &[u8], expires_at: Option<u64>) {
+    queue(key, Some((value.to_vec(), expires_at)));
+}
+
+// Queue a removed key for the backend
+pub fn queue_delete(key: &[u8]) {
+    queue(key, None);
+}
+
+fn queue(key: &[u8], write: PendingWrite) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    if let Ok(mut pending) = init_pending().lock() {
+        pending.insert(key.to_vec(), write);
+        QUEUED.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+// Apply everything queued so far to the backend
+fn flush() -> Result<usize, String> {
+    flush_to(backend())
+}
+
+// Apply everything queued so far to `sink`. A failed batch goes back on the
+// queue behind any newer write of the same keys, to be tried again next flush.
+fn flush_to(sink: &dyn PersistenceSink) -> Result<usize, String> {
+    let _flushing = FLUSHING.lock().map_err(|_| "Failed to acquire backend lock".to_string())?;
+    let writes: Vec<_> = match init_pending().lock() {
+        Ok(mut pending) => std::mem::take(&mut *pending).into_iter().collect(),
+        Err(_) => return Err("Failed to acquire backend lock".to_string()),
+    };
+    if writes.is_empty() {
+        return Ok(0);
+    }
+
+    let started = Instant::now();
+    let count = writes.len();
+    if let Err(err) = sink.apply(&writes) {
+        FAILED_FLUSHES.fetch_add(1, Ordering::Relaxed);
+        if let Ok(mut pending) = init_pending().lock() {
+            for (key, write) in writes {
+                pending.entry(key).or_insert(write);
+            }
+        }
+        return Err(err);
+    }
+    FLUSHED.fetch_add(count as u64, Ordering::Relaxed);
+    LAST_FLUSH_MICROS.store(started.elapsed().as_micros() as u64, Ordering::Relaxed);
+    Ok(count)
+}
+
+// The backend thread: flush every FLUSH_INTERVAL, keeping the last failure
+// for CUSTOM.BACKEND INFO since there is no context to log it with
+fn flush_loop() {
+    loop {
+        thread::sleep(FLUSH_INTERVAL);
+        if let Err(err) = flush() {
+            if let Ok(mut last_error) = LAST_ERROR.lock() {
+                *last_error = Some(err);
+            }
+        }
+    }
+}
+
+// Inspect and flush the storage backend:
+// CUSTOM.BACKEND INFO
+// CUSTOM.BACKEND FLUSH
+// FLUSH writes the queue out on the calling thread and blocks until the
+// backend has committed it, waiting for a flush already under way first.
+#[tracing::instrument(name = "custom.backend", skip_all)]
+pub fn custom_backend(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let subcommand = args.next_string()?.to_uppercase();
+    args.done()?;
+
+    match subcommand.as_str() {
+        "INFO" => {
+            let pending = init_pending().lock().map_or(0, |pending| pending.len());
+            let last_error = LAST_ERROR.lock().ok().and_then(|last_error| last_error.clone());
+            Ok(RedisValue::Array(vec![
+                RedisValue::SimpleStringStatic("backend"),
+                RedisValue::SimpleStringStatic(backend().name()),
+                RedisValue::SimpleStringStatic("persistent"),
+                RedisValue::Integer(if backend().persistent() { 1 } else { 0 }),
+                RedisValue::SimpleStringStatic("pending"),
+                RedisValue::Integer(pending as i64),
+                RedisValue::SimpleStringStatic("queued"),
+                RedisValue::Integer(QUEUED.load(Ordering::Relaxed) as i64),
+                RedisValue::SimpleStringStatic("flushed"),
+                RedisValue::Integer(FLUSHED.load(Ordering::Relaxed) as i64),
+                RedisValue::SimpleStringStatic("failed_flushes"),
+                RedisValue::Integer(FAILED_FLUSHES.load(Ordering::Relaxed) as i64),
+                RedisValue::SimpleStringStatic("last_flush_us"),
+                RedisValue::Integer(LAST_FLUSH_MICROS.load(Ordering::Relaxed) as i64),
+                RedisValue::SimpleStringStatic("last_error"),
+                last_error.map_or(RedisValue::Null, RedisValue::BulkString),
+            ]))
+        },
+        "FLUSH" => {
+            let flushed = flush().map_err(|err| RedisError::String(format!("Failed to flush: {}", err)))?;
+            Ok(RedisValue::Integer(flushed as i64))
+        },
+        _ => Err(RedisError::String(format!("Unknown CUSTOM.BACKEND subcommand: {}", subcommand))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Records what it is handed, refusing batches while `failing` is set
+    struct TestSink {
+        failing: AtomicBool,
+        applied: Mutex<HashMap<Vec<u8>, PendingWrite>>,
+    }
+
+    impl PersistenceSink for TestSink {
+        fn name(&self) -> &'static str {
+            "test"
+        }
+
+        fn persistent(&self) -> bool {
+            true
+        }
+
+        fn load(&self) -> Result<Vec<BulkEntry>, String> {
+            Ok(Vec::new())
+        }
+
+        fn apply(&self, writes: &[(Vec<u8>, PendingWrite)]) -> Result<(), String> {
+            if self.failing.load(Ordering::Relaxed) {
+                return Err("refused".to_string());
+            }
+            self.applied.lock().unwrap().extend(writes.iter().cloned());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn requeues_failed_batches_behind_newer_writes() {
+        // Other tests write to the map, and so to the same queue, meanwhile;
+        // only this test's keys are checked
+        ENABLED.store(true, Ordering::Relaxed);
+        let sink = TestSink { failing: AtomicBool::new(true), applied: Mutex::new(HashMap::new()) };
+
+        queue_put(b"flush:1", b"old", None);
+        queue_put(b"flush:2", b"kept", Some(42));
+        queue_delete(b"flush:3");
+        assert!(flush_to(&sink).is_err());
+        assert!(sink.applied.lock().unwrap().is_empty());
+
+        // A write made while the batch was out wins over the failed one
+        queue_put(b"flush:1", b"new", None);
+        sink.failing.store(false, Ordering::Relaxed);
+        assert!(flush_to(&sink).unwrap() >= 3);
+        ENABLED.store(false, Ordering::Relaxed);
+
+        let applied = sink.applied.lock().unwrap();
+        assert_eq!(applied[b"flush:1".as_slice()], Some((b"new".to_vec(), None)));
+        assert_eq!(applied[b"flush:2".as_slice()], Some((b"kept".to_vec(), Some(42))));
+        assert_eq!(applied[b"flush:3".as_slice()], None);
+    }
+}
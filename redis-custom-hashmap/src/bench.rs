@@ -0,0 +1,116 @@
+use std::ffi::CString;
+use std::thread;
+use std::time::{Duration, Instant};
+use redis_module::{Context, NextArg, RedisError, RedisResult, RedisString, RedisValue, ThreadSafeContext};
+
+use crate::{custom_hashmap_del, custom_hashmap_free, custom_hashmap_get, custom_hashmap_set};
+
+// Upper bound on worker threads for a single run
+const MAX_CONCURRENCY: u64 = 64;
+
+// Prefix of the keys written by a benchmark run
+const BENCH_PREFIX: &str = "__bench:";
+
+// Pad a benchmark key or value out to the requested size
+fn padded(base: String, size: usize) -> String {
+    let mut s = base;
+    while s.len() < size {
+        s.push('x');
+    }
+    s
+}
+
+// Run one worker's share of operations, cycling set -> get -> del on its own keys
+fn run_worker(worker: u64, ops: u64, keysize: usize, valsize: usize) -> Vec<Duration> {
+    let value = CString::new(padded(String::new(), valsize)).unwrap();
+    let mut latencies = Vec::with_capacity(ops as usize);
+
+    for i in 0..ops {
+        let key = CString::new(padded(format!("{}{}:{}", BENCH_PREFIX, worker, i / 3), keysize)).unwrap();
+        let start = Instant::now();
+        match i % 3 {
+            0 => { custom_hashmap_set(key.as_ptr(), value.as_ptr()); },
+            1 => custom_hashmap_free(custom_hashmap_get(key.as_ptr())),
+            _ => { custom_hashmap_del(key.as_ptr()); },
+        }
+        latencies.push(start.elapsed());
+
+        // Don't leave a key behind when ops isn't a multiple of three
+        if i + 1 == ops && i % 3 != 2 {
+            custom_hashmap_del(key.as_ptr());
+        }
+    }
+
+    latencies
+}
+
+// Run the benchmark across worker threads and summarize it
+fn run(ops: u64, keysize: usize, valsize: usize, concurrency: u64) -> RedisResult {
+    let started = Instant::now();
+    let workers: Vec<_> = (0..concurrency)
+        .map(|worker| {
+            // Spread the remainder over the first workers
+            let share = ops / concurrency + u64::from(worker < ops % concurrency);
+            thread::spawn(move || run_worker(worker, share, keysize, valsize))
+        })
+        .collect();
+
+    let mut latencies = Vec::with_capacity(ops as usize);
+    for worker in workers {
+        latencies.extend(worker.join().map_err(|_| RedisError::Str("Benchmark worker panicked"))?);
+    }
+    let elapsed = started.elapsed();
+
+    latencies.sort_unstable();
+    let percentile = |q: f64| {
+        let index = ((latencies.len() - 1) as f64 * q).round() as usize;
+        RedisValue::Integer(latencies[index].as_nanos() as i64)
+    };
+
+    Ok(RedisValue::Array(vec![
+        RedisValue::SimpleStringStatic("ops"),
+        RedisValue::Integer(ops as i64),
+        RedisValue::SimpleStringStatic("concurrency"),
+        RedisValue::Integer(concurrency as i64),
+        RedisValue::SimpleStringStatic("elapsed_us"),
+        RedisValue::Integer(elapsed.as_micros() as i64),
+        RedisValue::SimpleStringStatic("ops_per_sec"),
+        RedisValue::Integer((ops as f64 / elapsed.as_secs_f64()) as i64),
+        RedisValue::SimpleStringStatic("p50_ns"),
+        percentile(0.50),
+        RedisValue::SimpleStringStatic("p90_ns"),
+        percentile(0.90),
+        RedisValue::SimpleStringStatic("p99_ns"),
+        percentile(0.99),
+        RedisValue::SimpleStringStatic("max_ns"),
+        percentile(1.0),
+    ]))
+}
+
+// Micro-benchmark the hashmap through its FFI entry points:
+// CUSTOM.BENCH ops keysize valsize concurrency
+#[tracing::instrument(name = "custom.bench", skip_all)]
+pub fn custom_bench(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let ops = args.next_u64()?;
+    let keysize = args.next_u64()? as usize;
+    let valsize = args.next_u64()? as usize;
+    let concurrency = args.next_u64()?;
+    args.done()?;
+
+    if ops == 0 {
+        return Err(RedisError::Str("ops must be positive"));
+    }
+    if concurrency == 0 || concurrency > MAX_CONCURRENCY {
+        return Err(RedisError::String(format!("concurrency must be between 1 and {}", MAX_CONCURRENCY)));
+    }
+
+    // Workers contend on the map like real clients, so keep the event loop free meanwhile
+    let blocked_client = ctx.block_client();
+    thread::spawn(move || {
+        let thread_ctx = ThreadSafeContext::with_blocked_client(blocked_client);
+        thread_ctx.reply(run(ops, keysize, valsize, concurrency));
+    });
+
+    Ok(RedisValue::NoReply)
+}
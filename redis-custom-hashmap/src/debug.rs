@@ -0,0 +1,218 @@
+use redis_module::{Context, RedisError, RedisResult, RedisString};
+
+// Hashmap operations faults can be injected into
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+    Set,
+    Get,
+    Del,
+}
+
+// Entry point an operation came through
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Via {
+    Ffi,
+    Command,
+}
+
+// Fault injection for integration tests. Only compiled into debug builds;
+// in release builds the checks below are constant no-ops.
+#[cfg(debug_assertions)]
+mod faults {
+    use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    use super::{Op, Via};
+
+    // Fail the next `remaining` matching operations
+    struct FailRule {
+        op: Op,
+        via: Option<Via>,
+        remaining: u64,
+    }
+
+    static FAIL_RULES: Mutex<Vec<FailRule>> = Mutex::new(Vec::new());
+    static LATENCY_MICROS: AtomicU64 = AtomicU64::new(0);
+    static POISONED: AtomicBool = AtomicBool::new(false);
+
+    pub fn fail_next(op: Op, via: Option<Via>, count: u64) {
+        if let Ok(mut rules) = FAIL_RULES.lock() {
+            rules.push(FailRule { op, via, remaining: count });
+        }
+    }
+
+    pub fn set_latency(micros: u64) {
+        LATENCY_MICROS.store(micros, Ordering::Relaxed);
+    }
+
+    pub fn set_poisoned(poisoned: bool) {
+        POISONED.store(poisoned, Ordering::Relaxed);
+    }
+
+    pub fn poisoned() -> bool {
+        POISONED.load(Ordering::Relaxed)
+    }
+
+    pub fn reset() {
+        if let Ok(mut rules) = FAIL_RULES.lock() {
+            rules.clear();
+        }
+        set_latency(0);
+        set_poisoned(false);
+    }
+
+    // Apply injected latency, then consume a matching fail rule if there is one
+    pub fn fault(op: Op, via: Via) -> bool {
+        let latency = LATENCY_MICROS.load(Ordering::Relaxed);
+        if latency > 0 {
+            std::thread::sleep(Duration::from_micros(latency));
+        }
+
+        let mut rules = match FAIL_RULES.lock() {
+            Ok(rules) => rules,
+            Err(_) => return false,
+        };
+        let matched = rules.iter_mut()
+            .find(|rule| rule.op == op && rule.via.is_none_or(|rule_via| rule_via == via));
+        match matched {
+            Some(rule) => {
+                rule.remaining -= 1;
+                rules.retain(|rule| rule.remaining > 0);
+                true
+            },
+            None => false,
+        }
+    }
+
+    pub fn status() -> (u64, u64, bool) {
+        let pending = FAIL_RULES.lock().map(|rules| rules.iter().map(|rule| rule.remaining).sum()).unwrap_or(0);
+        (pending, LATENCY_MICROS.load(Ordering::Relaxed), poisoned())
+    }
+}
+
+// Whether this operation should fail (sleeping first if latency is injected)
+pub fn fault(op: Op, via: Via) -> bool {
+    #[cfg(debug_assertions)]
+    {
+        faults::fault(op, via)
+    }
+    #[cfg(not(debug_assertions))]
+    {
+        let _ = (op, via);
+        false
+    }
+}
+
+// Whether writers should behave as if the map's locks were poisoned
+pub fn poisoned() -> bool {
+    #[cfg(debug_assertions)]
+    {
+        faults::poisoned()
+    }
+    #[cfg(not(debug_assertions))]
+    {
+        false
+    }
+}
+
+// Fault injection for tests (debug builds only):
+// CUSTOM.DEBUG FAIL_NEXT set|get|del [count] [VIA ffi|command]
+// CUSTOM.DEBUG LATENCY micros
+// CUSTOM.DEBUG SLEEP millis
+// CUSTOM.DEBUG POISON on|off
+// CUSTOM.DEBUG RESET
+// CUSTOM.DEBUG STATUS
+#[cfg(debug_assertions)]
+#[tracing::instrument(name = "custom.debug", skip_all)]
+pub fn custom_debug(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    use std::time::Duration;
+    use redis_module::{NextArg, RedisValue};
+
+    let mut args = args.into_iter().skip(1).peekable();
+    let subcommand = args.next_string()?.to_uppercase();
+
+    match subcommand.as_str() {
+        "FAIL_NEXT" => {
+            let op = match args.next_string()?.to_lowercase().as_str() {
+                "set" => Op::Set,
+                "get" => Op::Get,
+                "del" => Op::Del,
+                other => return Err(RedisError::String(format!("Unknown operation: {}", other))),
+            };
+
+            let mut count = 1;
+            if args.peek().is_some_and(|arg| arg.parse_integer().is_ok()) {
+                count = args.next_u64()?;
+            }
+
+            let mut via = None;
+            if let Ok(option) = args.next_string() {
+                if !option.eq_ignore_ascii_case("VIA") {
+                    return Err(RedisError::String(format!("Unknown option: {}", option)));
+                }
+                via = match args.next_string()?.to_lowercase().as_str() {
+                    "ffi" => Some(Via::Ffi),
+                    "command" => Some(Via::Command),
+                    other => return Err(RedisError::String(format!("Unknown entry point: {}", other))),
+                };
+            }
+            args.done()?;
+
+            if count == 0 {
+                return Err(RedisError::Str("count must be positive"));
+            }
+            faults::fail_next(op, via, count);
+            Ok(RedisValue::SimpleStringStatic("OK"))
+        },
+        "LATENCY" => {
+            let micros = args.next_u64()?;
+            args.done()?;
+            faults::set_latency(micros);
+            Ok(RedisValue::SimpleStringStatic("OK"))
+        },
+        "SLEEP" => {
+            let millis = args.next_u64()?;
+            args.done()?;
+            std::thread::sleep(Duration::from_millis(millis));
+            Ok(RedisValue::SimpleStringStatic("OK"))
+        },
+        "POISON" => {
+            let mode = args.next_string()?;
+            args.done()?;
+            let poisoned = if mode.eq_ignore_ascii_case("ON") {
+                true
+            } else if mode.eq_ignore_ascii_case("OFF") {
+                false
+            } else {
+                return Err(RedisError::String(format!("Expected ON or OFF, got: {}", mode)));
+            };
+            faults::set_poisoned(poisoned);
+            Ok(RedisValue::SimpleStringStatic("OK"))
+        },
+        "RESET" => {
+            args.done()?;
+            faults::reset();
+            Ok(RedisValue::SimpleStringStatic("OK"))
+        },
+        "STATUS" => {
+            args.done()?;
+            let (pending_failures, latency, poisoned) = faults::status();
+            Ok(RedisValue::Array(vec![
+                RedisValue::SimpleStringStatic("pending_failures"),
+                RedisValue::Integer(pending_failures as i64),
+                RedisValue::SimpleStringStatic("latency_us"),
+                RedisValue::Integer(latency as i64),
+                RedisValue::SimpleStringStatic("poisoned"),
+                RedisValue::Integer(if poisoned { 1 } else { 0 }),
+            ]))
+        },
+        _ => Err(RedisError::String(format!("Unknown CUSTOM.DEBUG subcommand: {}", subcommand))),
+    }
+}
+
+#[cfg(not(debug_assertions))]
+#[tracing::instrument(name = "custom.debug", skip_all)]
+pub fn custom_debug(_ctx: &Context, _args: Vec<RedisString>) -> RedisResult {
+    Err(RedisError::Str("CUSTOM.DEBUG is only available in debug builds"))
+}
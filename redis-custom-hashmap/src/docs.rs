@@ -0,0 +1,435 @@
+use command_docs::{Arg, CommandDoc, KeySpec, KEY_ACCESS, KEY_DELETE, KEY_INSERT, KEY_NOT_KEY, KEY_OW, KEY_RO, KEY_RW, KEY_UPDATE};
+
+// Hashmap keys aren't keyspace keys, but are routed like them so a key's
+// commands reach the node whose hashmap holds it
+const KEY_READ: KeySpec = KeySpec::index(1, KEY_NOT_KEY | KEY_RO | KEY_ACCESS);
+const KEY_WRITE: KeySpec = KeySpec::index(1, KEY_NOT_KEY | KEY_RW | KEY_UPDATE);
+
+const KEY: Arg = Arg::key("key", 0);
+
+const SINCE: &str = "0.1.0";
+
+// Metadata for COMMAND DOCS / COMMAND INFO, attached at load by command_docs::register
+pub static COMMANDS: &[CommandDoc] = &[
+    CommandDoc {
+        name: "custom.set",
+        summary: "Sets a key, optionally with an expiry or only if a condition on its current value holds.",
+        complexity: Some("O(1), or O(N) in the length of the IF condition"),
+        since: SINCE,
+        arity: -3,
+        key_specs: &[KeySpec::index(1, KEY_NOT_KEY | KEY_RW | KEY_INSERT | KEY_UPDATE)],
+        args: &[
+            KEY,
+            Arg::string("value"),
+            Arg::integer("seconds").with_token("EX").optional(),
+            Arg::string("condition").with_token("IF").optional(),
+        ],
+    },
+    CommandDoc {
+        name: "custom.eval_policy",
+        summary: "Tests a CUSTOM.SET IF condition against a key without writing.",
+        complexity: Some("O(N) where N is the length of the condition"),
+        since: SINCE,
+        arity: 3,
+        key_specs: &[KEY_READ],
+        args: &[KEY, Arg::string("condition")],
+    },
+    CommandDoc {
+        name: "custom.get",
+        summary: "Returns the value of a key.",
+        complexity: Some("O(1)"),
+        since: SINCE,
+        arity: 2,
+        key_specs: &[KEY_READ],
+        args: &[KEY],
+    },
+    CommandDoc {
+        name: "custom.version",
+        summary: "Returns the module's version, commit, features and C API version.",
+        complexity: Some("O(1)"),
+        since: SINCE,
+        arity: 1,
+        key_specs: &[],
+        args: &[],
+    },
+    CommandDoc {
+        name: "custom.keys",
+        summary: "Returns every key in the hashmap.",
+        complexity: Some("O(N) where N is the number of keys"),
+        since: SINCE,
+        arity: 1,
+        key_specs: &[],
+        args: &[],
+    },
+    CommandDoc {
+        name: "custom.digest",
+        summary: "Returns an order-independent digest of every key and value, and the key count.",
+        complexity: Some("O(N) where N is the number of keys"),
+        since: SINCE,
+        arity: 1,
+        key_specs: &[],
+        args: &[],
+    },
+    CommandDoc {
+        name: "custom.verifyintegrity",
+        summary: "Checks every checksummed value against its checksum, optionally deleting the corrupted ones.",
+        complexity: Some("O(N) where N is the total size of the values"),
+        since: SINCE,
+        arity: -1,
+        key_specs: &[],
+        args: &[Arg::pure_token("fix", "FIX").optional()],
+    },
+    CommandDoc {
+        name: "custom.del",
+        summary: "Deletes a key.",
+        complexity: Some("O(1)"),
+        since: SINCE,
+        arity: 2,
+        key_specs: &[KEY_WRITE],
+        args: &[KEY],
+    },
+    CommandDoc {
+        name: "custom.consume",
+        summary: "Deletes a key and returns its value in one atomic step.",
+        complexity: Some("O(1)"),
+        since: SINCE,
+        arity: 2,
+        key_specs: &[KeySpec::index(1, KEY_NOT_KEY | KEY_RW | KEY_ACCESS | KEY_DELETE)],
+        args: &[KEY],
+    },
+    CommandDoc {
+        name: "custom.dumpkey",
+        summary: "Serializes a key, its remaining time to live and its tags into a versioned blob.",
+        complexity: Some("O(1) to access the key plus O(N) in the size of the value and tags"),
+        since: SINCE,
+        arity: 2,
+        key_specs: &[KEY_READ],
+        args: &[KEY],
+    },
+    CommandDoc {
+        name: "custom.restorekey",
+        summary: "Creates a key from a CUSTOM.DUMPKEY blob.",
+        complexity: Some("O(1) to create the key plus O(N) in the size of the blob"),
+        since: SINCE,
+        arity: -3,
+        key_specs: &[KeySpec::index(1, KEY_NOT_KEY | KEY_OW | KEY_UPDATE)],
+        args: &[KEY, Arg::string("blob"), Arg::pure_token("replace", "REPLACE").optional()],
+    },
+    CommandDoc {
+        name: "custom.load_bulk",
+        summary: "Loads a chunk of key, time to live and value triples for a bulk restore.",
+        complexity: Some("O(N) where N is the number of entries in the chunk, plus O(M) for each shard it touches, M being the shard's size"),
+        since: SINCE,
+        arity: -4,
+        key_specs: &[],
+        args: &[
+            Arg::integer("total").with_token("SIZEHINT").optional(),
+            Arg::block("entry", &[Arg::string("key"), Arg::integer("ttl_ms"), Arg::string("value")]).multiple(),
+        ],
+    },
+    CommandDoc {
+        name: "custom.import_hash",
+        summary: "Copies the fields of a Redis hash into the hashmap, optionally under a key prefix.",
+        complexity: Some("O(N) where N is the number of fields in the hash, plus O(M) for each shard it touches, M being the shard's size"),
+        since: SINCE,
+        arity: -2,
+        key_specs: &[KeySpec::index(1, KEY_RO | KEY_ACCESS)],
+        args: &[Arg::key("hash_key", 0), Arg::string("prefix").with_token("PREFIX").optional()],
+    },
+    CommandDoc {
+        name: "custom.export_hash",
+        summary: "Copies the hashmap's keys, optionally only those under a prefix, into a Redis hash.",
+        complexity: Some("O(N) where N is the number of keys in the hashmap"),
+        since: SINCE,
+        arity: -2,
+        key_specs: &[KeySpec::index(1, KEY_RW | KEY_INSERT | KEY_UPDATE)],
+        args: &[Arg::key("hash_key", 0), Arg::string("prefix").with_token("PREFIX").optional()],
+    },
+    CommandDoc {
+        name: "custom.expire",
+        summary: "Sets a key's time to live in seconds.",
+        complexity: Some("O(1)"),
+        since: SINCE,
+        arity: 3,
+        key_specs: &[KEY_WRITE],
+        args: &[KEY, Arg::integer("seconds")],
+    },
+    CommandDoc {
+        name: "custom.ttl",
+        summary: "Returns a key's remaining time to live in seconds.",
+        complexity: Some("O(1)"),
+        since: SINCE,
+        arity: 2,
+        key_specs: &[KEY_READ],
+        args: &[KEY],
+    },
+    CommandDoc {
+        name: "custom.ttl_batch",
+        summary: "Returns the remaining time to live in seconds of several keys.",
+        complexity: Some("O(N) where N is the number of keys"),
+        since: SINCE,
+        arity: -2,
+        // A count of 0 runs the range to the last argument
+        key_specs: &[KeySpec::range(1, 0, KEY_NOT_KEY | KEY_RO | KEY_ACCESS)],
+        args: &[KEY.multiple()],
+    },
+    CommandDoc {
+        name: "custom.expiring_in",
+        summary: "Lists the keys that expire within a number of seconds, soonest first.",
+        complexity: Some("O(log(N)+M) where N is the number of keys with an expiry and M the number returned"),
+        since: SINCE,
+        arity: -2,
+        key_specs: &[],
+        args: &[Arg::integer("seconds"), Arg::integer("count").with_token("LIMIT").optional()],
+    },
+    CommandDoc {
+        name: "custom.sample_expire",
+        summary: "Tunes and inspects the active expiry cycle.",
+        complexity: Some("O(1), or O(N) in the number of keys sampled for RUN"),
+        since: SINCE,
+        arity: -2,
+        key_specs: &[],
+        args: &[Arg::one_of("subcommand", &[
+            Arg::integer("effort").with_token("EFFORT"),
+            Arg::integer("hz").with_token("HZ"),
+            Arg::integer("percent").with_token("BUDGET"),
+            Arg::pure_token("run", "RUN"),
+            Arg::pure_token("stats", "STATS"),
+        ])],
+    },
+    CommandDoc {
+        name: "custom.mirror",
+        summary: "Configures write-through of key prefixes to native keys.",
+        complexity: Some("O(N) for ADD where N is the number of keys, O(1) otherwise"),
+        since: SINCE,
+        arity: -2,
+        key_specs: &[],
+        args: &[Arg::one_of("subcommand", &[
+            Arg::block("add", &[
+                Arg::string("prefix"),
+                Arg::one_of("target", &[
+                    Arg::pure_token("hash", "hash"),
+                    Arg::pure_token("string", "string"),
+                ]).with_token("TARGET"),
+                Arg::string("keyprefix").with_token("KEYPREFIX"),
+            ]).with_token("ADD"),
+            Arg::string("prefix").with_token("DEL"),
+            Arg::pure_token("list", "LIST"),
+            Arg::pure_token("progress", "PROGRESS"),
+        ])],
+    },
+    CommandDoc {
+        name: "custom.replicate_to",
+        summary: "Forwards hashmap mutations to another Redis server, or turns forwarding off or reports on it.",
+        complexity: Some("O(1)"),
+        since: SINCE,
+        arity: -2,
+        key_specs: &[],
+        args: &[Arg::one_of("target", &[
+            Arg::block("endpoint", &[
+                Arg::string("address"),
+                Arg::string("prefix").with_token("PREFIX").optional(),
+                Arg::string("password").with_token("AUTH").optional(),
+            ]),
+            Arg::pure_token("off", "OFF"),
+            Arg::pure_token("status", "STATUS"),
+        ])],
+    },
+    CommandDoc {
+        name: "custom.backend",
+        summary: "Inspects the storage backend or flushes queued writes to it.",
+        complexity: Some("O(1), or O(N) in the number of queued writes for FLUSH"),
+        since: SINCE,
+        arity: 2,
+        key_specs: &[],
+        args: &[Arg::one_of("subcommand", &[
+            Arg::pure_token("info", "INFO"),
+            Arg::pure_token("flush", "FLUSH"),
+        ])],
+    },
+    CommandDoc {
+        name: "custom.shards",
+        summary: "Reports per-shard entry counts, memory and write lock contention, or changes the shard count.",
+        complexity: Some("O(N) where N is the number of keys"),
+        since: SINCE,
+        arity: -1,
+        key_specs: &[],
+        args: &[Arg::one_of("subcommand", &[
+            Arg::pure_token("stats", "STATS"),
+            Arg::integer("count").with_token("CONFIG"),
+            Arg::pure_token("rebalance", "REBALANCE"),
+        ]).optional()],
+    },
+    CommandDoc {
+        name: "custom.scan",
+        summary: "Pages through keys, or lists a range of keys, in lexicographic order.",
+        complexity: Some("O(log(N)+M) where N is the number of keys and M the number of keys returned"),
+        since: SINCE,
+        arity: -2,
+        key_specs: &[],
+        args: &[Arg::one_of("mode", &[
+            Arg::block("scan", &[Arg::string("cursor"), Arg::integer("count").with_token("COUNT").optional()]),
+            Arg::block("range", &[
+                Arg::string("from"),
+                Arg::string("to"),
+                Arg::integer("limit").with_token("LIMIT").optional(),
+            ]).with_token("RANGE"),
+        ])],
+    },
+    CommandDoc {
+        name: "custom.getprefix",
+        summary: "Returns the keys starting with a prefix and their values, in lexicographic order.",
+        complexity: Some("O(log(N)+M) where N is the number of keys and M the number of keys returned"),
+        since: SINCE,
+        arity: -2,
+        key_specs: &[],
+        args: &[Arg::string("prefix"), Arg::integer("count").with_token("LIMIT").optional()],
+    },
+    CommandDoc {
+        name: "custom.sum",
+        summary: "Sums the numeric values of the keys starting with a prefix, counting values that aren't numbers.",
+        complexity: Some("O(N) where N is the number of keys, or O(log(N)+M) with the ordered feature where M is the number of keys matched"),
+        since: SINCE,
+        arity: 2,
+        key_specs: &[],
+        args: &[Arg::string("prefix")],
+    },
+    CommandDoc {
+        name: "custom.avg",
+        summary: "Averages the numeric values of the keys starting with a prefix, counting values that aren't numbers.",
+        complexity: Some("O(N) where N is the number of keys, or O(log(N)+M) with the ordered feature where M is the number of keys matched"),
+        since: SINCE,
+        arity: 2,
+        key_specs: &[],
+        args: &[Arg::string("prefix")],
+    },
+    CommandDoc {
+        name: "custom.max",
+        summary: "Returns the largest numeric value among the keys starting with a prefix and the key holding it.",
+        complexity: Some("O(N) where N is the number of keys, or O(log(N)+M) with the ordered feature where M is the number of keys matched"),
+        since: SINCE,
+        arity: 2,
+        key_specs: &[],
+        args: &[Arg::string("prefix")],
+    },
+    CommandDoc {
+        name: "custom.prefix_stats",
+        summary: "Reports approximate key counts per key prefix at a depth, most keys first.",
+        complexity: Some("O(N log N) where N is the number of prefixes counted at the depth"),
+        since: SINCE,
+        arity: -1,
+        key_specs: &[],
+        args: &[Arg::integer("depth").optional()],
+    },
+    CommandDoc {
+        name: "custom.protect",
+        summary: "Restricts writes to keys under a prefix.",
+        complexity: Some("O(N) where N is the number of protected prefixes"),
+        since: SINCE,
+        arity: -2,
+        key_specs: &[],
+        args: &[Arg::one_of("subcommand", &[
+            Arg::block("protect", &[Arg::string("prefix"), Arg::string("mode").with_token("MODE")]),
+            Arg::pure_token("list", "LIST"),
+        ])],
+    },
+    CommandDoc {
+        name: "custom.fence",
+        summary: "Advances or returns the module-wide fencing token.",
+        complexity: Some("O(1)"),
+        since: SINCE,
+        arity: 2,
+        key_specs: &[],
+        args: &[Arg::one_of("subcommand", &[
+            Arg::pure_token("next", "NEXT"),
+            Arg::pure_token("current", "CURRENT"),
+        ])],
+    },
+    CommandDoc {
+        name: "custom.tag",
+        summary: "Adds, removes or lists the tags of a key.",
+        complexity: Some("O(N) where N is the number of tags given"),
+        since: SINCE,
+        arity: -3,
+        key_specs: &[KeySpec::index(2, KEY_NOT_KEY | KEY_RW | KEY_UPDATE)],
+        args: &[Arg::one_of("operation", &[
+            Arg::block("add", &[KEY, Arg::string("tag").multiple()]).with_token("ADD"),
+            Arg::block("del", &[KEY, Arg::string("tag").multiple()]).with_token("DEL"),
+            Arg::block("list", &[KEY]).with_token("LIST"),
+        ])],
+    },
+    CommandDoc {
+        name: "custom.bytag",
+        summary: "Lists the keys carrying a tag, or deletes them all.",
+        complexity: Some("O(N) where N is the number of keys carrying the tag"),
+        since: SINCE,
+        arity: -2,
+        key_specs: &[],
+        args: &[Arg::string("tag"), Arg::pure_token("delete", "DELETE").optional()],
+    },
+    CommandDoc {
+        name: "custom.bench",
+        summary: "Benchmarks hashmap reads and writes.",
+        complexity: Some("O(N) where N is the number of operations"),
+        since: SINCE,
+        arity: 5,
+        key_specs: &[],
+        args: &[
+            Arg::integer("ops"),
+            Arg::integer("keysize"),
+            Arg::integer("valsize"),
+            Arg::integer("concurrency"),
+        ],
+    },
+    CommandDoc {
+        name: "custom.debug",
+        summary: "Injects faults and latency for testing callers.",
+        complexity: Some("O(1)"),
+        since: SINCE,
+        arity: -2,
+        key_specs: &[],
+        args: &[Arg::one_of("subcommand", &[
+            Arg::block("fail_next", &[
+                Arg::one_of("op", &[
+                    Arg::pure_token("set", "set"),
+                    Arg::pure_token("get", "get"),
+                    Arg::pure_token("del", "del"),
+                ]),
+                Arg::integer("count").optional(),
+                Arg::one_of("path", &[
+                    Arg::pure_token("ffi", "ffi"),
+                    Arg::pure_token("command", "command"),
+                ]).with_token("VIA").optional(),
+            ]).with_token("FAIL_NEXT"),
+            Arg::integer("micros").with_token("LATENCY"),
+            Arg::integer("millis").with_token("SLEEP"),
+            Arg::one_of("poison", &[
+                Arg::pure_token("on", "on"),
+                Arg::pure_token("off", "off"),
+            ]).with_token("POISON"),
+            Arg::pure_token("reset", "RESET"),
+            Arg::pure_token("status", "STATUS"),
+        ])],
+    },
+    CommandDoc {
+        name: "custom.trace",
+        summary: "Dumps recorded spans or configures span export.",
+        complexity: Some("O(N) where N is the number of spans returned"),
+        since: SINCE,
+        arity: -2,
+        key_specs: &[],
+        args: &[Arg::one_of("subcommand", &[
+            Arg::block("recent", &[Arg::integer("count").optional()]).with_token("RECENT"),
+            Arg::string("path").with_token("EXPORT"),
+            Arg::one_of("level", &[
+                Arg::pure_token("off", "off"),
+                Arg::pure_token("error", "error"),
+                Arg::pure_token("warn", "warn"),
+                Arg::pure_token("info", "info"),
+                Arg::pure_token("debug", "debug"),
+                Arg::pure_token("trace", "trace"),
+            ]).with_token("LEVEL"),
+            Arg::pure_token("status", "STATUS"),
+        ])],
+    },
+];
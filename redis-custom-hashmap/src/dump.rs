@@ -0,0 +1,195 @@
+use session_core::capi::crc32;
+use redis_module::{Context, NextArg, RedisError, RedisResult, RedisString, RedisValue};
+
+use crate::{init_hashmap, mirror, protect, store, tags};
+
+// First bytes of every dump blob
+const DUMP_MAGIC: &[u8; 4] = b"CHMD";
+
+// Bumped whenever the blob layout changes; older versions stay restorable
+const DUMP_VERSION: u16 = 1;
+
+// An entry as carried in a blob
+struct Dump {
+    value: Vec<u8>,
+    // Remaining time to live in milliseconds; relative so clocks don't need to agree
+    ttl_millis: Option<u64>,
+    tags: Vec<String>,
+}
+
+// Blob layout, integers little-endian:
+//   magic "CHMD" | version u16 | ttl_ms u64 (0 = no expiry)
+//   | value_len u32 | value | tag_count u32 | (tag_len u32 | tag)*
+//   | crc32 u32 of everything before it
+fn encode(dump: &Dump) -> Vec<u8> {
+    let mut blob = Vec::with_capacity(26 + dump.value.len());
+    blob.extend_from_slice(DUMP_MAGIC);
+    blob.extend_from_slice(&DUMP_VERSION.to_le_bytes());
+    blob.extend_from_slice(&dump.ttl_millis.unwrap_or(0).to_le_bytes());
+    push_bytes(&mut blob, &dump.value);
+    blob.extend_from_slice(&(dump.tags.len() as u32).to_le_bytes());
+    for tag in &dump.tags {
+        push_bytes(&mut blob, tag.as_bytes());
+    }
+    let crc = crc32(&blob);
+    blob.extend_from_slice(&crc.to_le_bytes());
+    blob
+}
+
+fn push_bytes(blob: &mut Vec<u8>, bytes: &[u8]) {
+    blob.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+    blob.extend_from_slice(bytes);
+}
+
+// Reads a blob front to back
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], RedisError> {
+        if self.bytes.len() < len {
+            return Err(RedisError::Str("Dump payload is truncated"));
+        }
+        let (head, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(head)
+    }
+
+    fn u16(&mut self) -> Result<u16, RedisError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, RedisError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, RedisError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn bytes(&mut self) -> Result<Vec<u8>, RedisError> {
+        let len = self.u32()? as usize;
+        Ok(self.take(len)?.to_vec())
+    }
+
+    fn string(&mut self) -> Result<String, RedisError> {
+        String::from_utf8(self.bytes()?).map_err(|_| RedisError::Str("Dump payload is not valid UTF-8"))
+    }
+}
+
+fn decode(blob: &[u8]) -> Result<Dump, RedisError> {
+    if blob.len() < DUMP_MAGIC.len() + 4 || &blob[..DUMP_MAGIC.len()] != DUMP_MAGIC {
+        return Err(RedisError::Str("Not a custom hashmap dump payload"));
+    }
+    let (body, crc) = blob.split_at(blob.len() - 4);
+    if crc32(body) != u32::from_le_bytes(crc.try_into().unwrap()) {
+        return Err(RedisError::Str("Dump payload checksum mismatch"));
+    }
+
+    let mut reader = Reader { bytes: &body[DUMP_MAGIC.len()..] };
+    let version = reader.u16()?;
+    if version == 0 || version > DUMP_VERSION {
+        return Err(RedisError::String(format!("Unsupported dump payload version: {}", version)));
+    }
+
+    let ttl_millis = Some(reader.u64()?).filter(|&ttl| ttl > 0);
+    let value = reader.bytes()?;
+    let tag_count = reader.u32()?;
+    let tags = (0..tag_count).map(|_| reader.string()).collect::<Result<Vec<_>, _>>()?;
+    if !reader.bytes.is_empty() {
+        return Err(RedisError::Str("Dump payload has trailing bytes"));
+    }
+
+    Ok(Dump { value, ttl_millis, tags })
+}
+
+// Serialize a key with its remaining TTL and tags: CUSTOM.DUMPKEY key
+#[tracing::instrument(name = "custom.dumpkey", skip_all)]
+pub fn custom_dumpkey(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let key = args.next_arg()?;
+    let key = key.as_slice();
+    args.done()?;
+
+    // Hold the shard so the value, expiry and tags come from the same moment
+    let hashmap = init_hashmap();
+    let _shard = hashmap.write(key)?;
+
+    let (value, expires_at) = match (hashmap.get(key), hashmap.expiry(key)) {
+        (Some(value), Some(expires_at)) => (value, expires_at),
+        _ => return Ok(RedisValue::Null),
+    };
+    let ttl_millis = expires_at.map(|expires_at| expires_at.saturating_sub(store::now_millis()).max(1));
+
+    Ok(RedisValue::StringBuffer(encode(&Dump { value, ttl_millis, tags: tags::tags_of(key) })))
+}
+
+// Recreate a key from a CUSTOM.DUMPKEY blob: CUSTOM.RESTOREKEY key blob [REPLACE]
+#[tracing::instrument(name = "custom.restorekey", skip_all)]
+pub fn custom_restorekey(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let key = args.next_arg()?.as_slice().to_vec();
+    let blob = args.next_arg()?;
+
+    let replace = match args.next_string() {
+        Ok(option) if option.eq_ignore_ascii_case("REPLACE") => true,
+        Ok(option) => return Err(RedisError::String(format!("Unknown option: {}", option))),
+        Err(_) => false,
+    };
+    args.done()?;
+
+    let dump = decode(blob.as_slice())?;
+    protect::check_write(&key)?;
+
+    let hashmap = init_hashmap();
+    let mut shard = hashmap.write(&key)?;
+    if !replace && shard.contains_key(&key) {
+        return Err(RedisError::String(format!("Target key is busy: {}", String::from_utf8_lossy(&key))));
+    }
+
+    let expires_at = dump.ttl_millis.map(|ttl| store::now_millis() + ttl);
+    mirror::forward(&key, Some(&dump.value));
+    shard.insert_with_expiry(key.clone(), dump.value.clone(), expires_at);
+    tags::set_tags(&key, &dump.tags)?;
+    drop(shard);
+    mirror::write_through(ctx, &key, Some(&dump.value));
+
+    Ok(RedisValue::SimpleStringStatic("OK"))
+}
+
+// Bulk-load a chunk of a snapshot:
+// CUSTOM.LOAD_BULK [SIZEHINT total] key ttl_ms value [key ttl_ms value ...]
+// A ttl_ms of 0 means no expiry. Existing keys are overwritten, and the reply
+// is the number of keys that were new. Meant for cold starts: writes are not
+// mirrored, so restore mirrored keyspace data from the same snapshot.
+#[tracing::instrument(name = "custom.load_bulk", skip_all)]
+pub fn custom_load_bulk(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1).peekable();
+
+    // Total keys expected across all chunks, so shards are sized once up front
+    let mut expected = 0;
+    if args.peek().is_some_and(|option| option.to_string_lossy().eq_ignore_ascii_case("SIZEHINT")) {
+        args.next();
+        expected = args.next_u64()? as usize;
+    }
+    if args.len() == 0 || !args.len().is_multiple_of(3) {
+        return Err(RedisError::WrongArity);
+    }
+
+    let now = store::now_millis();
+    let mut entries = Vec::with_capacity(args.len() / 3);
+    while let Ok(key) = args.next_arg() {
+        let ttl_millis = args.next_u64()?;
+        let value = args.next_arg()?;
+        entries.push((key.as_slice().to_vec(), value.as_slice().to_vec(), Some(ttl_millis).filter(|&ttl| ttl > 0).map(|ttl| now + ttl)));
+    }
+
+    // Check every key before writing any, so a refused chunk leaves nothing behind
+    for (key, _, _) in &entries {
+        protect::check_write(key)?;
+    }
+
+    let added = init_hashmap().load_bulk(entries, expected)?;
+    Ok(RedisValue::Integer(added as i64))
+}
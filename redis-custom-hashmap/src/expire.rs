@@ -0,0 +1,304 @@
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use redis_module::{Context, NextArg, RedisError, RedisResult, RedisString, RedisValue};
+
+use crate::{init_hashmap, protect};
+use crate::store::now_millis;
+
+// Keys sampled per loop at effort 1; each extra effort level adds a quarter
+const KEYS_PER_LOOP: usize = 20;
+
+// Keep sampling while more than this percentage of a sample had expired
+const ACCEPTABLE_STALE_PERCENT: usize = 10;
+
+const MAX_EFFORT: u32 = 10;
+
+const MAX_HZ: u32 = 500;
+
+// 0 disables the active cycle; expired keys are then only removed when read
+static EFFORT: AtomicU32 = AtomicU32::new(1);
+
+// Active cycles per second, set by `expire_hz=<n>` or CUSTOM.SAMPLE_EXPIRE HZ
+static HZ: AtomicU32 = AtomicU32::new(10);
+
+// Share of the interval a cycle may use at effort 1, in percent; set by
+// `expire_budget=<percent>` or CUSTOM.SAMPLE_EXPIRE BUDGET
+static BUDGET_PERCENT: AtomicU32 = AtomicU32::new(25);
+
+static CYCLES: AtomicU64 = AtomicU64::new(0);
+static SAMPLED: AtomicU64 = AtomicU64::new(0);
+static EXPIRED: AtomicU64 = AtomicU64::new(0);
+static LAST_CYCLE_MICROS: AtomicU64 = AtomicU64::new(0);
+static EXPIRED_ON_ACCESS: AtomicU64 = AtomicU64::new(0);
+
+// xorshift state for picking samples
+static RANDOM_STATE: AtomicU64 = AtomicU64::new(0);
+
+fn next_random() -> u64 {
+    let mut x = RANDOM_STATE.load(Ordering::Relaxed);
+    if x == 0 {
+        x = now_millis() | 1;
+    }
+    x ^= x << 13;
+    x ^= x >> 7;
+    x ^= x << 17;
+    RANDOM_STATE.store(x, Ordering::Relaxed);
+    x
+}
+
+// Time between active cycles
+fn interval() -> Duration {
+    Duration::from_secs(1) / HZ.load(Ordering::Relaxed)
+}
+
+fn store_hz(hz: u64) -> Result<(), String> {
+    if hz == 0 || hz > MAX_HZ as u64 {
+        return Err(format!("hz must be between 1 and {}", MAX_HZ));
+    }
+    HZ.store(hz as u32, Ordering::Relaxed);
+    Ok(())
+}
+
+fn store_budget(percent: u64) -> Result<(), String> {
+    if percent == 0 || percent > 100 {
+        return Err("budget must be between 1 and 100 percent".to_string());
+    }
+    BUDGET_PERCENT.store(percent as u32, Ordering::Relaxed);
+    Ok(())
+}
+
+// Apply the `expire_hz=<n>` module argument
+pub fn set_hz(hz: &str) -> Result<(), String> {
+    let hz = hz.parse().map_err(|_| format!("Invalid expire_hz: {}", hz))?;
+    store_hz(hz)
+}
+
+// Apply the `expire_budget=<percent>` module argument
+pub fn set_budget(percent: &str) -> Result<(), String> {
+    let percent = percent.parse().map_err(|_| format!("Invalid expire_budget: {}", percent))?;
+    store_budget(percent)
+}
+
+// Sample volatile keys and remove the expired ones, repeating while samples
+// are mostly stale and the time budget lasts. Returns (sampled, expired).
+fn run_cycle(effort: u32) -> (usize, usize) {
+    let started = Instant::now();
+    let extra = (effort - 1) as usize;
+    let per_loop = KEYS_PER_LOOP + KEYS_PER_LOOP / 4 * extra;
+    let stale_percent = ACCEPTABLE_STALE_PERCENT - extra;
+    let percent = (BUDGET_PERCENT.load(Ordering::Relaxed) + 2 * (effort - 1)).min(100);
+    let budget = interval() * percent / 100;
+
+    let hashmap = init_hashmap();
+    let (mut sampled, mut expired) = (0, 0);
+    loop {
+        let now = now_millis();
+        let keys = hashmap.sample_volatile(per_loop, |len| next_random() as usize % len);
+        if keys.is_empty() {
+            break;
+        }
+
+        let mut stale = 0;
+        for key in &keys {
+            match hashmap.write(key) {
+                Ok(mut shard) => {
+                    if shard.remove_expired(key, now) {
+                        stale += 1;
+                    }
+                },
+                Err(_) => return (sampled, expired),
+            }
+        }
+        sampled += keys.len();
+        expired += stale;
+
+        if stale * 100 <= keys.len() * stale_percent || started.elapsed() >= budget {
+            break;
+        }
+    }
+
+    CYCLES.fetch_add(1, Ordering::Relaxed);
+    SAMPLED.fetch_add(sampled as u64, Ordering::Relaxed);
+    EXPIRED.fetch_add(expired as u64, Ordering::Relaxed);
+    LAST_CYCLE_MICROS.store(started.elapsed().as_micros() as u64, Ordering::Relaxed);
+    (sampled, expired)
+}
+
+fn tick(ctx: &Context, _data: ()) {
+    let effort = EFFORT.load(Ordering::Relaxed);
+    if effort > 0 {
+        run_cycle(effort);
+    }
+    ctx.create_timer(interval(), tick, ());
+}
+
+// Start the active expiry cycle at module load
+pub fn start(ctx: &Context) {
+    ctx.create_timer(interval(), tick, ());
+}
+
+// The lazy half of expiry: remove a key a read found missing if it is
+// only missing because it expired, instead of leaving it to the cycle
+pub fn remove_if_expired(key: &[u8]) {
+    let now = now_millis();
+    let hashmap = init_hashmap();
+    if !hashmap.has_expired(key, now) {
+        return;
+    }
+    if let Ok(mut shard) = hashmap.write(key) {
+        if shard.remove_expired(key, now) {
+            EXPIRED_ON_ACCESS.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+// When a key given `seconds` to live expires. Saturates, so no TTL wraps
+// around into the past.
+pub fn expires_after(seconds: u64) -> u64 {
+    now_millis().saturating_add(seconds.saturating_mul(1000))
+}
+
+// Set a key's expiry: CUSTOM.EXPIRE key seconds
+#[tracing::instrument(name = "custom.expire", skip_all)]
+pub fn custom_expire(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let key = args.next_arg()?;
+    let key = key.as_slice();
+    let seconds = args.next_u64()?;
+    args.done()?;
+    protect::check_write(key)?;
+
+    let hashmap = init_hashmap();
+    let mut shard = hashmap.write(key)?;
+    let updated = shard.set_expiry(key, Some(expires_after(seconds)));
+
+    Ok(RedisValue::Integer(if updated { 1 } else { 0 }))
+}
+
+// Seconds until a key expires; -1 if it has no expiry, -2 if it doesn't exist
+#[tracing::instrument(name = "custom.ttl", skip_all)]
+pub fn custom_ttl(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let key = args.next_arg()?;
+    args.done()?;
+
+    let ttl = ttl_secs(key.as_slice());
+    if ttl == -2 {
+        remove_if_expired(key.as_slice());
+    }
+    Ok(RedisValue::Integer(ttl))
+}
+
+pub fn ttl_secs(key: &[u8]) -> i64 {
+    match init_hashmap().expiry(key) {
+        None => -2,
+        Some(None) => -1,
+        Some(Some(expires_at)) => remaining_secs(expires_at),
+    }
+}
+
+// Whole seconds left until `expires_at`, rounded up
+fn remaining_secs(expires_at: u64) -> i64 {
+    expires_at.saturating_sub(now_millis()).div_ceil(1000) as i64
+}
+
+// CUSTOM.TTL for several keys at once: CUSTOM.TTL_BATCH key [key ...]
+#[tracing::instrument(name = "custom.ttl_batch", skip_all)]
+pub fn custom_ttl_batch(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    if args.len() < 2 {
+        return Err(RedisError::WrongArity);
+    }
+    Ok(RedisValue::Array(args.iter().skip(1)
+        .map(|key| RedisValue::Integer(ttl_secs(key.as_slice())))
+        .collect()))
+}
+
+// Keys that expire within a window, soonest first: CUSTOM.EXPIRING_IN seconds [LIMIT n]
+// Replies [key, ttl, key, ttl, ...] with each key's remaining seconds.
+#[tracing::instrument(name = "custom.expiring_in", skip_all)]
+pub fn custom_expiring_in(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let seconds = args.next_u64()?;
+    let limit = match args.next_string() {
+        Ok(option) if option.eq_ignore_ascii_case("LIMIT") => args.next_u64()? as usize,
+        Ok(option) => return Err(RedisError::String(format!("Unknown option: {}", option))),
+        Err(_) => usize::MAX,
+    };
+    args.done()?;
+
+    let until = expires_after(seconds);
+    let keys = init_hashmap().expiring_before(until, limit);
+    Ok(RedisValue::Array(keys.into_iter()
+        .flat_map(|(key, expires_at)| [RedisValue::StringBuffer(key), RedisValue::Integer(remaining_secs(expires_at))])
+        .collect()))
+}
+
+// Tune and inspect the active expiry cycle:
+// CUSTOM.SAMPLE_EXPIRE EFFORT 0-10
+// CUSTOM.SAMPLE_EXPIRE HZ 1-500
+// CUSTOM.SAMPLE_EXPIRE BUDGET 1-100
+// CUSTOM.SAMPLE_EXPIRE RUN
+// CUSTOM.SAMPLE_EXPIRE STATS
+#[tracing::instrument(name = "custom.sample_expire", skip_all)]
+pub fn custom_sample_expire(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let subcommand = args.next_string()?.to_uppercase();
+
+    match subcommand.as_str() {
+        "EFFORT" => {
+            let effort = args.next_u64()?;
+            args.done()?;
+            if effort > MAX_EFFORT as u64 {
+                return Err(RedisError::String(format!("effort must be between 0 and {}", MAX_EFFORT)));
+            }
+            EFFORT.store(effort as u32, Ordering::Relaxed);
+            Ok(RedisValue::SimpleStringStatic("OK"))
+        },
+        "HZ" => {
+            let hz = args.next_u64()?;
+            args.done()?;
+            store_hz(hz).map_err(RedisError::String)?;
+            Ok(RedisValue::SimpleStringStatic("OK"))
+        },
+        "BUDGET" => {
+            let percent = args.next_u64()?;
+            args.done()?;
+            store_budget(percent).map_err(RedisError::String)?;
+            Ok(RedisValue::SimpleStringStatic("OK"))
+        },
+        "RUN" => {
+            args.done()?;
+            let (sampled, expired) = run_cycle(EFFORT.load(Ordering::Relaxed).max(1));
+            Ok(RedisValue::Array(vec![
+                RedisValue::SimpleStringStatic("sampled"),
+                RedisValue::Integer(sampled as i64),
+                RedisValue::SimpleStringStatic("expired"),
+                RedisValue::Integer(expired as i64),
+            ]))
+        },
+        "STATS" => {
+            args.done()?;
+            Ok(RedisValue::Array(vec![
+                RedisValue::SimpleStringStatic("effort"),
+                RedisValue::Integer(EFFORT.load(Ordering::Relaxed) as i64),
+                RedisValue::SimpleStringStatic("hz"),
+                RedisValue::Integer(HZ.load(Ordering::Relaxed) as i64),
+                RedisValue::SimpleStringStatic("budget_percent"),
+                RedisValue::Integer(BUDGET_PERCENT.load(Ordering::Relaxed) as i64),
+                RedisValue::SimpleStringStatic("volatile_keys"),
+                RedisValue::Integer(init_hashmap().volatile_count() as i64),
+                RedisValue::SimpleStringStatic("cycles"),
+                RedisValue::Integer(CYCLES.load(Ordering::Relaxed) as i64),
+                RedisValue::SimpleStringStatic("sampled"),
+                RedisValue::Integer(SAMPLED.load(Ordering::Relaxed) as i64),
+                RedisValue::SimpleStringStatic("expired"),
+                RedisValue::Integer(EXPIRED.load(Ordering::Relaxed) as i64),
+                RedisValue::SimpleStringStatic("expired_on_access"),
+                RedisValue::Integer(EXPIRED_ON_ACCESS.load(Ordering::Relaxed) as i64),
+                RedisValue::SimpleStringStatic("last_cycle_us"),
+                RedisValue::Integer(LAST_CYCLE_MICROS.load(Ordering::Relaxed) as i64),
+            ]))
+        },
+        _ => Err(RedisError::String(format!("Unknown CUSTOM.SAMPLE_EXPIRE subcommand: {}", subcommand))),
+    }
+}
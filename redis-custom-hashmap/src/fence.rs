@@ -0,0 +1,42 @@
+use redis_module::{Context, NextArg, RedisError, RedisResult, RedisString, RedisValue};
+
+// Keyspace key holding the current fencing token, so it is saved with the
+// dataset and survives restarts and failovers
+const FENCE_KEY: &str = "custom:fence";
+
+fn current(ctx: &Context) -> Result<i64, RedisError> {
+    match ctx.call("GET", &[FENCE_KEY])? {
+        RedisValue::Null => Ok(0),
+        RedisValue::BulkString(token) | RedisValue::SimpleString(token) => token.parse().map_err(|_| {
+            RedisError::String(format!("{} does not hold a fencing token", FENCE_KEY))
+        }),
+        RedisValue::StringBuffer(token) => String::from_utf8_lossy(&token).parse().map_err(|_| {
+            RedisError::String(format!("{} does not hold a fencing token", FENCE_KEY))
+        }),
+        other => Err(RedisError::String(format!("Unexpected reply from GET: {:?}", other))),
+    }
+}
+
+// Module-wide fencing token:
+// CUSTOM.FENCE NEXT
+// CUSTOM.FENCE CURRENT
+// NEXT increments the token and returns it; CURRENT returns it unchanged, 0
+// before the first NEXT. A writer holding an older token than CURRENT has
+// been superseded and its writes should be refused.
+#[tracing::instrument(name = "custom.fence", skip_all)]
+pub fn custom_fence(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let subcommand = args.next_string()?.to_uppercase();
+    args.done()?;
+
+    match subcommand.as_str() {
+        "NEXT" => {
+            let token = ctx.call("INCR", &[FENCE_KEY])?;
+            // Replicas and the AOF run the same increment, so they agree on the token
+            ctx.replicate_verbatim();
+            Ok(token)
+        },
+        "CURRENT" => Ok(RedisValue::Integer(current(ctx)?)),
+        _ => Err(RedisError::String(format!("Unknown CUSTOM.FENCE subcommand: {}", subcommand))),
+    }
+}
@@ -0,0 +1,92 @@
+use redis_module::{Context, NextArg, RedisError, RedisResult, RedisString, RedisValue};
+
+use crate::{init_hashmap, protect};
+
+// Fields written per HSET call when exporting
+const EXPORT_BATCH: usize = 500;
+
+// Bytes of a string in a reply to RedisModule_Call
+fn reply_bytes(value: RedisValue) -> Result<Vec<u8>, RedisError> {
+    match value {
+        RedisValue::BulkString(value) | RedisValue::SimpleString(value) => Ok(value.into_bytes()),
+        RedisValue::StringBuffer(value) => Ok(value),
+        RedisValue::BulkRedisString(value) => Ok(value.as_slice().to_vec()),
+        other => Err(RedisError::String(format!("Unexpected reply from HGETALL: {:?}", other))),
+    }
+}
+
+// PREFIX p, or no prefix
+fn parse_prefix(args: &mut impl Iterator<Item = RedisString>) -> Result<Vec<u8>, RedisError> {
+    let prefix = match args.next_string() {
+        Ok(option) if option.eq_ignore_ascii_case("PREFIX") => args.next_arg()?.as_slice().to_vec(),
+        Ok(option) => return Err(RedisError::String(format!("Unknown option: {}", option))),
+        Err(_) => Vec::new(),
+    };
+    args.done()?;
+    Ok(prefix)
+}
+
+// Copy a native Redis hash into the hashmap: CUSTOM.IMPORT_HASH hash_key [PREFIX p]
+// Each field becomes the key p<field> holding the field's value, replacing
+// any value and expiry it had. Replies with the number of fields imported.
+// Protections are checked for every key before any is written; like
+// CUSTOM.LOAD_BULK, imported keys are not mirrored.
+#[tracing::instrument(name = "custom.import_hash", skip_all)]
+pub fn custom_import_hash(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let hash_key = args.next_arg()?;
+    let prefix = parse_prefix(&mut args)?;
+
+    let fields = match ctx.call("HGETALL", &[hash_key.as_slice()])? {
+        RedisValue::Array(fields) => fields,
+        other => return Err(RedisError::String(format!("Unexpected reply from HGETALL: {:?}", other))),
+    };
+
+    let mut entries = Vec::with_capacity(fields.len() / 2);
+    let mut fields = fields.into_iter();
+    while let (Some(field), Some(value)) = (fields.next(), fields.next()) {
+        let key = [prefix.as_slice(), &reply_bytes(field)?].concat();
+        entries.push((key, reply_bytes(value)?, None));
+    }
+
+    for (key, _, _) in &entries {
+        protect::check_write(key)?;
+    }
+
+    let imported = entries.len();
+    init_hashmap().load_bulk(entries, 0)?;
+    Ok(RedisValue::Integer(imported as i64))
+}
+
+// Copy hashmap keys into a native Redis hash: CUSTOM.EXPORT_HASH hash_key [PREFIX p]
+// Every key starting with p is written as a field named after the rest of
+// the key; without PREFIX every key is exported. Fields already in the hash
+// are overwritten, others are left alone. Replies with the number of fields written.
+#[tracing::instrument(name = "custom.export_hash", skip_all)]
+pub fn custom_export_hash(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let hash_key = args.next_arg()?;
+    let prefix = parse_prefix(&mut args)?;
+
+    let hashmap = init_hashmap();
+    let entries: Vec<(Vec<u8>, Vec<u8>)> = hashmap.keys()
+        .into_iter()
+        .filter(|key| key.starts_with(&prefix))
+        .filter_map(|key| {
+            let value = hashmap.get(&key)?;
+            Some((key[prefix.len()..].to_vec(), value))
+        })
+        .collect();
+
+    for batch in entries.chunks(EXPORT_BATCH) {
+        let mut hset_args: Vec<&[u8]> = Vec::with_capacity(1 + batch.len() * 2);
+        hset_args.push(hash_key.as_slice());
+        for (field, value) in batch {
+            hset_args.push(field);
+            hset_args.push(value);
+        }
+        ctx.call("HSET", &hset_args[..])?;
+    }
+
+    Ok(RedisValue::Integer(entries.len() as i64))
+}
@@ -0,0 +1,70 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use redis_module::{Context, NextArg, RedisError, RedisResult, RedisString, RedisValue};
+
+use crate::init_hashmap;
+
+// Whether new values are stored with a checksum, set by the `checksums=yes` module argument
+static CHECKSUMS: AtomicBool = AtomicBool::new(false);
+
+// Reads that found a value no longer matching its checksum
+static CORRUPT_READS: AtomicU64 = AtomicU64::new(0);
+// Corrupted entries found and removed by CUSTOM.VERIFYINTEGRITY
+static CORRUPT_FOUND: AtomicU64 = AtomicU64::new(0);
+static CORRUPT_REMOVED: AtomicU64 = AtomicU64::new(0);
+
+pub fn checksums_enabled() -> bool {
+    CHECKSUMS.load(Ordering::Relaxed)
+}
+
+// Apply the `checksums=yes|no` module argument
+pub fn set_checksums(value: &str) -> Result<(), String> {
+    let enabled = match value.to_lowercase().as_str() {
+        "yes" => true,
+        "no" => false,
+        _ => return Err(format!("Invalid checksums: {}, expected yes or no", value)),
+    };
+    CHECKSUMS.store(enabled, Ordering::Relaxed);
+    Ok(())
+}
+
+pub fn record_corrupt_read() {
+    CORRUPT_READS.fetch_add(1, Ordering::Relaxed);
+}
+
+// Scan every checksummed value: CUSTOM.VERIFYINTEGRITY [FIX]
+// Replies with how many values were checked and the keys of those that no
+// longer match their checksum, which FIX deletes, plus the running counters.
+#[tracing::instrument(name = "custom.verifyintegrity", skip_all)]
+pub fn custom_verifyintegrity(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let fix = match args.next_string() {
+        Ok(option) if option.eq_ignore_ascii_case("FIX") => true,
+        Ok(option) => return Err(RedisError::String(format!("Unknown option: {}", option))),
+        Err(_) => false,
+    };
+    args.done()?;
+
+    let (checked, corrupted) = init_hashmap().verify_integrity(fix)?;
+    CORRUPT_FOUND.fetch_add(corrupted.len() as u64, Ordering::Relaxed);
+    if fix {
+        CORRUPT_REMOVED.fetch_add(corrupted.len() as u64, Ordering::Relaxed);
+    }
+    if !corrupted.is_empty() {
+        ctx.log_warning(&format!("CUSTOM.VERIFYINTEGRITY found {} corrupted values", corrupted.len()));
+    }
+
+    Ok(RedisValue::Array(vec![
+        RedisValue::SimpleStringStatic("checksums"),
+        RedisValue::Integer(checksums_enabled() as i64),
+        RedisValue::SimpleStringStatic("checked"),
+        RedisValue::Integer(checked as i64),
+        RedisValue::SimpleStringStatic("corrupted"),
+        RedisValue::Array(corrupted.into_iter().map(RedisValue::StringBuffer).collect()),
+        RedisValue::SimpleStringStatic("corrupt_reads"),
+        RedisValue::Integer(CORRUPT_READS.load(Ordering::Relaxed) as i64),
+        RedisValue::SimpleStringStatic("corrupt_found"),
+        RedisValue::Integer(CORRUPT_FOUND.load(Ordering::Relaxed) as i64),
+        RedisValue::SimpleStringStatic("corrupt_removed"),
+        RedisValue::Integer(CORRUPT_REMOVED.load(Ordering::Relaxed) as i64),
+    ]))
+}
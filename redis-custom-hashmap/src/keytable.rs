@@ -0,0 +1,153 @@
+use std::collections::HashMap;
+#[cfg(feature = "interned")]
+use std::sync::Arc;
+
+// Interned prefixes end at the last occurrence of this byte, which they include
+#[cfg(feature = "interned")]
+const PREFIX_DELIMITER: u8 = b':';
+
+// Key bytes a table holds: as clients see them, as actually stored, and the
+// number of distinct prefixes they were stored under
+#[derive(Default, Clone, Copy)]
+pub struct KeyBytes {
+    pub logical: usize,
+    pub stored: usize,
+    pub prefixes: usize,
+}
+
+// The keys of one shard snapshot. Without the `interned` feature a plain map.
+#[cfg(not(feature = "interned"))]
+#[derive(Clone)]
+pub struct KeyTable<V> {
+    map: HashMap<Vec<u8>, V>,
+}
+
+#[cfg(not(feature = "interned"))]
+impl<V> Default for KeyTable<V> {
+    fn default() -> Self {
+        KeyTable { map: HashMap::new() }
+    }
+}
+
+#[cfg(not(feature = "interned"))]
+impl<V> KeyTable<V> {
+    pub fn get(&self, key: &[u8]) -> Option<&V> {
+        self.map.get(key)
+    }
+
+    pub fn contains_key(&self, key: &[u8]) -> bool {
+        self.map.contains_key(key)
+    }
+
+    pub fn insert(&mut self, key: Vec<u8>, value: V) -> Option<V> {
+        self.map.insert(key, value)
+    }
+
+    pub fn remove(&mut self, key: &[u8]) -> Option<V> {
+        self.map.remove(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn reserve(&mut self, additional: usize) {
+        self.map.reserve(additional);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Vec<u8>, &V)> {
+        self.map.iter().map(|(key, value)| (key.clone(), value))
+    }
+
+    pub fn key_bytes(&self) -> KeyBytes {
+        let logical = self.map.keys().map(Vec::len).sum();
+        KeyBytes { logical, stored: logical, prefixes: 0 }
+    }
+}
+
+// The keys sharing one prefix, by what follows it
+#[cfg(feature = "interned")]
+type Group<V> = HashMap<Box<[u8]>, V>;
+
+// With the `interned` feature, keys are grouped by prefix so a prefix shared
+// by many keys (a tenant id, a namespace) is stored once per shard, and
+// shard snapshots share it instead of copying it.
+#[cfg(feature = "interned")]
+#[derive(Clone)]
+pub struct KeyTable<V> {
+    groups: HashMap<Arc<[u8]>, Group<V>>,
+    len: usize,
+}
+
+#[cfg(feature = "interned")]
+impl<V> Default for KeyTable<V> {
+    fn default() -> Self {
+        KeyTable { groups: HashMap::new(), len: 0 }
+    }
+}
+
+// A key's prefix, up to and including its last delimiter, and the rest
+#[cfg(feature = "interned")]
+fn split(key: &[u8]) -> (&[u8], &[u8]) {
+    let at = key.iter().rposition(|byte| *byte == PREFIX_DELIMITER).map_or(0, |position| position + 1);
+    key.split_at(at)
+}
+
+#[cfg(feature = "interned")]
+impl<V> KeyTable<V> {
+    pub fn get(&self, key: &[u8]) -> Option<&V> {
+        let (prefix, rest) = split(key);
+        self.groups.get(prefix)?.get(rest)
+    }
+
+    pub fn contains_key(&self, key: &[u8]) -> bool {
+        self.get(key).is_some()
+    }
+
+    pub fn insert(&mut self, key: Vec<u8>, value: V) -> Option<V> {
+        let (prefix, rest) = split(&key);
+        // Only a new prefix is copied; known ones are looked up by slice
+        if !self.groups.contains_key(prefix) {
+            self.groups.insert(Arc::from(prefix), HashMap::new());
+        }
+        let previous = self.groups.get_mut(prefix).unwrap().insert(Box::from(rest), value);
+        if previous.is_none() {
+            self.len += 1;
+        }
+        previous
+    }
+
+    pub fn remove(&mut self, key: &[u8]) -> Option<V> {
+        let (prefix, rest) = split(key);
+        let group = self.groups.get_mut(prefix)?;
+        let removed = group.remove(rest)?;
+        if group.is_empty() {
+            self.groups.remove(prefix);
+        }
+        self.len -= 1;
+        Some(removed)
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    // Keys are spread over groups of unknown sizes, so they grow as keys arrive
+    pub fn reserve(&mut self, _additional: usize) {}
+
+    pub fn iter(&self) -> impl Iterator<Item = (Vec<u8>, &V)> {
+        self.groups.iter().flat_map(|(prefix, group)| {
+            group.iter().map(move |(rest, value)| ([&prefix[..], rest].concat(), value))
+        })
+    }
+
+    pub fn key_bytes(&self) -> KeyBytes {
+        let mut bytes = KeyBytes { prefixes: self.groups.len(), ..KeyBytes::default() };
+        for (prefix, group) in &self.groups {
+            let rest: usize = group.keys().map(|rest| rest.len()).sum();
+            bytes.logical += prefix.len() * group.len() + rest;
+            bytes.stored += prefix.len() + rest;
+        }
+        bytes
+    }
+}
@@ -1,158 +1,660 @@
-use std::collections::HashMap;
-use std::sync::RwLock;
+use std::alloc::Layout;
+use session_core::capi::{custom_hashmap_mget_result, custom_hashmap_wire_header, ABI_VERSION, PROTECTED, WIRE_MISMATCH, WIRE_VERSION};
+use session_core::BuildInfo;
 use redis_module::{
-    Context, NextArg, RedisError, RedisResult, RedisString, RedisValue,
+    Context, NextArg, RedisError, RedisResult, RedisString, RedisValue, Status,
+};
+
+mod backend;
+mod bench;
+mod debug;
+mod docs;
+mod dump;
+mod expire;
+mod fence;
+mod hash;
+mod integrity;
+mod keytable;
+mod mirror;
+mod numeric;
+mod policy;
+mod prefixes;
+mod protect;
+mod replicate;
+mod ring;
+mod scan;
+mod shards;
+mod store;
+mod tags;
+
+use store::ShardedMap;
+
+// This build, as CUSTOM.VERSION, MODULE LIST and the startup log report it
+const BUILD_INFO: BuildInfo = BuildInfo {
+    module: "custom_hashmap",
+    version: env!("CARGO_PKG_VERSION"),
+    git_sha: env!("GIT_SHA"),
+    features: &[
+        #[cfg(feature = "ordered")]
+        "ordered",
+        #[cfg(feature = "interned")]
+        "interned",
+        #[cfg(feature = "redb")]
+        "redb",
+        #[cfg(debug_assertions)]
+        "debug",
+    ],
 };
 
 // Global hashmap to store our key-value pairs
-static mut CUSTOM_HASHMAP: Option<RwLock<HashMap<String, String>>> = None;
+static mut CUSTOM_HASHMAP: Option<ShardedMap> = None;
 
 // Initialize the hashmap
-pub fn init_hashmap() -> &'static RwLock<HashMap<String, String>> {
+pub fn init_hashmap() -> &'static ShardedMap {
     unsafe {
         if CUSTOM_HASHMAP.is_none() {
-            CUSTOM_HASHMAP = Some(RwLock::new(HashMap::new()));
+            CUSTOM_HASHMAP = Some(ShardedMap::new());
         }
         CUSTOM_HASHMAP.as_ref().unwrap()
     }
 }
 
-// Public API functions for other modules to use directly
-#[no_mangle]
-pub extern "C" fn custom_hashmap_set(key: *const libc::c_char, value: *const libc::c_char) -> libc::c_int {
-    if key.is_null() || value.is_null() {
+// Bytes of a NUL-terminated C string
+unsafe fn c_bytes<'a>(ptr: *const libc::c_char) -> &'a [u8] {
+    std::ffi::CStr::from_ptr(ptr).to_bytes()
+}
+
+// Bytes of a pointer and length pair; NULL is only allowed for an empty slice
+unsafe fn raw_bytes<'a>(ptr: *const u8, len: usize) -> Option<&'a [u8]> {
+    match (ptr.is_null(), len) {
+        (_, 0) => Some(&[]),
+        (true, _) => None,
+        (false, len) => Some(std::slice::from_raw_parts(ptr, len)),
+    }
+}
+
+// Shared by custom_hashmap_set and custom_hashmap_set_bin
+fn ffi_set(key: &[u8], value: &[u8]) -> libc::c_int {
+    if debug::fault(debug::Op::Set, debug::Via::Ffi) || protect::check_write(key).is_err() {
         return 0;
     }
-    
-    let key_str = unsafe { std::ffi::CStr::from_ptr(key).to_string_lossy().to_string() };
-    let value_str = unsafe { std::ffi::CStr::from_ptr(value).to_string_lossy().to_string() };
-    
+
     let hashmap = init_hashmap();
-    match hashmap.write() {
-        Ok(mut map) => {
-            map.insert(key_str, value_str);
+    match hashmap.write(key) {
+        Ok(mut shard) => {
+            mirror::queue_write(key, Some(value));
+            shard.insert(key.to_vec(), value.to_vec());
             1
         },
         Err(_) => 0,
     }
 }
 
+// Shared by custom_hashmap_get and custom_hashmap_get_bin
+fn ffi_get(key: &[u8]) -> Option<Vec<u8>> {
+    if debug::fault(debug::Op::Get, debug::Via::Ffi) {
+        return None;
+    }
+    // Lock-free: never waits on writers, unless a miss finds the key expired
+    let value = init_hashmap().get(key);
+    if value.is_none() {
+        expire::remove_if_expired(key);
+    }
+    value
+}
+
+// Shared by custom_hashmap_del and custom_hashmap_del_bin
+fn ffi_del(key: &[u8]) -> libc::c_int {
+    if debug::fault(debug::Op::Del, debug::Via::Ffi) {
+        return 0;
+    }
+    if protect::check_write(key).is_err() {
+        return PROTECTED;
+    }
+
+    let hashmap = init_hashmap();
+    match hashmap.write(key) {
+        Ok(mut shard) => {
+            if shard.remove(key).is_some() {
+                tags::forget_key(key);
+                mirror::queue_write(key, None);
+                1
+            } else {
+                0
+            }
+        },
+        Err(_) => 0,
+    }
+}
+
+// Public API functions for other modules to use directly
+#[no_mangle]
+#[tracing::instrument(level = "debug", skip_all)]
+pub extern "C" fn custom_hashmap_set(key: *const libc::c_char, value: *const libc::c_char) -> libc::c_int {
+    if key.is_null() || value.is_null() {
+        return 0;
+    }
+    unsafe { ffi_set(c_bytes(key), c_bytes(value)) }
+}
+
+// A value with an embedded NUL can't be returned as a C string; it reads as
+// missing here and is only reachable through custom_hashmap_get_bin
 #[no_mangle]
+#[tracing::instrument(level = "debug", skip_all)]
 pub extern "C" fn custom_hashmap_get(key: *const libc::c_char) -> *mut libc::c_char {
     if key.is_null() {
         return std::ptr::null_mut();
     }
+    match ffi_get(unsafe { c_bytes(key) }).map(std::ffi::CString::new) {
+        Some(Ok(value)) => value.into_raw(),
+        _ => std::ptr::null_mut(),
+    }
+}
+
+// Release a string returned by custom_hashmap_get or custom_hashmap_consume
+#[no_mangle]
+pub extern "C" fn custom_hashmap_free(value: *mut libc::c_char) {
+    if !value.is_null() {
+        drop(unsafe { std::ffi::CString::from_raw(value) });
+    }
+}
+
+// Layout of an mget arena: the pointer table followed by the values
+fn mget_layout(arena_size: usize) -> Option<Layout> {
+    Layout::from_size_align(arena_size, std::mem::align_of::<*mut libc::c_char>()).ok()
+}
+
+// Look up many keys in one call. The pointer table and every value are
+// copied into a single allocation, released with custom_hashmap_mget_free.
+#[no_mangle]
+#[tracing::instrument(level = "debug", skip_all)]
+pub extern "C" fn custom_hashmap_mget(
+    keys: *const *const libc::c_char,
+    count: usize,
+    out: *mut custom_hashmap_mget_result,
+) -> libc::c_int {
+    if out.is_null() || (keys.is_null() && count > 0) {
+        return 0;
+    }
+    
+    // A caller built against another layout would misread whatever we write
+    if !unsafe { (*out).header.matches::<custom_hashmap_mget_result>() } {
+        return WIRE_MISMATCH;
+    }
     
-    let key_str = unsafe { std::ffi::CStr::from_ptr(key).to_string_lossy().to_string() };
+    if debug::fault(debug::Op::Get, debug::Via::Ffi) {
+        return 0;
+    }
     
+    // Lock-free, like single gets, and expired keys are removed the same way
     let hashmap = init_hashmap();
-    match hashmap.read() {
-        Ok(map) => {
-            match map.get(&key_str) {
+    let keys = if count == 0 { &[][..] } else { unsafe { std::slice::from_raw_parts(keys, count) } };
+    let values: Vec<Option<Vec<u8>>> = keys.iter()
+        .map(|&key| {
+            if key.is_null() {
+                return None;
+            }
+            let key = unsafe { c_bytes(key) };
+            let value = hashmap.get(key);
+            if value.is_none() {
+                expire::remove_if_expired(key);
+            }
+            // Like custom_hashmap_get, a value with an embedded NUL reads as missing
+            value.filter(|value| !value.contains(&0))
+        })
+        .collect();
+    
+    fill_mget_result(&values, out)
+}
+
+// Copy `values` into one arena and point `out` at it; the caller has checked `out`'s header
+fn fill_mget_result(values: &[Option<Vec<u8>>], out: *mut custom_hashmap_mget_result) -> libc::c_int {
+    let count = values.len();
+    let table_size = count * std::mem::size_of::<*mut libc::c_char>();
+    let arena_size = (table_size + values.iter().flatten().map(|value| value.len() + 1).sum::<usize>()).max(1);
+    let layout = match mget_layout(arena_size) {
+        Some(layout) => layout,
+        None => return 0,
+    };
+    
+    unsafe {
+        let arena = std::alloc::alloc(layout);
+        if arena.is_null() {
+            return 0;
+        }
+        
+        let table = arena as *mut *mut libc::c_char;
+        let mut offset = table_size;
+        for (index, value) in values.iter().enumerate() {
+            let value_ptr = match value {
                 Some(value) => {
-                    let c_str = std::ffi::CString::new(value.clone()).unwrap();
-                    c_str.into_raw()
+                    let dst = arena.add(offset);
+                    std::ptr::copy_nonoverlapping(value.as_ptr(), dst, value.len());
+                    *dst.add(value.len()) = 0;
+                    offset += value.len() + 1;
+                    dst as *mut libc::c_char
                 },
                 None => std::ptr::null_mut(),
-            }
-        },
-        Err(_) => std::ptr::null_mut(),
+            };
+            *table.add(index) = value_ptr;
+        }
+        
+        let mut result = custom_hashmap_mget_result {
+            header: custom_hashmap_wire_header::new::<custom_hashmap_mget_result>(),
+            values: table,
+            count,
+            arena_size,
+        };
+        result.seal();
+        *out = result;
     }
+    1
 }
 
+// Fetch the live keys under a prefix with their values, alternating key and
+// value in a custom_hashmap_mget result. Needs the ordered index.
 #[no_mangle]
+#[tracing::instrument(level = "debug", skip_all)]
+pub extern "C" fn custom_hashmap_getprefix(
+    prefix: *const libc::c_char,
+    limit: usize,
+    out: *mut custom_hashmap_mget_result,
+) -> libc::c_int {
+    if prefix.is_null() || out.is_null() {
+        return 0;
+    }
+    if !unsafe { (*out).header.matches::<custom_hashmap_mget_result>() } {
+        return WIRE_MISMATCH;
+    }
+    if debug::fault(debug::Op::Get, debug::Via::Ffi) {
+        return 0;
+    }
+
+    #[cfg(feature = "ordered")]
+    {
+        // Pairs that can't be returned as C strings are skipped
+        let values: Vec<Option<Vec<u8>>> = init_hashmap().prefix_entries(unsafe { c_bytes(prefix) }, limit)
+            .into_iter()
+            .filter(|(key, value)| !key.contains(&0) && !value.contains(&0))
+            .flat_map(|(key, value)| [Some(key), Some(value)])
+            .collect();
+        fill_mget_result(&values, out)
+    }
+    #[cfg(not(feature = "ordered"))]
+    {
+        let _ = limit;
+        0
+    }
+}
+
+// Release the arena of a custom_hashmap_mget result
+#[no_mangle]
+pub extern "C" fn custom_hashmap_mget_free(result: *mut custom_hashmap_mget_result) {
+    if result.is_null() {
+        return;
+    }
+    
+    let result = unsafe { &mut *result };
+    if result.values.is_null() || !result.is_valid() {
+        return;
+    }
+    if let Some(layout) = mget_layout(result.arena_size) {
+        unsafe { std::alloc::dealloc(result.values as *mut u8, layout) };
+    }
+    *result = custom_hashmap_mget_result::default();
+}
+
+#[no_mangle]
+#[tracing::instrument(level = "debug", skip_all)]
 pub extern "C" fn custom_hashmap_del(key: *const libc::c_char) -> libc::c_int {
     if key.is_null() {
         return 0;
     }
-    
-    let key_str = unsafe { std::ffi::CStr::from_ptr(key).to_string_lossy().to_string() };
-    
+    unsafe { ffi_del(c_bytes(key)) }
+}
+
+// Remove a key and return its value in one step, so of two racing callers
+// only one ever gets it. The result is released with custom_hashmap_free.
+// A value with an embedded NUL is left in place, like a missing key.
+#[no_mangle]
+#[tracing::instrument(level = "debug", skip_all)]
+pub extern "C" fn custom_hashmap_consume(key: *const libc::c_char) -> *mut libc::c_char {
+    if key.is_null() {
+        return std::ptr::null_mut();
+    }
+
+    let key = unsafe { c_bytes(key) };
+
+    if debug::fault(debug::Op::Del, debug::Via::Ffi) || protect::check_write(key).is_err() {
+        return std::ptr::null_mut();
+    }
+
     let hashmap = init_hashmap();
-    match hashmap.write() {
-        Ok(mut map) => {
-            if map.remove(&key_str).is_some() { 1 } else { 0 }
+    let mut shard = match hashmap.write(key) {
+        Ok(shard) => shard,
+        Err(_) => return std::ptr::null_mut(),
+    };
+    // The shard is held, so the value checked is the one removed
+    if hashmap.get(key).is_none_or(|value| value.contains(&0)) {
+        return std::ptr::null_mut();
+    }
+    match shard.remove(key).map(std::ffi::CString::new) {
+        Some(Ok(value)) => {
+            tags::forget_key(key);
+            mirror::queue_write(key, None);
+            value.into_raw()
         },
-        Err(_) => 0,
+        _ => std::ptr::null_mut(),
+    }
+}
+
+// The C API version this build implements, so callers can refuse a module
+// built against an incompatible custom-hashmap-sys
+#[no_mangle]
+pub extern "C" fn custom_hashmap_abi_version() -> u32 {
+    ABI_VERSION
+}
+
+// Store a value under a key, both given as pointer and length so they may
+// hold any bytes, NUL included
+#[no_mangle]
+#[tracing::instrument(level = "debug", skip_all)]
+pub extern "C" fn custom_hashmap_set_bin(key: *const u8, key_len: usize, value: *const u8, value_len: usize) -> libc::c_int {
+    match unsafe { (raw_bytes(key, key_len), raw_bytes(value, value_len)) } {
+        (Some(key), Some(value)) => ffi_set(key, value),
+        _ => 0,
+    }
+}
+
+// Look up a key given as pointer and length. The value's length is written
+// to `value_len`; the value is released with custom_hashmap_free_bin.
+#[no_mangle]
+#[tracing::instrument(level = "debug", skip_all)]
+pub extern "C" fn custom_hashmap_get_bin(key: *const u8, key_len: usize, value_len: *mut usize) -> *mut u8 {
+    let key = match unsafe { raw_bytes(key, key_len) } {
+        Some(key) if !value_len.is_null() => key,
+        _ => return std::ptr::null_mut(),
+    };
+    match ffi_get(key) {
+        Some(value) => {
+            unsafe { *value_len = value.len() };
+            // An empty value still comes back as a non-NULL pointer
+            Box::into_raw(value.into_boxed_slice()) as *mut u8
+        },
+        None => std::ptr::null_mut(),
+    }
+}
+
+// Remove a key given as pointer and length
+#[no_mangle]
+#[tracing::instrument(level = "debug", skip_all)]
+pub extern "C" fn custom_hashmap_del_bin(key: *const u8, key_len: usize) -> libc::c_int {
+    match unsafe { raw_bytes(key, key_len) } {
+        Some(key) => ffi_del(key),
+        None => 0,
+    }
+}
+
+// Release a value returned by custom_hashmap_get_bin
+#[no_mangle]
+pub extern "C" fn custom_hashmap_free_bin(value: *mut u8, value_len: usize) {
+    if !value.is_null() {
+        drop(unsafe { Box::from_raw(std::ptr::slice_from_raw_parts_mut(value, value_len)) });
     }
 }
 
-// Custom command to set a key-value pair
-fn custom_set(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+// Custom command to set a key-value pair: CUSTOM.SET key value [EX seconds] [IF condition]
+// Replies nil without writing when the condition doesn't hold.
+#[tracing::instrument(name = "custom.set", skip_all)]
+fn custom_set(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
     let mut args = args.into_iter().skip(1);
-    let key = args.next_string()?;
-    let value = args.next_string()?;
+    let key = args.next_arg()?.as_slice().to_vec();
+    let value = args.next_arg()?.as_slice().to_vec();
+    
+    let mut expires_at = None;
+    let mut condition = None;
+    while let Ok(option) = args.next_string() {
+        match option.to_uppercase().as_str() {
+            "EX" => expires_at = Some(expire::expires_after(args.next_u64()?)),
+            "IF" => condition = Some(policy::Condition::parse(args.next_arg()?.as_slice())?),
+            _ => return Err(RedisError::String(format!("Unknown option: {}", option))),
+        }
+    }
+    
+    if debug::fault(debug::Op::Set, debug::Via::Command) {
+        return Err(RedisError::Str("Injected failure"));
+    }
+    protect::check_write(&key)?;
     
     let hashmap = init_hashmap();
-    let mut map = hashmap.write().map_err(|_| {
-        RedisError::String("Failed to acquire write lock".to_string())
-    })?;
+    let mut shard = hashmap.write(&key)?;
+    // The shard is held, so the key can't change between the check and the write
+    if condition.is_some_and(|condition| !condition.holds(&key)) {
+        return Ok(RedisValue::Null);
+    }
     
-    map.insert(key, value);
+    mirror::forward(&key, Some(&value));
+    shard.insert_with_expiry(key.clone(), value.clone(), expires_at);
+    drop(shard);
+    mirror::write_through(ctx, &key, Some(&value));
     
     Ok(RedisValue::SimpleStringStatic("OK"))
 }
 
 // Custom command to get a value by key
+#[tracing::instrument(name = "custom.get", skip_all)]
 fn custom_get(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
     let mut args = args.into_iter().skip(1);
-    let key = args.next_string()?;
+    let key = args.next_arg()?;
+    
+    if debug::fault(debug::Op::Get, debug::Via::Command) {
+        return Err(RedisError::Str("Injected failure"));
+    }
     
     let hashmap = init_hashmap();
-    let map = hashmap.read().map_err(|_| {
-        RedisError::String("Failed to acquire read lock".to_string())
-    })?;
     
-    match map.get(&key) {
-        Some(value) => Ok(RedisValue::BulkString(value.clone().into())),
-        None => Ok(RedisValue::Null),
+    match hashmap.get(key.as_slice()) {
+        Some(value) => Ok(RedisValue::StringBuffer(value)),
+        None => {
+            expire::remove_if_expired(key.as_slice());
+            Ok(RedisValue::Null)
+        },
     }
 }
 
 // List all keys in the custom hashmap
+#[tracing::instrument(name = "custom.keys", skip_all)]
 fn custom_keys(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
     if args.len() != 1 {
         return Err(RedisError::WrongArity);
     }
     
     let hashmap = init_hashmap();
-    let map = hashmap.read().map_err(|_| {
-        RedisError::String("Failed to acquire read lock".to_string())
-    })?;
     
-    let keys: Vec<RedisValue> = map.keys()
-        .map(|k| RedisValue::BulkString(k.clone().into()))
+    let keys: Vec<RedisValue> = hashmap.keys()
+        .into_iter()
+        .map(RedisValue::StringBuffer)
         .collect();
     
     Ok(RedisValue::Array(keys))
 }
 
+// Fingerprint of the whole hashmap, to compare a primary, a replica and a backup:
+// CUSTOM.DIGEST replies [digest, hex, keys, count]
+#[tracing::instrument(name = "custom.digest", skip_all)]
+fn custom_digest(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    if args.len() != 1 {
+        return Err(RedisError::WrongArity);
+    }
+
+    let digest = init_hashmap().digest();
+    Ok(RedisValue::Array(vec![
+        RedisValue::SimpleStringStatic("digest"),
+        RedisValue::BulkString(digest.hex()),
+        RedisValue::SimpleStringStatic("keys"),
+        RedisValue::Integer(digest.count() as i64),
+    ]))
+}
+
 // Delete a key from the custom hashmap
-fn custom_del(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+#[tracing::instrument(name = "custom.del", skip_all)]
+fn custom_del(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
     let mut args = args.into_iter().skip(1);
-    let key = args.next_string()?;
+    let key = args.next_arg()?.as_slice().to_vec();
+    
+    if debug::fault(debug::Op::Del, debug::Via::Command) {
+        return Err(RedisError::Str("Injected failure"));
+    }
+    protect::check_write(&key)?;
     
     let hashmap = init_hashmap();
-    let mut map = hashmap.write().map_err(|_| {
-        RedisError::String("Failed to acquire write lock".to_string())
-    })?;
+    let mut shard = hashmap.write(&key)?;
     
-    let removed = map.remove(&key).is_some();
+    let removed = shard.remove(&key).is_some();
+    if removed {
+        tags::forget_key(&key);
+        mirror::forward(&key, None);
+        drop(shard);
+        mirror::write_through(ctx, &key, None);
+    }
     
     Ok(RedisValue::Integer(if removed { 1 } else { 0 }))
 }
 
+// Atomically get and delete a key: CUSTOM.CONSUME key
+#[tracing::instrument(name = "custom.consume", skip_all)]
+fn custom_consume(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let key = args.next_arg()?.as_slice().to_vec();
+    args.done()?;
+    
+    if debug::fault(debug::Op::Del, debug::Via::Command) {
+        return Err(RedisError::Str("Injected failure"));
+    }
+    protect::check_write(&key)?;
+    
+    let hashmap = init_hashmap();
+    let mut shard = hashmap.write(&key)?;
+    
+    match shard.remove(&key) {
+        Some(value) => {
+            tags::forget_key(&key);
+            mirror::forward(&key, None);
+            drop(shard);
+            mirror::write_through(ctx, &key, None);
+            Ok(RedisValue::StringBuffer(value))
+        },
+        None => Ok(RedisValue::Null),
+    }
+}
+
+// Describe this build: CUSTOM.VERSION
+#[tracing::instrument(name = "custom.version", skip_all)]
+fn custom_version(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    if args.len() != 1 {
+        return Err(RedisError::WrongArity);
+    }
+
+    Ok(RedisValue::Array(vec![
+        RedisValue::SimpleStringStatic("module"),
+        RedisValue::SimpleStringStatic(BUILD_INFO.module),
+        RedisValue::SimpleStringStatic("version"),
+        RedisValue::SimpleStringStatic(BUILD_INFO.version),
+        RedisValue::SimpleStringStatic("git_sha"),
+        RedisValue::SimpleStringStatic(BUILD_INFO.git_sha),
+        RedisValue::SimpleStringStatic("features"),
+        RedisValue::Array(BUILD_INFO.features.iter().map(|feature| RedisValue::SimpleStringStatic(feature)).collect()),
+        RedisValue::SimpleStringStatic("abi_version"),
+        RedisValue::Integer(BUILD_INFO.abi_version() as i64),
+        RedisValue::SimpleStringStatic("wire_version"),
+        RedisValue::Integer(WIRE_VERSION as i64),
+    ]))
+}
+
+// Inspect recorded spans: CUSTOM.TRACE RECENT|EXPORT|LEVEL|STATUS ...
+fn custom_trace(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    module_tracing::trace_command("CUSTOM.TRACE", args)
+}
+
+// Module load hook
+fn init(ctx: &Context, args: &[RedisString]) -> Status {
+    ctx.log_notice(&format!("Loading {}", BUILD_INFO));
+    for arg in args {
+        let arg = arg.to_string_lossy();
+        let applied = match arg.split_once('=') {
+            Some((name, value)) if name.eq_ignore_ascii_case("shards") => shards::set_initial_count(value),
+            Some((name, value)) if name.eq_ignore_ascii_case("checksums") => integrity::set_checksums(value),
+            Some((name, value)) if name.eq_ignore_ascii_case("prefix_depth") => prefixes::set_depth(value),
+            Some((name, value)) if name.eq_ignore_ascii_case("expire_hz") => expire::set_hz(value),
+            Some((name, value)) if name.eq_ignore_ascii_case("expire_budget") => expire::set_budget(value),
+            Some((name, value)) if name.eq_ignore_ascii_case("backend") => backend::select(value),
+            Some((name, value)) if name.eq_ignore_ascii_case("backend_path") => backend::set_path(value),
+            _ => Err(format!("Unknown module argument: {}", arg)),
+        };
+        if let Err(err) = applied {
+            ctx.log_warning(&err);
+            return Status::Err;
+        }
+    }
+    match backend::start() {
+        Ok(0) => {},
+        Ok(loaded) => ctx.log_notice(&format!("Loaded {} keys from the storage backend", loaded)),
+        Err(err) => {
+            ctx.log_warning(&format!("Failed to start the storage backend: {}", err));
+            return Status::Err;
+        },
+    }
+    module_tracing::init();
+    command_docs::register(ctx, docs::COMMANDS);
+    mirror::start(ctx);
+    expire::start(ctx);
+    Status::Ok
+}
+
 // Redis module initialization with the correct format for v2.0.7
 redis_module::redis_module! {
     name: "custom_hashmap",
-    version: 1,
+    version: session_core::version::module_version(env!("CARGO_PKG_VERSION")),
     allocator: (redis_module::alloc::RedisAlloc, redis_module::alloc::RedisAlloc),
     data_types: [],
+    init: init,
     commands: [
         ["custom.set", custom_set, "write", 1, 1, 1],
         ["custom.get", custom_get, "readonly", 1, 1, 1],
+        ["custom.version", custom_version, "readonly", 0, 0, 0],
+        ["custom.eval_policy", policy::custom_eval_policy, "readonly", 1, 1, 1],
         ["custom.keys", custom_keys, "readonly", 0, 0, 0],
+        ["custom.scan", scan::custom_scan, "readonly", 0, 0, 0],
+        ["custom.getprefix", scan::custom_getprefix, "readonly", 0, 0, 0],
+        ["custom.sum", numeric::custom_sum, "readonly", 0, 0, 0],
+        ["custom.avg", numeric::custom_avg, "readonly", 0, 0, 0],
+        ["custom.max", numeric::custom_max, "readonly", 0, 0, 0],
+        ["custom.digest", custom_digest, "readonly", 0, 0, 0],
+        ["custom.verifyintegrity", integrity::custom_verifyintegrity, "write", 0, 0, 0],
         ["custom.del", custom_del, "write", 1, 1, 1],
+        ["custom.consume", custom_consume, "write", 1, 1, 1],
+        ["custom.dumpkey", dump::custom_dumpkey, "readonly", 1, 1, 1],
+        ["custom.restorekey", dump::custom_restorekey, "write", 1, 1, 1],
+        ["custom.load_bulk", dump::custom_load_bulk, "write", 0, 0, 0],
+        ["custom.import_hash", hash::custom_import_hash, "write", 1, 1, 1],
+        ["custom.export_hash", hash::custom_export_hash, "write", 1, 1, 1],
+        ["custom.expire", expire::custom_expire, "write", 1, 1, 1],
+        ["custom.ttl", expire::custom_ttl, "readonly", 1, 1, 1],
+        ["custom.ttl_batch", expire::custom_ttl_batch, "readonly", 1, -1, 1],
+        ["custom.expiring_in", expire::custom_expiring_in, "readonly", 0, 0, 0],
+        ["custom.sample_expire", expire::custom_sample_expire, "admin", 0, 0, 0],
+        ["custom.mirror", mirror::custom_mirror, "admin", 0, 0, 0],
+        ["custom.backend", backend::custom_backend, "admin", 0, 0, 0],
+        ["custom.replicate_to", replicate::custom_replicate_to, "admin", 0, 0, 0],
+        ["custom.shards", shards::custom_shards, "admin", 0, 0, 0],
+        ["custom.prefix_stats", prefixes::custom_prefix_stats, "readonly", 0, 0, 0],
+        ["custom.protect", protect::custom_protect, "admin", 0, 0, 0],
+        ["custom.fence", fence::custom_fence, "write", 0, 0, 0],
+        ["custom.tag", tags::custom_tag, "write", 2, 2, 1],
+        ["custom.bytag", tags::custom_bytag, "write", 0, 0, 0],
+        ["custom.bench", bench::custom_bench, "admin", 0, 0, 0],
+        ["custom.debug", debug::custom_debug, "admin", 0, 0, 0],
+        ["custom.trace", custom_trace, "admin", 0, 0, 0],
     ],
 }
 
@@ -169,4 +671,77 @@ mod tests {
         let result = add(2, 2);
         assert_eq!(result, 4);
     }
+
+    // Invalid UTF-8 and embedded NULs, each test on its own keys since the map is global
+    const BINARY_VALUE: &[u8] = b"\xff\x00\xfebinary";
+
+    fn get_bin(key: &[u8]) -> Option<Vec<u8>> {
+        let mut len = 0;
+        let ptr = custom_hashmap_get_bin(key.as_ptr(), key.len(), &mut len);
+        if ptr.is_null() {
+            return None;
+        }
+        let value = unsafe { std::slice::from_raw_parts(ptr, len) }.to_vec();
+        custom_hashmap_free_bin(ptr, len);
+        Some(value)
+    }
+
+    #[test]
+    fn store_keeps_binary_keys_apart() {
+        let hashmap = init_hashmap();
+        let lossy_twins: [&[u8]; 2] = [b"store:\xff", b"store:\xfe"];
+        for key in lossy_twins {
+            hashmap.write(key).unwrap().insert(key.to_vec(), [key, BINARY_VALUE].concat());
+        }
+        for key in lossy_twins {
+            assert_eq!(hashmap.get(key), Some([key, BINARY_VALUE].concat()));
+        }
+        assert!(hashmap.keys().contains(&b"store:\xff".to_vec()));
+    }
+
+    #[test]
+    fn bin_api_round_trips_any_bytes() {
+        let key = b"bin:\x00\xff";
+        assert_eq!(custom_hashmap_set_bin(key.as_ptr(), key.len(), BINARY_VALUE.as_ptr(), BINARY_VALUE.len()), 1);
+        assert_eq!(get_bin(key).as_deref(), Some(BINARY_VALUE));
+        // The key stops at its length, not at the NUL
+        assert_eq!(get_bin(b"bin:"), None);
+
+        assert_eq!(custom_hashmap_del_bin(key.as_ptr(), key.len()), 1);
+        assert_eq!(get_bin(key), None);
+        assert_eq!(custom_hashmap_del_bin(key.as_ptr(), key.len()), 0);
+    }
+
+    #[test]
+    fn bin_api_returns_empty_values() {
+        let key = b"bin:empty";
+        assert_eq!(custom_hashmap_set_bin(key.as_ptr(), key.len(), std::ptr::null(), 0), 1);
+        assert_eq!(get_bin(key), Some(Vec::new()));
+        assert_eq!(custom_hashmap_set_bin(std::ptr::null(), 1, b"x".as_ptr(), 1), 0);
+    }
+
+    #[test]
+    fn c_string_api_skips_values_with_nul() {
+        let key = b"cstr:nul";
+        custom_hashmap_set_bin(key.as_ptr(), key.len(), BINARY_VALUE.as_ptr(), BINARY_VALUE.len());
+        let c_key = std::ffi::CString::new(&key[..]).unwrap();
+
+        assert!(custom_hashmap_get(c_key.as_ptr()).is_null());
+        // Consuming can't hand the value back, so it must stay put
+        assert!(custom_hashmap_consume(c_key.as_ptr()).is_null());
+        assert_eq!(get_bin(key).as_deref(), Some(BINARY_VALUE));
+    }
+
+    #[test]
+    fn c_string_api_keeps_invalid_utf8() {
+        let key = std::ffi::CString::new(&b"cstr:\xff"[..]).unwrap();
+        let value = std::ffi::CString::new(&b"\xfe\xfd"[..]).unwrap();
+        assert_eq!(custom_hashmap_set(key.as_ptr(), value.as_ptr()), 1);
+
+        let read = custom_hashmap_consume(key.as_ptr());
+        assert!(!read.is_null());
+        assert_eq!(unsafe { std::ffi::CStr::from_ptr(read) }.to_bytes(), b"\xfe\xfd");
+        custom_hashmap_free(read);
+        assert_eq!(get_bin(b"cstr:\xff"), None);
+    }
 }
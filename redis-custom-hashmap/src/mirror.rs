@@ -0,0 +1,330 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, RwLock};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use redis_module::{Context, NextArg, RedisError, RedisResult, RedisString, RedisValue};
+
+use crate::{init_hashmap, replicate};
+
+// How often writes made through the C API are flushed to the keyspace
+const MIRROR_FLUSH_INTERVAL: Duration = Duration::from_millis(100);
+
+// Shape of the mirrored data in the real keyspace
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum MirrorTarget {
+    // One string key per entry: <keyprefix><key>
+    String,
+    // One hash per rule: <keyprefix><prefix>, field = key without the prefix
+    Hash,
+}
+
+impl MirrorTarget {
+    fn name(&self) -> &'static str {
+        match self {
+            MirrorTarget::String => "string",
+            MirrorTarget::Hash => "hash",
+        }
+    }
+}
+
+// A write-through rule for hashmap keys starting with `prefix`
+#[derive(Debug, Clone)]
+struct MirrorRule {
+    prefix: String,
+    target: MirrorTarget,
+    key_prefix: String,
+}
+
+// A mutation waiting to be mirrored: the value, or `None` if the key was deleted
+type PendingWrite = Option<Vec<u8>>;
+
+// Writes ever queued, and how many of those the flush timer has applied, so
+// callers can wait for everything queued up to some point to reach the keyspace
+static QUEUED: AtomicU64 = AtomicU64::new(0);
+static FLUSHED: AtomicU64 = AtomicU64::new(0);
+
+static mut MIRROR_RULES: Option<RwLock<Vec<MirrorRule>>> = None;
+static mut PENDING_WRITES: Option<Mutex<HashMap<Vec<u8>, PendingWrite>>> = None;
+
+// Initialize the mirror rules
+fn init_rules() -> &'static RwLock<Vec<MirrorRule>> {
+    unsafe {
+        if MIRROR_RULES.is_none() {
+            MIRROR_RULES = Some(RwLock::new(Vec::new()));
+        }
+        MIRROR_RULES.as_ref().unwrap()
+    }
+}
+
+// Initialize the queue of writes made without a context; one entry per key,
+// so the queue never outgrows the map and only a key's last write is applied
+fn init_pending() -> &'static Mutex<HashMap<Vec<u8>, PendingWrite>> {
+    unsafe {
+        if PENDING_WRITES.is_none() {
+            PENDING_WRITES = Some(Mutex::new(HashMap::new()));
+        }
+        PENDING_WRITES.as_ref().unwrap()
+    }
+}
+
+// Find the most specific rule covering a key
+fn matching_rule(key: &[u8]) -> Option<MirrorRule> {
+    let rules = init_rules().read().ok()?;
+    rules.iter()
+        .filter(|rule| key.starts_with(rule.prefix.as_bytes()))
+        .max_by_key(|rule| rule.prefix.len())
+        .cloned()
+}
+
+// Apply one mutation to the keyspace according to its rule
+fn apply(ctx: &Context, rule: &MirrorRule, key: &[u8], value: Option<&[u8]>) {
+    let result = match rule.target {
+        MirrorTarget::String => {
+            let target_key = [rule.key_prefix.as_bytes(), key].concat();
+            match value {
+                Some(value) => ctx.call("SET", &[target_key.as_slice(), value]),
+                None => ctx.call("DEL", &[target_key.as_slice()]),
+            }
+        },
+        MirrorTarget::Hash => {
+            let target_key = format!("{}{}", rule.key_prefix, rule.prefix);
+            let field = &key[rule.prefix.len()..];
+            match value {
+                Some(value) => ctx.call("HSET", &[target_key.as_bytes(), field, value]),
+                None => ctx.call("HDEL", &[target_key.as_bytes(), field]),
+            }
+        },
+    };
+
+    if let Err(err) = result {
+        ctx.log_warning(&format!("Failed to mirror key {}: {}", String::from_utf8_lossy(key), err));
+    }
+}
+
+// Forward a write made by a command to the CUSTOM.REPLICATE_TO endpoint, if
+// any. Called with the key's shard lock held, so the replica sees writes to
+// a key in the order they were made.
+pub fn forward(key: &[u8], value: Option<&[u8]>) {
+    replicate::forward(key, value);
+}
+
+// Mirror a write made by a command, if a rule covers the key. This calls
+// back into Redis, so it runs after the shard lock is released; queued C API
+// writes are applied first so an older one can't land on top of it.
+pub fn write_through(ctx: &Context, key: &[u8], value: Option<&[u8]>) {
+    if let Some(rule) = matching_rule(key) {
+        drain(ctx);
+        apply(ctx, &rule, key, value);
+    }
+}
+
+// Queue a write made through the C API (no context available) for the next
+// flush; the replica gets it straight away
+pub fn queue_write(key: &[u8], value: Option<&[u8]>) {
+    replicate::forward(key, value);
+    if matching_rule(key).is_none() {
+        return;
+    }
+
+    if let Ok(mut pending) = init_pending().lock() {
+        pending.insert(key.to_vec(), value.map(|v| v.to_vec()));
+        QUEUED.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+// Apply the last queued write of every key to the keyspace
+fn drain(ctx: &Context) {
+    let (writes, queued) = match init_pending().lock() {
+        Ok(mut pending) => (std::mem::take(&mut *pending), QUEUED.load(Ordering::Relaxed)),
+        Err(_) => return,
+    };
+
+    for (key, value) in writes {
+        // Rules may have changed since the write was queued
+        if let Some(rule) = matching_rule(&key) {
+            apply(ctx, &rule, &key, value.as_deref());
+        }
+    }
+    FLUSHED.store(queued, Ordering::Relaxed);
+}
+
+// Timer callback draining queued writes into the keyspace
+fn flush_pending(ctx: &Context, _data: ()) {
+    drain(ctx);
+    ctx.create_timer(MIRROR_FLUSH_INTERVAL, flush_pending, ());
+}
+
+// Copy the keys a new rule covers into the keyspace. Keys a more specific
+// rule covers are left to it.
+fn backfill(ctx: &Context, rule: &MirrorRule) -> usize {
+    let hashmap = init_hashmap();
+    let mut copied = 0;
+    for key in hashmap.keys() {
+        if !key.starts_with(rule.prefix.as_bytes()) || matching_rule(&key).is_none_or(|covering| covering.prefix != rule.prefix) {
+            continue;
+        }
+        if let Some(value) = hashmap.get(&key) {
+            apply(ctx, rule, &key, Some(&value));
+            copied += 1;
+        }
+    }
+    copied
+}
+
+// Start the flush timer at module load
+pub fn start(ctx: &Context) {
+    ctx.create_timer(MIRROR_FLUSH_INTERVAL, flush_pending, ());
+}
+
+// Manage mirroring rules:
+// CUSTOM.MIRROR ADD prefix TARGET hash|string KEYPREFIX keyprefix
+// CUSTOM.MIRROR DEL prefix
+// CUSTOM.MIRROR LIST
+// CUSTOM.MIRROR PROGRESS
+// ADD copies the keys already under the prefix and replies with how many.
+#[tracing::instrument(name = "custom.mirror", skip_all)]
+pub fn custom_mirror(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let subcommand = args.next_string()?.to_uppercase();
+
+    let rules = init_rules();
+
+    match subcommand.as_str() {
+        "ADD" => {
+            let prefix = args.next_string()?;
+
+            let mut target = None;
+            let mut key_prefix = None;
+            while let Ok(option) = args.next_string() {
+                match option.to_uppercase().as_str() {
+                    "TARGET" => {
+                        let name = args.next_string()?;
+                        target = Some(if name.eq_ignore_ascii_case("hash") {
+                            MirrorTarget::Hash
+                        } else if name.eq_ignore_ascii_case("string") {
+                            MirrorTarget::String
+                        } else {
+                            return Err(RedisError::String(format!("Unknown mirror target: {}", name)));
+                        });
+                    },
+                    "KEYPREFIX" => key_prefix = Some(args.next_string()?),
+                    _ => return Err(RedisError::String(format!("Unknown option: {}", option))),
+                }
+            }
+
+            let rule = MirrorRule {
+                prefix,
+                target: target.ok_or(RedisError::Str("TARGET is required"))?,
+                key_prefix: key_prefix.ok_or(RedisError::Str("KEYPREFIX is required"))?,
+            };
+
+            let mut rules = rules.write().map_err(|_| {
+                RedisError::String("Failed to acquire write lock".to_string())
+            })?;
+            rules.retain(|existing| existing.prefix != rule.prefix);
+            rules.push(rule.clone());
+            drop(rules);
+
+            // Queued writes are older than what the map holds; apply them
+            // first so they can't land on top of the copy
+            drain(ctx);
+            Ok(RedisValue::Integer(backfill(ctx, &rule) as i64))
+        },
+        "DEL" => {
+            let prefix = args.next_string()?;
+            args.done()?;
+
+            let mut rules = rules.write().map_err(|_| {
+                RedisError::String("Failed to acquire write lock".to_string())
+            })?;
+            let before = rules.len();
+            rules.retain(|rule| rule.prefix != prefix);
+
+            Ok(RedisValue::Integer(if rules.len() < before { 1 } else { 0 }))
+        },
+        "LIST" => {
+            args.done()?;
+
+            let rules = rules.read().map_err(|_| {
+                RedisError::String("Failed to acquire read lock".to_string())
+            })?;
+
+            let list = rules.iter()
+                .map(|rule| RedisValue::Array(vec![
+                    RedisValue::BulkString(rule.prefix.clone()),
+                    RedisValue::SimpleStringStatic(rule.target.name()),
+                    RedisValue::BulkString(rule.key_prefix.clone()),
+                ]))
+                .collect();
+
+            Ok(RedisValue::Array(list))
+        },
+        "PROGRESS" => {
+            args.done()?;
+
+            let rules = rules.read().map_err(|_| {
+                RedisError::String("Failed to acquire read lock".to_string())
+            })?.len();
+
+            Ok(RedisValue::Array(vec![
+                RedisValue::SimpleStringStatic("rules"),
+                RedisValue::Integer(rules as i64),
+                RedisValue::SimpleStringStatic("queued"),
+                RedisValue::Integer(QUEUED.load(Ordering::Relaxed) as i64),
+                RedisValue::SimpleStringStatic("flushed"),
+                RedisValue::Integer(FLUSHED.load(Ordering::Relaxed) as i64),
+            ]))
+        },
+        _ => Err(RedisError::String(format!("Unknown CUSTOM.MIRROR subcommand: {}", subcommand))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The rules and queue are global, so each test uses its own prefixes
+    fn add_rule(prefix: &str, target: MirrorTarget) {
+        init_rules().write().unwrap().push(MirrorRule {
+            prefix: prefix.to_string(),
+            target,
+            key_prefix: "mirrored:".to_string(),
+        });
+    }
+
+    fn pending(key: &[u8]) -> Option<PendingWrite> {
+        init_pending().lock().unwrap().get(key).cloned()
+    }
+
+    #[test]
+    fn picks_the_most_specific_rule() {
+        add_rule("rule:", MirrorTarget::Hash);
+        add_rule("rule:user:", MirrorTarget::String);
+        assert_eq!(matching_rule(b"rule:user:1").unwrap().target, MirrorTarget::String);
+        assert_eq!(matching_rule(b"rule:order:1").unwrap().target, MirrorTarget::Hash);
+        assert!(matching_rule(b"unruled:1").is_none());
+    }
+
+    #[test]
+    fn queues_only_covered_keys() {
+        add_rule("covered:", MirrorTarget::String);
+        queue_write(b"covered:1", Some(b"v"));
+        queue_write(b"uncovered:1", Some(b"v"));
+        assert_eq!(pending(b"covered:1"), Some(Some(b"v".to_vec())));
+        assert_eq!(pending(b"uncovered:1"), None);
+    }
+
+    #[test]
+    fn coalesces_writes_per_key() {
+        add_rule("coalesced:", MirrorTarget::String);
+        queue_write(b"coalesced:1", Some(b"first"));
+        queue_write(b"coalesced:1", Some(b"second"));
+        queue_write(b"coalesced:2", Some(b"kept"));
+        assert_eq!(pending(b"coalesced:1"), Some(Some(b"second".to_vec())));
+
+        // A delete replaces the queued value rather than being lost
+        queue_write(b"coalesced:1", None);
+        assert_eq!(pending(b"coalesced:1"), Some(None));
+        assert_eq!(pending(b"coalesced:2"), Some(Some(b"kept".to_vec())));
+    }
+}
@@ -0,0 +1,118 @@
+use redis_module::{Context, NextArg, RedisError, RedisResult, RedisString, RedisValue};
+
+use crate::init_hashmap;
+
+// A value read as a number. Integers stay exact; anything else that parses is a float.
+#[derive(Clone, Copy)]
+enum Number {
+    Integer(i64),
+    Float(f64),
+}
+
+impl Number {
+    fn parse(value: &[u8]) -> Option<Number> {
+        let text = std::str::from_utf8(value).ok()?.trim();
+        if let Ok(integer) = text.parse() {
+            return Some(Number::Integer(integer));
+        }
+        text.parse::<f64>().ok().filter(|float| float.is_finite()).map(Number::Float)
+    }
+
+    fn as_f64(&self) -> f64 {
+        match self {
+            Number::Integer(integer) => *integer as f64,
+            Number::Float(float) => *float,
+        }
+    }
+
+    fn reply(&self) -> RedisValue {
+        match self {
+            Number::Integer(integer) => RedisValue::Integer(*integer),
+            Number::Float(float) => RedisValue::Float(*float),
+        }
+    }
+}
+
+// A numeric value and the key holding it
+type Keyed = (Vec<u8>, Number);
+
+// The values under the prefix given as the only argument that read as
+// numbers, with their keys, and how many values didn't
+fn numbers(args: Vec<RedisString>) -> Result<(Vec<Keyed>, usize), RedisError> {
+    let mut args = args.into_iter().skip(1);
+    let prefix = args.next_arg()?;
+    args.done()?;
+
+    let entries = init_hashmap().entries_under(prefix.as_slice());
+    let total = entries.len();
+    let numbers: Vec<Keyed> = entries.into_iter()
+        .filter_map(|(key, value)| Number::parse(&value).map(|number| (key, number)))
+        .collect();
+    let non_numeric = total - numbers.len();
+    Ok((numbers, non_numeric))
+}
+
+// The reply shared by the aggregates: the result fields, then how many
+// values were aggregated and how many were skipped for not being numbers
+fn reply(mut fields: Vec<RedisValue>, count: usize, non_numeric: usize) -> RedisValue {
+    fields.extend([
+        RedisValue::SimpleStringStatic("count"),
+        RedisValue::Integer(count as i64),
+        RedisValue::SimpleStringStatic("non_numeric"),
+        RedisValue::Integer(non_numeric as i64),
+    ]);
+    RedisValue::Array(fields)
+}
+
+// Sum the numeric values under a prefix: CUSTOM.SUM prefix
+// The sum is an integer while every value is one and it fits, otherwise a double.
+#[tracing::instrument(name = "custom.sum", skip_all)]
+pub fn custom_sum(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let (numbers, non_numeric) = numbers(args)?;
+
+    let mut integers: i128 = 0;
+    let mut floats: Option<f64> = None;
+    for (_, number) in &numbers {
+        match number {
+            Number::Integer(integer) => integers += *integer as i128,
+            Number::Float(float) => *floats.get_or_insert(0.0) += float,
+        }
+    }
+    let sum = match (floats, i64::try_from(integers)) {
+        (None, Ok(integer)) => Number::Integer(integer),
+        (floats, _) => Number::Float(integers as f64 + floats.unwrap_or(0.0)),
+    };
+    Ok(reply(vec![RedisValue::SimpleStringStatic("sum"), sum.reply()], numbers.len(), non_numeric))
+}
+
+// Average the numeric values under a prefix: CUSTOM.AVG prefix
+// The average is a double, nil when no value is a number.
+#[tracing::instrument(name = "custom.avg", skip_all)]
+pub fn custom_avg(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let (numbers, non_numeric) = numbers(args)?;
+
+    let avg = match numbers.len() {
+        0 => RedisValue::Null,
+        count => RedisValue::Float(numbers.iter().map(|(_, number)| number.as_f64()).sum::<f64>() / count as f64),
+    };
+    Ok(reply(vec![RedisValue::SimpleStringStatic("avg"), avg], numbers.len(), non_numeric))
+}
+
+// The largest numeric value under a prefix: CUSTOM.MAX prefix
+// Also replies with the key holding it; both are nil when no value is a number.
+#[tracing::instrument(name = "custom.max", skip_all)]
+pub fn custom_max(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let (numbers, non_numeric) = numbers(args)?;
+
+    let max = numbers.iter().max_by(|(_, a), (_, b)| a.as_f64().total_cmp(&b.as_f64()));
+    let (max, key) = match max {
+        Some((key, number)) => (number.reply(), RedisValue::StringBuffer(key.clone())),
+        None => (RedisValue::Null, RedisValue::Null),
+    };
+    Ok(reply(vec![
+        RedisValue::SimpleStringStatic("max"),
+        max,
+        RedisValue::SimpleStringStatic("key"),
+        key,
+    ], numbers.len(), non_numeric))
+}
@@ -0,0 +1,434 @@
+use std::cmp::Ordering;
+use redis_module::{Context, NextArg, RedisError, RedisResult, RedisString, RedisValue};
+
+use crate::expire::ttl_secs;
+use crate::init_hashmap;
+
+// Nesting and length allowed in a condition, so a hostile one can't exhaust the stack
+const MAX_DEPTH: usize = 32;
+const MAX_TOKENS: usize = 256;
+
+// What a condition can see of the key it guards
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    // The current value, null for a missing key
+    Old,
+    // Whether the key exists
+    Exists,
+    // Seconds until the key expires, -1 without an expiry, -2 when missing
+    Ttl,
+    // Length of the current value in bytes, 0 when missing
+    Len,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Value {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Bytes(Vec<u8>),
+}
+
+impl Value {
+    fn truthy(&self) -> bool {
+        match self {
+            Value::Null => false,
+            Value::Bool(b) => *b,
+            Value::Int(n) => *n != 0,
+            Value::Bytes(bytes) => !bytes.is_empty(),
+        }
+    }
+
+    fn as_int(&self) -> Option<i64> {
+        match self {
+            Value::Int(n) => Some(*n),
+            Value::Bytes(bytes) => std::str::from_utf8(bytes).ok()?.parse().ok(),
+            _ => None,
+        }
+    }
+
+    // Numbers compare numerically, even when stored as text; other values only
+    // against their own kind. None when the two can't be compared.
+    fn compare(&self, other: &Value) -> Option<Ordering> {
+        match (self, other) {
+            (Value::Int(_), _) | (_, Value::Int(_)) => Some(self.as_int()?.cmp(&other.as_int()?)),
+            (Value::Bytes(a), Value::Bytes(b)) => Some(a.cmp(b)),
+            (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+            (Value::Null, Value::Null) => Some(Ordering::Equal),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl CompareOp {
+    fn holds(self, ordering: Option<Ordering>) -> bool {
+        match (self, ordering) {
+            (CompareOp::Ne, None) => true,
+            (_, None) => false,
+            (CompareOp::Eq, Some(ordering)) => ordering == Ordering::Equal,
+            (CompareOp::Ne, Some(ordering)) => ordering != Ordering::Equal,
+            (CompareOp::Lt, Some(ordering)) => ordering == Ordering::Less,
+            (CompareOp::Le, Some(ordering)) => ordering != Ordering::Greater,
+            (CompareOp::Gt, Some(ordering)) => ordering == Ordering::Greater,
+            (CompareOp::Ge, Some(ordering)) => ordering != Ordering::Less,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Expr {
+    Field(Field),
+    Literal(Value),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Compare(CompareOp, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Ident(String),
+    Int(i64),
+    Str(Vec<u8>),
+    Op(&'static str),
+    Open,
+    Close,
+}
+
+fn invalid(condition: &[u8], reason: &str) -> RedisError {
+    RedisError::String(format!("Invalid condition: {} ({})", String::from_utf8_lossy(condition), reason))
+}
+
+fn tokenize(condition: &[u8]) -> Result<Vec<Token>, RedisError> {
+    const OPS: [&str; 10] = ["==", "!=", "<=", ">=", "&&", "||", "<", ">", "!", "="];
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < condition.len() {
+        let rest = &condition[i..];
+        match rest[0] {
+            b' ' | b'\t' => i += 1,
+            b'(' => {
+                tokens.push(Token::Open);
+                i += 1;
+            },
+            b')' => {
+                tokens.push(Token::Close);
+                i += 1;
+            },
+            quote @ (b'\'' | b'"') => {
+                // Quotes are escaped by doubling them, as in SQL
+                let mut literal = Vec::new();
+                let mut j = 1;
+                loop {
+                    match rest.get(j) {
+                        None => return Err(invalid(condition, "unterminated string")),
+                        Some(&c) if c == quote && rest.get(j + 1) == Some(&quote) => {
+                            literal.push(quote);
+                            j += 2;
+                        },
+                        Some(&c) if c == quote => break,
+                        Some(&c) => {
+                            literal.push(c);
+                            j += 1;
+                        },
+                    }
+                }
+                tokens.push(Token::Str(literal));
+                i += j + 1;
+            },
+            c if c.is_ascii_digit() || (c == b'-' && rest.get(1).is_some_and(u8::is_ascii_digit)) => {
+                let len = 1 + rest[1..].iter().take_while(|c| c.is_ascii_digit()).count();
+                let number = std::str::from_utf8(&rest[..len]).unwrap();
+                tokens.push(Token::Int(number.parse().map_err(|_| invalid(condition, "number out of range"))?));
+                i += len;
+            },
+            c if c.is_ascii_alphabetic() || c == b'_' => {
+                let len = rest.iter().take_while(|c| c.is_ascii_alphanumeric() || **c == b'_').count();
+                tokens.push(Token::Ident(String::from_utf8_lossy(&rest[..len]).to_lowercase()));
+                i += len;
+            },
+            _ => match OPS.iter().find(|op| rest.starts_with(op.as_bytes())) {
+                // A lone `=` reads as `==`
+                Some(&"=") => {
+                    tokens.push(Token::Op("=="));
+                    i += 1;
+                },
+                Some(op) => {
+                    tokens.push(Token::Op(op));
+                    i += op.len();
+                },
+                None => return Err(invalid(condition, &format!("unexpected character at {}", i))),
+            },
+        }
+    }
+    if tokens.len() > MAX_TOKENS {
+        return Err(invalid(condition, "too long"));
+    }
+    Ok(tokens)
+}
+
+// Recursive descent over: or := and (|| and)*, and := not (&& not)*,
+// not := ! not | cmp, cmp := atom (op atom)?, atom := literal | field | ( or )
+struct Parser<'a> {
+    condition: &'a [u8],
+    tokens: Vec<Token>,
+    pos: usize,
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn eat_keyword(&mut self, symbol: &str, word: &str) -> bool {
+        let matched = match self.peek() {
+            Some(Token::Op(op)) => *op == symbol,
+            Some(Token::Ident(ident)) => ident == word,
+            _ => false,
+        };
+        if matched {
+            self.pos += 1;
+        }
+        matched
+    }
+
+    fn nested<T>(&mut self, parse: impl FnOnce(&mut Self) -> Result<T, RedisError>) -> Result<T, RedisError> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(invalid(self.condition, "nested too deeply"));
+        }
+        let parsed = parse(self);
+        self.depth -= 1;
+        parsed
+    }
+
+    fn or(&mut self) -> Result<Expr, RedisError> {
+        let mut expr = self.and()?;
+        while self.eat_keyword("||", "or") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, RedisError> {
+        let mut expr = self.not()?;
+        while self.eat_keyword("&&", "and") {
+            expr = Expr::And(Box::new(expr), Box::new(self.not()?));
+        }
+        Ok(expr)
+    }
+
+    fn not(&mut self) -> Result<Expr, RedisError> {
+        if self.eat_keyword("!", "not") {
+            return self.nested(|parser| Ok(Expr::Not(Box::new(parser.not()?))));
+        }
+        self.compare()
+    }
+
+    fn compare(&mut self) -> Result<Expr, RedisError> {
+        let left = self.atom()?;
+        let op = match self.peek() {
+            Some(Token::Op("==")) => CompareOp::Eq,
+            Some(Token::Op("!=")) => CompareOp::Ne,
+            Some(Token::Op("<")) => CompareOp::Lt,
+            Some(Token::Op("<=")) => CompareOp::Le,
+            Some(Token::Op(">")) => CompareOp::Gt,
+            Some(Token::Op(">=")) => CompareOp::Ge,
+            _ => return Ok(left),
+        };
+        self.pos += 1;
+        Ok(Expr::Compare(op, Box::new(left), Box::new(self.atom()?)))
+    }
+
+    fn atom(&mut self) -> Result<Expr, RedisError> {
+        match self.next() {
+            Some(Token::Open) => {
+                let expr = self.nested(|parser| parser.or())?;
+                match self.next() {
+                    Some(Token::Close) => Ok(expr),
+                    _ => Err(invalid(self.condition, "missing )")),
+                }
+            },
+            Some(Token::Int(n)) => Ok(Expr::Literal(Value::Int(n))),
+            Some(Token::Str(s)) => Ok(Expr::Literal(Value::Bytes(s))),
+            Some(Token::Ident(ident)) => match ident.as_str() {
+                "old" => Ok(Expr::Field(Field::Old)),
+                "exists" => Ok(Expr::Field(Field::Exists)),
+                "ttl" => Ok(Expr::Field(Field::Ttl)),
+                "len" => Ok(Expr::Field(Field::Len)),
+                "null" | "nil" => Ok(Expr::Literal(Value::Null)),
+                "true" => Ok(Expr::Literal(Value::Bool(true))),
+                "false" => Ok(Expr::Literal(Value::Bool(false))),
+                _ => Err(invalid(self.condition, &format!("unknown name {}", ident))),
+            },
+            Some(_) => Err(invalid(self.condition, "expected a value")),
+            None => Err(invalid(self.condition, "unexpected end")),
+        }
+    }
+}
+
+// A parsed CUSTOM.SET IF condition
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Condition(Expr);
+
+impl Condition {
+    pub fn parse(condition: &[u8]) -> Result<Condition, RedisError> {
+        let mut parser = Parser { condition, tokens: tokenize(condition)?, pos: 0, depth: 0 };
+        let expr = parser.or()?;
+        if parser.pos < parser.tokens.len() {
+            return Err(invalid(condition, "unexpected trailing input"));
+        }
+        Ok(Condition(expr))
+    }
+
+    // Whether the condition holds for the key's current state. Callers hold
+    // the key's shard so the state can't change before their write.
+    pub fn holds(&self, key: &[u8]) -> bool {
+        let subject = Subject { value: init_hashmap().get(key), key };
+        eval(&self.0, &subject).truthy()
+    }
+}
+
+struct Subject<'a> {
+    key: &'a [u8],
+    value: Option<Vec<u8>>,
+}
+
+fn eval(expr: &Expr, subject: &Subject) -> Value {
+    match expr {
+        Expr::Field(Field::Old) => subject.value.clone().map_or(Value::Null, Value::Bytes),
+        Expr::Field(Field::Exists) => Value::Bool(subject.value.is_some()),
+        Expr::Field(Field::Ttl) => Value::Int(ttl_secs(subject.key)),
+        Expr::Field(Field::Len) => Value::Int(subject.value.as_ref().map_or(0, |value| value.len() as i64)),
+        Expr::Literal(value) => value.clone(),
+        Expr::Not(inner) => Value::Bool(!eval(inner, subject).truthy()),
+        Expr::And(left, right) => Value::Bool(eval(left, subject).truthy() && eval(right, subject).truthy()),
+        Expr::Or(left, right) => Value::Bool(eval(left, subject).truthy() || eval(right, subject).truthy()),
+        Expr::Compare(op, left, right) => Value::Bool(op.holds(eval(left, subject).compare(&eval(right, subject)))),
+    }
+}
+
+// Test a condition against a key without writing: CUSTOM.EVAL_POLICY key condition
+// Replies 1 if CUSTOM.SET key value IF condition would write, 0 otherwise.
+#[tracing::instrument(name = "custom.eval_policy", skip_all)]
+pub fn custom_eval_policy(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let key = args.next_arg()?;
+    let condition = Condition::parse(args.next_arg()?.as_slice())?;
+    args.done()?;
+
+    Ok(RedisValue::Integer(condition.holds(key.as_slice()) as i64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::now_millis;
+
+    fn holds(condition: &str, key: &[u8]) -> bool {
+        Condition::parse(condition.as_bytes()).unwrap().holds(key)
+    }
+
+    fn set(key: &[u8], value: &[u8], expires_at: Option<u64>) {
+        init_hashmap().write(key).unwrap().insert_with_expiry(key.to_vec(), value.to_vec(), expires_at);
+    }
+
+    #[test]
+    fn parses_precedence_and_keywords() {
+        let parsed = Condition::parse(b"!exists || old = 'a' && len > 0").unwrap();
+        let expected = Expr::Or(
+            Box::new(Expr::Not(Box::new(Expr::Field(Field::Exists)))),
+            Box::new(Expr::And(
+                Box::new(Expr::Compare(CompareOp::Eq, Box::new(Expr::Field(Field::Old)), Box::new(Expr::Literal(Value::Bytes(b"a".to_vec()))))),
+                Box::new(Expr::Compare(CompareOp::Gt, Box::new(Expr::Field(Field::Len)), Box::new(Expr::Literal(Value::Int(0))))),
+            )),
+        );
+        assert_eq!(parsed, Condition(expected));
+        assert_eq!(Condition::parse(b"NOT exists OR old == nil").unwrap(), Condition::parse(b"!exists || old == null").unwrap());
+    }
+
+    #[test]
+    fn parses_quoted_strings() {
+        assert_eq!(tokenize(b"'it''s'").unwrap(), vec![Token::Str(b"it's".to_vec())]);
+        assert_eq!(tokenize(b"\"a'b\"").unwrap(), vec![Token::Str(b"a'b".to_vec())]);
+        assert_eq!(tokenize(b"-12").unwrap(), vec![Token::Int(-12)]);
+    }
+
+    #[test]
+    fn refuses_malformed_conditions() {
+        for condition in [
+            &b""[..],
+            b"old ==",
+            b"(exists",
+            b"exists)",
+            b"'open",
+            b"size > 1",
+            b"old # 1",
+            b"99999999999999999999 > 1",
+            b"exists exists",
+        ] {
+            assert!(Condition::parse(condition).is_err(), "{}", String::from_utf8_lossy(condition));
+        }
+    }
+
+    #[test]
+    fn limits_depth_and_length() {
+        let deep = format!("{}exists{}", "(".repeat(MAX_DEPTH + 1), ")".repeat(MAX_DEPTH + 1));
+        assert!(Condition::parse(deep.as_bytes()).is_err());
+        let nots = "!".repeat(MAX_DEPTH + 1) + "exists";
+        assert!(Condition::parse(nots.as_bytes()).is_err());
+        let long = vec!["exists"; MAX_TOKENS].join(" && ");
+        assert!(Condition::parse(long.as_bytes()).is_err());
+        assert!(Condition::parse(format!("{}exists{}", "(".repeat(MAX_DEPTH), ")".repeat(MAX_DEPTH)).as_bytes()).is_ok());
+    }
+
+    #[test]
+    fn evaluates_against_missing_keys() {
+        let key = b"policy:missing";
+        assert!(holds("!exists", key));
+        assert!(holds("old == null", key));
+        assert!(holds("len == 0", key));
+        assert!(holds("ttl == -2", key));
+        assert!(!holds("old == ''", key));
+        // Values of different kinds never compare, so only != holds
+        assert!(holds("old != 1", key));
+        assert!(!holds("old < 1", key));
+    }
+
+    #[test]
+    fn evaluates_against_stored_values() {
+        let key = b"policy:counter";
+        set(key, b"10", None);
+        assert!(holds("exists && old == '10'", key));
+        // Numbers compare numerically even when stored as text
+        assert!(holds("old > 9", key));
+        assert!(!holds("old > '9'", key));
+        assert!(holds("len = 2", key));
+        assert!(holds("ttl == -1", key));
+
+        let key = b"policy:expiring";
+        set(key, b"v", Some(now_millis() + 60_000));
+        assert!(holds("ttl > 0 && ttl <= 60", key));
+
+        let key = b"policy:expired";
+        set(key, b"v", Some(now_millis() - 1));
+        assert!(holds("!exists && ttl == -2", key));
+    }
+}
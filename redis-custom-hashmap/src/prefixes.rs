@@ -0,0 +1,155 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use redis_module::{Context, NextArg, RedisError, RedisResult, RedisString, RedisValue};
+
+// A prefix ends at a `:`, which it includes; the prefix at depth n ends at the n-th one
+const DELIMITER: u8 = b':';
+
+// Deepest prefixes that can be counted
+const MAX_DEPTH: usize = 8;
+
+// Distinct prefixes counted per depth; keys under prefixes beyond these count as `other`
+const MAX_PREFIXES: usize = 10_000;
+
+// Depths counted, set by the `prefix_depth=<n>` module argument; 0 counts nothing
+static DEPTH: AtomicUsize = AtomicUsize::new(2);
+
+// Key counts at one depth. Keys are counted when stored and uncounted when
+// removed, so expired keys not yet removed still count.
+#[derive(Default)]
+struct DepthCounts {
+    prefixes: HashMap<Vec<u8>, u64>,
+    // Keys with fewer than `depth` delimiters
+    unprefixed: u64,
+    other: u64,
+}
+
+impl DepthCounts {
+    fn add(&mut self, prefix: Option<&[u8]>) {
+        match prefix {
+            Some(prefix) => {
+                let full = self.prefixes.len() >= MAX_PREFIXES;
+                match self.prefixes.get_mut(prefix) {
+                    Some(count) => *count += 1,
+                    None if full => self.other += 1,
+                    None => {
+                        self.prefixes.insert(prefix.to_vec(), 1);
+                    },
+                }
+            },
+            None => self.unprefixed += 1,
+        }
+    }
+
+    fn remove(&mut self, prefix: Option<&[u8]>) {
+        match prefix {
+            Some(prefix) => match self.prefixes.get_mut(prefix) {
+                Some(count) => {
+                    *count -= 1;
+                    if *count == 0 {
+                        self.prefixes.remove(prefix);
+                    }
+                },
+                None => self.other = self.other.saturating_sub(1),
+            },
+            None => self.unprefixed = self.unprefixed.saturating_sub(1),
+        }
+    }
+}
+
+static mut PREFIX_COUNTS: Option<Mutex<Vec<DepthCounts>>> = None;
+
+// Initialize the per-depth key counts
+fn init_counts() -> &'static Mutex<Vec<DepthCounts>> {
+    unsafe {
+        if PREFIX_COUNTS.is_none() {
+            PREFIX_COUNTS = Some(Mutex::new((0..MAX_DEPTH).map(|_| DepthCounts::default()).collect()));
+        }
+        PREFIX_COUNTS.as_ref().unwrap()
+    }
+}
+
+pub fn depth() -> usize {
+    DEPTH.load(Ordering::Relaxed)
+}
+
+// Apply the `prefix_depth=<n>` module argument; the map is still empty so every key gets counted
+pub fn set_depth(depth: &str) -> Result<(), String> {
+    let depth = depth.parse().ok()
+        .filter(|depth| *depth <= MAX_DEPTH)
+        .ok_or_else(|| format!("Invalid prefix_depth: {}, expected 0 to {}", depth, MAX_DEPTH))?;
+    DEPTH.store(depth, Ordering::Relaxed);
+    Ok(())
+}
+
+// The prefix of a key at each depth from 1 to `depth`, None where it has no more delimiters
+fn prefixes(key: &[u8], depth: usize) -> impl Iterator<Item = Option<&[u8]>> {
+    let mut ends = key.iter().enumerate()
+        .filter(|(_, byte)| **byte == DELIMITER)
+        .map(|(index, _)| index + 1);
+    (0..depth).map(move |_| ends.next().map(|end| &key[..end]))
+}
+
+fn update(key: &[u8], update: impl Fn(&mut DepthCounts, Option<&[u8]>)) {
+    let depth = depth();
+    if depth == 0 {
+        return;
+    }
+    if let Ok(mut counts) = init_counts().lock() {
+        for (level, prefix) in counts.iter_mut().zip(prefixes(key, depth)) {
+            update(level, prefix);
+        }
+    }
+}
+
+// Count a key newly stored in the map
+pub fn key_added(key: &[u8]) {
+    update(key, DepthCounts::add);
+}
+
+// Uncount a key removed from the map
+pub fn key_removed(key: &[u8]) {
+    update(key, DepthCounts::remove);
+}
+
+// Approximate key counts per prefix: CUSTOM.PREFIX_STATS [depth]
+// Replies with the depth, the number of keys with fewer delimiters, the
+// number under prefixes past the tracking limit, and `[prefix, count, ...]`
+// for every counted prefix, most keys first.
+#[tracing::instrument(name = "custom.prefix_stats", skip_all)]
+pub fn custom_prefix_stats(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let tracked = depth();
+    let depth = match args.next_u64() {
+        Ok(depth) => depth as usize,
+        Err(_) => tracked,
+    };
+    args.done()?;
+    if tracked == 0 {
+        return Err(RedisError::Str("Prefix counting is off: load the module with prefix_depth=<n>"));
+    }
+    if depth == 0 || depth > tracked {
+        return Err(RedisError::String(format!("Depth must be between 1 and {}", tracked)));
+    }
+
+    let counts = init_counts().lock().map_err(|_| {
+        RedisError::String("Failed to acquire prefix counts lock".to_string())
+    })?;
+    let level = &counts[depth - 1];
+    let mut prefixes: Vec<_> = level.prefixes.iter().collect();
+    prefixes.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then_with(|| a.cmp(b)));
+
+    Ok(RedisValue::Array(vec![
+        RedisValue::SimpleStringStatic("depth"),
+        RedisValue::Integer(depth as i64),
+        RedisValue::SimpleStringStatic("unprefixed"),
+        RedisValue::Integer(level.unprefixed as i64),
+        RedisValue::SimpleStringStatic("other"),
+        RedisValue::Integer(level.other as i64),
+        RedisValue::SimpleStringStatic("prefixes"),
+        RedisValue::Array(prefixes.into_iter()
+            .flat_map(|(prefix, count)| [RedisValue::StringBuffer(prefix.clone()), RedisValue::Integer(*count as i64)])
+            .collect()),
+    ]))
+}
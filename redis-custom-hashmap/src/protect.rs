@@ -0,0 +1,132 @@
+use std::cell::RefCell;
+use std::sync::RwLock;
+use redis_module::{Context, NextArg, RedisError, RedisResult, RedisString, RedisValue};
+
+// Who may write keys under a protected prefix
+#[derive(Debug, Clone, PartialEq)]
+enum Mode {
+    // Nobody
+    ReadOnly,
+    // Only callers acting as this module (see custom_hashmap_act_as)
+    Owner(String),
+}
+
+impl Mode {
+    fn parse(mode: &str) -> Result<Option<Mode>, RedisError> {
+        if mode.eq_ignore_ascii_case("readonly") {
+            return Ok(Some(Mode::ReadOnly));
+        }
+        if mode.eq_ignore_ascii_case("none") {
+            return Ok(None);
+        }
+        match mode.split_once(':') {
+            Some((kind, module)) if kind.eq_ignore_ascii_case("owner") && !module.is_empty() => {
+                Ok(Some(Mode::Owner(module.to_string())))
+            },
+            _ => Err(RedisError::String(format!("Unknown protection mode: {}", mode))),
+        }
+    }
+
+    fn name(&self) -> String {
+        match self {
+            Mode::ReadOnly => "readonly".to_string(),
+            Mode::Owner(module) => format!("owner:{}", module),
+        }
+    }
+}
+
+// Protected prefixes, longest first so the most specific one wins
+static mut PROTECTIONS: Option<RwLock<Vec<(String, Mode)>>> = None;
+
+// Initialize the protection table
+fn init_protections() -> &'static RwLock<Vec<(String, Mode)>> {
+    unsafe {
+        if PROTECTIONS.is_none() {
+            PROTECTIONS = Some(RwLock::new(Vec::new()));
+        }
+        PROTECTIONS.as_ref().unwrap()
+    }
+}
+
+thread_local! {
+    // Module the current thread acts for, set through custom_hashmap_act_as
+    static ACTING_AS: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+// Name the module the calling thread acts for; NULL clears it. Commands run
+// through RedisModule_Call execute on the caller's thread, so they see it too.
+#[no_mangle]
+pub extern "C" fn custom_hashmap_act_as(module: *const libc::c_char) {
+    let module = if module.is_null() {
+        None
+    } else {
+        Some(unsafe { std::ffi::CStr::from_ptr(module).to_string_lossy().to_string() })
+    };
+    ACTING_AS.with(|acting_as| *acting_as.borrow_mut() = module);
+}
+
+// Refuse a write to `key` if its prefix is protected against the current caller
+pub fn check_write(key: &[u8]) -> Result<(), RedisError> {
+    let protections = init_protections().read().map_err(|_| {
+        RedisError::String("Failed to acquire read lock".to_string())
+    })?;
+    let (prefix, mode) = match protections.iter().find(|(prefix, _)| key.starts_with(prefix.as_bytes())) {
+        Some(protection) => protection,
+        None => return Ok(()),
+    };
+    let key = String::from_utf8_lossy(key);
+
+    match mode {
+        Mode::ReadOnly => Err(RedisError::String(format!("Key {} is under read-only prefix {}", key, prefix))),
+        Mode::Owner(owner) => {
+            if ACTING_AS.with(|acting_as| acting_as.borrow().as_deref() == Some(owner.as_str())) {
+                Ok(())
+            } else {
+                Err(RedisError::String(format!("Key {} is under prefix {} owned by module {}", key, prefix, owner)))
+            }
+        },
+    }
+}
+
+// Restrict writes to keys under a prefix:
+// CUSTOM.PROTECT prefix MODE readonly|owner:<module>|none
+// CUSTOM.PROTECT LIST
+#[tracing::instrument(name = "custom.protect", skip_all)]
+pub fn custom_protect(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let prefix = args.next_string()?;
+
+    if prefix.eq_ignore_ascii_case("LIST") && args.len() == 0 {
+        let protections = init_protections().read().map_err(|_| {
+            RedisError::String("Failed to acquire read lock".to_string())
+        })?;
+        return Ok(RedisValue::Array(protections.iter()
+            .map(|(prefix, mode)| RedisValue::Array(vec![
+                RedisValue::BulkString(prefix.clone()),
+                RedisValue::BulkString(mode.name()),
+            ]))
+            .collect()));
+    }
+
+    let option = args.next_string()?;
+    if !option.eq_ignore_ascii_case("MODE") {
+        return Err(RedisError::String(format!("Expected MODE, got: {}", option)));
+    }
+    let mode = Mode::parse(&args.next_string()?)?;
+    args.done()?;
+
+    if prefix.is_empty() {
+        return Err(RedisError::Str("Prefix must not be empty"));
+    }
+
+    let mut protections = init_protections().write().map_err(|_| {
+        RedisError::String("Failed to acquire write lock".to_string())
+    })?;
+    protections.retain(|(existing, _)| *existing != prefix);
+    if let Some(mode) = mode {
+        protections.push((prefix, mode));
+        protections.sort_by(|a, b| b.0.len().cmp(&a.0.len()).then_with(|| a.0.cmp(&b.0)));
+    }
+
+    Ok(RedisValue::SimpleStringStatic("OK"))
+}
@@ -0,0 +1,294 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::Duration;
+use redis_module::{Context, NextArg, RedisError, RedisResult, RedisString, RedisValue};
+
+// Mutations waiting to be forwarded; beyond this many new ones are dropped
+const QUEUE_CAPACITY: usize = 10_000;
+
+// Most mutations written to the replica before reading their replies
+const PIPELINE: usize = 256;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(1);
+const IO_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Reconnect delay after the first failure, doubled per failure up to the maximum
+const BACKOFF_START: Duration = Duration::from_millis(100);
+const BACKOFF_MAX: Duration = Duration::from_secs(30);
+
+// A mutation to forward; `None` means the key was deleted
+struct Mutation {
+    key: Vec<u8>,
+    value: Option<Vec<u8>>,
+}
+
+#[derive(Default)]
+struct Stats {
+    connected: AtomicBool,
+    // Set when the replica is replaced or turned off, so its thread exits
+    stopped: AtomicBool,
+    forwarded: AtomicU64,
+    dropped: AtomicU64,
+    // Mutations the replica answered with an error
+    failed: AtomicU64,
+    reconnects: AtomicU64,
+    last_error: Mutex<Option<String>>,
+}
+
+impl Stats {
+    fn fail(&self, err: String) {
+        self.connected.store(false, Ordering::Relaxed);
+        if let Ok(mut last_error) = self.last_error.lock() {
+            *last_error = Some(err);
+        }
+    }
+}
+
+// The endpoint mutations are forwarded to
+struct Replica {
+    address: String,
+    prefix: Vec<u8>,
+    sender: SyncSender<Mutation>,
+    stats: Arc<Stats>,
+}
+
+static mut REPLICA: Option<RwLock<Option<Replica>>> = None;
+
+// Initialize the replica slot
+fn init_replica() -> &'static RwLock<Option<Replica>> {
+    unsafe {
+        if REPLICA.is_none() {
+            REPLICA = Some(RwLock::new(None));
+        }
+        REPLICA.as_ref().unwrap()
+    }
+}
+
+// Forward a mutation to the replica, if one is configured. Called with a
+// shard lock held, so it only queues; the replication thread sends it.
+pub fn forward(key: &[u8], value: Option<&[u8]>) {
+    let replica = match init_replica().read() {
+        Ok(replica) => replica,
+        Err(_) => return,
+    };
+    let Some(replica) = replica.as_ref() else { return };
+
+    let mutation = Mutation { key: key.to_vec(), value: value.map(|v| v.to_vec()) };
+    if let Err(TrySendError::Full(_)) = replica.sender.try_send(mutation) {
+        replica.stats.dropped.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+// A command as a RESP array of bulk strings
+fn encode(out: &mut Vec<u8>, args: &[&[u8]]) {
+    out.extend_from_slice(format!("*{}\r\n", args.len()).as_bytes());
+    for arg in args {
+        out.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+        out.extend_from_slice(arg);
+        out.extend_from_slice(b"\r\n");
+    }
+}
+
+// Reads the one-line replies SET, DEL and AUTH give
+struct Connection {
+    stream: TcpStream,
+    reader: BufReader<TcpStream>,
+}
+
+impl Connection {
+    fn open(address: &str, password: Option<&str>) -> Result<Connection, String> {
+        let socket_addr = address.to_socket_addrs()
+            .map_err(|e| format!("Failed to resolve {}: {}", address, e))?
+            .next()
+            .ok_or_else(|| format!("Failed to resolve {}", address))?;
+        let stream = TcpStream::connect_timeout(&socket_addr, CONNECT_TIMEOUT)
+            .map_err(|e| format!("Failed to connect to {}: {}", address, e))?;
+        stream.set_read_timeout(Some(IO_TIMEOUT)).map_err(|e| e.to_string())?;
+        stream.set_write_timeout(Some(IO_TIMEOUT)).map_err(|e| e.to_string())?;
+        let reader = BufReader::new(stream.try_clone().map_err(|e| e.to_string())?);
+
+        let mut connection = Connection { stream, reader };
+        if let Some(password) = password {
+            let mut auth = Vec::new();
+            encode(&mut auth, &[b"AUTH", password.as_bytes()]);
+            connection.send(&auth)?;
+            if let Some(err) = connection.read_reply()? {
+                return Err(format!("AUTH failed: {}", err));
+            }
+        }
+        Ok(connection)
+    }
+
+    fn send(&mut self, commands: &[u8]) -> Result<(), String> {
+        self.stream.write_all(commands).map_err(|e| format!("Failed to send: {}", e))
+    }
+
+    // The error of an error reply, None for any other reply
+    fn read_reply(&mut self) -> Result<Option<String>, String> {
+        let mut line = String::new();
+        match self.reader.read_line(&mut line) {
+            Ok(0) => Err("Connection closed".to_string()),
+            Ok(_) => Ok(line.strip_prefix('-').map(|err| err.trim_end().to_string())),
+            Err(e) => Err(format!("Failed to read reply: {}", e)),
+        }
+    }
+}
+
+// Send a batch and read one reply per mutation
+fn send_batch(connection: &mut Connection, prefix: &[u8], batch: &[Mutation], stats: &Stats) -> Result<(), String> {
+    let mut commands = Vec::new();
+    for mutation in batch {
+        let key = [prefix, &mutation.key].concat();
+        match &mutation.value {
+            Some(value) => encode(&mut commands, &[b"SET", &key, value]),
+            None => encode(&mut commands, &[b"DEL", &key]),
+        }
+    }
+    connection.send(&commands)?;
+
+    for _ in batch {
+        if let Some(err) = connection.read_reply()? {
+            stats.failed.fetch_add(1, Ordering::Relaxed);
+            if let Ok(mut last_error) = stats.last_error.lock() {
+                *last_error = Some(err);
+            }
+        }
+    }
+    stats.forwarded.fetch_add(batch.len() as u64, Ordering::Relaxed);
+    Ok(())
+}
+
+// Replication thread: forward queued mutations in order, reconnecting with
+// backoff. A batch that fails mid-way is sent again in full, which is safe
+// because SET and DEL are idempotent.
+fn run(address: String, password: Option<String>, prefix: Vec<u8>, receiver: Receiver<Mutation>, stats: Arc<Stats>) {
+    let mut connection = None;
+    let mut backoff = BACKOFF_START;
+
+    while let Ok(first) = receiver.recv() {
+        let mut batch = vec![first];
+        batch.extend(receiver.try_iter().take(PIPELINE - 1));
+
+        loop {
+            if stats.stopped.load(Ordering::Relaxed) {
+                return;
+            }
+            if connection.is_none() {
+                match Connection::open(&address, password.as_deref()) {
+                    Ok(opened) => {
+                        connection = Some(opened);
+                        stats.connected.store(true, Ordering::Relaxed);
+                        backoff = BACKOFF_START;
+                    },
+                    Err(err) => {
+                        stats.fail(err);
+                        thread::sleep(backoff);
+                        backoff = (backoff * 2).min(BACKOFF_MAX);
+                        continue;
+                    },
+                }
+            }
+
+            let Some(open) = connection.as_mut() else { continue };
+            match send_batch(open, &prefix, &batch, &stats) {
+                Ok(()) => break,
+                Err(err) => {
+                    connection = None;
+                    stats.reconnects.fetch_add(1, Ordering::Relaxed);
+                    stats.fail(err);
+                },
+            }
+        }
+    }
+}
+
+// Forward hashmap mutations to another Redis server:
+// CUSTOM.REPLICATE_TO host:port [PREFIX prefix] [AUTH password]
+// CUSTOM.REPLICATE_TO OFF
+// CUSTOM.REPLICATE_TO STATUS
+#[tracing::instrument(name = "custom.replicate_to", skip_all)]
+pub fn custom_replicate_to(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let target = args.next_string()?;
+
+    match target.to_uppercase().as_str() {
+        "OFF" => {
+            args.done()?;
+            let mut replica = init_replica().write().map_err(|_| {
+                RedisError::String("Failed to acquire write lock".to_string())
+            })?;
+            if let Some(replica) = replica.take() {
+                replica.stats.stopped.store(true, Ordering::Relaxed);
+            }
+            Ok(RedisValue::SimpleStringStatic("OK"))
+        },
+        "STATUS" => {
+            args.done()?;
+            let replica = init_replica().read().map_err(|_| {
+                RedisError::String("Failed to acquire read lock".to_string())
+            })?;
+            let Some(replica) = replica.as_ref() else {
+                return Ok(RedisValue::Array(vec![
+                    RedisValue::SimpleStringStatic("address"),
+                    RedisValue::Null,
+                ]));
+            };
+            let stats = &replica.stats;
+            let last_error = stats.last_error.lock().ok().and_then(|last_error| last_error.clone());
+            Ok(RedisValue::Array(vec![
+                RedisValue::SimpleStringStatic("address"),
+                RedisValue::BulkString(replica.address.clone()),
+                RedisValue::SimpleStringStatic("prefix"),
+                RedisValue::StringBuffer(replica.prefix.clone()),
+                RedisValue::SimpleStringStatic("connected"),
+                RedisValue::Integer(stats.connected.load(Ordering::Relaxed) as i64),
+                RedisValue::SimpleStringStatic("forwarded"),
+                RedisValue::Integer(stats.forwarded.load(Ordering::Relaxed) as i64),
+                RedisValue::SimpleStringStatic("dropped"),
+                RedisValue::Integer(stats.dropped.load(Ordering::Relaxed) as i64),
+                RedisValue::SimpleStringStatic("failed"),
+                RedisValue::Integer(stats.failed.load(Ordering::Relaxed) as i64),
+                RedisValue::SimpleStringStatic("reconnects"),
+                RedisValue::Integer(stats.reconnects.load(Ordering::Relaxed) as i64),
+                RedisValue::SimpleStringStatic("last_error"),
+                last_error.map_or(RedisValue::Null, RedisValue::BulkString),
+            ]))
+        },
+        _ => {
+            if !target.contains(':') {
+                return Err(RedisError::String(format!("Invalid address: {}, expected host:port", target)));
+            }
+            let mut prefix = Vec::new();
+            let mut password = None;
+            while let Ok(option) = args.next_string() {
+                match option.to_uppercase().as_str() {
+                    "PREFIX" => prefix = args.next_arg()?.as_slice().to_vec(),
+                    "AUTH" => password = Some(args.next_string()?),
+                    _ => return Err(RedisError::String(format!("Unknown option: {}", option))),
+                }
+            }
+
+            let mut replica = init_replica().write().map_err(|_| {
+                RedisError::String("Failed to acquire write lock".to_string())
+            })?;
+            let (sender, receiver) = mpsc::sync_channel(QUEUE_CAPACITY);
+            let stats = Arc::new(Stats::default());
+            let thread_stats = stats.clone();
+            let (address, thread_prefix) = (target.clone(), prefix.clone());
+            thread::Builder::new()
+                .name("custom-hashmap-replicate".to_string())
+                .spawn(move || run(address, password, thread_prefix, receiver, thread_stats))
+                .map_err(|e| RedisError::String(format!("Failed to start replication thread: {}", e)))?;
+
+            // The previous replica's thread exits once its queue is dropped
+            if let Some(previous) = replica.replace(Replica { address: target, prefix, sender, stats }) {
+                previous.stats.stopped.store(true, Ordering::Relaxed);
+            }
+            Ok(RedisValue::SimpleStringStatic("OK"))
+        },
+    }
+}
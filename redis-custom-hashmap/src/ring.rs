@@ -0,0 +1,110 @@
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::Duration;
+use session_core::capi::{
+    custom_hashmap_ring, custom_hashmap_ring_slot, RING_OP_DEL, RING_OP_GET, RING_OP_SET, WIRE_MISMATCH,
+};
+
+use crate::protect::custom_hashmap_act_as;
+use crate::{custom_hashmap_del, custom_hashmap_get, custom_hashmap_set};
+
+// Empty polls spent spinning, then yielding, before the server thread starts sleeping
+const SPIN_POLLS: u32 = 128;
+const YIELD_POLLS: u32 = 256;
+
+// Sleep between polls of an idle ring; bounds the latency of the first request after a lull
+const IDLE_SLEEP: Duration = Duration::from_micros(50);
+
+// The ring, handed to the thread serving it
+struct RingPtr(*mut custom_hashmap_ring);
+
+// Safety: the caller keeps the ring alive until `stopped` is set, and each
+// queue index is only ever stored by one side
+unsafe impl Send for RingPtr {}
+
+fn idle(polls: u32) {
+    if polls < SPIN_POLLS {
+        std::hint::spin_loop();
+    } else if polls < SPIN_POLLS + YIELD_POLLS {
+        thread::yield_now();
+    } else {
+        thread::sleep(IDLE_SLEEP);
+    }
+}
+
+// Run one request through the same path as the matching direct call
+fn complete(request: &custom_hashmap_ring_slot) -> custom_hashmap_ring_slot {
+    let mut completion = custom_hashmap_ring_slot { tag: request.tag, op: request.op, ..Default::default() };
+    completion.status = match request.op {
+        RING_OP_GET => {
+            completion.result = custom_hashmap_get(request.key);
+            if completion.result.is_null() { 0 } else { 1 }
+        },
+        RING_OP_SET => custom_hashmap_set(request.key, request.value),
+        RING_OP_DEL => custom_hashmap_del(request.key),
+        _ => 0,
+    };
+    completion
+}
+
+// Serve requests until the caller closes the ring. Each pass takes every
+// request published so far (as many as the completion queue has room for)
+// and publishes all their completions with a single store.
+fn serve(ring: RingPtr) {
+    let ring = unsafe { &*ring.0 };
+    let capacity = ring.capacity as u64;
+    let (requests, completions) = (&ring.requests, &ring.completions);
+    let mut polls = 0;
+
+    while ring.closed.load(Ordering::Acquire) == 0 {
+        let request_tail = requests.tail.load(Ordering::Relaxed);
+        let completion_head = completions.head.load(Ordering::Relaxed);
+        let pending = requests.head.load(Ordering::Acquire) - request_tail;
+        let room = capacity - (completion_head - completions.tail.load(Ordering::Acquire));
+        let batch = pending.min(room);
+        if batch == 0 {
+            idle(polls);
+            polls = polls.saturating_add(1);
+            continue;
+        }
+        polls = 0;
+
+        for offset in 0..batch {
+            unsafe {
+                let request = &*requests.slots.add(((request_tail + offset) % capacity) as usize);
+                let completion = completions.slots.add(((completion_head + offset) % capacity) as usize);
+                *completion = complete(request);
+            }
+        }
+        completions.head.store(completion_head + batch, Ordering::Release);
+        requests.tail.store(request_tail + batch, Ordering::Release);
+    }
+
+    ring.stopped.store(1, Ordering::Release);
+}
+
+// Start a thread serving a caller's ring (see custom_hashmap_ring in custom-hashmap-sys)
+#[no_mangle]
+pub extern "C" fn custom_hashmap_ring_attach(ring: *mut custom_hashmap_ring, module: *const libc::c_char) -> libc::c_int {
+    if ring.is_null() {
+        return 0;
+    }
+    let attached = unsafe { &*ring };
+    if !attached.header.matches::<custom_hashmap_ring>() {
+        return WIRE_MISMATCH;
+    }
+    if attached.capacity == 0 || attached.requests.slots.is_null() || attached.completions.slots.is_null() {
+        return 0;
+    }
+
+    let module = (!module.is_null()).then(|| unsafe { std::ffi::CStr::from_ptr(module) }.to_owned());
+    let ring = RingPtr(ring);
+    let spawned = thread::Builder::new()
+        .name("custom-hashmap-ring".to_string())
+        .spawn(move || {
+            // Writes through the ring are made on this thread, so it carries the caller's identity
+            custom_hashmap_act_as(module.as_ref().map_or(std::ptr::null(), |module| module.as_ptr()));
+            serve(ring);
+        });
+    if spawned.is_ok() { 1 } else { 0 }
+}
@@ -0,0 +1,122 @@
+#[cfg(feature = "ordered")]
+use std::ops::Bound;
+use redis_module::{Context, RedisError, RedisResult, RedisString};
+#[cfg(feature = "ordered")]
+use redis_module::{NextArg, RedisValue};
+
+#[cfg(feature = "ordered")]
+use crate::init_hashmap;
+
+// Keys per page when SCAN is given no COUNT
+#[cfg(feature = "ordered")]
+const DEFAULT_COUNT: usize = 10;
+
+// Prefix of the cursors CUSTOM.SCAN hands out, so no key can be mistaken for the start cursor
+#[cfg(feature = "ordered")]
+const CURSOR_PREFIX: u8 = b'>';
+
+// A ZRANGEBYLEX-style bound: `-` / `+` for open ends, `[key` inclusive, `(key` exclusive
+#[cfg(feature = "ordered")]
+fn parse_bound<'a>(bound: &'a [u8], open: &str) -> Result<Bound<&'a [u8]>, RedisError> {
+    if bound == open.as_bytes() {
+        return Ok(Bound::Unbounded);
+    }
+    match bound.split_first() {
+        Some((b'[', key)) => Ok(Bound::Included(key)),
+        Some((b'(', key)) => Ok(Bound::Excluded(key)),
+        _ => Err(RedisError::String(format!(
+            "Invalid range bound: {}, expected {}, [key or (key", String::from_utf8_lossy(bound), open,
+        ))),
+    }
+}
+
+// Page through keys in lexicographic order:
+// CUSTOM.SCAN cursor [COUNT n]
+// CUSTOM.SCAN RANGE from to [LIMIT n]
+// SCAN starts at cursor 0 and replies [next cursor, [key, ...]], the cursor
+// being 0 again once every key has been returned. Keys added or removed
+// while paging are seen or skipped by their position, never repeated.
+#[cfg(feature = "ordered")]
+#[tracing::instrument(name = "custom.scan", skip_all)]
+pub fn custom_scan(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let first = args.next_arg()?;
+
+    if first.as_slice().eq_ignore_ascii_case(b"RANGE") {
+        let from = args.next_arg()?;
+        let to = args.next_arg()?;
+        let limit = match args.next_string() {
+            Ok(option) if option.eq_ignore_ascii_case("LIMIT") => args.next_u64()? as usize,
+            Ok(option) => return Err(RedisError::String(format!("Unknown option: {}", option))),
+            Err(_) => usize::MAX,
+        };
+        args.done()?;
+
+        let range = (parse_bound(from.as_slice(), "-")?, parse_bound(to.as_slice(), "+")?);
+        let keys = init_hashmap().ordered_keys(range, limit);
+        return Ok(RedisValue::Array(keys.into_iter().map(RedisValue::StringBuffer).collect()));
+    }
+
+    let start = match first.as_slice() {
+        b"0" => Bound::Unbounded,
+        cursor => match cursor.split_first() {
+            Some((&CURSOR_PREFIX, last_key)) => Bound::Excluded(last_key),
+            _ => return Err(RedisError::String(format!("Invalid cursor: {}", String::from_utf8_lossy(cursor)))),
+        },
+    };
+    let count = match args.next_string() {
+        Ok(option) if option.eq_ignore_ascii_case("COUNT") => args.next_u64()? as usize,
+        Ok(option) => return Err(RedisError::String(format!("Unknown option: {}", option))),
+        Err(_) => DEFAULT_COUNT,
+    };
+    args.done()?;
+    if count == 0 {
+        return Err(RedisError::Str("COUNT must be positive"));
+    }
+
+    // Fetch one extra key to tell whether another page follows
+    let mut keys = init_hashmap().ordered_keys((start, Bound::Unbounded), count + 1);
+    let cursor = if keys.len() > count {
+        keys.truncate(count);
+        [&[CURSOR_PREFIX], keys[count - 1].as_slice()].concat()
+    } else {
+        b"0".to_vec()
+    };
+
+    Ok(RedisValue::Array(vec![
+        RedisValue::StringBuffer(cursor),
+        RedisValue::Array(keys.into_iter().map(RedisValue::StringBuffer).collect()),
+    ]))
+}
+
+#[cfg(not(feature = "ordered"))]
+#[tracing::instrument(name = "custom.scan", skip_all)]
+pub fn custom_scan(_ctx: &Context, _args: Vec<RedisString>) -> RedisResult {
+    Err(RedisError::Str("CUSTOM.SCAN is not available: module built without the ordered feature"))
+}
+
+// Fetch the keys under a prefix with their values: CUSTOM.GETPREFIX prefix [LIMIT n]
+// Replies [key, value, key, value, ...] in lexicographic key order.
+#[cfg(feature = "ordered")]
+#[tracing::instrument(name = "custom.getprefix", skip_all)]
+pub fn custom_getprefix(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let prefix = args.next_arg()?;
+    let limit = match args.next_string() {
+        Ok(option) if option.eq_ignore_ascii_case("LIMIT") => args.next_u64()? as usize,
+        Ok(option) => return Err(RedisError::String(format!("Unknown option: {}", option))),
+        Err(_) => usize::MAX,
+    };
+    args.done()?;
+
+    let entries = init_hashmap().prefix_entries(prefix.as_slice(), limit);
+    Ok(RedisValue::Array(entries.into_iter()
+        .flat_map(|(key, value)| [RedisValue::StringBuffer(key), RedisValue::StringBuffer(value)])
+        .collect()))
+}
+
+#[cfg(not(feature = "ordered"))]
+#[tracing::instrument(name = "custom.getprefix", skip_all)]
+pub fn custom_getprefix(_ctx: &Context, _args: Vec<RedisString>) -> RedisResult {
+    Err(RedisError::Str("CUSTOM.GETPREFIX is not available: module built without the ordered feature"))
+}
@@ -0,0 +1,109 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use redis_module::{Context, NextArg, RedisError, RedisResult, RedisString, RedisValue};
+
+use crate::init_hashmap;
+use crate::store::MAX_SHARDS;
+
+// Shard count the next REBALANCE moves to; 0 until one is configured
+static CONFIGURED: AtomicUsize = AtomicUsize::new(0);
+
+fn parse_count(count: u64) -> Result<usize, RedisError> {
+    match count as usize {
+        count @ 1..=MAX_SHARDS => Ok(count),
+        _ => Err(RedisError::String(format!("Shard count must be between 1 and {}", MAX_SHARDS))),
+    }
+}
+
+// Shard count configured but not yet applied by a rebalance
+fn configured() -> usize {
+    match CONFIGURED.load(Ordering::Relaxed) {
+        0 => init_hashmap().shard_count(),
+        configured => configured,
+    }
+}
+
+// Apply the `shards=<n>` module argument; the map is still empty so nothing moves
+pub fn set_initial_count(count: &str) -> Result<(), String> {
+    let count = count.parse().ok()
+        .and_then(|count| parse_count(count).ok())
+        .ok_or_else(|| format!("Invalid shards: {}, expected 1 to {}", count, MAX_SHARDS))?;
+    init_hashmap().rebalance(count).map_err(|e| e.to_string())?;
+    CONFIGURED.store(count, Ordering::Relaxed);
+    Ok(())
+}
+
+// Per-shard statistics and shard count tuning:
+// CUSTOM.SHARDS [STATS]
+// CUSTOM.SHARDS CONFIG count
+// CUSTOM.SHARDS REBALANCE
+// CONFIG only records the new count; REBALANCE re-hashes every entry into it
+// while holding every shard's write lock, so run it when the store is quiet.
+#[tracing::instrument(name = "custom.shards", skip_all)]
+pub fn custom_shards(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let subcommand = args.next_string().map_or_else(|_| "STATS".to_string(), |subcommand| subcommand.to_uppercase());
+
+    match subcommand.as_str() {
+        "STATS" => {
+            args.done()?;
+            let hashmap = init_hashmap();
+            let stats = hashmap.shard_stats();
+            let (key_bytes, stored_key_bytes, prefixes) = stats.iter().fold((0, 0, 0), |(logical, stored, prefixes), shard| {
+                (logical + shard.keys.logical, stored + shard.keys.stored, prefixes + shard.keys.prefixes)
+            });
+            let shards = stats.into_iter()
+                .enumerate()
+                .map(|(index, stats)| RedisValue::Array(vec![
+                    RedisValue::SimpleStringStatic("shard"),
+                    RedisValue::Integer(index as i64),
+                    RedisValue::SimpleStringStatic("entries"),
+                    RedisValue::Integer(stats.entries as i64),
+                    RedisValue::SimpleStringStatic("bytes"),
+                    RedisValue::Integer(stats.bytes as i64),
+                    RedisValue::SimpleStringStatic("writes"),
+                    RedisValue::Integer(stats.writes as i64),
+                    RedisValue::SimpleStringStatic("contended"),
+                    RedisValue::Integer(stats.contended as i64),
+                    RedisValue::SimpleStringStatic("wait_us"),
+                    RedisValue::Integer(stats.wait_micros as i64),
+                ]))
+                .collect();
+            Ok(RedisValue::Array(vec![
+                RedisValue::SimpleStringStatic("count"),
+                RedisValue::Integer(hashmap.shard_count() as i64),
+                RedisValue::SimpleStringStatic("configured"),
+                RedisValue::Integer(configured() as i64),
+                RedisValue::SimpleStringStatic("key_bytes"),
+                RedisValue::Integer(key_bytes as i64),
+                RedisValue::SimpleStringStatic("stored_key_bytes"),
+                RedisValue::Integer(stored_key_bytes as i64),
+                RedisValue::SimpleStringStatic("interned_prefixes"),
+                RedisValue::Integer(prefixes as i64),
+                RedisValue::SimpleStringStatic("shards"),
+                RedisValue::Array(shards),
+            ]))
+        },
+        "CONFIG" => {
+            let count = parse_count(args.next_u64()?)?;
+            args.done()?;
+            CONFIGURED.store(count, Ordering::Relaxed);
+            Ok(RedisValue::SimpleStringStatic("OK"))
+        },
+        "REBALANCE" => {
+            args.done()?;
+            let hashmap = init_hashmap();
+            let from = hashmap.shard_count();
+            let to = configured();
+            let moved = hashmap.rebalance(to)?;
+            Ok(RedisValue::Array(vec![
+                RedisValue::SimpleStringStatic("from"),
+                RedisValue::Integer(from as i64),
+                RedisValue::SimpleStringStatic("to"),
+                RedisValue::Integer(to as i64),
+                RedisValue::SimpleStringStatic("moved"),
+                RedisValue::Integer(moved as i64),
+            ]))
+        },
+        _ => Err(RedisError::String(format!("Unknown CUSTOM.SHARDS subcommand: {}", subcommand))),
+    }
+}
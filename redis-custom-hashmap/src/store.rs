@@ -0,0 +1,618 @@
+use std::collections::{BTreeSet, HashMap};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, TryLockError};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use arc_swap::ArcSwap;
+use redis_module::RedisError;
+use session_core::capi::{crc32, shard_in, SHARD_COUNT};
+use session_core::Digest;
+
+use crate::keytable::{KeyBytes, KeyTable};
+use crate::{backend, debug, integrity, mirror, prefixes, tags};
+
+// Milliseconds since the Unix epoch, the unit expiry times are kept in
+pub fn now_millis() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
+
+// A key, its value and when it expires, as given to a bulk load
+pub type BulkEntry = (Vec<u8>, Vec<u8>, Option<u64>);
+
+// A stored value and, for volatile keys, when it expires
+#[derive(Debug, Clone)]
+struct Entry {
+    value: Vec<u8>,
+    expires_at: Option<u64>,
+    // CRC-32 of the value, for entries written while checksums are on
+    checksum: Option<u32>,
+}
+
+impl Entry {
+    fn new(value: Vec<u8>, expires_at: Option<u64>) -> Entry {
+        let checksum = integrity::checksums_enabled().then(|| crc32(&value));
+        Entry { value, expires_at, checksum }
+    }
+
+    fn is_expired(&self, now: u64) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+
+    // Whether the value still matches its checksum; entries without one always do
+    fn is_intact(&self) -> bool {
+        self.checksum.is_none_or(|checksum| crc32(&self.value) == checksum)
+    }
+
+    // The value, unless it no longer matches its checksum, which is counted
+    fn into_verified(self) -> Option<Vec<u8>> {
+        if self.is_intact() {
+            Some(self.value)
+        } else {
+            integrity::record_corrupt_read();
+            None
+        }
+    }
+}
+
+// Keys that have an expiry, in a vector so they can be sampled at random and
+// ordered by expiry time so the soonest can be listed
+#[derive(Default)]
+struct VolatileKeys {
+    keys: Vec<Vec<u8>>,
+    // Position in `keys` and expiry time of each key
+    positions: HashMap<Vec<u8>, (usize, u64)>,
+    by_expiry: BTreeSet<(u64, Vec<u8>)>,
+}
+
+impl VolatileKeys {
+    fn add(&mut self, key: &[u8], expires_at: u64) {
+        match self.positions.get_mut(key) {
+            Some((_, indexed)) => {
+                if *indexed != expires_at {
+                    self.by_expiry.remove(&(*indexed, key.to_vec()));
+                    self.by_expiry.insert((expires_at, key.to_vec()));
+                    *indexed = expires_at;
+                }
+            },
+            None => {
+                self.positions.insert(key.to_vec(), (self.keys.len(), expires_at));
+                self.keys.push(key.to_vec());
+                self.by_expiry.insert((expires_at, key.to_vec()));
+            },
+        }
+    }
+
+    fn remove(&mut self, key: &[u8]) {
+        if let Some((position, expires_at)) = self.positions.remove(key) {
+            self.by_expiry.remove(&(expires_at, key.to_vec()));
+            self.keys.swap_remove(position);
+            if let Some(moved) = self.keys.get(position) {
+                if let Some(entry) = self.positions.get_mut(moved) {
+                    entry.0 = position;
+                }
+            }
+        }
+    }
+}
+
+// Most shards the map can be split over. Every slot is allocated up front so
+// shards never move while writers hold them; only the first `count` are used.
+pub const MAX_SHARDS: usize = 1024;
+
+// One shard: readers load the current snapshot, writers clone it, modify and swap
+#[derive(Default)]
+struct Shard {
+    map: ArcSwap<KeyTable<Entry>>,
+    // Serializes writers of this shard; readers never touch it
+    writer: Mutex<()>,
+    // Writer lock acquisitions, those that had to wait, and time spent waiting
+    writes: AtomicU64,
+    contended: AtomicU64,
+    wait_micros: AtomicU64,
+}
+
+impl Shard {
+    // Take the writer lock, counting whether it had to wait
+    fn lock(&self) -> Result<MutexGuard<'_, ()>, RedisError> {
+        let _span = tracing::debug_span!("lock_wait", lock = "shard").entered();
+        self.writes.fetch_add(1, Ordering::Relaxed);
+        match self.writer.try_lock() {
+            Ok(guard) => Ok(guard),
+            Err(TryLockError::WouldBlock) => {
+                let start = Instant::now();
+                let guard = self.writer.lock().map_err(|_| {
+                    RedisError::String("Failed to acquire write lock".to_string())
+                })?;
+                self.contended.fetch_add(1, Ordering::Relaxed);
+                self.wait_micros.fetch_add(start.elapsed().as_micros() as u64, Ordering::Relaxed);
+                Ok(guard)
+            },
+            Err(TryLockError::Poisoned(_)) => Err(RedisError::String("Failed to acquire write lock".to_string())),
+        }
+    }
+}
+
+// Size and write lock contention of one shard, for CUSTOM.SHARDS
+pub struct ShardStats {
+    pub entries: usize,
+    // Bytes of keys and values, expired entries included
+    pub bytes: usize,
+    // Key bytes before and after prefix interning
+    pub keys: KeyBytes,
+    pub writes: u64,
+    pub contended: u64,
+    pub wait_micros: u64,
+}
+
+// Read-mostly key/value store. Reads never take a lock and see a consistent
+// snapshot of their shard; writes to different shards don't contend.
+// Expired entries stay in their shard, invisible to readers, until a writer
+// or the active expiry cycle removes them.
+pub struct ShardedMap {
+    shards: Vec<Shard>,
+    // Shards keys are split over, changed only by a rebalance
+    count: AtomicUsize,
+    // Taken after a shard's writer lock, never before
+    volatile: Mutex<VolatileKeys>,
+    // Every key in lexicographic order, for CUSTOM.SCAN; same locking rule as `volatile`
+    #[cfg(feature = "ordered")]
+    ordered: Mutex<BTreeSet<Vec<u8>>>,
+}
+
+// Exclusive write access to the shard holding a key
+pub struct ShardWriter<'a> {
+    shard: &'a Shard,
+    volatile: &'a Mutex<VolatileKeys>,
+    #[cfg(feature = "ordered")]
+    ordered: &'a Mutex<BTreeSet<Vec<u8>>>,
+    _guard: MutexGuard<'a, ()>,
+}
+
+impl ShardWriter<'_> {
+    fn track_expiry(&self, key: &[u8], expires_at: Option<u64>) {
+        if let Ok(mut volatile) = self.volatile.lock() {
+            match expires_at {
+                Some(expires_at) => volatile.add(key, expires_at),
+                None => volatile.remove(key),
+            }
+        }
+    }
+
+    // An expired entry being dropped takes its tags with it, and its mirrored
+    // copy too unless the key is being overwritten
+    fn forget_expired(&self, key: &[u8], previous: Option<Entry>, overwritten: bool) -> Option<Vec<u8>> {
+        match previous {
+            Some(entry) if entry.is_expired(now_millis()) => {
+                tags::forget_key(key);
+                if !overwritten {
+                    mirror::queue_write(key, None);
+                }
+                None
+            },
+            previous => previous.map(|entry| entry.value),
+        }
+    }
+
+    pub fn contains_key(&self, key: &[u8]) -> bool {
+        let now = now_millis();
+        self.shard.map.load().get(key).is_some_and(|entry| !entry.is_expired(now))
+    }
+
+    // Store a value without an expiry, clearing any previous one
+    pub fn insert(&mut self, key: Vec<u8>, value: Vec<u8>) -> Option<Vec<u8>> {
+        self.insert_with_expiry(key, value, None)
+    }
+
+    // Store a value that expires at `expires_at` (milliseconds since the epoch)
+    pub fn insert_with_expiry(&mut self, key: Vec<u8>, value: Vec<u8>, expires_at: Option<u64>) -> Option<Vec<u8>> {
+        self.track_expiry(&key, expires_at);
+        #[cfg(feature = "ordered")]
+        if let Ok(mut ordered) = self.ordered.lock() {
+            ordered.insert(key.clone());
+        }
+        backend::queue_put(&key, &value, expires_at);
+        let mut next = KeyTable::clone(&self.shard.map.load());
+        let previous = next.insert(key.clone(), Entry::new(value, expires_at));
+        self.shard.map.store(Arc::new(next));
+        if previous.is_none() {
+            prefixes::key_added(&key);
+        }
+        self.forget_expired(&key, previous, true)
+    }
+
+    // Set or clear the expiry of a live key; false if there is no such key
+    pub fn set_expiry(&mut self, key: &[u8], expires_at: Option<u64>) -> bool {
+        let current = self.shard.map.load();
+        let entry = match current.get(key) {
+            Some(entry) if !entry.is_expired(now_millis()) => entry,
+            _ => return false,
+        };
+        if entry.expires_at != expires_at {
+            self.track_expiry(key, expires_at);
+            backend::queue_put(key, &entry.value, expires_at);
+            let mut next = KeyTable::clone(&current);
+            next.insert(key.to_vec(), Entry { expires_at, ..entry.clone() });
+            self.shard.map.store(Arc::new(next));
+        }
+        true
+    }
+
+    // Remove a key; an entry that had already expired counts as absent
+    pub fn remove(&mut self, key: &[u8]) -> Option<Vec<u8>> {
+        let current = self.shard.map.load();
+        if !current.contains_key(key) {
+            return None;
+        }
+        self.track_expiry(key, None);
+        #[cfg(feature = "ordered")]
+        if let Ok(mut ordered) = self.ordered.lock() {
+            ordered.remove(key);
+        }
+        let mut next = KeyTable::clone(&current);
+        let previous = next.remove(key);
+        self.shard.map.store(Arc::new(next));
+        prefixes::key_removed(key);
+        backend::queue_delete(key);
+        self.forget_expired(key, previous, false)
+    }
+
+    // Remove a key only if it has expired
+    pub fn remove_expired(&mut self, key: &[u8], now: u64) -> bool {
+        let expired = self.shard.map.load().get(key).is_some_and(|entry| entry.is_expired(now));
+        if expired {
+            self.remove(key);
+        }
+        expired
+    }
+}
+
+impl ShardedMap {
+    pub fn new() -> Self {
+        ShardedMap {
+            shards: (0..MAX_SHARDS).map(|_| Shard::default()).collect(),
+            count: AtomicUsize::new(SHARD_COUNT),
+            volatile: Mutex::new(VolatileKeys::default()),
+            #[cfg(feature = "ordered")]
+            ordered: Mutex::new(BTreeSet::new()),
+        }
+    }
+
+    // Number of shards keys are currently split over
+    pub fn shard_count(&self) -> usize {
+        self.count.load(Ordering::Acquire)
+    }
+
+    fn active_shards(&self) -> &[Shard] {
+        &self.shards[..self.shard_count()]
+    }
+
+    // Shards are independently swapped; a write clones only one of them
+    fn shard_index(&self, key: &[u8]) -> usize {
+        shard_in(key, self.shard_count())
+    }
+
+    fn shard(&self, key: &[u8]) -> &Shard {
+        &self.shards[self.shard_index(key)]
+    }
+
+    // Lock-free lookup. A value that no longer matches its checksum reads as missing.
+    pub fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        let now = now_millis();
+        self.shard(key).map.load().get(key)
+            .filter(|entry| !entry.is_expired(now))
+            .and_then(|entry| entry.clone().into_verified())
+    }
+
+    pub fn contains_key(&self, key: &[u8]) -> bool {
+        let now = now_millis();
+        self.shard(key).map.load().get(key).is_some_and(|entry| !entry.is_expired(now))
+    }
+
+    // Whether a key is still stored but has expired
+    pub fn has_expired(&self, key: &[u8], now: u64) -> bool {
+        self.shard(key).map.load().get(key).is_some_and(|entry| entry.is_expired(now))
+    }
+
+    // Expiry of a live key: None if absent, Some(None) if it never expires
+    pub fn expiry(&self, key: &[u8]) -> Option<Option<u64>> {
+        let now = now_millis();
+        self.shard(key).map.load().get(key)
+            .filter(|entry| !entry.is_expired(now))
+            .map(|entry| entry.expires_at)
+    }
+
+    // All live keys, shard by shard (each shard is a consistent snapshot)
+    pub fn keys(&self) -> Vec<Vec<u8>> {
+        let now = now_millis();
+        self.active_shards().iter()
+            .flat_map(|shard| {
+                shard.map.load().iter()
+                    .filter(|(_, entry)| !entry.is_expired(now))
+                    .map(|(key, _)| key)
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    // Order-independent digest of every live key and value. Expiry times are
+    // left out: a copy restored from relative TTLs never has exactly the same ones.
+    pub fn digest(&self) -> Digest {
+        let now = now_millis();
+        let mut digest = Digest::default();
+        for shard in self.active_shards() {
+            for (key, entry) in shard.map.load().iter().filter(|(_, entry)| !entry.is_expired(now)) {
+                digest.add(&[&key, &entry.value]);
+            }
+        }
+        digest
+    }
+
+    // Up to `limit` live keys within `range`, in lexicographic order
+    #[cfg(feature = "ordered")]
+    pub fn ordered_keys(&self, range: (std::ops::Bound<&[u8]>, std::ops::Bound<&[u8]>), limit: usize) -> Vec<Vec<u8>> {
+        let ordered = match self.ordered.lock() {
+            Ok(ordered) => ordered,
+            Err(_) => return Vec::new(),
+        };
+        // Expired keys stay indexed until they are removed
+        ordered.range::<[u8], _>(range)
+            .filter(|key| self.contains_key(key))
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+
+    // Up to `limit` live keys starting with `prefix` and their values, in lexicographic order
+    #[cfg(feature = "ordered")]
+    pub fn prefix_entries(&self, prefix: &[u8], limit: usize) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let ordered = match self.ordered.lock() {
+            Ok(ordered) => ordered,
+            Err(_) => return Vec::new(),
+        };
+        ordered.range::<[u8], _>((std::ops::Bound::Included(prefix), std::ops::Bound::Unbounded))
+            .take_while(|key| key.starts_with(prefix))
+            .filter_map(|key| self.get(key).map(|value| (key.clone(), value)))
+            .take(limit)
+            .collect()
+    }
+
+    // Every live key starting with `prefix` and its value, in no particular order
+    #[cfg(feature = "ordered")]
+    pub fn entries_under(&self, prefix: &[u8]) -> Vec<(Vec<u8>, Vec<u8>)> {
+        self.prefix_entries(prefix, usize::MAX)
+    }
+
+    // Without the ordered index every shard is scanned
+    #[cfg(not(feature = "ordered"))]
+    pub fn entries_under(&self, prefix: &[u8]) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let now = now_millis();
+        self.active_shards().iter()
+            .flat_map(|shard| {
+                shard.map.load().iter()
+                    .filter(|(key, entry)| key.starts_with(prefix) && !entry.is_expired(now))
+                    .filter_map(|(key, entry)| entry.clone().into_verified().map(|value| (key, value)))
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    // Number of keys with an expiry, including expired ones not yet removed
+    pub fn volatile_count(&self) -> usize {
+        self.volatile.lock().map_or(0, |volatile| volatile.keys.len())
+    }
+
+    // Up to `limit` live keys expiring at or before `until` (milliseconds since
+    // the epoch) with their expiry times, soonest first
+    pub fn expiring_before(&self, until: u64, limit: usize) -> Vec<(Vec<u8>, u64)> {
+        let now = now_millis();
+        let volatile = match self.volatile.lock() {
+            Ok(volatile) => volatile,
+            Err(_) => return Vec::new(),
+        };
+        // Expired keys stay indexed until they are removed, and sort first
+        volatile.by_expiry.range((now + 1, Vec::new())..)
+            .take_while(|(expires_at, _)| *expires_at <= until)
+            .take(limit)
+            .map(|(expires_at, key)| (key.clone(), *expires_at))
+            .collect()
+    }
+
+    // Up to `count` keys with an expiry, picked at random by `pick(len)`
+    pub fn sample_volatile(&self, count: usize, mut pick: impl FnMut(usize) -> usize) -> Vec<Vec<u8>> {
+        let volatile = match self.volatile.lock() {
+            Ok(volatile) => volatile,
+            Err(_) => return Vec::new(),
+        };
+        if volatile.keys.len() <= count {
+            return volatile.keys.clone();
+        }
+        (0..count).map(|_| volatile.keys[pick(volatile.keys.len())].clone()).collect()
+    }
+
+    // Store a batch of (key, value, expires_at) entries for a bulk restore. Each
+    // shard the batch touches is locked, cloned and swapped once, with room for
+    // its share of `expected` keys in total, instead of once per entry. Mirroring
+    // rules are not applied, but a persistent backend gets every entry.
+    // Returns how many keys were new.
+    pub fn load_bulk(&self, entries: Vec<BulkEntry>, expected: usize) -> Result<usize, RedisError> {
+        if debug::poisoned() {
+            return Err(RedisError::String("Failed to acquire write lock".to_string()));
+        }
+
+        let count = self.shard_count();
+        let mut batches: Vec<Vec<BulkEntry>> = (0..count).map(|_| Vec::new()).collect();
+        for entry in entries {
+            batches[self.shard_index(&entry.0)].push(entry);
+        }
+
+        let now = now_millis();
+        let mut added = 0;
+        let mut replaced_expired = Vec::new();
+        for (shard, batch) in self.shards.iter().zip(batches) {
+            if batch.is_empty() {
+                continue;
+            }
+            let _guard = shard.lock()?;
+            if self.shard_count() != count {
+                return Err(RedisError::Str("Shards were rebalanced during the load"));
+            }
+
+            let mut next = KeyTable::clone(&shard.map.load());
+            next.reserve((expected / count).saturating_sub(next.len()).max(batch.len()));
+            let mut volatile = self.volatile.lock().map_err(|_| {
+                RedisError::String("Failed to acquire write lock".to_string())
+            })?;
+            #[cfg(feature = "ordered")]
+            let mut ordered = self.ordered.lock().map_err(|_| {
+                RedisError::String("Failed to acquire write lock".to_string())
+            })?;
+
+            for (key, value, expires_at) in batch {
+                match expires_at {
+                    Some(expires_at) => volatile.add(&key, expires_at),
+                    None => volatile.remove(&key),
+                }
+                #[cfg(feature = "ordered")]
+                ordered.insert(key.clone());
+                backend::queue_put(&key, &value, expires_at);
+                match next.insert(key.clone(), Entry::new(value, expires_at)) {
+                    Some(previous) if !previous.is_expired(now) => {},
+                    Some(_) => {
+                        replaced_expired.push(key);
+                        added += 1;
+                    },
+                    None => {
+                        prefixes::key_added(&key);
+                        added += 1;
+                    },
+                }
+            }
+            shard.map.store(Arc::new(next));
+        }
+
+        // Expired entries that were replaced take their tags with them
+        for key in replaced_expired {
+            tags::forget_key(&key);
+        }
+        Ok(added)
+    }
+
+    // Lock the shard holding `key` for writing
+    pub fn write(&self, key: &[u8]) -> Result<ShardWriter<'_>, RedisError> {
+        if debug::poisoned() {
+            return Err(RedisError::String("Failed to acquire write lock".to_string()));
+        }
+
+        // A rebalance holds every lock, so once ours is taken the count can't
+        // change; if it changed while we waited, the key may live elsewhere now
+        loop {
+            let count = self.shard_count();
+            let shard = &self.shards[shard_in(key, count)];
+            let guard = shard.lock()?;
+            if self.shard_count() != count {
+                continue;
+            }
+            return Ok(ShardWriter {
+                shard,
+                volatile: &self.volatile,
+                #[cfg(feature = "ordered")]
+                ordered: &self.ordered,
+                _guard: guard,
+            });
+        }
+    }
+
+    // Check every checksummed value against its checksum, returning how many
+    // were checked and the keys of those that no longer match. With `fix`
+    // the corrupted entries are removed, each under its shard's write lock.
+    pub fn verify_integrity(&self, fix: bool) -> Result<(usize, Vec<Vec<u8>>), RedisError> {
+        let mut checked = 0;
+        let mut corrupted = Vec::new();
+        for shard in self.active_shards() {
+            for (key, entry) in shard.map.load().iter().filter(|(_, entry)| entry.checksum.is_some()) {
+                checked += 1;
+                if !entry.is_intact() {
+                    corrupted.push(key);
+                }
+            }
+        }
+
+        if fix {
+            for key in &corrupted {
+                let mut writer = self.write(key)?;
+                // Rewritten since the scan: the new value is the one to keep
+                let still_corrupted = writer.shard.map.load().get(key).is_some_and(|entry| !entry.is_intact());
+                if still_corrupted {
+                    writer.remove(key);
+                }
+            }
+        }
+        Ok((checked, corrupted))
+    }
+
+    // Size and contention of each shard in use
+    pub fn shard_stats(&self) -> Vec<ShardStats> {
+        self.active_shards().iter()
+            .map(|shard| {
+                let map = shard.map.load();
+                ShardStats {
+                    entries: map.len(),
+                    bytes: map.iter().map(|(key, entry)| key.len() + entry.value.len()).sum(),
+                    keys: map.key_bytes(),
+                    writes: shard.writes.load(Ordering::Relaxed),
+                    contended: shard.contended.load(Ordering::Relaxed),
+                    wait_micros: shard.wait_micros.load(Ordering::Relaxed),
+                }
+            })
+            .collect()
+    }
+
+    // Re-hash every entry over `count` shards, holding every writer lock
+    // throughout. Lock-free readers may still look a key up under either
+    // count, so each shard first holds the entries of both layouts, then the
+    // count switches, then the old entries go. Contention counters restart
+    // since they described the old layout. Returns how many entries moved.
+    pub fn rebalance(&self, count: usize) -> Result<usize, RedisError> {
+        if count == 0 || count > MAX_SHARDS {
+            return Err(RedisError::String(format!("Shard count must be between 1 and {}", MAX_SHARDS)));
+        }
+        if debug::poisoned() {
+            return Err(RedisError::String("Failed to acquire write lock".to_string()));
+        }
+
+        let from = self.shard_count();
+        let touched = &self.shards[..from.max(count)];
+        let _guards = touched.iter()
+            .map(|shard| shard.writer.lock())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| RedisError::String("Failed to acquire write lock".to_string()))?;
+
+        let mut next: Vec<KeyTable<Entry>> = (0..count).map(|_| KeyTable::default()).collect();
+        let mut moved = 0;
+        for (index, shard) in self.shards[..from].iter().enumerate() {
+            for (key, entry) in shard.map.load().iter() {
+                let target = shard_in(&key, count);
+                if target != index {
+                    moved += 1;
+                }
+                next[target].insert(key, entry.clone());
+            }
+        }
+
+        for (shard, entries) in self.shards.iter().zip(&next) {
+            let mut both = KeyTable::clone(&shard.map.load());
+            for (key, entry) in entries.iter() {
+                both.insert(key, entry.clone());
+            }
+            shard.map.store(Arc::new(both));
+        }
+        self.count.store(count, Ordering::Release);
+        for (index, shard) in touched.iter().enumerate() {
+            let entries = next.get_mut(index).map(std::mem::take).unwrap_or_default();
+            shard.map.store(Arc::new(entries));
+            shard.writes.store(0, Ordering::Relaxed);
+            shard.contended.store(0, Ordering::Relaxed);
+            shard.wait_micros.store(0, Ordering::Relaxed);
+        }
+        Ok(moved)
+    }
+}
@@ -0,0 +1,213 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::RwLock;
+use redis_module::{Context, NextArg, RedisError, RedisResult, RedisString, RedisValue};
+
+use crate::{init_hashmap, mirror, protect};
+
+// Tag index in both directions: tag -> keys for lookups, key -> tags for cleanup on delete
+#[derive(Default)]
+struct TagIndex {
+    by_tag: HashMap<String, HashSet<Vec<u8>>>,
+    by_key: HashMap<Vec<u8>, HashSet<String>>,
+}
+
+impl TagIndex {
+    fn add(&mut self, key: &[u8], tag: &str) -> bool {
+        let added = self.by_key.entry(key.to_vec()).or_default().insert(tag.to_string());
+        if added {
+            self.by_tag.entry(tag.to_string()).or_default().insert(key.to_vec());
+        }
+        added
+    }
+
+    fn remove(&mut self, key: &[u8], tag: &str) -> bool {
+        let removed = match self.by_key.get_mut(key) {
+            Some(tags) => {
+                let removed = tags.remove(tag);
+                if tags.is_empty() {
+                    self.by_key.remove(key);
+                }
+                removed
+            },
+            None => false,
+        };
+
+        if removed {
+            if let Some(keys) = self.by_tag.get_mut(tag) {
+                keys.remove(key);
+                if keys.is_empty() {
+                    self.by_tag.remove(tag);
+                }
+            }
+        }
+        removed
+    }
+
+    fn remove_key(&mut self, key: &[u8]) {
+        if let Some(tags) = self.by_key.remove(key) {
+            for tag in tags {
+                if let Some(keys) = self.by_tag.get_mut(&tag) {
+                    keys.remove(key);
+                    if keys.is_empty() {
+                        self.by_tag.remove(&tag);
+                    }
+                }
+            }
+        }
+    }
+}
+
+static mut TAG_INDEX: Option<RwLock<TagIndex>> = None;
+
+// Initialize the tag index
+fn init_tags() -> &'static RwLock<TagIndex> {
+    unsafe {
+        if TAG_INDEX.is_none() {
+            TAG_INDEX = Some(RwLock::new(TagIndex::default()));
+        }
+        TAG_INDEX.as_ref().unwrap()
+    }
+}
+
+// Drop all tags of a deleted key
+pub fn forget_key(key: &[u8]) {
+    if let Ok(mut index) = init_tags().write() {
+        index.remove_key(key);
+    }
+}
+
+// Tags of a key, sorted
+pub fn tags_of(key: &[u8]) -> Vec<String> {
+    let mut tags: Vec<String> = init_tags().read()
+        .map(|index| index.by_key.get(key).map(|tags| tags.iter().cloned().collect()).unwrap_or_default())
+        .unwrap_or_default();
+    tags.sort();
+    tags
+}
+
+// Replace all tags of a key; the caller holds the key's shard
+pub fn set_tags(key: &[u8], tags: &[String]) -> Result<(), RedisError> {
+    let mut index = init_tags().write().map_err(|_| {
+        RedisError::String("Failed to acquire write lock".to_string())
+    })?;
+    index.remove_key(key);
+    for tag in tags {
+        index.add(key, tag);
+    }
+    Ok(())
+}
+
+// Manage tags on a key:
+// CUSTOM.TAG ADD key tag [tag ...]
+// CUSTOM.TAG DEL key tag [tag ...]
+// CUSTOM.TAG LIST key
+#[tracing::instrument(name = "custom.tag", skip_all)]
+pub fn custom_tag(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let subcommand = args.next_string()?.to_uppercase();
+    let key = args.next_arg()?;
+    let key = key.as_slice();
+
+    match subcommand.as_str() {
+        "ADD" => {
+            let tags: Vec<String> = args.map(|tag| tag.to_string_lossy()).collect();
+            if tags.is_empty() {
+                return Err(RedisError::WrongArity);
+            }
+
+            // Hold the key's shard so it can't be deleted between the check and the insert
+            let shard = init_hashmap().write(key)?;
+            if !shard.contains_key(key) {
+                return Err(RedisError::String(format!("No such key: {}", String::from_utf8_lossy(key))));
+            }
+
+            let mut index = init_tags().write().map_err(|_| {
+                RedisError::String("Failed to acquire write lock".to_string())
+            })?;
+            let added = tags.iter().filter(|tag| index.add(key, tag)).count();
+
+            Ok(RedisValue::Integer(added as i64))
+        },
+        "DEL" => {
+            let tags: Vec<String> = args.map(|tag| tag.to_string_lossy()).collect();
+            if tags.is_empty() {
+                return Err(RedisError::WrongArity);
+            }
+
+            let mut index = init_tags().write().map_err(|_| {
+                RedisError::String("Failed to acquire write lock".to_string())
+            })?;
+            let removed = tags.iter().filter(|tag| index.remove(key, tag)).count();
+
+            Ok(RedisValue::Integer(removed as i64))
+        },
+        "LIST" => {
+            args.done()?;
+
+            let index = init_tags().read().map_err(|_| {
+                RedisError::String("Failed to acquire read lock".to_string())
+            })?;
+
+            let mut tags: Vec<&String> = index.by_key.get(key)
+                .map(|tags| tags.iter().collect())
+                .unwrap_or_default();
+            tags.sort();
+
+            Ok(RedisValue::Array(tags.into_iter().map(|tag| RedisValue::BulkString(tag.clone())).collect()))
+        },
+        _ => Err(RedisError::String(format!("Unknown CUSTOM.TAG subcommand: {}", subcommand))),
+    }
+}
+
+// List the keys carrying a tag, or delete them all: CUSTOM.BYTAG tag [DELETE]
+#[tracing::instrument(name = "custom.bytag", skip_all)]
+pub fn custom_bytag(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let tag = args.next_string()?;
+
+    let delete = match args.next_string() {
+        Ok(option) if option.eq_ignore_ascii_case("DELETE") => true,
+        Ok(option) => return Err(RedisError::String(format!("Unknown option: {}", option))),
+        Err(_) => false,
+    };
+    args.done()?;
+
+    if !delete {
+        let index = init_tags().read().map_err(|_| {
+            RedisError::String("Failed to acquire read lock".to_string())
+        })?;
+
+        let mut keys: Vec<&Vec<u8>> = index.by_tag.get(&tag)
+            .map(|keys| keys.iter().collect())
+            .unwrap_or_default();
+        keys.sort();
+
+        return Ok(RedisValue::Array(keys.into_iter().map(|key| RedisValue::StringBuffer(key.clone())).collect()));
+    }
+
+    let keys: Vec<Vec<u8>> = init_tags().read().map_err(|_| {
+        RedisError::String("Failed to acquire read lock".to_string())
+    })?
+        .by_tag.get(&tag)
+        .map(|keys| keys.iter().cloned().collect())
+        .unwrap_or_default();
+
+    // Keys are removed one shard at a time; the shard lock is taken before the tags lock.
+    // Keys under a prefix protected against the caller are left alone.
+    let mut deleted = 0;
+    for key in keys {
+        if protect::check_write(&key).is_err() {
+            continue;
+        }
+        let mut shard = init_hashmap().write(&key)?;
+        forget_key(&key);
+        if shard.remove(&key).is_some() {
+            mirror::forward(&key, None);
+            drop(shard);
+            mirror::write_through(ctx, &key, None);
+            deleted += 1;
+        }
+    }
+
+    Ok(RedisValue::Integer(deleted))
+}
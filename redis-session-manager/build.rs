@@ -0,0 +1,16 @@
+use std::process::Command;
+
+// Record the commit the module is built from for CUSTOM.VERSION and
+// SESSION.VERSION. GIT_SHA in the environment wins, for builds from a
+// source archive.
+fn main() {
+    println!("cargo:rerun-if-env-changed=GIT_SHA");
+    println!("cargo:rerun-if-changed=../.git/HEAD");
+    println!("cargo:rerun-if-changed=../.git/refs");
+
+    let sha = std::env::var("GIT_SHA").ok().or_else(|| {
+        let output = Command::new("git").args(["rev-parse", "--short=12", "HEAD"]).output().ok()?;
+        output.status.success().then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+    });
+    println!("cargo:rustc-env=GIT_SHA={}", sha.filter(|sha| !sha.is_empty()).as_deref().unwrap_or("unknown"));
+}
@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use chrono::Utc;
+use redis_module::{Context, NextArg, RedisError, RedisResult, RedisString, RedisValue};
+
+use crate::init_sessions;
+
+// Per-minute buckets kept: the hour SESSION.ACTIVITY reports on, plus the
+// minute leaving it, which still counts for the part of it inside the window
+const MINUTES: i64 = 61;
+
+// Windows reported, in minutes, with their reply names
+const WINDOWS: &[(&str, i64)] = &[("1m", 1), ("5m", 5), ("1h", 60)];
+
+// A last minute this many times busier than the hour's average is a burst
+const BURST_FACTOR: f64 = 5.0;
+
+// Fewer operations in the last minute never make a burst, however quiet the hour
+const BURST_MIN: f64 = 10.0;
+
+// A session's operations per minute over the last hour
+struct Activity {
+    // Minute of the newest bucket, in minutes since the epoch
+    minute: i64,
+    counts: [u32; MINUTES as usize],
+}
+
+impl Activity {
+    fn slot(minute: i64) -> usize {
+        minute.rem_euclid(MINUTES) as usize
+    }
+
+    // Operations in a minute, 0 for one outside the buckets kept
+    fn at(&self, minute: i64) -> u32 {
+        if minute > self.minute || minute <= self.minute - MINUTES {
+            0
+        } else {
+            self.counts[Activity::slot(minute)]
+        }
+    }
+
+    fn record(&mut self, minute: i64) {
+        // Clear the buckets of the minutes passed since the last operation
+        for passed in (self.minute + 1..=minute).take(MINUTES as usize) {
+            self.counts[Activity::slot(passed)] = 0;
+        }
+        self.minute = self.minute.max(minute);
+        let count = &mut self.counts[Activity::slot(minute)];
+        *count = count.saturating_add(1);
+    }
+
+    // Operations in the `window` minutes up to now. The minute leaving the
+    // window is weighted by how much of it is still inside, as if its
+    // operations had been spread evenly over it.
+    fn count(&self, minute: i64, elapsed: f64, window: i64) -> f64 {
+        let whole: u32 = (minute - window + 1..=minute).map(|minute| self.at(minute)).sum();
+        whole as f64 + self.at(minute - window) as f64 * (1.0 - elapsed)
+    }
+}
+
+static mut ACTIVITY: Option<Mutex<HashMap<String, Activity>>> = None;
+
+// Initialize the activity counters
+fn init_activity() -> &'static Mutex<HashMap<String, Activity>> {
+    unsafe {
+        if ACTIVITY.is_none() {
+            ACTIVITY = Some(Mutex::new(HashMap::new()));
+        }
+        ACTIVITY.as_ref().unwrap()
+    }
+}
+
+// The current minute since the epoch and how much of it has passed
+fn now() -> (i64, f64) {
+    let millis = Utc::now().timestamp_millis();
+    (millis.div_euclid(60_000), millis.rem_euclid(60_000) as f64 / 60_000.0)
+}
+
+// Count an operation on a session
+pub fn record(session_id: &str) {
+    let (minute, _) = now();
+    let Ok(mut activity) = init_activity().lock() else { return };
+    activity.entry(session_id.to_string())
+        .or_insert_with(|| Activity { minute, counts: [0; MINUTES as usize] })
+        .record(minute);
+}
+
+// Forget sessions without an operation in the last hour, including removed ones
+pub fn prune() {
+    let (minute, _) = now();
+    if let Ok(mut activity) = init_activity().lock() {
+        activity.retain(|_, activity| activity.minute > minute - MINUTES);
+    }
+}
+
+// Sessions with an operation in the last hour
+pub fn tracked() -> usize {
+    init_activity().lock().map_or(0, |activity| activity.len())
+}
+
+// How busy a session has been: SESSION.ACTIVITY session_id
+// Replies with the touches and data operations of the last minute, 5 minutes
+// and hour, and `state`: `idle` with none in the last 5 minutes, `burst` when
+// the last minute is far busier than the hour's average, else `active`.
+#[tracing::instrument(name = "session.activity", skip_all)]
+pub fn session_activity(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let session_id = args.next_string()?;
+    args.done()?;
+
+    let sessions_map = init_sessions().read().map_err(|_| {
+        RedisError::String("Failed to acquire read lock".to_string())
+    })?;
+    if sessions_map.get(&session_id).is_none() {
+        return Err(RedisError::String(format!("Session not found: {}", session_id)));
+    }
+    drop(sessions_map);
+
+    let (minute, elapsed) = now();
+    let counts: Vec<f64> = match init_activity().lock() {
+        Ok(activity) => match activity.get(&session_id) {
+            Some(activity) => WINDOWS.iter().map(|(_, window)| activity.count(minute, elapsed, *window)).collect(),
+            None => vec![0.0; WINDOWS.len()],
+        },
+        Err(_) => return Err(RedisError::String("Failed to acquire activity lock".to_string())),
+    };
+
+    let (last_minute, last_five, last_hour) = (counts[0], counts[1], counts[2]);
+    let state = if last_five.round() == 0.0 {
+        "idle"
+    } else if last_minute >= BURST_MIN && last_minute >= BURST_FACTOR * last_hour / 60.0 {
+        "burst"
+    } else {
+        "active"
+    };
+
+    let mut reply: Vec<RedisValue> = WINDOWS.iter()
+        .zip(&counts)
+        .flat_map(|((name, _), count)| [RedisValue::SimpleStringStatic(name), RedisValue::Integer(count.round() as i64)])
+        .collect();
+    reply.extend([RedisValue::SimpleStringStatic("state"), RedisValue::SimpleStringStatic(state)]);
+    Ok(RedisValue::Array(reply))
+}
@@ -0,0 +1,236 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use redis_module::{Context, NextArg, RedisError, RedisResult, RedisString, RedisValue};
+
+use crate::{init_sessions, spill};
+
+// HyperLogLog precision: 2^14 registers, about 0.8% standard error
+const HLL_PRECISION: u32 = 14;
+
+// Count-Min dimensions: overestimates by at most ~0.1% of the total with 98% confidence
+const CMS_WIDTH: usize = 2048;
+const CMS_DEPTH: usize = 4;
+
+// Largest TOPK accepted
+const MAX_TOPK: usize = 1000;
+
+// Last histogram bucket, open-ended from 2^63
+const MAX_BUCKET: usize = 64;
+
+// Top-k entries and histogram buckets counting fewer sessions are left out,
+// so a reply never singles out a handful of users
+const MIN_REPORTED_COUNT: u64 = 5;
+
+fn hash_with_seed(value: &str, seed: u64) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    seed.hash(&mut hasher);
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+// Distinct-value estimate in fixed memory
+struct HyperLogLog {
+    registers: Vec<u8>,
+}
+
+impl HyperLogLog {
+    fn new() -> HyperLogLog {
+        HyperLogLog { registers: vec![0; 1 << HLL_PRECISION] }
+    }
+
+    fn add(&mut self, value: &str) {
+        let hash = hash_with_seed(value, 0);
+        let index = (hash >> (64 - HLL_PRECISION)) as usize;
+        // Position of the first set bit in the remaining bits, with a sentinel so it's bounded
+        let rank = ((hash << HLL_PRECISION) | (1 << (HLL_PRECISION - 1))).leading_zeros() as u8 + 1;
+        if rank > self.registers[index] {
+            self.registers[index] = rank;
+        }
+    }
+
+    fn estimate(&self) -> u64 {
+        let m = self.registers.len() as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / m);
+        let sum: f64 = self.registers.iter().map(|&rank| 2f64.powi(-(rank as i32))).sum();
+        let raw = alpha * m * m / sum;
+
+        // Linear counting is more accurate while many registers are still empty
+        let zeros = self.registers.iter().filter(|&&rank| rank == 0).count();
+        if raw <= 2.5 * m && zeros > 0 {
+            (m * (m / zeros as f64).ln()).round() as u64
+        } else {
+            raw.round() as u64
+        }
+    }
+}
+
+// Frequency estimates in fixed memory; never underestimates
+struct CountMin {
+    counters: Vec<u64>,
+}
+
+impl CountMin {
+    fn new() -> CountMin {
+        CountMin { counters: vec![0; CMS_WIDTH * CMS_DEPTH] }
+    }
+
+    // Count one occurrence and return the new estimate
+    fn add(&mut self, value: &str) -> u64 {
+        let mut estimate = u64::MAX;
+        for row in 0..CMS_DEPTH {
+            let cell = row * CMS_WIDTH + (hash_with_seed(value, row as u64 + 1) as usize % CMS_WIDTH);
+            self.counters[cell] += 1;
+            estimate = estimate.min(self.counters[cell]);
+        }
+        estimate
+    }
+}
+
+// The k most frequent values, tracked over a Count-Min sketch
+struct TopK {
+    k: usize,
+    sketch: CountMin,
+    candidates: HashMap<String, u64>,
+}
+
+impl TopK {
+    fn new(k: usize) -> TopK {
+        TopK { k, sketch: CountMin::new(), candidates: HashMap::new() }
+    }
+
+    fn add(&mut self, value: &str) {
+        let estimate = self.sketch.add(value);
+        if let Some(count) = self.candidates.get_mut(value) {
+            *count = estimate;
+            return;
+        }
+        if self.candidates.len() < self.k {
+            self.candidates.insert(value.to_string(), estimate);
+            return;
+        }
+        // Evict the least frequent candidate if this value has overtaken it
+        let weakest = self.candidates.iter()
+            .min_by_key(|(_, count)| **count)
+            .map(|(weakest, count)| (weakest.clone(), *count));
+        if let Some((weakest, count)) = weakest {
+            if estimate > count {
+                self.candidates.remove(&weakest);
+                self.candidates.insert(value.to_string(), estimate);
+            }
+        }
+    }
+
+    fn top(self) -> Vec<(String, u64)> {
+        let mut top: Vec<(String, u64)> = self.candidates.into_iter()
+            .filter(|(_, count)| *count >= MIN_REPORTED_COUNT)
+            .collect();
+        top.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        top
+    }
+}
+
+// Counts of numeric values in power-of-two buckets: [0, 1), [1, 2), [2, 4), ...
+#[derive(Default)]
+struct Histogram {
+    buckets: Vec<u64>,
+    non_numeric: u64,
+}
+
+impl Histogram {
+    fn add(&mut self, value: &str) {
+        let number = match value.trim().parse::<f64>() {
+            Ok(number) if number.is_finite() && number >= 0.0 => number,
+            _ => {
+                self.non_numeric += 1;
+                return;
+            },
+        };
+        let bucket = if number < 1.0 { 0 } else { (number.log2().floor() as usize + 1).min(MAX_BUCKET) };
+        if self.buckets.len() <= bucket {
+            self.buckets.resize(bucket + 1, 0);
+        }
+        self.buckets[bucket] += 1;
+    }
+
+    fn reply(self) -> Vec<RedisValue> {
+        let mut reply = Vec::new();
+        for (bucket, count) in self.buckets.into_iter().enumerate() {
+            if count < MIN_REPORTED_COUNT {
+                continue;
+            }
+            let label = match bucket {
+                0 => "0-1".to_string(),
+                MAX_BUCKET => format!("{}-inf", 1u64 << (MAX_BUCKET - 1)),
+                _ => format!("{}-{}", 1u64 << (bucket - 1), 1u64 << bucket),
+            };
+            reply.push(RedisValue::BulkString(label));
+            reply.push(RedisValue::Integer(count as i64));
+        }
+        reply.push(RedisValue::SimpleStringStatic("non_numeric"));
+        reply.push(RedisValue::Integer(self.non_numeric as i64));
+        reply
+    }
+}
+
+enum Aggregate {
+    Cardinality,
+    TopK(usize),
+    Histogram,
+}
+
+// Aggregate a data field across all live sessions without returning any
+// session's data: SESSION.AGGREGATE field [TOPK n | CARDINALITY | HISTOGRAM]
+// CARDINALITY (the default) estimates the number of distinct values, TOPK the
+// n most common values and their counts, HISTOGRAM the spread of numeric
+// values in power-of-two buckets.
+#[tracing::instrument(name = "session.aggregate", skip_all)]
+pub fn session_aggregate(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let field = args.next_string()?;
+
+    let aggregate = match args.next_string() {
+        Ok(option) => match option.to_uppercase().as_str() {
+            "CARDINALITY" => Aggregate::Cardinality,
+            "HISTOGRAM" => Aggregate::Histogram,
+            "TOPK" => {
+                let k = args.next_u64()? as usize;
+                if k == 0 || k > MAX_TOPK {
+                    return Err(RedisError::String(format!("TOPK must be between 1 and {}", MAX_TOPK)));
+                }
+                Aggregate::TopK(k)
+            },
+            _ => return Err(RedisError::String(format!("Unknown option: {}", option))),
+        },
+        Err(_) => Aggregate::Cardinality,
+    };
+    args.done()?;
+
+    let sessions = init_sessions();
+    let sessions_map = sessions.read().map_err(|_| {
+        RedisError::String("Failed to acquire read lock".to_string())
+    })?;
+    let values = sessions_map.values()
+        .filter(|session| !session.is_expired())
+        .filter_map(|session| spill::value(ctx, session, &field));
+
+    match aggregate {
+        Aggregate::Cardinality => {
+            let mut hll = HyperLogLog::new();
+            values.for_each(|value| hll.add(&value));
+            Ok(RedisValue::Integer(hll.estimate() as i64))
+        },
+        Aggregate::TopK(k) => {
+            let mut top = TopK::new(k);
+            values.for_each(|value| top.add(&value));
+            Ok(RedisValue::Array(top.top().into_iter()
+                .flat_map(|(value, count)| [RedisValue::BulkString(value), RedisValue::Integer(count as i64)])
+                .collect()))
+        },
+        Aggregate::Histogram => {
+            let mut histogram = Histogram::default();
+            values.for_each(|value| histogram.add(&value));
+            Ok(RedisValue::Array(histogram.reply()))
+        },
+    }
+}
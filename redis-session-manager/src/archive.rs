@@ -0,0 +1,221 @@
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use chrono::{DateTime, Utc};
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use redis_module::{Context, NextArg, RedisError, RedisResult, RedisString, RedisValue};
+use serde::{Deserialize, Serialize};
+use session_core::events;
+
+use crate::{binding, bridge, changes, eviction, init_sessions, retry, spill, unlink_user_key, webhooks, Session, SessionExt};
+
+// Archived sessions live in native string keys under this prefix
+const ARCHIVE_PREFIX: &str = "session:archive:";
+
+// How long an archive is kept when no TTL is given (7 days)
+const DEFAULT_ARCHIVE_TTL_SECS: u64 = 7 * 24 * 60 * 60;
+
+// What goes into an archive key, before compression
+#[derive(Serialize, Deserialize)]
+struct Archive {
+    session: Session,
+    // Sessions serialize without their secrets, so those travel separately
+    secrets: HashMap<String, String>,
+    archived_at: DateTime<Utc>,
+}
+
+// The session id is a hash tag, so in a cluster the archive key lives in
+// the slot SESSION.ARCHIVE / SESSION.UNARCHIVE are routed to
+fn archive_key(session_id: &str) -> String {
+    format!("{}{{{}}}", ARCHIVE_PREFIX, session_id)
+}
+
+// JSON, zlib-compressed, base64-encoded so it round-trips through string replies
+fn encode(archive: &Archive) -> Result<String, RedisError> {
+    let json = serde_json::to_vec(archive).map_err(|e| {
+        RedisError::String(format!("Failed to serialize session: {}", e))
+    })?;
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&json)
+        .and_then(|_| encoder.finish())
+        .map(|compressed| STANDARD.encode(compressed))
+        .map_err(|e| RedisError::String(format!("Failed to compress session: {}", e)))
+}
+
+fn decode(payload: &[u8]) -> Result<Archive, RedisError> {
+    let corrupt = |e: &dyn std::fmt::Display| RedisError::String(format!("Corrupt session archive: {}", e));
+    let compressed = STANDARD.decode(payload).map_err(|e| corrupt(&e))?;
+    let mut json = Vec::new();
+    ZlibDecoder::new(compressed.as_slice()).read_to_end(&mut json).map_err(|e| corrupt(&e))?;
+    serde_json::from_slice(&json).map_err(|e| corrupt(&e))
+}
+
+// Move a session out of memory into a compressed native key:
+// SESSION.ARCHIVE session_id [TTL seconds]
+#[tracing::instrument(name = "session.archive", skip_all)]
+pub fn archive_session(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let session_id = args.next_string()?;
+
+    let mut ttl_secs = DEFAULT_ARCHIVE_TTL_SECS;
+    if let Ok(option) = args.next_string() {
+        if !option.eq_ignore_ascii_case("TTL") {
+            return Err(RedisError::String(format!("Unknown option: {}", option)));
+        }
+        ttl_secs = args.next_u64()?;
+        if ttl_secs == 0 {
+            return Err(RedisError::Str("TTL must be positive"));
+        }
+    }
+    args.done()?;
+
+    let sessions = init_sessions();
+    let mut sessions_map = sessions.write().map_err(|_| {
+        RedisError::String("Failed to acquire write lock".to_string())
+    })?;
+
+    let mut session = sessions_map.remove(&session_id)
+        .ok_or_else(|| RedisError::String(format!("Session not found: {}", session_id)))?;
+
+    // Bindings belong to a live client connection and aren't archived
+    binding::forget(&session);
+    session.bound_client = None;
+    session.idle = false;
+    // Spilled values are deleted with the session, so the archive keeps them inline
+    session.data = spill::resolved_data(ctx, &session);
+    session.spilled.clear();
+
+    let archive = Archive {
+        secrets: std::mem::take(&mut session.secrets),
+        session,
+        archived_at: Utc::now(),
+    };
+    let key = archive_key(&session_id);
+    let stored = encode(&archive).and_then(|payload| {
+        ctx.call("SET", &[key.as_str(), &payload, "EX", &ttl_secs.to_string()])
+            .map_err(|e| RedisError::String(format!("Failed to write {}: {}", key, e)))
+    });
+
+    // The user key no longer leads anywhere until the session is unarchived
+    let result = stored.and_then(|_| unlink_user_key(ctx, &archive.session.user_key));
+    if let Err(err) = result {
+        let _ = ctx.call("DEL", &[key.as_str()]);
+        let Archive { mut session, secrets, .. } = archive;
+        session.secrets = secrets;
+        sessions_map.insert(session_id, session);
+        return Err(err);
+    }
+
+    changes::record_deletion(&session_id);
+    webhooks::emit(events::ARCHIVED, &session_id, &archive.session.user_key);
+    Ok(RedisValue::BulkString(key))
+}
+
+// Bring an archived session back into memory: SESSION.UNARCHIVE session_id
+#[tracing::instrument(name = "session.unarchive", skip_all)]
+pub fn unarchive_session(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let session_id = args.next_string()?;
+    args.done()?;
+
+    let key = archive_key(&session_id);
+    let payload = match ctx.call("GET", &[key.as_str()]) {
+        Ok(RedisValue::BulkString(payload)) | Ok(RedisValue::SimpleString(payload)) => payload.into_bytes(),
+        Ok(RedisValue::StringBuffer(payload)) => payload,
+        Ok(RedisValue::Null) => return Err(RedisError::String(format!("No archived session: {}", session_id))),
+        Ok(other) => return Err(RedisError::String(format!("Unexpected GET reply: {:?}", other))),
+        Err(err) => return Err(RedisError::String(format!("Failed to read {}: {}", key, err))),
+    };
+    let Archive { mut session, secrets, .. } = decode(&payload)?;
+
+    let sessions = init_sessions();
+    let mut sessions_map = sessions.write().map_err(|_| {
+        RedisError::String("Failed to acquire write lock".to_string())
+    })?;
+
+    if sessions_map.contains_key(&session_id) {
+        return Err(RedisError::String(format!("Session already active: {}", session_id)));
+    }
+    // The user may have started a new session while this one was archived
+    if let Some(current) = bridge::get(ctx, &session.user_key)? {
+        if current != session_id && sessions_map.contains_key(&current) {
+            return Err(RedisError::String(format!(
+                "User key {} now belongs to session {}", session.user_key, current,
+            )));
+        }
+    }
+    retry::check_backpressure()?;
+    eviction::make_room_in(ctx, &mut sessions_map)?;
+    bridge::set(ctx, &session.user_key, &session_id)?;
+
+    session.secrets = secrets;
+    session.touch();
+    session.mark_changed();
+    webhooks::emit(events::UNARCHIVED, &session_id, &session.user_key);
+    sessions_map.insert(session_id, session);
+
+    let _ = ctx.call("DEL", &[key.as_str()]);
+    Ok(RedisValue::SimpleStringStatic("OK"))
+}
+
+// A string reply as bytes
+fn reply_bytes(value: RedisValue) -> Option<Vec<u8>> {
+    match value {
+        RedisValue::BulkString(text) | RedisValue::SimpleString(text) => Some(text.into_bytes()),
+        RedisValue::StringBuffer(bytes) => Some(bytes),
+        _ => None,
+    }
+}
+
+// Rewrite every archive whose session isn't in format `version`, keeping its
+// expiry. Returns how many were rewritten and how many couldn't be read.
+pub fn migrate_archives(ctx: &Context, version: u32) -> Result<(usize, usize), RedisError> {
+    let pattern = format!("{}*", ARCHIVE_PREFIX);
+    let (mut rewritten, mut unreadable) = (0, 0);
+    let mut cursor = "0".to_string();
+    loop {
+        let (next, keys) = match ctx.call("SCAN", &[cursor.as_str(), "MATCH", &pattern, "COUNT", "1000"])? {
+            RedisValue::Array(mut reply) if reply.len() == 2 => {
+                let keys = reply.pop();
+                (reply.pop().and_then(reply_bytes), keys)
+            },
+            other => return Err(RedisError::String(format!("Unexpected SCAN reply: {:?}", other))),
+        };
+        let keys = match keys {
+            Some(RedisValue::Array(keys)) => keys.into_iter().filter_map(reply_bytes),
+            other => return Err(RedisError::String(format!("Unexpected SCAN reply: {:?}", other))),
+        };
+
+        for key in keys {
+            let key = String::from_utf8_lossy(&key).into_owned();
+            let payload = match ctx.call("GET", &[key.as_str()]).ok().and_then(reply_bytes) {
+                Some(payload) => payload,
+                // Expired or unarchived since the scan
+                None => continue,
+            };
+            let mut archive = match decode(&payload) {
+                Ok(archive) => archive,
+                Err(_) => {
+                    unreadable += 1;
+                    continue;
+                },
+            };
+            if archive.session.format_version == version {
+                continue;
+            }
+            archive.session.format_version = version;
+            let payload = encode(&archive)?;
+            ctx.call("SET", &[key.as_str(), &payload, "KEEPTTL"])
+                .map_err(|e| RedisError::String(format!("Failed to write {}: {}", key, e)))?;
+            rewritten += 1;
+        }
+
+        cursor = next.map(|next| String::from_utf8_lossy(&next).into_owned()).unwrap_or_default();
+        if cursor == "0" || cursor.is_empty() {
+            return Ok((rewritten, unreadable));
+        }
+    }
+}
@@ -0,0 +1,208 @@
+use std::thread;
+use std::time::{Duration, Instant};
+use redis_module::{Context, NextArg, RedisError, RedisResult, RedisString, RedisValue, ThreadSafeContext};
+use uuid::Uuid;
+
+use crate::{bridge, changes, init_sessions, Session};
+
+// Upper bound on worker threads for a single run
+const MAX_CONCURRENCY: u64 = 64;
+
+// Prefix of the user keys created by a benchmark run
+const BENCH_PREFIX: &str = "__bench:";
+
+// Ring slots used by SESSION.BENCH BRIDGE unless BATCH is given
+const DEFAULT_RING_BATCH: u64 = 96;
+
+// Pad a benchmark key or value out to the requested size
+fn padded(base: String, size: usize) -> String {
+    let mut s = base;
+    while s.len() < size {
+        s.push('x');
+    }
+    s
+}
+
+// One step of the create -> add_data -> get_data -> delete cycle
+fn run_op(ctx: &Context, step: u64, user_key: &str, session_id: &str, value: &str) -> Result<(), RedisError> {
+    let sessions = init_sessions();
+    match step {
+        0 => {
+            bridge::set(ctx, user_key, session_id)?;
+            let mut sessions_map = sessions.write().map_err(|_| {
+                RedisError::String("Failed to acquire write lock".to_string())
+            })?;
+            sessions_map.insert(session_id.to_string(), Session::new(session_id.to_string(), user_key.to_string(), changes::next_seq()));
+        },
+        1 => {
+            let mut sessions_map = sessions.write().map_err(|_| {
+                RedisError::String("Failed to acquire write lock".to_string())
+            })?;
+            if let Some(session) = sessions_map.get_mut(session_id) {
+                session.data.insert("field".to_string(), value.to_string());
+            }
+        },
+        2 => {
+            let sessions_map = sessions.read().map_err(|_| {
+                RedisError::String("Failed to acquire read lock".to_string())
+            })?;
+            let _ = sessions_map.get(session_id).and_then(|session| session.data.get("field"));
+        },
+        _ => {
+            sessions.write().map_err(|_| {
+                RedisError::String("Failed to acquire write lock".to_string())
+            })?.remove(session_id);
+            bridge::del(ctx, user_key)?;
+        },
+    }
+    Ok(())
+}
+
+// Run one worker's share of operations; each op holds the module lock like a real command
+fn run_worker(worker: u64, ops: u64, keysize: usize, valsize: usize) -> Result<Vec<Duration>, RedisError> {
+    let thread_ctx = ThreadSafeContext::new();
+    let value = padded(String::new(), valsize);
+    let mut latencies = Vec::with_capacity(ops as usize);
+    let mut user_key = String::new();
+    let mut session_id = String::new();
+
+    for i in 0..ops {
+        let step = i % 4;
+        if step == 0 {
+            user_key = padded(format!("{}{}:{}", BENCH_PREFIX, worker, i / 4), keysize);
+            session_id = Uuid::new_v4().to_string();
+        }
+
+        let ctx = thread_ctx.lock();
+        let start = Instant::now();
+        run_op(&ctx, step, &user_key, &session_id, &value)?;
+        latencies.push(start.elapsed());
+
+        // Don't leave a session behind when ops isn't a multiple of four
+        if i + 1 == ops && step != 3 {
+            run_op(&ctx, 3, &user_key, &session_id, &value)?;
+        }
+    }
+
+    Ok(latencies)
+}
+
+// Run the benchmark across worker threads and summarize it
+fn run(ops: u64, keysize: usize, valsize: usize, concurrency: u64) -> RedisResult {
+    let started = Instant::now();
+    let workers: Vec<_> = (0..concurrency)
+        .map(|worker| {
+            // Spread the remainder over the first workers
+            let share = ops / concurrency + u64::from(worker < ops % concurrency);
+            thread::spawn(move || run_worker(worker, share, keysize, valsize))
+        })
+        .collect();
+
+    let mut latencies = Vec::with_capacity(ops as usize);
+    for worker in workers {
+        latencies.extend(worker.join().map_err(|_| RedisError::Str("Benchmark worker panicked"))??);
+    }
+    let elapsed = started.elapsed();
+
+    latencies.sort_unstable();
+    let percentile = |q: f64| {
+        let index = ((latencies.len() - 1) as f64 * q).round() as usize;
+        RedisValue::Integer(latencies[index].as_nanos() as i64)
+    };
+
+    Ok(RedisValue::Array(vec![
+        RedisValue::SimpleStringStatic("ops"),
+        RedisValue::Integer(ops as i64),
+        RedisValue::SimpleStringStatic("concurrency"),
+        RedisValue::Integer(concurrency as i64),
+        RedisValue::SimpleStringStatic("bridge_path"),
+        RedisValue::SimpleStringStatic(bridge::preferred_path()),
+        RedisValue::SimpleStringStatic("elapsed_us"),
+        RedisValue::Integer(elapsed.as_micros() as i64),
+        RedisValue::SimpleStringStatic("ops_per_sec"),
+        RedisValue::Integer((ops as f64 / elapsed.as_secs_f64()) as i64),
+        RedisValue::SimpleStringStatic("p50_ns"),
+        percentile(0.50),
+        RedisValue::SimpleStringStatic("p90_ns"),
+        percentile(0.90),
+        RedisValue::SimpleStringStatic("p99_ns"),
+        percentile(0.99),
+        RedisValue::SimpleStringStatic("max_ns"),
+        percentile(1.0),
+    ]))
+}
+
+// Compare direct C calls with the ring transport on the same workload
+fn run_bridge(ops: u64, batch: u32) -> RedisResult {
+    let (direct, ring) = bridge::compare_transports(BENCH_PREFIX, ops, batch)?;
+    let ops_per_sec = |elapsed: Duration| RedisValue::Integer((ops as f64 / elapsed.as_secs_f64()) as i64);
+    Ok(RedisValue::Array(vec![
+        RedisValue::SimpleStringStatic("ops"),
+        RedisValue::Integer(ops as i64),
+        RedisValue::SimpleStringStatic("batch"),
+        RedisValue::Integer(batch as i64),
+        RedisValue::SimpleStringStatic("direct_elapsed_us"),
+        RedisValue::Integer(direct.as_micros() as i64),
+        RedisValue::SimpleStringStatic("direct_ops_per_sec"),
+        ops_per_sec(direct),
+        RedisValue::SimpleStringStatic("ring_elapsed_us"),
+        RedisValue::Integer(ring.as_micros() as i64),
+        RedisValue::SimpleStringStatic("ring_ops_per_sec"),
+        ops_per_sec(ring),
+    ]))
+}
+
+// Micro-benchmark the session lifecycle through the bridge, or compare the
+// FFI transports:
+// SESSION.BENCH ops keysize valsize concurrency
+// SESSION.BENCH BRIDGE ops [BATCH n]
+#[tracing::instrument(name = "session.bench", skip_all)]
+pub fn session_bench(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1).peekable();
+    if args.peek().is_some_and(|first| first.to_string_lossy().eq_ignore_ascii_case("BRIDGE")) {
+        args.next();
+        let ops = args.next_u64()?;
+        let batch = match args.next_string() {
+            Ok(option) if option.eq_ignore_ascii_case("BATCH") => args.next_u64()?,
+            Ok(option) => return Err(RedisError::String(format!("Unknown option: {}", option))),
+            Err(_) => DEFAULT_RING_BATCH,
+        };
+        args.done()?;
+        if ops == 0 {
+            return Err(RedisError::Str("ops must be positive"));
+        }
+        if batch == 0 || batch > bridge::MAX_RING_CAPACITY as u64 {
+            return Err(RedisError::String(format!("BATCH must be between 1 and {}", bridge::MAX_RING_CAPACITY)));
+        }
+
+        // No session state is touched, so the run needs no module lock at all
+        let blocked_client = ctx.block_client();
+        thread::spawn(move || {
+            let thread_ctx = ThreadSafeContext::with_blocked_client(blocked_client);
+            thread_ctx.reply(run_bridge(ops, batch as u32));
+        });
+        return Ok(RedisValue::NoReply);
+    }
+
+    let ops = args.next_u64()?;
+    let keysize = args.next_u64()? as usize;
+    let valsize = args.next_u64()? as usize;
+    let concurrency = args.next_u64()?;
+    args.done()?;
+
+    if ops == 0 {
+        return Err(RedisError::Str("ops must be positive"));
+    }
+    if concurrency == 0 || concurrency > MAX_CONCURRENCY {
+        return Err(RedisError::String(format!("concurrency must be between 1 and {}", MAX_CONCURRENCY)));
+    }
+
+    // Workers take the module lock per operation, so the event loop keeps serving between ops
+    let blocked_client = ctx.block_client();
+    thread::spawn(move || {
+        let thread_ctx = ThreadSafeContext::with_blocked_client(blocked_client);
+        thread_ctx.reply(run(ops, keysize, valsize, concurrency));
+    });
+
+    Ok(RedisValue::NoReply)
+}
@@ -0,0 +1,195 @@
+use std::collections::HashMap;
+use std::os::raw::c_void;
+use std::sync::RwLock;
+use redis_module::{raw, Context, NextArg, RedisError, RedisResult, RedisString, RedisValue, Status};
+use session_core::events;
+
+use crate::retry::{self, BridgeOp};
+use crate::{changes, init_sessions, unlink_user_key, webhooks, writable_session, Session, SessionExt};
+
+// What happens to a bound session when its client disconnects
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DisconnectAction {
+    Delete,
+    Idle,
+}
+
+// Index of client id -> bound session ids, so a disconnect doesn't scan every session
+static mut CLIENT_BINDINGS: Option<RwLock<HashMap<u64, HashMap<String, DisconnectAction>>>> = None;
+
+// Initialize the client bindings index
+fn init_bindings() -> &'static RwLock<HashMap<u64, HashMap<String, DisconnectAction>>> {
+    unsafe {
+        if CLIENT_BINDINGS.is_none() {
+            CLIENT_BINDINGS = Some(RwLock::new(HashMap::new()));
+        }
+        CLIENT_BINDINGS.as_ref().unwrap()
+    }
+}
+
+// Drop a session from the bindings index (called when the session is deleted)
+pub fn forget(session: &Session) {
+    if let Some(client_id) = session.bound_client {
+        if let Ok(mut bindings) = init_bindings().write() {
+            if let Some(sessions) = bindings.get_mut(&client_id) {
+                sessions.remove(&session.id);
+                if sessions.is_empty() {
+                    bindings.remove(&client_id);
+                }
+            }
+        }
+    }
+}
+
+// Bind a session to the calling client: SESSION.BIND session_id [ON_DISCONNECT DELETE|IDLE]
+#[tracing::instrument(name = "session.bind", skip_all)]
+pub fn bind_session(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    if args.len() != 2 && args.len() != 4 {
+        return Err(RedisError::WrongArity);
+    }
+
+    let mut args = args.into_iter().skip(1);
+    let session_id = args.next_string()?;
+
+    let mut action = DisconnectAction::Idle;
+    if let Ok(option) = args.next_string() {
+        if !option.eq_ignore_ascii_case("ON_DISCONNECT") {
+            return Err(RedisError::String(format!("Unknown option: {}", option)));
+        }
+        let mode = args.next_string()?;
+        action = if mode.eq_ignore_ascii_case("DELETE") {
+            DisconnectAction::Delete
+        } else if mode.eq_ignore_ascii_case("IDLE") {
+            DisconnectAction::Idle
+        } else {
+            return Err(RedisError::String(format!("Unknown ON_DISCONNECT mode: {}", mode)));
+        };
+    }
+
+    let client_id = ctx.get_client_id();
+
+    let sessions = init_sessions();
+    let mut sessions_map = sessions.write().map_err(|_| {
+        RedisError::String("Failed to acquire write lock".to_string())
+    })?;
+
+    let session = writable_session(&mut sessions_map, &session_id)?;
+
+    // Rebinding moves the session off its previous client
+    forget(session);
+    session.bound_client = Some(client_id);
+    session.idle = false;
+    session.mark_changed();
+
+    let mut bindings = init_bindings().write().map_err(|_| {
+        RedisError::String("Failed to acquire write lock".to_string())
+    })?;
+    bindings.entry(client_id).or_default().insert(session_id, action);
+
+    Ok(RedisValue::SimpleStringStatic("OK"))
+}
+
+// Remove a session's client binding
+#[tracing::instrument(name = "session.unbind", skip_all)]
+pub fn unbind_session(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let session_id = args.next_string()?;
+
+    let sessions = init_sessions();
+    let mut sessions_map = sessions.write().map_err(|_| {
+        RedisError::String("Failed to acquire write lock".to_string())
+    })?;
+
+    match sessions_map.get_mut(&session_id) {
+        Some(session) if session.bound_client.is_some() => {
+            forget(session);
+            session.bound_client = None;
+            session.mark_changed();
+            Ok(RedisValue::Integer(1))
+        },
+        Some(_) => Ok(RedisValue::Integer(0)),
+        None => Err(RedisError::String(format!("Session not found: {}", session_id))),
+    }
+}
+
+// Delete or idle every session bound to a client that just disconnected
+fn reap_client_sessions(ctx: &Context, client_id: u64) {
+    let bound = match init_bindings().write() {
+        Ok(mut bindings) => bindings.remove(&client_id),
+        Err(_) => return,
+    };
+    let bound = match bound {
+        Some(bound) => bound,
+        None => return,
+    };
+
+    let mut sessions_map = match init_sessions().write() {
+        Ok(map) => map,
+        Err(_) => return,
+    };
+
+    for (session_id, action) in bound {
+        let still_bound = sessions_map.get(&session_id)
+            .is_some_and(|session| session.bound_client == Some(client_id));
+        if !still_bound {
+            continue;
+        }
+
+        match action {
+            DisconnectAction::Delete => {
+                if let Some(session) = sessions_map.remove(&session_id) {
+                    changes::record_deletion(&session_id);
+                    webhooks::emit(events::DELETED, &session_id, &session.user_key);
+                    if let Err(err) = unlink_user_key(ctx, &session.user_key) {
+                        retry::enqueue(ctx, &session_id, BridgeOp::Del { key: session.user_key.clone() }, err);
+                    }
+                }
+            },
+            DisconnectAction::Idle => {
+                if let Some(session) = sessions_map.get_mut(&session_id) {
+                    session.bound_client = None;
+                    session.idle = true;
+                    session.mark_changed();
+                }
+            },
+        }
+    }
+}
+
+// Server event callback for client connect/disconnect
+unsafe extern "C" fn on_client_change(
+    ctx: *mut raw::RedisModuleCtx,
+    _eid: raw::RedisModuleEvent,
+    subevent: u64,
+    data: *mut c_void,
+) {
+    if subevent != raw::REDISMODULE_SUBEVENT_CLIENT_CHANGE_DISCONNECTED as u64 || data.is_null() {
+        return;
+    }
+
+    let client_info = &*(data as *const raw::RedisModuleClientInfoV1);
+    let ctx = Context::new(ctx);
+    reap_client_sessions(&ctx, client_info.id);
+}
+
+// Subscribe to client change events at module load
+pub fn subscribe_client_events(ctx: &Context) -> Status {
+    let event = raw::RedisModuleEvent {
+        id: raw::REDISMODULE_EVENT_CLIENT_CHANGE as u64,
+        dataver: 1,
+    };
+
+    let res = unsafe {
+        match raw::RedisModule_SubscribeToServerEvent {
+            Some(subscribe) => subscribe(ctx.ctx, event, Some(on_client_change)),
+            None => raw::REDISMODULE_ERR as i32,
+        }
+    };
+
+    if res == raw::REDISMODULE_OK as i32 {
+        Status::Ok
+    } else {
+        ctx.log_warning("Failed to subscribe to client change events");
+        Status::Err
+    }
+}
@@ -0,0 +1,891 @@
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock, RwLock};
+use std::time::{Duration, Instant};
+use custom_hashmap_client::{Client, Ring, RingOp, RingReply};
+use redis_module::{Context, NextArg, RedisError, RedisResult, RedisString, RedisValue};
+use session_core::capi::ABI_VERSION;
+
+use crate::http::Metric;
+use crate::timers::PROMOTE;
+use crate::watchdog;
+
+// Client for the custom hashmap's C API, loaded on first use. FFI calls
+// reach it from the bridge worker thread as well as the main thread.
+static CLIENT: OnceLock<Client> = OnceLock::new();
+
+// Bridge to a hashmap module built against a different C API version anyway
+static ALLOW_ABI_MISMATCH: AtomicBool = AtomicBool::new(false);
+
+// C API version of a loaded hashmap module that was refused, 0 if none was
+static REFUSED_ABI: AtomicU32 = AtomicU32::new(0);
+
+pub fn set_allow_abi_mismatch(allow: bool) {
+    ALLOW_ABI_MISMATCH.store(allow, Ordering::Relaxed);
+}
+
+pub fn allow_abi_mismatch() -> bool {
+    ALLOW_ABI_MISMATCH.load(Ordering::Relaxed)
+}
+
+fn abi_mismatch(abi_version: u32) -> RedisError {
+    RedisError::String(format!(
+        "custom hashmap C API version {} differs from this module's {}; load with allow_abi_mismatch=yes to bridge anyway",
+        abi_version, ABI_VERSION,
+    ))
+}
+
+// Load the custom hashmap library (retried on every call until it succeeds).
+// A module reporting another C API version is refused for good, so its calls
+// go through Redis commands instead; builds too old to report one are used.
+fn client() -> Result<&'static Client, RedisError> {
+    match REFUSED_ABI.load(Ordering::Relaxed) {
+        0 => {},
+        refused => return Err(abi_mismatch(refused)),
+    }
+    if let Some(client) = CLIENT.get() {
+        return Ok(client);
+    }
+
+    // If we can't load the library, the router falls back to Redis commands
+    let client = Client::load().map_err(client_error)?;
+    match client.abi_version() {
+        Some(abi_version) if abi_version != ABI_VERSION && !allow_abi_mismatch() => {
+            REFUSED_ABI.store(abi_version, Ordering::Relaxed);
+            Err(abi_mismatch(abi_version))
+        },
+        // Another thread may have loaded it meanwhile; either copy will do
+        _ => Ok(CLIENT.get_or_init(|| client)),
+    }
+}
+
+// C API version of the loaded hashmap module, None if it isn't loaded or
+// doesn't report one
+pub fn hashmap_abi_version() -> Option<u32> {
+    match REFUSED_ABI.load(Ordering::Relaxed) {
+        0 => client().ok()?.abi_version(),
+        refused => Some(refused),
+    }
+}
+
+fn client_error(err: custom_hashmap_client::Error) -> RedisError {
+    RedisError::String(err.to_string())
+}
+
+// The name this module loads under, which CUSTOM.PROTECT owner:<module> refers to
+const MODULE_NAME: &str = session_core::SESSION_MANAGER_MODULE;
+
+// Run a hashmap write as this module, so it is accepted under prefixes the
+// custom hashmap protects with `owner:session_manager`. Without the C API the
+// write goes out unidentified.
+fn as_owner<T>(write: impl FnOnce() -> Result<T, RedisError>) -> Result<T, RedisError> {
+    match client() {
+        Ok(client) => client.acting_as(MODULE_NAME, write).map_err(client_error)?,
+        Err(_) => write(),
+    }
+}
+
+// Ring transport to the custom hashmap; while one is attached the FFI path
+// sends its operations through it instead of making one C call each
+static mut RING: Option<RwLock<Option<Ring<'static>>>> = None;
+
+// Initialize the ring slot
+fn init_ring() -> &'static RwLock<Option<Ring<'static>>> {
+    unsafe {
+        if RING.is_none() {
+            RING = Some(RwLock::new(None));
+        }
+        RING.as_ref().unwrap()
+    }
+}
+
+// Slots per direction of a ring attached without an explicit capacity
+const DEFAULT_RING_CAPACITY: u32 = 256;
+
+// Largest ring SESSION.BRIDGE RING ON and SESSION.BENCH BRIDGE accept
+pub const MAX_RING_CAPACITY: u32 = 65_536;
+
+// Send operations through the attached ring, or None if there is none
+fn ring_submit(ops: &[RingOp]) -> Option<Result<Vec<RingReply>, RedisError>> {
+    let ring = init_ring().read().ok()?;
+    let replies = ring.as_ref()?.submit(ops).map_err(client_error);
+    Some(replies.and_then(|replies| replies.into_iter().map(|reply| reply.map_err(client_error)).collect()))
+}
+
+fn unexpected_ring_reply(reply: Option<RingReply>) -> RedisError {
+    RedisError::String(format!("Unexpected ring reply: {:?}", reply))
+}
+
+// Get a value from the custom hashmap via FFI
+#[tracing::instrument(level = "debug", skip_all)]
+fn ffi_get(key: &str) -> Result<Option<String>, RedisError> {
+    let key = key.to_string();
+    watchdog::run_ffi("get", move || match ring_submit(&[RingOp::Get(&key)]) {
+        Some(replies) => match replies?.pop() {
+            Some(RingReply::Value(value)) => Ok(value),
+            other => Err(unexpected_ring_reply(other)),
+        },
+        None => client()?.get(&key).map_err(client_error),
+    })
+}
+
+// Get several values from the custom hashmap in one FFI call
+#[tracing::instrument(level = "debug", skip_all)]
+fn ffi_mget(keys: &[&str]) -> Result<Vec<Option<String>>, RedisError> {
+    let keys: Vec<String> = keys.iter().map(|key| key.to_string()).collect();
+    watchdog::run_ffi("mget", move || {
+        let keys: Vec<&str> = keys.iter().map(String::as_str).collect();
+        let ops: Vec<RingOp> = keys.iter().map(|key| RingOp::Get(key)).collect();
+        match ring_submit(&ops) {
+            Some(replies) => replies?.into_iter()
+                .map(|reply| match reply {
+                    RingReply::Value(value) => Ok(value),
+                    other => Err(unexpected_ring_reply(Some(other))),
+                })
+                .collect(),
+            None => client()?.mget(&keys).map_err(client_error),
+        }
+    })
+}
+
+// Set a value in the custom hashmap via FFI
+#[tracing::instrument(level = "debug", skip_all)]
+fn ffi_set(key: &str, value: &str) -> Result<(), RedisError> {
+    let (key, value) = (key.to_string(), value.to_string());
+    watchdog::run_ffi("set", move || match ring_submit(&[RingOp::Set(&key, &value)]) {
+        Some(replies) => match replies?.pop() {
+            Some(RingReply::Stored) => Ok(()),
+            other => Err(unexpected_ring_reply(other)),
+        },
+        None => as_owner(|| client()?.set(&key, &value).map_err(client_error)),
+    })
+}
+
+// Delete a key from the custom hashmap via FFI, returning whether it existed
+#[tracing::instrument(level = "debug", skip_all)]
+fn ffi_del(key: &str) -> Result<bool, RedisError> {
+    let key = key.to_string();
+    watchdog::run_ffi("del", move || match ring_submit(&[RingOp::Del(&key)]) {
+        Some(replies) => match replies?.pop() {
+            Some(RingReply::Removed(removed)) => Ok(removed),
+            other => Err(unexpected_ring_reply(other)),
+        },
+        None => as_owner(|| client()?.del(&key).map_err(client_error)),
+    })
+}
+
+// Time `ops` set/get/del operations on throwaway keys through direct C calls,
+// then through a ring of `batch` slots, each batch holding the sets, gets and
+// deletes of batch / 3 keys. Returns both elapsed times.
+pub fn compare_transports(key_prefix: &str, ops: u64, batch: u32) -> Result<(Duration, Duration), RedisError> {
+    let client = client()?;
+    let cycles = ops.div_ceil(3);
+    let key = |cycle: u64| format!("{}{}", key_prefix, cycle);
+
+    let started = Instant::now();
+    client.acting_as(MODULE_NAME, || -> Result<(), RedisError> {
+        for cycle in 0..cycles {
+            let key = key(cycle);
+            client.set(&key, &key).map_err(client_error)?;
+            client.get(&key).map_err(client_error)?;
+            client.del(&key).map_err(client_error)?;
+        }
+        Ok(())
+    }).map_err(client_error)??;
+    let direct = started.elapsed();
+
+    let ring = client.ring(batch, Some(MODULE_NAME)).map_err(client_error)?;
+    let keys_per_batch = (batch as u64 / 3).max(1);
+    let started = Instant::now();
+    for first in (0..cycles).step_by(keys_per_batch as usize) {
+        let keys: Vec<String> = (first..(first + keys_per_batch).min(cycles)).map(key).collect();
+        let ops: Vec<RingOp> = keys.iter().map(|key| RingOp::Set(key, key))
+            .chain(keys.iter().map(|key| RingOp::Get(key)))
+            .chain(keys.iter().map(|key| RingOp::Del(key)))
+            .collect();
+        for reply in ring.submit(&ops).map_err(client_error)? {
+            reply.map_err(client_error)?;
+        }
+    }
+    Ok((direct, started.elapsed()))
+}
+
+// Names the command path calls, changeable for deployments that rename the custom.* commands
+struct FallbackCommands {
+    get: String,
+    set: String,
+    del: String,
+}
+
+static mut FALLBACK_COMMANDS: Option<RwLock<FallbackCommands>> = None;
+
+// Initialize the fallback command names
+fn init_fallback_commands() -> &'static RwLock<FallbackCommands> {
+    unsafe {
+        if FALLBACK_COMMANDS.is_none() {
+            FALLBACK_COMMANDS = Some(RwLock::new(FallbackCommands {
+                get: "custom.get".to_string(),
+                set: "custom.set".to_string(),
+                del: "custom.del".to_string(),
+            }));
+        }
+        FALLBACK_COMMANDS.as_ref().unwrap()
+    }
+}
+
+// Call the command path's commands by other names; None keeps the current name
+pub fn set_fallback_commands(get: Option<String>, set: Option<String>, del: Option<String>) {
+    if let Ok(mut commands) = init_fallback_commands().write() {
+        if let Some(get) = get {
+            commands.get = get;
+        }
+        if let Some(set) = set {
+            commands.set = set;
+        }
+        if let Some(del) = del {
+            commands.del = del;
+        }
+    }
+}
+
+// The fallback commands as (operation, command name)
+fn fallback_commands() -> Result<[(&'static str, String); 3], RedisError> {
+    let commands = init_fallback_commands().read().map_err(|_| {
+        RedisError::String("Failed to acquire read lock".to_string())
+    })?;
+    Ok([("get", commands.get.clone()), ("set", commands.set.clone()), ("del", commands.del.clone())])
+}
+
+// Whether the server knows a command by this name, per COMMAND INFO
+fn command_available(ctx: &Context, name: &str) -> bool {
+    match ctx.call("COMMAND", &["INFO", name]) {
+        Ok(RedisValue::Array(info)) => info.first().is_some_and(|info| !matches!(info, RedisValue::Null)),
+        _ => false,
+    }
+}
+
+// The fallback commands with whether each is available, for SESSION.BRIDGE STATUS
+fn fallback_status(ctx: &Context) -> Result<Vec<RedisValue>, RedisError> {
+    Ok(fallback_commands()?.into_iter()
+        .map(|(op, name)| {
+            let available = command_available(ctx, &name);
+            RedisValue::Array(vec![
+                RedisValue::SimpleStringStatic(op),
+                RedisValue::BulkString(name),
+                RedisValue::Integer(if available { 1 } else { 0 }),
+            ])
+        })
+        .collect())
+}
+
+// Get a value through the fallback get command (custom.get)
+fn call_get(ctx: &Context, key: &str) -> Result<Option<String>, RedisError> {
+    let [(_, name), ..] = fallback_commands()?;
+    match ctx.call(&name, &[key]) {
+        Ok(RedisValue::BulkString(value)) | Ok(RedisValue::SimpleString(value)) => Ok(Some(value)),
+        Ok(RedisValue::StringBuffer(value)) => Ok(Some(String::from_utf8_lossy(&value).to_string())),
+        Ok(RedisValue::Null) => Ok(None),
+        Ok(other) => Err(RedisError::String(format!("Unexpected {} reply: {:?}", name, other))),
+        Err(err) => Err(RedisError::String(format!("Failed to call {}: {}", name, err))),
+    }
+}
+
+// Set a value through the fallback set command (custom.set)
+fn call_set(ctx: &Context, key: &str, value: &str) -> Result<(), RedisError> {
+    let [_, (_, name), _] = fallback_commands()?;
+    as_owner(|| {
+        ctx.call(&name, &[key, value])
+            .map(|_| ())
+            .map_err(|err| RedisError::String(format!("Failed to call {}: {}", name, err)))
+    })
+}
+
+// Delete a key through the fallback del command (custom.del)
+fn call_del(ctx: &Context, key: &str) -> Result<bool, RedisError> {
+    let [.., (_, name)] = fallback_commands()?;
+    as_owner(|| match ctx.call(&name, &[key]) {
+        Ok(RedisValue::Integer(removed)) => Ok(removed > 0),
+        Ok(_) => Ok(false),
+        Err(err) => Err(RedisError::String(format!("Failed to call {}: {}", name, err))),
+    })
+}
+
+// Native Redis hash holding user key -> session id mappings while the hashmap module is unavailable
+const NATIVE_KEY: &str = "session:keymap";
+
+// Set while mappings live in the native hash; cleared once they're all promoted
+static NATIVE_ACTIVE: AtomicBool = AtomicBool::new(false);
+static PROMOTIONS: AtomicU64 = AtomicU64::new(0);
+
+fn reply_string(reply: RedisValue) -> Option<String> {
+    match reply {
+        RedisValue::BulkString(value) | RedisValue::SimpleString(value) => Some(value),
+        RedisValue::StringBuffer(value) => Some(String::from_utf8_lossy(&value).to_string()),
+        _ => None,
+    }
+}
+
+// Get a mapping from the native hash
+fn native_get(ctx: &Context, key: &str) -> Result<Option<String>, RedisError> {
+    ctx.call("HGET", &[NATIVE_KEY, key])
+        .map(reply_string)
+        .map_err(|err| RedisError::String(format!("Failed to read native key mapping: {}", err)))
+}
+
+// Store a mapping in the native hash
+fn native_set(ctx: &Context, key: &str, value: &str) -> Result<(), RedisError> {
+    ctx.call("HSET", &[NATIVE_KEY, key, value])
+        .map(|_| ())
+        .map_err(|err| RedisError::String(format!("Failed to write native key mapping: {}", err)))?;
+    NATIVE_ACTIVE.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+// Remove a mapping from the native hash
+fn native_del(ctx: &Context, key: &str) -> Result<bool, RedisError> {
+    match ctx.call("HDEL", &[NATIVE_KEY, key]) {
+        Ok(RedisValue::Integer(removed)) => Ok(removed > 0),
+        Ok(_) => Ok(false),
+        Err(err) => Err(RedisError::String(format!("Failed to delete native key mapping: {}", err))),
+    }
+}
+
+// Move native mappings into the hashmap once it's reachable again; returns
+// how many were promoted
+fn promote(ctx: &Context) -> usize {
+    let entries = match ctx.call("HGETALL", &[NATIVE_KEY]) {
+        Ok(RedisValue::Array(entries)) => entries,
+        _ => return 0,
+    };
+
+    let mut promoted = 0;
+    let mut fields = entries.into_iter().map(reply_string);
+    while let (Some(Some(key)), Some(Some(session_id))) = (fields.next(), fields.next()) {
+        // Stop at the first failure: the hashmap is still unavailable
+        if dispatch_write("set", || ffi_set(&key, &session_id), || call_set(ctx, &key, &session_id)).is_err() {
+            return promoted;
+        }
+        let _ = native_del(ctx, &key);
+        PROMOTIONS.fetch_add(1, Ordering::Relaxed);
+        promoted += 1;
+    }
+
+    if let Ok(RedisValue::Integer(0)) = ctx.call("EXISTS", &[NATIVE_KEY]) {
+        NATIVE_ACTIVE.store(false, Ordering::Relaxed);
+        ctx.log_notice("Custom hashmap available again; all native key mappings promoted");
+    }
+    promoted
+}
+
+fn promote_tick(ctx: &Context, _data: ()) {
+    let promoted = if NATIVE_ACTIVE.load(Ordering::Relaxed) { promote(ctx) } else { 0 };
+    ctx.create_timer(PROMOTE.next(promoted, 0), promote_tick, ());
+}
+
+// Pick up mappings left in the native hash (e.g. loaded from disk) and start
+// promoting them. Fallback commands missing now are only warned about: the
+// custom hashmap module may simply be loaded after this one.
+pub fn start(ctx: &Context) {
+    if let Ok(commands) = fallback_commands() {
+        for (op, name) in commands {
+            if !command_available(ctx, &name) {
+                ctx.log_warning(&format!(
+                    "Bridge fallback command for {} is not available: {}; the command path fails until it is", op, name,
+                ));
+            }
+        }
+    }
+    if let Ok(RedisValue::Integer(exists)) = ctx.call("EXISTS", &[NATIVE_KEY]) {
+        NATIVE_ACTIVE.store(exists > 0, Ordering::Relaxed);
+    }
+    ctx.create_timer(PROMOTE.current(), promote_tick, ());
+}
+
+// Smoothing factor for the latency and failure averages
+const EWMA_ALPHA: f64 = 0.2;
+// Latency penalty (microseconds) charged for a 100% failure rate
+const FAILURE_PENALTY_US: f64 = 10_000.0;
+// The other path must score this much better before we switch (hysteresis)
+const SWITCH_RATIO: f64 = 1.5;
+// Minimum samples on a path before its score is trusted
+const MIN_SAMPLES: u64 = 8;
+// In AUTO mode, every Nth operation probes the non-preferred path to keep its stats fresh
+const PROBE_INTERVAL: u64 = 64;
+
+// The two ways of reaching the custom hashmap
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BridgePath {
+    Ffi,
+    Call,
+}
+
+impl BridgePath {
+    fn name(&self) -> &'static str {
+        match self {
+            BridgePath::Ffi => "ffi",
+            BridgePath::Call => "call",
+        }
+    }
+
+    fn other(&self) -> BridgePath {
+        match self {
+            BridgePath::Ffi => BridgePath::Call,
+            BridgePath::Call => BridgePath::Ffi,
+        }
+    }
+}
+
+// Recent health of one path
+#[derive(Debug, Default)]
+struct PathStats {
+    latency_us: f64,
+    failure_rate: f64,
+    samples: u64,
+    failures: u64,
+}
+
+impl PathStats {
+    fn record(&mut self, elapsed_us: f64, failed: bool) {
+        let failure = if failed { 1.0 } else { 0.0 };
+        if self.samples == 0 {
+            self.latency_us = elapsed_us;
+            self.failure_rate = failure;
+        } else {
+            self.latency_us += EWMA_ALPHA * (elapsed_us - self.latency_us);
+            self.failure_rate += EWMA_ALPHA * (failure - self.failure_rate);
+        }
+        self.samples += 1;
+        if failed {
+            self.failures += 1;
+        }
+    }
+
+    // Lower is better
+    fn score(&self) -> f64 {
+        self.latency_us + self.failure_rate * FAILURE_PENALTY_US
+    }
+}
+
+// Routing state shared by all bridge operations
+struct BridgeState {
+    ffi: PathStats,
+    call: PathStats,
+    preferred: BridgePath,
+    // Set by SESSION.BRIDGE MODE FFI|CALL; None means AUTO
+    forced: Option<BridgePath>,
+    operations: u64,
+    switches: u64,
+}
+
+impl BridgeState {
+    fn stats_mut(&mut self, path: BridgePath) -> &mut PathStats {
+        match path {
+            BridgePath::Ffi => &mut self.ffi,
+            BridgePath::Call => &mut self.call,
+        }
+    }
+
+    fn stats(&self, path: BridgePath) -> &PathStats {
+        match path {
+            BridgePath::Ffi => &self.ffi,
+            BridgePath::Call => &self.call,
+        }
+    }
+
+    // Pick the path for the next operation
+    fn choose(&mut self) -> BridgePath {
+        self.operations += 1;
+        if let Some(path) = self.forced {
+            return path;
+        }
+        if self.operations.is_multiple_of(PROBE_INTERVAL) {
+            return self.preferred.other();
+        }
+        self.preferred
+    }
+
+    // Record an outcome and re-evaluate the preferred path
+    fn record(&mut self, path: BridgePath, elapsed_us: f64, failed: bool) {
+        self.stats_mut(path).record(elapsed_us, failed);
+
+        let current = self.stats(self.preferred);
+        let candidate = self.stats(self.preferred.other());
+        if candidate.samples >= MIN_SAMPLES && candidate.score() * SWITCH_RATIO < current.score() {
+            self.preferred = self.preferred.other();
+            self.switches += 1;
+        }
+    }
+}
+
+static mut BRIDGE_STATE: Option<Mutex<BridgeState>> = None;
+
+// Initialize the bridge routing state
+fn init_bridge_state() -> &'static Mutex<BridgeState> {
+    unsafe {
+        if BRIDGE_STATE.is_none() {
+            BRIDGE_STATE = Some(Mutex::new(BridgeState {
+                ffi: PathStats::default(),
+                call: PathStats::default(),
+                preferred: BridgePath::Ffi,
+                forced: None,
+                operations: 0,
+                switches: 0,
+            }));
+        }
+        BRIDGE_STATE.as_ref().unwrap()
+    }
+}
+
+// Run one path, timing it and feeding the outcome back into the router
+fn run_path<T>(name: &'static str, path: BridgePath, op: impl FnOnce() -> Result<T, RedisError>) -> Result<T, RedisError> {
+    let start = Instant::now();
+    let result = op();
+    let elapsed = start.elapsed();
+    let elapsed_us = elapsed.as_secs_f64() * 1_000_000.0;
+
+    // FFI calls enforce the deadline themselves; commands can only be accounted for
+    if path == BridgePath::Call {
+        watchdog::check_call(name, elapsed);
+    }
+
+    if let Ok(mut state) = init_bridge_state().lock() {
+        state.record(path, elapsed_us, result.is_err());
+    }
+
+    result
+}
+
+fn first_path() -> BridgePath {
+    match init_bridge_state().lock() {
+        Ok(mut state) => state.choose(),
+        Err(_) => BridgePath::Ffi,
+    }
+}
+
+// Route an operation to the healthier path, falling back to the other one on error
+fn dispatch<T>(
+    name: &'static str,
+    ffi: impl FnOnce() -> Result<T, RedisError>,
+    call: impl FnOnce() -> Result<T, RedisError>,
+) -> Result<T, RedisError> {
+    match first_path() {
+        BridgePath::Ffi => run_path(name, BridgePath::Ffi, ffi).or_else(|_| run_path(name, BridgePath::Call, call)),
+        BridgePath::Call => run_path(name, BridgePath::Call, call).or_else(|_| run_path(name, BridgePath::Ffi, ffi)),
+    }
+}
+
+// A write that timed out is still running on the FFI worker. Once it
+// finishes it would overwrite anything written since, so until then writes
+// fail rather than going out another way.
+fn write_stuck() -> bool {
+    matches!(watchdog::stuck_op(), Some("set" | "del"))
+}
+
+// Route a write like `dispatch`, except that an FFI write that timed out is
+// not run again on commands, and no write is made while one is stuck
+fn dispatch_write<T>(
+    name: &'static str,
+    ffi: impl FnOnce() -> Result<T, RedisError>,
+    call: impl FnOnce() -> Result<T, RedisError>,
+) -> Result<T, RedisError> {
+    if write_stuck() {
+        return Err(RedisError::String(format!("Bridge {} refused: a timed-out write is still running", name)));
+    }
+
+    match first_path() {
+        BridgePath::Ffi => match run_path(name, BridgePath::Ffi, ffi) {
+            Err(err) if write_stuck() => Err(err),
+            result => result.or_else(|_| run_path(name, BridgePath::Call, call)),
+        },
+        BridgePath::Call => run_path(name, BridgePath::Call, call).or_else(|_| run_path(name, BridgePath::Ffi, ffi)),
+    }
+}
+
+// Path the router currently sends operations to first
+pub fn preferred_path() -> &'static str {
+    match init_bridge_state().lock() {
+        Ok(state) => state.forced.unwrap_or(state.preferred).name(),
+        Err(_) => BridgePath::Ffi.name(),
+    }
+}
+
+// How the next bridge operation would be routed, for SESSION.EXPLAIN; the
+// router's state is only read, never advanced
+pub fn explain_route() -> Vec<RedisValue> {
+    let (first, probe) = match init_bridge_state().lock() {
+        Ok(state) => match state.forced {
+            Some(path) => (path, false),
+            None if (state.operations + 1).is_multiple_of(PROBE_INTERVAL) => (state.preferred.other(), true),
+            None => (state.preferred, false),
+        },
+        Err(_) => (BridgePath::Ffi, false),
+    };
+    let ring = init_ring().read().is_ok_and(|ring| ring.is_some());
+    let ffi_transport = match (ring, watchdog::timeout_ms()) {
+        (true, 0) => "ring",
+        (false, 0) => "direct",
+        (true, _) => "ring on worker thread",
+        (false, _) => "direct on worker thread",
+    };
+
+    vec![
+        RedisValue::SimpleStringStatic("bridge_path"),
+        RedisValue::SimpleStringStatic(first.name()),
+        RedisValue::SimpleStringStatic("bridge_fallback"),
+        RedisValue::SimpleStringStatic(first.other().name()),
+        RedisValue::SimpleStringStatic("bridge_probe"),
+        RedisValue::Integer(if probe { 1 } else { 0 }),
+        RedisValue::SimpleStringStatic("ffi_transport"),
+        RedisValue::SimpleStringStatic(ffi_transport),
+        RedisValue::SimpleStringStatic("ffi_deadline_ms"),
+        RedisValue::Integer(watchdog::timeout_ms() as i64),
+        // A stuck worker makes every FFI attempt fail straight over to the fallback
+        RedisValue::SimpleStringStatic("ffi_worker_stuck"),
+        RedisValue::Integer(if watchdog::worker_stuck() { 1 } else { 0 }),
+        RedisValue::SimpleStringStatic("native_fallback"),
+        RedisValue::Integer(if NATIVE_ACTIVE.load(Ordering::Relaxed) { 1 } else { 0 }),
+    ]
+}
+
+// Look up a key in the custom hashmap, or in the native hash while it holds mappings
+pub fn get(ctx: &Context, key: &str) -> Result<Option<String>, RedisError> {
+    let result = dispatch("get", || ffi_get(key), || call_get(ctx, key));
+    if !NATIVE_ACTIVE.load(Ordering::Relaxed) {
+        return result;
+    }
+    match result {
+        Ok(Some(value)) => Ok(Some(value)),
+        // Not promoted yet, or the hashmap is gone
+        _ => native_get(ctx, key),
+    }
+}
+
+// Look up several keys at once; values come back in the order of `keys`
+pub fn mget(ctx: &Context, keys: &[&str]) -> Result<Vec<Option<String>>, RedisError> {
+    let mut values = dispatch(
+        "mget",
+        || ffi_mget(keys),
+        || keys.iter().map(|key| call_get(ctx, key)).collect(),
+    );
+    if !NATIVE_ACTIVE.load(Ordering::Relaxed) {
+        return values;
+    }
+    if let Ok(found) = values.as_mut() {
+        for (key, value) in keys.iter().zip(found.iter_mut()) {
+            if value.is_none() {
+                *value = native_get(ctx, key)?;
+            }
+        }
+        return values;
+    }
+    keys.iter().map(|key| native_get(ctx, key)).collect()
+}
+
+// Store a key in the custom hashmap, falling back to the native hash if neither path works
+pub fn set(ctx: &Context, key: &str, value: &str) -> Result<(), RedisError> {
+    match dispatch_write("set", || ffi_set(key, value), || call_set(ctx, key, value)) {
+        Ok(()) => Ok(()),
+        // The mapping may still land in the hashmap once the stuck write finishes
+        Err(err) if write_stuck() => Err(err),
+        Err(err) => {
+            ctx.log_warning(&format!("Custom hashmap unavailable, storing mapping natively: {}", err));
+            native_set(ctx, key, value)
+        },
+    }
+}
+
+// Remove a key from the custom hashmap (and the native hash), returning whether it existed
+pub fn del(ctx: &Context, key: &str) -> Result<bool, RedisError> {
+    let native = if NATIVE_ACTIVE.load(Ordering::Relaxed) {
+        native_del(ctx, key)?
+    } else {
+        false
+    };
+    match dispatch_write("del", || ffi_del(key), || call_del(ctx, key)) {
+        Ok(removed) => Ok(removed || native),
+        Err(err) if write_stuck() => Err(err),
+        Err(_) if NATIVE_ACTIVE.load(Ordering::Relaxed) => Ok(native),
+        Err(err) => Err(err),
+    }
+}
+
+// Write, read back and delete a throwaway key through one path, bypassing
+// path selection so each path is checked on its own
+fn round_trip(
+    key: &str,
+    set: impl FnOnce(&str) -> Result<(), RedisError>,
+    get: impl FnOnce() -> Result<Option<String>, RedisError>,
+    del: impl FnOnce() -> Result<bool, RedisError>,
+) -> Result<(), RedisError> {
+    set(key)?;
+    let read = get();
+    let removed = del()?;
+    match read? {
+        Some(value) if value == key && removed => Ok(()),
+        Some(value) if value != key => Err(RedisError::String(format!("Read back {:?} instead of {:?}", value, key))),
+        Some(_) => Err(RedisError::Str("Key was gone before it was deleted")),
+        None => Err(RedisError::Str("Key was not found after it was set")),
+    }
+}
+
+// SESSION.SELFTEST check of the FFI path
+pub fn check_ffi(key: &str) -> Result<(), RedisError> {
+    round_trip(key, |value| ffi_set(key, value), || ffi_get(key), || ffi_del(key))
+}
+
+// SESSION.SELFTEST check of the command path
+pub fn check_command(ctx: &Context, key: &str) -> Result<(), RedisError> {
+    round_trip(key, |value| call_set(ctx, key, value), || call_get(ctx, key), || call_del(ctx, key))
+}
+
+// Routing metrics for the HTTP status endpoint
+pub fn metrics() -> Vec<Metric> {
+    let mut metrics = vec![
+        Metric::gauge(
+            "session_manager_bridge_native_fallback",
+            "Whether user key mappings are held in the native fallback hash",
+            if NATIVE_ACTIVE.load(Ordering::Relaxed) { 1.0 } else { 0.0 },
+        ),
+        Metric::counter(
+            "session_manager_bridge_promotions_total",
+            "Native mappings moved back into the custom hashmap",
+            PROMOTIONS.load(Ordering::Relaxed),
+        ),
+    ];
+    if let Ok(state) = init_bridge_state().lock() {
+        metrics.push(Metric::counter("session_manager_bridge_operations_total", "Bridge operations routed", state.operations));
+        metrics.push(Metric::counter("session_manager_bridge_switches_total", "Changes of the preferred bridge path", state.switches));
+        metrics.push(Metric::gauge("session_manager_bridge_ffi_latency_us", "Smoothed FFI path latency", state.ffi.latency_us));
+        metrics.push(Metric::gauge("session_manager_bridge_ffi_failure_rate", "Smoothed FFI path failure rate", state.ffi.failure_rate));
+        metrics.push(Metric::counter("session_manager_bridge_ffi_failures_total", "Failed FFI path operations", state.ffi.failures));
+        metrics.push(Metric::gauge("session_manager_bridge_call_latency_us", "Smoothed command path latency", state.call.latency_us));
+        metrics.push(Metric::gauge("session_manager_bridge_call_failure_rate", "Smoothed command path failure rate", state.call.failure_rate));
+        metrics.push(Metric::counter("session_manager_bridge_call_failures_total", "Failed command path operations", state.call.failures));
+    }
+    metrics
+}
+
+// Inspect or override bridge routing:
+// SESSION.BRIDGE STATUS
+// SESSION.BRIDGE MODE AUTO|FFI|CALL
+// SESSION.BRIDGE TIMEOUT milliseconds
+// SESSION.BRIDGE EVENTS
+// SESSION.BRIDGE RING ON [capacity] | OFF
+#[tracing::instrument(name = "session.bridge", skip_all)]
+pub fn session_bridge(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1).peekable();
+    let subcommand = args.next_string()?.to_uppercase();
+
+    let mut state = init_bridge_state().lock().map_err(|_| {
+        RedisError::String("Failed to acquire bridge lock".to_string())
+    })?;
+
+    match subcommand.as_str() {
+        "STATUS" => {
+            args.done()?;
+
+            let mode = match state.forced {
+                Some(path) => path.name(),
+                None => "auto",
+            };
+
+            let mut reply = vec![
+                RedisValue::SimpleStringStatic("mode"),
+                RedisValue::SimpleStringStatic(mode),
+                RedisValue::SimpleStringStatic("preferred"),
+                RedisValue::SimpleStringStatic(state.preferred.name()),
+                RedisValue::SimpleStringStatic("switches"),
+                RedisValue::Integer(state.switches as i64),
+                RedisValue::SimpleStringStatic("native_fallback"),
+                RedisValue::Integer(if NATIVE_ACTIVE.load(Ordering::Relaxed) { 1 } else { 0 }),
+                RedisValue::SimpleStringStatic("native_mappings"),
+                RedisValue::Integer(match ctx.call("HLEN", &[NATIVE_KEY]) {
+                    Ok(RedisValue::Integer(len)) => len,
+                    _ => 0,
+                }),
+                RedisValue::SimpleStringStatic("promotions"),
+                RedisValue::Integer(PROMOTIONS.load(Ordering::Relaxed) as i64),
+                RedisValue::SimpleStringStatic("fallback_commands"),
+                RedisValue::Array(fallback_status(ctx)?),
+            ];
+            reply.extend(watchdog::status());
+            if let Ok(ring) = init_ring().read() {
+                let stats = ring.as_ref().map(Ring::stats).unwrap_or_default();
+                reply.extend([
+                    RedisValue::SimpleStringStatic("ring"),
+                    RedisValue::Integer(if ring.is_some() { 1 } else { 0 }),
+                    RedisValue::SimpleStringStatic("ring_ops"),
+                    RedisValue::Integer(stats.ops as i64),
+                    RedisValue::SimpleStringStatic("ring_batches"),
+                    RedisValue::Integer(stats.batches as i64),
+                    RedisValue::SimpleStringStatic("ring_full"),
+                    RedisValue::Integer(stats.full as i64),
+                ]);
+            }
+            for path in [BridgePath::Ffi, BridgePath::Call] {
+                let stats = state.stats(path);
+                reply.push(RedisValue::SimpleStringStatic(path.name()));
+                reply.push(RedisValue::Array(vec![
+                    RedisValue::SimpleStringStatic("latency_us"),
+                    RedisValue::Float(stats.latency_us),
+                    RedisValue::SimpleStringStatic("failure_rate"),
+                    RedisValue::Float(stats.failure_rate),
+                    RedisValue::SimpleStringStatic("samples"),
+                    RedisValue::Integer(stats.samples as i64),
+                    RedisValue::SimpleStringStatic("failures"),
+                    RedisValue::Integer(stats.failures as i64),
+                ]));
+            }
+
+            Ok(RedisValue::Array(reply))
+        },
+        "MODE" => {
+            let mode = args.next_string()?.to_uppercase();
+            args.done()?;
+
+            state.forced = match mode.as_str() {
+                "AUTO" => None,
+                "FFI" => Some(BridgePath::Ffi),
+                "CALL" => Some(BridgePath::Call),
+                _ => return Err(RedisError::String(format!("Unknown bridge mode: {}", mode))),
+            };
+
+            Ok(RedisValue::SimpleStringStatic("OK"))
+        },
+        "TIMEOUT" => {
+            let timeout_ms = args.next_u64()?;
+            args.done()?;
+            watchdog::set_timeout(timeout_ms);
+            Ok(RedisValue::SimpleStringStatic("OK"))
+        },
+        "EVENTS" => {
+            args.done()?;
+            Ok(RedisValue::Array(watchdog::events()))
+        },
+        "RING" => {
+            let toggle = args.next_string()?.to_uppercase();
+            let capacity = match toggle.as_str() {
+                "ON" if args.peek().is_none() => Some(DEFAULT_RING_CAPACITY),
+                "ON" => match args.next_u64()? {
+                    capacity if capacity == 0 || capacity > MAX_RING_CAPACITY as u64 => {
+                        return Err(RedisError::String(format!("Ring capacity must be between 1 and {}", MAX_RING_CAPACITY)));
+                    },
+                    capacity => Some(capacity as u32),
+                },
+                "OFF" => None,
+                _ => return Err(RedisError::String(format!("Unknown ring setting: {}", toggle))),
+            };
+            args.done()?;
+
+            let mut ring = init_ring().write().map_err(|_| {
+                RedisError::String("Failed to acquire write lock".to_string())
+            })?;
+            // Detach the current ring first; dropping it waits for the module to let go
+            *ring = None;
+            if let Some(capacity) = capacity {
+                *ring = Some(client()?.ring(capacity, Some(MODULE_NAME)).map_err(client_error)?);
+            }
+            Ok(RedisValue::SimpleStringStatic("OK"))
+        },
+        _ => Err(RedisError::String(format!("Unknown SESSION.BRIDGE subcommand: {}", subcommand))),
+    }
+}
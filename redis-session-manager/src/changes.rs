@@ -0,0 +1,129 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use redis_module::{Context, NextArg, RedisError, RedisResult, RedisString, RedisValue};
+use serde_json::Value;
+
+use crate::{history, init_sessions, spill, Session};
+use crate::sensitive::Redactor;
+
+// Deletions remembered for incremental exports; older cursors need a full export
+pub const MAX_TOMBSTONES: usize = 100_000;
+
+// Global change sequence, bumped on every session mutation
+static CHANGE_SEQ: AtomicU64 = AtomicU64::new(0);
+
+// Sequence number below which deletions are no longer known
+static TOMBSTONE_HORIZON: AtomicU64 = AtomicU64::new(0);
+
+// Recently deleted sessions as (sequence, session id), oldest first
+static mut TOMBSTONES: Option<Mutex<VecDeque<(u64, String)>>> = None;
+
+// Initialize the tombstone log
+fn init_tombstones() -> &'static Mutex<VecDeque<(u64, String)>> {
+    unsafe {
+        if TOMBSTONES.is_none() {
+            TOMBSTONES = Some(Mutex::new(VecDeque::new()));
+        }
+        TOMBSTONES.as_ref().unwrap()
+    }
+}
+
+// Allocate the next change sequence number
+pub fn next_seq() -> u64 {
+    CHANGE_SEQ.fetch_add(1, Ordering::SeqCst) + 1
+}
+
+// Remember that a session was deleted
+pub fn record_deletion(session_id: &str) {
+    history::record_deletion(session_id);
+    let seq = next_seq();
+    if let Ok(mut tombstones) = init_tombstones().lock() {
+        tombstones.push_back((seq, session_id.to_string()));
+        while tombstones.len() > MAX_TOMBSTONES {
+            if let Some((dropped, _)) = tombstones.pop_front() {
+                TOMBSTONE_HORIZON.store(dropped, Ordering::SeqCst);
+            }
+        }
+    }
+}
+
+// Whether deletions after a cursor are still known, so an incremental export from it is complete
+pub fn is_retained(since: u64) -> bool {
+    since == 0 || since >= TOMBSTONE_HORIZON.load(Ordering::SeqCst)
+}
+
+// Sessions changed and deleted after a cursor, each with the sequence number
+// of its change
+pub struct Changes {
+    // The cursor to pass next time
+    pub cursor: u64,
+    pub changed: Vec<(u64, Value)>,
+    pub deleted: Vec<(u64, String)>,
+}
+
+// Collect changes after a cursor, serializing each changed session with
+// spilled values resolved and `prepare` applied. No deletions are reported
+// from cursor 0, which stands for a full snapshot.
+pub fn collect(ctx: &Context, since: u64, prepare: impl Fn(&Session, &mut Value)) -> Result<Changes, RedisError> {
+    let sessions = init_sessions();
+    let sessions_map = sessions.read().map_err(|_| {
+        RedisError::String("Failed to acquire read lock".to_string())
+    })?;
+
+    // Read the cursor under the sessions lock so no write can slip between it and the snapshot
+    let cursor = CHANGE_SEQ.load(Ordering::SeqCst);
+
+    let mut changed = Vec::new();
+    for session in sessions_map.values().filter(|session| session.change_seq > since) {
+        let mut json = serde_json::to_value(session).map_err(|e| {
+            RedisError::String(format!("Failed to serialize session: {}", e))
+        })?;
+        prepare(session, &mut json);
+        spill::resolve_session(ctx, session, &mut json);
+        changed.push((session.change_seq, json));
+    }
+
+    let deleted = if since == 0 {
+        Vec::new()
+    } else {
+        let tombstones = init_tombstones().lock().map_err(|_| {
+            RedisError::String("Failed to acquire tombstone lock".to_string())
+        })?;
+        tombstones.iter()
+            .filter(|(seq, id)| *seq > since && *seq <= cursor && !sessions_map.contains_key(id))
+            .cloned()
+            .collect()
+    };
+
+    Ok(Changes { cursor, changed, deleted })
+}
+
+// Export sessions changed after a cursor: SESSION.EXPORT [SINCE cursor] [REVEAL]
+// Reply: [cursor, [session json, ...], [deleted session id, ...]]
+#[tracing::instrument(name = "session.export", skip_all)]
+pub fn export_sessions(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+
+    let mut since = 0;
+    let mut reveal = false;
+    while let Ok(option) = args.next_string() {
+        match option.to_uppercase().as_str() {
+            "SINCE" => since = args.next_u64()?,
+            "REVEAL" => reveal = true,
+            _ => return Err(RedisError::String(format!("Unknown option: {}", option))),
+        }
+    }
+    let redactor = Redactor::for_caller(ctx, reveal)?;
+
+    if !is_retained(since) {
+        return Err(RedisError::Str("Cursor is older than the retained change log, run a full SESSION.EXPORT"));
+    }
+
+    let changes = collect(ctx, since, |_, json| redactor.redact_session(json))?;
+    Ok(RedisValue::Array(vec![
+        RedisValue::Integer(changes.cursor as i64),
+        RedisValue::Array(changes.changed.into_iter().map(|(_, json)| RedisValue::BulkString(json.to_string())).collect()),
+        RedisValue::Array(changes.deleted.into_iter().map(|(_, id)| RedisValue::BulkString(id)).collect()),
+    ]))
+}
@@ -0,0 +1,226 @@
+use std::sync::Mutex;
+use chrono::{DateTime, Utc};
+use redis_module::{Context, NextArg, RedisError, RedisResult, RedisString, RedisValue};
+use serde::Deserialize;
+use session_core::ttl;
+
+use crate::{bridge, eviction, expiry, history, retry, spill, throttle, watchdog};
+
+// Settings read from the config file. Every one is optional: a setting left
+// out keeps its current value, so a reload never resets what the file
+// doesn't mention.
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct ConfigFile {
+    ttl: TtlConfig,
+    limits: LimitsConfig,
+    bridge: BridgeConfig,
+    persistence: PersistenceConfig,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct TtlConfig {
+    // As SESSION.EXPIRY_GRACE SET
+    grace_secs: Option<u64>,
+    // As the idle_timeout module argument; sessions already created keep theirs
+    idle_timeout_secs: Option<u64>,
+    // As the max_lifetime module argument; applies to existing sessions by the next sweep
+    max_lifetime_secs: Option<u64>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct LimitsConfig {
+    backpressure_depth: Option<usize>,
+    evict_memory_percent: Option<u64>,
+    max_sessions: Option<usize>,
+    // reject, evict-lru or evict-oldest, as the max_sessions_policy module argument
+    max_sessions_policy: Option<String>,
+    // As SESSION.THROTTLE_CREATE USER and IP
+    throttle_user: Option<ThrottleConfig>,
+    throttle_ip: Option<ThrottleConfig>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ThrottleConfig {
+    limit: u64,
+    window_secs: u64,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct BridgeConfig {
+    // As SESSION.BRIDGE TIMEOUT
+    timeout_ms: Option<u64>,
+    fallback_get: Option<String>,
+    fallback_set: Option<String>,
+    fallback_del: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct PersistenceConfig {
+    spill_threshold: Option<u64>,
+    history_secs: Option<u64>,
+}
+
+impl ConfigFile {
+    // Refuse the whole file over one bad value, before any of it is applied
+    fn validate(&self) -> Result<(), String> {
+        for (name, secs) in [
+            ("ttl.grace_secs", self.ttl.grace_secs),
+            ("ttl.idle_timeout_secs", self.ttl.idle_timeout_secs),
+            ("ttl.max_lifetime_secs", self.ttl.max_lifetime_secs),
+            ("persistence.history_secs", self.persistence.history_secs),
+        ] {
+            if let Some(secs) = secs {
+                ttl::check(secs, name)?;
+            }
+        }
+        if self.limits.evict_memory_percent.is_some_and(|percent| percent > 100) {
+            return Err("limits.evict_memory_percent must be at most 100".to_string());
+        }
+        if let Some(policy) = &self.limits.max_sessions_policy {
+            if eviction::LimitPolicy::parse(policy).is_none() {
+                return Err(format!("limits.max_sessions_policy must be reject, evict-lru or evict-oldest, not {}", policy));
+            }
+        }
+        for (name, throttle) in [("throttle_user", &self.limits.throttle_user), ("throttle_ip", &self.limits.throttle_ip)] {
+            if throttle.as_ref().is_some_and(|throttle| throttle.limit == 0 || throttle.window_secs == 0) {
+                return Err(format!("limits.{}: limit and window_secs must be positive", name));
+            }
+        }
+        let bridge = &self.bridge;
+        for (name, command) in [("fallback_get", &bridge.fallback_get), ("fallback_set", &bridge.fallback_set), ("fallback_del", &bridge.fallback_del)] {
+            if command.as_ref().is_some_and(|command| command.is_empty()) {
+                return Err(format!("bridge.{} must not be empty", name));
+            }
+        }
+        Ok(())
+    }
+
+    fn apply(self) -> Result<(), String> {
+        if let Some(secs) = self.ttl.grace_secs {
+            expiry::set_grace_secs(secs);
+        }
+        if let Some(secs) = self.ttl.idle_timeout_secs {
+            expiry::set_idle_timeout_secs(secs);
+        }
+        if let Some(secs) = self.ttl.max_lifetime_secs {
+            expiry::set_max_lifetime_secs(secs);
+        }
+        if let Some(depth) = self.limits.backpressure_depth {
+            retry::set_backpressure_depth(depth);
+        }
+        if let Some(percent) = self.limits.evict_memory_percent {
+            eviction::set_evict_memory_percent(percent);
+        }
+        if let Some(max) = self.limits.max_sessions {
+            eviction::set_max_sessions(max);
+        }
+        if let Some(policy) = self.limits.max_sessions_policy.as_deref().and_then(eviction::LimitPolicy::parse) {
+            eviction::set_limit_policy(policy);
+        }
+        if let Some(ThrottleConfig { limit, window_secs }) = self.limits.throttle_user {
+            throttle::set_limit(false, limit, window_secs).map_err(|e| e.to_string())?;
+        }
+        if let Some(ThrottleConfig { limit, window_secs }) = self.limits.throttle_ip {
+            throttle::set_limit(true, limit, window_secs).map_err(|e| e.to_string())?;
+        }
+        if let Some(timeout_ms) = self.bridge.timeout_ms {
+            watchdog::set_timeout(timeout_ms);
+        }
+        bridge::set_fallback_commands(self.bridge.fallback_get, self.bridge.fallback_set, self.bridge.fallback_del);
+        if let Some(bytes) = self.persistence.spill_threshold {
+            spill::set_spill_threshold(bytes);
+        }
+        if let Some(secs) = self.persistence.history_secs {
+            history::set_history_secs(secs);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct ConfigState {
+    path: Option<String>,
+    loads: u64,
+    last_loaded: Option<DateTime<Utc>>,
+    last_error: Option<String>,
+}
+
+static mut CONFIG: Option<Mutex<ConfigState>> = None;
+
+// Initialize the config file state
+fn init_config() -> &'static Mutex<ConfigState> {
+    unsafe {
+        if CONFIG.is_none() {
+            CONFIG = Some(Mutex::new(ConfigState::default()));
+        }
+        CONFIG.as_ref().unwrap()
+    }
+}
+
+fn read(path: &str) -> Result<(), String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    let file: ConfigFile = toml::from_str(&text).map_err(|e| format!("Invalid config file {}: {}", path, e))?;
+    file.validate().map_err(|e| format!("Invalid config file {}: {}", path, e))?;
+    file.apply()
+}
+
+fn load(state: &mut ConfigState, path: &str) -> Result<(), String> {
+    let result = read(path);
+    match &result {
+        Ok(()) => {
+            state.loads += 1;
+            state.last_loaded = Some(Utc::now());
+            state.last_error = None;
+        },
+        Err(err) => state.last_error = Some(err.clone()),
+    }
+    result
+}
+
+// Read the file named by the `config=<path>` module argument; it's read again on SESSION.CONFIG RELOAD
+pub fn load_at_start(path: String) -> Result<(), String> {
+    let mut state = init_config().lock().map_err(|_| "Failed to acquire config lock".to_string())?;
+    let result = load(&mut state, &path);
+    state.path = Some(path);
+    result
+}
+
+// Reload or inspect the config file:
+// SESSION.CONFIG RELOAD
+// SESSION.CONFIG STATUS
+#[tracing::instrument(name = "session.config", skip_all)]
+pub fn session_config(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let subcommand = args.next_string()?.to_uppercase();
+    args.done()?;
+
+    let mut state = init_config().lock().map_err(|_| {
+        RedisError::String("Failed to acquire config lock".to_string())
+    })?;
+    match subcommand.as_str() {
+        "RELOAD" => {
+            let path = state.path.clone()
+                .ok_or(RedisError::Str("No config file: load the module with config=<path>"))?;
+            load(&mut state, &path).map_err(RedisError::String)?;
+            ctx.log_notice(&format!("Reloaded config file {}", path));
+            Ok(RedisValue::SimpleStringStatic("OK"))
+        },
+        "STATUS" => Ok(RedisValue::Array(vec![
+            RedisValue::SimpleStringStatic("path"),
+            state.path.clone().map_or(RedisValue::Null, RedisValue::BulkString),
+            RedisValue::SimpleStringStatic("loads"),
+            RedisValue::Integer(state.loads as i64),
+            RedisValue::SimpleStringStatic("last_loaded"),
+            state.last_loaded.map_or(RedisValue::Null, |at| RedisValue::BulkString(at.to_rfc3339())),
+            RedisValue::SimpleStringStatic("last_error"),
+            state.last_error.clone().map_or(RedisValue::Null, RedisValue::BulkString),
+        ])),
+        _ => Err(RedisError::String(format!("Unknown SESSION.CONFIG subcommand: {}", subcommand))),
+    }
+}
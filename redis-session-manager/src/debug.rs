@@ -0,0 +1,33 @@
+use redis_module::{Context, NextArg, RedisError, RedisResult, RedisString, RedisValue};
+
+// Debugging aids for staging:
+// SESSION.DEBUG LEAKCHECK
+// LEAKCHECK replies with the buffers still held across the C boundary in
+// total, then per call site the buffers received, released and outstanding.
+#[tracing::instrument(name = "session.debug", skip_all)]
+pub fn session_debug(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let subcommand = args.next_string()?.to_uppercase();
+    args.done()?;
+
+    match subcommand.as_str() {
+        "LEAKCHECK" => {
+            let balances = custom_hashmap_client::balances();
+            let outstanding: u64 = balances.iter().map(|balance| balance.outstanding()).sum();
+            Ok(RedisValue::Array(vec![
+                RedisValue::SimpleStringStatic("outstanding"),
+                RedisValue::Integer(outstanding as i64),
+                RedisValue::SimpleStringStatic("sites"),
+                RedisValue::Array(balances.iter()
+                    .map(|balance| RedisValue::Array(vec![
+                        RedisValue::SimpleStringStatic(balance.site.name()),
+                        RedisValue::Integer(balance.allocated as i64),
+                        RedisValue::Integer(balance.freed as i64),
+                        RedisValue::Integer(balance.outstanding() as i64),
+                    ]))
+                    .collect()),
+            ]))
+        },
+        _ => Err(RedisError::String(format!("Unknown SESSION.DEBUG subcommand: {}", subcommand))),
+    }
+}
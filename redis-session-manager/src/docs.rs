@@ -0,0 +1,882 @@
+use command_docs::{Arg, CommandDoc, KeySpec, KEY_ACCESS, KEY_DELETE, KEY_INSERT, KEY_NOT_KEY, KEY_RO, KEY_RW, KEY_UPDATE};
+
+// Session ids and custom hashmap keys aren't keyspace keys, but are routed
+// like them so a session's commands reach the node that holds it
+const SESSION_READ: KeySpec = KeySpec::index(1, KEY_NOT_KEY | KEY_RO | KEY_ACCESS);
+const SESSION_WRITE: KeySpec = KeySpec::index(1, KEY_NOT_KEY | KEY_RW | KEY_UPDATE);
+
+const SESSION_ID: Arg = Arg::key("session_id", 0);
+
+const PRIORITY: Arg = Arg::one_of("priority", &[
+    Arg::pure_token("low", "LOW"),
+    Arg::pure_token("normal", "NORMAL"),
+    Arg::pure_token("high", "HIGH"),
+]).with_token("PRIORITY");
+
+const DATA_PAGE: Arg = Arg::one_of("page", &[
+    Arg::block("limit", &[Arg::integer("offset"), Arg::integer("count")]).with_token("LIMIT"),
+    Arg::block("cursor", &[
+        Arg::integer("cursor"),
+        Arg::integer("count").with_token("COUNT").optional(),
+    ]).with_token("CURSOR"),
+]).optional();
+
+const SINCE: &str = "0.1.0";
+
+// Metadata for COMMAND DOCS / COMMAND INFO, attached at load by command_docs::register
+pub static COMMANDS: &[CommandDoc] = &[
+    CommandDoc {
+        name: "session.create",
+        summary: "Creates a session for a user key, or returns the key's live session.",
+        complexity: Some("O(1)"),
+        since: SINCE,
+        arity: -2,
+        key_specs: &[KeySpec::index(1, KEY_NOT_KEY | KEY_RW | KEY_INSERT)
+            .with_notes("Custom hashmap key mapped to the session id")],
+        args: &[
+            Arg::key("key", 0),
+            Arg::one_of("expiry", &[
+                Arg::integer("seconds").with_token("TTL"),
+                Arg::integer("idle-seconds").with_token("IDLE"),
+            ]).optional(),
+            Arg::string("app").with_token("APP").optional(),
+            PRIORITY.optional(),
+            Arg::string("name").with_token("TEMPLATE").optional(),
+        ],
+    },
+    CommandDoc {
+        name: "session.get_or_create",
+        summary: "Returns the live session of a user key, creating it if needed, with whether it was created.",
+        complexity: Some("O(N) where N is the number of data fields"),
+        since: SINCE,
+        arity: -2,
+        key_specs: &[KeySpec::index(1, KEY_NOT_KEY | KEY_RW | KEY_INSERT)
+            .with_notes("Custom hashmap key mapped to the session id")],
+        args: &[
+            Arg::key("key", 0),
+            Arg::one_of("expiry", &[
+                Arg::integer("seconds").with_token("TTL"),
+                Arg::integer("idle-seconds").with_token("IDLE"),
+            ]).optional(),
+            Arg::string("app").with_token("APP").optional(),
+            PRIORITY.optional(),
+            Arg::string("name").with_token("TEMPLATE").optional(),
+        ],
+    },
+    CommandDoc {
+        name: "session.get",
+        summary: "Returns a session as JSON, optionally only a window of its data.",
+        complexity: Some("O(N) where N is the number of data fields returned"),
+        since: SINCE,
+        arity: -2,
+        key_specs: &[SESSION_READ],
+        args: &[
+            SESSION_ID,
+            Arg::integer("seconds").with_token("MAXAGE").optional(),
+            Arg::pure_token("reveal", "REVEAL").optional(),
+            Arg::string("key_id").with_token("DECRYPT").optional(),
+            DATA_PAGE,
+        ],
+    },
+    CommandDoc {
+        name: "session.mget",
+        summary: "Returns many sessions as JSON in one call, nil for unknown ids.",
+        complexity: Some("O(N) where N is the total size of the sessions returned"),
+        since: SINCE,
+        arity: -2,
+        key_specs: &[KeySpec::range(1, 0, KEY_NOT_KEY | KEY_RO | KEY_ACCESS)],
+        args: &[SESSION_ID.multiple()],
+    },
+    CommandDoc {
+        name: "session.get_at",
+        summary: "Returns a session's data fields as they were at a past time.",
+        complexity: Some("O(N) where N is the number of retained changes of the session"),
+        since: SINCE,
+        arity: -3,
+        key_specs: &[SESSION_READ],
+        args: &[
+            SESSION_ID,
+            Arg::string("timestamp"),
+            Arg::pure_token("reveal", "REVEAL").optional(),
+        ],
+    },
+    CommandDoc {
+        name: "session.impersonate",
+        summary: "Creates a short-lived session acting as another one on behalf of an admin.",
+        complexity: Some("O(N) where N is the number of data fields of the target session"),
+        since: SINCE,
+        arity: -3,
+        key_specs: &[SESSION_READ],
+        args: &[
+            Arg::key("target_id", 0),
+            Arg::string("admin_id"),
+            Arg::integer("seconds").with_token("TTL").optional(),
+        ],
+    },
+    CommandDoc {
+        name: "session.list",
+        summary: "Lists sessions, optionally sorted, limited and filtered by app.",
+        complexity: Some("O(N) where N is the number of sessions returned"),
+        since: SINCE,
+        arity: -1,
+        key_specs: &[],
+        args: &[
+            Arg::block("sort", &[
+                Arg::pure_token("by", "BY"),
+                Arg::one_of("field", &[
+                    Arg::pure_token("created", "created"),
+                    Arg::pure_token("last_accessed", "last_accessed"),
+                    Arg::pure_token("ttl", "ttl"),
+                ]),
+                Arg::one_of("order", &[
+                    Arg::pure_token("asc", "ASC"),
+                    Arg::pure_token("desc", "DESC"),
+                ]).optional(),
+            ]).with_token("SORT").optional(),
+            Arg::integer("count").with_token("LIMIT").optional(),
+            Arg::string("app").with_token("APP").optional(),
+        ],
+    },
+    CommandDoc {
+        name: "session.count",
+        summary: "Returns the number of sessions, optionally of one app.",
+        complexity: Some("O(1)"),
+        since: SINCE,
+        arity: -1,
+        key_specs: &[],
+        args: &[Arg::string("app").with_token("APP").optional()],
+    },
+    CommandDoc {
+        name: "session.version",
+        summary: "Returns the module's version, commit, features and C API version, and whether the hashmap module's matches.",
+        complexity: Some("O(1)"),
+        since: SINCE,
+        arity: 1,
+        key_specs: &[],
+        args: &[],
+    },
+    CommandDoc {
+        name: "session.hello",
+        summary: "Describes the module's capabilities, reply format versions, limits and enabled subsystems for client libraries.",
+        complexity: Some("O(1)"),
+        since: SINCE,
+        arity: 1,
+        key_specs: &[],
+        args: &[],
+    },
+    CommandDoc {
+        name: "session.digest",
+        summary: "Returns an order-independent digest of every live session, and the session count.",
+        complexity: Some("O(N) where N is the number of sessions"),
+        since: SINCE,
+        arity: 1,
+        key_specs: &[],
+        args: &[],
+    },
+    CommandDoc {
+        name: "session.explain",
+        summary: "Reports how a command would be routed, without running it.",
+        complexity: Some("O(1)"),
+        since: SINCE,
+        arity: -2,
+        key_specs: &[],
+        args: &[Arg::string("command"), Arg::string("arg").optional().multiple()],
+    },
+    CommandDoc {
+        name: "session.info",
+        summary: "Returns the store size and the current background timer intervals.",
+        complexity: Some("O(1)"),
+        since: SINCE,
+        arity: 1,
+        key_specs: &[],
+        args: &[],
+    },
+    CommandDoc {
+        name: "session.aggregate",
+        summary: "Estimates aggregates of a data field across all sessions without returning session data.",
+        complexity: Some("O(N) where N is the number of sessions"),
+        since: SINCE,
+        arity: -2,
+        key_specs: &[],
+        args: &[
+            Arg::string("field"),
+            Arg::one_of("aggregate", &[
+                Arg::integer("n").with_token("TOPK"),
+                Arg::pure_token("cardinality", "CARDINALITY"),
+                Arg::pure_token("histogram", "HISTOGRAM"),
+            ]).optional(),
+        ],
+    },
+    CommandDoc {
+        name: "session.touch",
+        summary: "Records an access to a session, optionally resetting its TTL, and returns the seconds left until it expires.",
+        complexity: Some("O(1)"),
+        since: SINCE,
+        arity: -2,
+        key_specs: &[SESSION_WRITE],
+        args: &[SESSION_ID, Arg::integer("ttl").optional()],
+    },
+    CommandDoc {
+        name: "session.expireat",
+        summary: "Sets a hard expiry time on a session that accesses and TTL renewals never extend.",
+        complexity: Some("O(1)"),
+        since: SINCE,
+        arity: 3,
+        key_specs: &[SESSION_WRITE],
+        args: &[SESSION_ID, Arg::integer("unix_ts")],
+    },
+    CommandDoc {
+        name: "session.persist",
+        summary: "Removes a session's TTL, idle timeout and deadline so it no longer expires.",
+        complexity: Some("O(1)"),
+        since: SINCE,
+        arity: 2,
+        key_specs: &[SESSION_WRITE],
+        args: &[SESSION_ID],
+    },
+    CommandDoc {
+        name: "session.ttl",
+        summary: "Returns the seconds until a session expires, -1 if it has no expiry, or -2 if it doesn't exist.",
+        complexity: Some("O(1)"),
+        since: SINCE,
+        arity: 2,
+        key_specs: &[KeySpec::index(1, KEY_NOT_KEY | KEY_RO)],
+        args: &[SESSION_ID],
+    },
+    CommandDoc {
+        name: "session.bind_ttl_to_key",
+        summary: "Keeps a session's expiry in step with a native key's TTL, or removes that binding.",
+        complexity: Some("O(1)"),
+        since: SINCE,
+        arity: -2,
+        key_specs: &[SESSION_WRITE, KeySpec::index(2, KEY_RW | KEY_UPDATE)],
+        args: &[
+            SESSION_ID,
+            Arg::block("binding", &[
+                Arg::key("key", 1),
+                Arg::one_of("source", &[
+                    Arg::pure_token("from_key", "FROM_KEY"),
+                    Arg::pure_token("from_session", "FROM_SESSION"),
+                ]).optional(),
+            ]).optional(),
+        ],
+    },
+    CommandDoc {
+        name: "session.expire_idle",
+        summary: "Deletes sessions idle for longer than a number of seconds, LOW priority first and never HIGH.",
+        complexity: Some("O(N) where N is the number of sessions deleted"),
+        since: SINCE,
+        arity: -2,
+        key_specs: &[],
+        args: &[
+            Arg::integer("seconds"),
+            Arg::string("app").with_token("APP").optional(),
+            Arg::integer("count").with_token("LIMIT").optional(),
+        ],
+    },
+    CommandDoc {
+        name: "session.simulate_expiry",
+        summary: "Reports which sessions the expiry sweep would remove under the current or a hypothetical policy.",
+        complexity: Some("O(N) where N is the number of sessions that would be removed"),
+        since: SINCE,
+        arity: -1,
+        key_specs: &[],
+        args: &[
+            Arg::integer("seconds").with_token("AFTER").optional(),
+            Arg::integer("seconds").with_token("GRACE").optional(),
+            Arg::integer("seconds").with_token("IDLE").optional(),
+            Arg::integer("percent").with_token("EVICT_PERCENT").optional(),
+            Arg::integer("count").with_token("LIMIT").optional(),
+        ],
+    },
+    CommandDoc {
+        name: "session.add_data",
+        summary: "Sets one data field of a session, optionally as a typed value.",
+        complexity: Some("O(1)"),
+        since: SINCE,
+        arity: -4,
+        key_specs: &[SESSION_WRITE],
+        args: &[SESSION_ID, Arg::string("field"), Arg::string("value"), Arg::one_of("type", &[
+            Arg::pure_token("string", "STRING"),
+            Arg::pure_token("int", "INT"),
+            Arg::pure_token("float", "FLOAT"),
+            Arg::pure_token("bool", "BOOL"),
+            Arg::pure_token("json", "JSON"),
+        ]).with_token("TYPE").optional()],
+    },
+    CommandDoc {
+        name: "session.get_data",
+        summary: "Returns one data field of a session, or every field under a path.",
+        complexity: Some("O(1) for a field, O(N) for a path.* subtree"),
+        since: SINCE,
+        arity: -3,
+        key_specs: &[SESSION_READ],
+        args: &[SESSION_ID, Arg::string("field"), Arg::pure_token("reveal", "REVEAL").optional()],
+    },
+    CommandDoc {
+        name: "session.set_data",
+        summary: "Sets a value at a dotted path in a session's data.",
+        complexity: Some("O(1)"),
+        since: SINCE,
+        arity: 4,
+        key_specs: &[SESSION_WRITE],
+        args: &[SESSION_ID, Arg::string("path"), Arg::string("value")],
+    },
+    CommandDoc {
+        name: "session.patch",
+        summary: "Applies a JSON merge patch to a session's data in one atomic step.",
+        complexity: Some("O(N*M) where N is the number of values in the patch and M the number of data fields"),
+        since: SINCE,
+        arity: 3,
+        key_specs: &[SESSION_WRITE],
+        args: &[SESSION_ID, Arg::string("patch")],
+    },
+    CommandDoc {
+        name: "session.set_meta",
+        summary: "Changes a session's attributes other than its data, such as its priority.",
+        complexity: Some("O(1)"),
+        since: SINCE,
+        arity: 4,
+        key_specs: &[SESSION_WRITE],
+        args: &[SESSION_ID, PRIORITY],
+    },
+    CommandDoc {
+        name: "session.del_data",
+        summary: "Deletes a data field, or a whole subtree with path.*.",
+        complexity: Some("O(1) for a field, O(N) for a path.* subtree"),
+        since: SINCE,
+        arity: 3,
+        key_specs: &[SESSION_WRITE],
+        args: &[SESSION_ID, Arg::string("field")],
+    },
+    CommandDoc {
+        name: "session.get_all_data",
+        summary: "Returns a session's data as a flat field/value array.",
+        complexity: Some("O(N) where N is the number of data fields returned"),
+        since: SINCE,
+        arity: -2,
+        key_specs: &[SESSION_READ],
+        args: &[SESSION_ID, Arg::pure_token("reveal", "REVEAL").optional(), DATA_PAGE],
+    },
+    CommandDoc {
+        name: "session.data_keys",
+        summary: "Returns the names of a session's data fields, optionally matching a glob.",
+        complexity: Some("O(N) where N is the number of data fields"),
+        since: SINCE,
+        arity: -2,
+        key_specs: &[SESSION_READ],
+        args: &[SESSION_ID, Arg::pattern("pattern").with_token("MATCH").optional()],
+    },
+    CommandDoc {
+        name: "session.field_type",
+        summary: "Returns the type a session data field was written as: string, int, float, boolean or json.",
+        complexity: Some("O(1)"),
+        since: SINCE,
+        arity: -3,
+        key_specs: &[SESSION_READ],
+        args: &[SESSION_ID, Arg::string("field"), Arg::pure_token("reveal", "REVEAL").optional()],
+    },
+    CommandDoc {
+        name: "session.field_types",
+        summary: "Reports or sets how data writes that would change a field's type are handled.",
+        complexity: Some("O(1)"),
+        since: SINCE,
+        arity: -1,
+        key_specs: &[],
+        args: &[Arg::one_of("mode", &[
+            Arg::pure_token("loose", "LOOSE"),
+            Arg::pure_token("strict", "STRICT"),
+            Arg::pure_token("coerce", "COERCE"),
+        ]).with_token("MODE").optional()],
+    },
+    CommandDoc {
+        name: "session.delete",
+        summary: "Deletes a session and its user key mapping.",
+        complexity: Some("O(1)"),
+        since: SINCE,
+        arity: -2,
+        key_specs: &[KeySpec::index(1, KEY_NOT_KEY | KEY_RW | KEY_DELETE)],
+        args: &[SESSION_ID, Arg::string("token").with_token("TOKEN").optional()],
+    },
+    CommandDoc {
+        name: "session.archive",
+        summary: "Moves a session out of memory into a compressed native key.",
+        complexity: Some("O(N) where N is the size of the session"),
+        since: SINCE,
+        arity: -2,
+        key_specs: &[KeySpec::index(1, KEY_NOT_KEY | KEY_RW | KEY_DELETE)
+            .with_notes("Also writes session:archive:{session_id}, which hashes to the same slot")],
+        args: &[SESSION_ID, Arg::integer("seconds").with_token("TTL").optional()],
+    },
+    CommandDoc {
+        name: "session.unarchive",
+        summary: "Restores an archived session into memory.",
+        complexity: Some("O(N) where N is the size of the session"),
+        since: SINCE,
+        arity: 2,
+        key_specs: &[KeySpec::index(1, KEY_NOT_KEY | KEY_RW | KEY_INSERT)
+            .with_notes("Also reads and deletes session:archive:{session_id}, which hashes to the same slot")],
+        args: &[SESSION_ID],
+    },
+    CommandDoc {
+        name: "session.compare",
+        summary: "Returns a field-level diff of two sessions' data.",
+        complexity: Some("O(N) where N is the number of data fields of both sessions"),
+        since: SINCE,
+        arity: 3,
+        key_specs: &[KeySpec::range(1, 2, KEY_NOT_KEY | KEY_RO | KEY_ACCESS)],
+        args: &[Arg::key("left_session_id", 0), Arg::key("right_session_id", 0)],
+    },
+    CommandDoc {
+        name: "session.secret",
+        summary: "Stores, verifies or deletes a hashed secret on a session.",
+        complexity: Some("O(1), dominated by the password hash"),
+        since: SINCE,
+        arity: -4,
+        key_specs: &[KeySpec::index(2, KEY_NOT_KEY | KEY_RW | KEY_UPDATE)],
+        args: &[Arg::one_of("operation", &[
+            Arg::block("set", &[Arg::key("session_id", 0), Arg::string("name"), Arg::string("plaintext")])
+                .with_token("SET"),
+            Arg::block("verify", &[Arg::key("session_id", 0), Arg::string("name"), Arg::string("candidate")])
+                .with_token("VERIFY"),
+            Arg::block("del", &[Arg::key("session_id", 0), Arg::string("name")]).with_token("DEL"),
+        ])],
+    },
+    CommandDoc {
+        name: "session.refresh",
+        summary: "Mints, exchanges or revokes single-use refresh tokens paired with sessions.",
+        complexity: Some("O(1), dominated by the password hash"),
+        since: SINCE,
+        arity: -3,
+        key_specs: &[],
+        args: &[Arg::one_of("operation", &[
+            Arg::block("create", &[
+                Arg::string("session_id"),
+                Arg::string("duration").with_token("TTL"),
+                Arg::string("duration").with_token("ACCESS_TTL").optional(),
+            ]).with_token("CREATE"),
+            Arg::string("token").with_token("EXCHANGE"),
+            Arg::string("token").with_token("REVOKE"),
+        ])],
+    },
+    CommandDoc {
+        name: "session.nonce",
+        summary: "Records a request nonce on a session, rejecting one seen within its window.",
+        complexity: Some("O(N) where N is the number of nonces remembered for the session"),
+        since: SINCE,
+        arity: -4,
+        key_specs: &[KeySpec::index(2, KEY_NOT_KEY | KEY_RW | KEY_UPDATE)],
+        args: &[Arg::block("check", &[
+            Arg::key("session_id", 0),
+            Arg::string("nonce"),
+            Arg::integer("seconds").with_token("EX").optional(),
+        ]).with_token("CHECK")],
+    },
+    CommandDoc {
+        name: "session.sensitive",
+        summary: "Adds, removes or lists the data fields redacted from session replies.",
+        complexity: Some("O(N) where N is the number of fields given or listed"),
+        since: SINCE,
+        arity: -2,
+        key_specs: &[],
+        args: &[Arg::one_of("subcommand", &[
+            Arg::string("field").multiple().with_token("ADD"),
+            Arg::string("field").multiple().with_token("DEL"),
+            Arg::pure_token("list", "LIST"),
+        ])],
+    },
+    CommandDoc {
+        name: "session.encryption",
+        summary: "Manages tenant encryption keys and the data fields stored encrypted.",
+        complexity: Some("O(N) where N is the number of keys or fields given or listed; KEY DEL without FORCE is O(N) in the data fields of all sessions"),
+        since: SINCE,
+        arity: -3,
+        key_specs: &[],
+        args: &[Arg::one_of("subcommand", &[
+            Arg::one_of("key", &[
+                Arg::block("add", &[Arg::string("tenant"), Arg::string("key_id"), Arg::string("key")]).with_token("ADD"),
+                Arg::block("del", &[Arg::string("key_id"), Arg::pure_token("force", "FORCE").optional()]).with_token("DEL"),
+                Arg::pure_token("list", "LIST"),
+            ]).with_token("KEY"),
+            Arg::one_of("field", &[
+                Arg::string("field").multiple().with_token("ADD"),
+                Arg::string("field").multiple().with_token("DEL"),
+                Arg::pure_token("list", "LIST"),
+            ]).with_token("FIELD"),
+        ])],
+    },
+    CommandDoc {
+        name: "session.template",
+        summary: "Defines, shows, deletes or lists templates of data for new sessions.",
+        complexity: Some("O(N) where N is the number of fields in the template"),
+        since: SINCE,
+        arity: -2,
+        key_specs: &[],
+        args: &[Arg::one_of("subcommand", &[
+            Arg::block("set", &[Arg::string("name"), Arg::string("json")]).with_token("SET"),
+            Arg::string("name").with_token("GET"),
+            Arg::string("name").with_token("DEL"),
+            Arg::pure_token("list", "LIST"),
+        ])],
+    },
+    CommandDoc {
+        name: "session.on_create",
+        summary: "Adds, deletes, lists or clears Redis commands run right after each session is created.",
+        complexity: Some("O(N) where N is the number of configured commands"),
+        since: SINCE,
+        arity: -2,
+        key_specs: &[],
+        args: &[Arg::one_of("subcommand", &[
+            Arg::block("add", &[Arg::string("command"), Arg::string("arg").optional().multiple()]).with_token("ADD"),
+            Arg::integer("index").with_token("DEL"),
+            Arg::pure_token("list", "LIST"),
+            Arg::pure_token("clear", "CLEAR"),
+        ])],
+    },
+    CommandDoc {
+        name: "session.bridge",
+        summary: "Inspects and configures the bridge to the custom hashmap module.",
+        complexity: Some("O(1)"),
+        since: SINCE,
+        arity: -2,
+        key_specs: &[],
+        args: &[Arg::one_of("subcommand", &[
+            Arg::pure_token("status", "STATUS"),
+            Arg::one_of("mode", &[
+                Arg::pure_token("auto", "AUTO"),
+                Arg::pure_token("ffi", "FFI"),
+                Arg::pure_token("call", "CALL"),
+            ]).with_token("MODE"),
+            Arg::integer("milliseconds").with_token("TIMEOUT"),
+            Arg::pure_token("events", "EVENTS"),
+            Arg::one_of("ring", &[
+                Arg::block("on", &[Arg::integer("capacity").optional()]).with_token("ON"),
+                Arg::pure_token("off", "OFF"),
+            ]).with_token("RING"),
+        ])],
+    },
+    CommandDoc {
+        name: "session.expiry_warning",
+        summary: "Configures notifications sent shortly before sessions expire.",
+        complexity: Some("O(1)"),
+        since: SINCE,
+        arity: -2,
+        key_specs: &[KeySpec::keyword("STREAM", KEY_RW | KEY_INSERT)
+            .with_notes("Stream warnings are appended to from now on")],
+        args: &[Arg::one_of("subcommand", &[
+            Arg::block("set", &[
+                Arg::integer("seconds"),
+                Arg::one_of("target", &[
+                    Arg::string("channel").with_token("CHANNEL"),
+                    Arg::key("key", 0).with_token("STREAM"),
+                ]).optional(),
+            ]).with_token("SET"),
+            Arg::pure_token("off", "OFF"),
+            Arg::pure_token("get", "GET"),
+        ])],
+    },
+    CommandDoc {
+        name: "session.webhook",
+        summary: "Manages webhooks that receive session lifecycle events over HTTP.",
+        complexity: Some("O(1) for ADD and DEL, O(N) for LIST and the dead-letter subcommands"),
+        since: SINCE,
+        arity: -2,
+        key_specs: &[],
+        args: &[Arg::one_of("subcommand", &[
+            Arg::block("add", &[
+                Arg::string("name"),
+                Arg::string("url"),
+                Arg::string("event").with_token("EVENTS").multiple().optional(),
+            ]).with_token("ADD"),
+            Arg::string("name").with_token("DEL"),
+            Arg::pure_token("list", "LIST"),
+            Arg::pure_token("stats", "STATS"),
+            Arg::pure_token("deadletters", "DEADLETTERS"),
+            Arg::pure_token("redeliver", "REDELIVER"),
+            Arg::pure_token("purge", "PURGE"),
+        ])],
+    },
+    CommandDoc {
+        name: "session.events",
+        summary: "Configures the pub/sub and keyspace notifications fired on session lifecycle events.",
+        complexity: Some("O(1), or O(N) in the number of events for FILTER"),
+        since: SINCE,
+        arity: -2,
+        key_specs: &[],
+        args: &[Arg::one_of("subcommand", &[
+            Arg::string("channel").with_token("CHANNEL"),
+            Arg::block("keyspace", &[
+                Arg::one_of("state", &[
+                    Arg::pure_token("on", "ON"),
+                    Arg::pure_token("off", "OFF"),
+                ]),
+            ]).with_token("KEYSPACE"),
+            Arg::block("filter", &[
+                Arg::string("event").multiple().optional(),
+            ]).with_token("FILTER"),
+            Arg::pure_token("get", "GET"),
+        ])],
+    },
+    CommandDoc {
+        name: "session.throttle_create",
+        summary: "Sets or inspects the rate limits on session creation per user key and client IP.",
+        complexity: Some("O(1), or O(N) in the number of tracked user keys or IPs for OFF"),
+        since: SINCE,
+        arity: -2,
+        key_specs: &[],
+        args: &[Arg::one_of("subcommand", &[
+            Arg::block("user", &[Arg::integer("limit"), Arg::integer("window_seconds")]).with_token("USER"),
+            Arg::block("ip", &[Arg::integer("limit"), Arg::integer("window_seconds")]).with_token("IP"),
+            Arg::one_of("off", &[Arg::pure_token("user", "USER"), Arg::pure_token("ip", "IP")]).with_token("OFF"),
+            Arg::pure_token("status", "STATUS"),
+        ])],
+    },
+    CommandDoc {
+        name: "session.maintenance",
+        summary: "Schedules, runs or reports the daily maintenance jobs.",
+        complexity: Some("O(1) for SCHEDULE, OFF and STATUS; O(N) where N is the number of sessions for RUN"),
+        since: SINCE,
+        arity: -2,
+        key_specs: &[],
+        args: &[Arg::one_of("subcommand", &[
+            Arg::block("schedule", &[Arg::string("time"), Arg::string("tasks").with_token("TASKS")]).with_token("SCHEDULE"),
+            Arg::pure_token("off", "OFF"),
+            Arg::block("run", &[Arg::string("tasks").with_token("TASKS").optional()]).with_token("RUN"),
+            Arg::pure_token("status", "STATUS"),
+        ])],
+    },
+    CommandDoc {
+        name: "session.flush_persistence",
+        summary: "Waits until every mirrored write queued so far has been applied to the keyspace.",
+        complexity: Some("O(1)"),
+        since: SINCE,
+        arity: -1,
+        key_specs: &[],
+        args: &[Arg::integer("ms").with_token("TIMEOUT").optional()],
+    },
+    CommandDoc {
+        name: "session.hotfields",
+        summary: "Returns the most read data fields across all sessions, from sampled counts.",
+        complexity: Some("O(N log N) where N is the number of distinct fields read"),
+        since: SINCE,
+        arity: -1,
+        key_specs: &[],
+        args: &[Arg::integer("n").with_token("TOP").optional()],
+    },
+    CommandDoc {
+        name: "session.activity",
+        summary: "Returns a session's touches and data operations over the last minute, 5 minutes and hour, and whether it is idle, active or bursting.",
+        complexity: Some("O(1)"),
+        since: SINCE,
+        arity: 2,
+        key_specs: &[KeySpec::index(1, KEY_NOT_KEY | KEY_RO)],
+        args: &[SESSION_ID],
+    },
+    CommandDoc {
+        name: "session.expiry_grace",
+        summary: "Sets or returns how long expired sessions stay readable.",
+        complexity: Some("O(1)"),
+        since: SINCE,
+        arity: -2,
+        key_specs: &[],
+        args: &[Arg::one_of("subcommand", &[
+            Arg::integer("seconds").with_token("SET"),
+            Arg::pure_token("get", "GET"),
+        ])],
+    },
+    CommandDoc {
+        name: "session.export",
+        summary: "Exports sessions changed after a cursor, with deleted session ids.",
+        complexity: Some("O(N) where N is the number of sessions"),
+        since: SINCE,
+        arity: -1,
+        key_specs: &[],
+        args: &[
+            Arg::integer("cursor").with_token("SINCE").optional(),
+            Arg::pure_token("reveal", "REVEAL").optional(),
+        ],
+    },
+    CommandDoc {
+        name: "session.replicate_to",
+        summary: "Streams session changes to a standby running this module, or turns streaming off or reports on it.",
+        complexity: Some("O(1)"),
+        since: SINCE,
+        arity: -2,
+        key_specs: &[],
+        args: &[Arg::one_of("target", &[
+            Arg::block("endpoint", &[
+                Arg::string("address"),
+                Arg::string("password").with_token("AUTH").optional(),
+            ]),
+            Arg::pure_token("off", "OFF"),
+            Arg::pure_token("status", "STATUS"),
+        ])],
+    },
+    CommandDoc {
+        name: "session.replica_apply",
+        summary: "Applies a session change streamed from a primary by SESSION.REPLICATE_TO.",
+        complexity: Some("O(1), or O(N) for RESET where N is the number of sessions"),
+        since: SINCE,
+        arity: -2,
+        key_specs: &[],
+        args: &[Arg::one_of("change", &[
+            Arg::string("json").with_token("UPSERT"),
+            Arg::string("session-id").with_token("DEL"),
+            Arg::pure_token("reset", "RESET"),
+        ])],
+    },
+    CommandDoc {
+        name: "session.migrate_format",
+        summary: "Switches the session format new sessions are written in and rewrites stored sessions and archives in it.",
+        complexity: Some("O(N) where N is the number of sessions and archive keys"),
+        since: SINCE,
+        arity: -1,
+        key_specs: &[],
+        args: &[Arg::integer("version").optional()],
+    },
+    CommandDoc {
+        name: "session.dlq",
+        summary: "Lists, retries or purges dead-lettered bridge writes.",
+        complexity: Some("O(N) where N is the number of dead-lettered writes"),
+        since: SINCE,
+        arity: -2,
+        key_specs: &[],
+        args: &[Arg::one_of("subcommand", &[
+            Arg::pure_token("list", "LIST"),
+            Arg::string("id").with_token("RETRY"),
+            Arg::string("id").with_token("PURGE"),
+        ])],
+    },
+    CommandDoc {
+        name: "session.lockstats",
+        summary: "Returns multi-session lock metrics.",
+        complexity: Some("O(1)"),
+        since: SINCE,
+        arity: 1,
+        key_specs: &[],
+        args: &[],
+    },
+    CommandDoc {
+        name: "session.shards",
+        summary: "Reports per-shard statistics of the hashmap, or changes its shard count, through CUSTOM.SHARDS.",
+        complexity: Some("O(N) where N is the number of hashmap keys"),
+        since: SINCE,
+        arity: -1,
+        key_specs: &[],
+        args: &[Arg::one_of("subcommand", &[
+            Arg::pure_token("stats", "STATS"),
+            Arg::integer("count").with_token("CONFIG"),
+            Arg::pure_token("rebalance", "REBALANCE"),
+        ]).optional()],
+    },
+    CommandDoc {
+        name: "session.config",
+        summary: "Reloads the config file, or reports which file was loaded and how the last load went.",
+        complexity: Some("O(1)"),
+        since: SINCE,
+        arity: 2,
+        key_specs: &[],
+        args: &[Arg::one_of("subcommand", &[
+            Arg::pure_token("reload", "RELOAD"),
+            Arg::pure_token("status", "STATUS"),
+        ])],
+    },
+    CommandDoc {
+        name: "session.hook",
+        summary: "Loads, unloads or lists sandboxed WebAssembly hooks.",
+        complexity: Some("O(N) where N is the size of the module being loaded"),
+        since: SINCE,
+        arity: -2,
+        key_specs: &[],
+        args: &[Arg::one_of("subcommand", &[
+            Arg::block("load", &[Arg::string("point"), Arg::string("wasm-bytes")]).with_token("LOAD"),
+            Arg::string("point").with_token("UNLOAD"),
+            Arg::pure_token("list", "LIST"),
+        ])],
+    },
+    CommandDoc {
+        name: "session.bench",
+        summary: "Benchmarks the bridge to the custom hashmap module, or compares its direct and ring transports.",
+        complexity: Some("O(N) where N is the number of operations"),
+        since: SINCE,
+        arity: -3,
+        key_specs: &[],
+        args: &[Arg::one_of("run", &[
+            Arg::block("lifecycle", &[
+                Arg::integer("ops"),
+                Arg::integer("keysize"),
+                Arg::integer("valsize"),
+                Arg::integer("concurrency"),
+            ]),
+            Arg::block("bridge", &[
+                Arg::integer("ops"),
+                Arg::integer("batch").with_token("BATCH").optional(),
+            ]).with_token("BRIDGE"),
+        ])],
+    },
+    CommandDoc {
+        name: "session.selftest",
+        summary: "Exercises the store, both bridge paths, timers and the keyspace and reports each check.",
+        complexity: Some("O(1)"),
+        since: SINCE,
+        arity: 1,
+        key_specs: &[],
+        args: &[],
+    },
+    CommandDoc {
+        name: "session.debug",
+        summary: "Reports buffers received from the custom hashmap's C API and not yet released, per call site.",
+        complexity: Some("O(1)"),
+        since: SINCE,
+        arity: 2,
+        key_specs: &[],
+        args: &[Arg::one_of("subcommand", &[
+            Arg::pure_token("leakcheck", "LEAKCHECK"),
+        ])],
+    },
+    CommandDoc {
+        name: "session.bind",
+        summary: "Binds a session to the calling client connection.",
+        complexity: Some("O(1)"),
+        since: SINCE,
+        arity: -2,
+        key_specs: &[SESSION_WRITE],
+        args: &[
+            SESSION_ID,
+            Arg::one_of("action", &[
+                Arg::pure_token("delete", "DELETE"),
+                Arg::pure_token("idle", "IDLE"),
+            ]).with_token("ON_DISCONNECT").optional(),
+        ],
+    },
+    CommandDoc {
+        name: "session.unbind",
+        summary: "Releases a session's binding to a client connection.",
+        complexity: Some("O(1)"),
+        since: SINCE,
+        arity: 2,
+        key_specs: &[SESSION_WRITE],
+        args: &[SESSION_ID],
+    },
+    CommandDoc {
+        name: "session.trace",
+        summary: "Dumps recorded spans or configures span export.",
+        complexity: Some("O(N) where N is the number of spans returned"),
+        since: SINCE,
+        arity: -2,
+        key_specs: &[],
+        args: &[Arg::one_of("subcommand", &[
+            Arg::block("recent", &[Arg::integer("count").optional()]).with_token("RECENT"),
+            Arg::string("path").with_token("EXPORT"),
+            Arg::one_of("level", &[
+                Arg::pure_token("off", "off"),
+                Arg::pure_token("error", "error"),
+                Arg::pure_token("warn", "warn"),
+                Arg::pure_token("info", "info"),
+                Arg::pure_token("debug", "debug"),
+                Arg::pure_token("trace", "trace"),
+            ]).with_token("LEVEL"),
+            Arg::pure_token("status", "STATUS"),
+        ])],
+    },
+];
@@ -0,0 +1,429 @@
+use std::collections::{BTreeSet, HashMap};
+use std::sync::RwLock;
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use base64::Engine;
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use redis_module::{AclPermissions, Context, NextArg, RedisError, RedisResult, RedisString, RedisValue};
+use serde_json::Value;
+use session_core::errors;
+
+use crate::{init_sessions, spill, tree, Session};
+
+// Marks a stored value as ciphertext: enc:<key id>:<base64 nonce and ciphertext>
+const SEALED_PREFIX: &str = "enc:";
+
+// Tenant of sessions created without an app
+const DEFAULT_TENANT: &str = "default";
+
+// Bytes of a ChaCha20-Poly1305 key and nonce
+const KEY_BYTES: usize = 32;
+const NONCE_BYTES: usize = 12;
+
+// DECRYPT key_id is allowed for users with read access to this key prefix plus the key id
+const DECRYPT_ACL_PREFIX: &str = "session:key:";
+
+#[derive(Default)]
+struct Keyring {
+    // Key material and owning tenant by key id
+    keys: HashMap<String, (String, [u8; KEY_BYTES])>,
+    // Key id new values of each tenant are sealed with
+    current: HashMap<String, String>,
+    // Key ids in the order they were added, so deleting a tenant's current
+    // key hands over to its newest remaining one
+    added: Vec<String>,
+}
+
+impl Keyring {
+    fn add(&mut self, tenant: String, key_id: String, key: [u8; KEY_BYTES]) {
+        self.keys.insert(key_id.clone(), (tenant.clone(), key));
+        self.added.push(key_id.clone());
+        self.current.insert(tenant, key_id);
+    }
+
+    // Remove a key; if it was its tenant's current one, the tenant's newest
+    // remaining key becomes current. Returns whether the key existed.
+    fn remove(&mut self, key_id: &str) -> bool {
+        let Some((tenant, _)) = self.keys.remove(key_id) else { return false };
+        self.added.retain(|added| added != key_id);
+        if self.current.get(&tenant).is_some_and(|current| current == key_id) {
+            let newest = self.added.iter().rev()
+                .find(|added| self.keys.get(*added).is_some_and(|(owner, _)| *owner == tenant))
+                .cloned();
+            match newest {
+                Some(newest) => self.current.insert(tenant, newest),
+                None => self.current.remove(&tenant),
+            };
+        }
+        true
+    }
+}
+
+// Field names (or dotted subtree paths) whose values are stored encrypted
+static mut ENCRYPTED_FIELDS: Option<RwLock<BTreeSet<String>>> = None;
+static mut KEYRING: Option<RwLock<Keyring>> = None;
+
+// Initialize the encrypted field set
+fn init_encrypted_fields() -> &'static RwLock<BTreeSet<String>> {
+    unsafe {
+        if ENCRYPTED_FIELDS.is_none() {
+            ENCRYPTED_FIELDS = Some(RwLock::new(BTreeSet::new()));
+        }
+        ENCRYPTED_FIELDS.as_ref().unwrap()
+    }
+}
+
+// Initialize the tenant keyring
+fn init_keyring() -> &'static RwLock<Keyring> {
+    unsafe {
+        if KEYRING.is_none() {
+            KEYRING = Some(RwLock::new(Keyring::default()));
+        }
+        KEYRING.as_ref().unwrap()
+    }
+}
+
+// Whether any field is stored encrypted
+pub fn is_enabled() -> bool {
+    init_encrypted_fields().read().is_ok_and(|fields| !fields.is_empty())
+}
+
+// Tenants are apps; sessions without one share the default tenant
+fn tenant(session: &Session) -> &str {
+    session.app.as_deref().unwrap_or(DEFAULT_TENANT)
+}
+
+// Ties a ciphertext to the session and field it was written to, so it can't be replayed elsewhere
+fn aad(session_id: &str, field: &str) -> Vec<u8> {
+    [session_id.as_bytes(), b"\0", field.as_bytes()].concat()
+}
+
+fn is_encrypted(fields: &BTreeSet<String>, field: &str) -> bool {
+    fields.iter().any(|encrypted| field == encrypted || tree::in_subtree(field, encrypted))
+}
+
+fn is_sealed_with(value: &str, key_id: &str) -> bool {
+    value.strip_prefix(SEALED_PREFIX)
+        .and_then(|sealed| sealed.strip_prefix(key_id))
+        .is_some_and(|sealed| sealed.starts_with(':'))
+}
+
+// Number of stored values sealed under a key, spilled ones included
+fn sealed_count(ctx: &Context, key_id: &str) -> Result<usize, RedisError> {
+    let sessions_map = init_sessions().read().map_err(|_| {
+        RedisError::String("Failed to acquire read lock".to_string())
+    })?;
+    Ok(sessions_map.values()
+        .flat_map(|session| session.data.keys().map(move |field| (session, field)))
+        .filter(|(session, field)| spill::value(ctx, session, field).is_some_and(|value| is_sealed_with(&value, key_id)))
+        .count())
+}
+
+// The value to store for a field written to a session: ciphertext under the
+// tenant's current key when the field is encrypted, otherwise the value itself
+pub fn seal(session: &Session, field: &str, value: String) -> Result<String, RedisError> {
+    let fields = init_encrypted_fields().read().map_err(|_| {
+        RedisError::String("Failed to acquire read lock".to_string())
+    })?;
+    if !is_encrypted(&fields, field) {
+        return Ok(value);
+    }
+
+    let keyring = init_keyring().read().map_err(|_| {
+        RedisError::String("Failed to acquire read lock".to_string())
+    })?;
+    let tenant = tenant(session);
+    let (key_id, key) = keyring.current.get(tenant)
+        .and_then(|key_id| Some((key_id, &keyring.keys.get(key_id)?.1)))
+        .ok_or_else(|| RedisError::String(format!(
+            "Field {} is encrypted but tenant {} has no encryption key", field, tenant,
+        )))?;
+
+    let mut nonce = [0u8; NONCE_BYTES];
+    OsRng.fill_bytes(&mut nonce);
+    let payload = Payload { msg: value.as_bytes(), aad: &aad(&session.id, field) };
+    let ciphertext = ChaCha20Poly1305::new(Key::from_slice(key))
+        .encrypt(Nonce::from_slice(&nonce), payload)
+        .map_err(|_| RedisError::Str("Failed to encrypt field"))?;
+
+    Ok(format!("{}{}:{}", SEALED_PREFIX, key_id, URL_SAFE_NO_PAD.encode([&nonce[..], &ciphertext].concat())))
+}
+
+// Opens values sealed under one key id for a caller allowed to use it
+pub struct Decryptor {
+    key_id: String,
+    key: [u8; KEY_BYTES],
+}
+
+impl Decryptor {
+    // The current user must be able to read DECRYPT_ACL_PREFIX<key_id>, e.g. `%R~session:key:*`
+    pub fn for_caller(ctx: &Context, key_id: String) -> Result<Decryptor, RedisError> {
+        let user = ctx.get_current_user();
+        let acl_key = format!("{}{}", DECRYPT_ACL_PREFIX, key_id);
+        ctx.acl_check_key_permission(&user, &ctx.create_string(acl_key.as_str()), &AclPermissions::ACCESS).map_err(|_| {
+            RedisError::String(format!("{} DECRYPT requires read access to the key {}", errors::NO_PERMISSION, acl_key))
+        })?;
+
+        let keyring = init_keyring().read().map_err(|_| {
+            RedisError::String("Failed to acquire read lock".to_string())
+        })?;
+        let key = keyring.keys.get(&key_id)
+            .map(|(_, key)| *key)
+            .ok_or_else(|| RedisError::String(format!("Unknown encryption key: {}", key_id)))?;
+        Ok(Decryptor { key_id, key })
+    }
+
+    // The plaintext of a value sealed under this key, None for anything else
+    fn open(&self, session_id: &str, field: &str, value: &str) -> Option<String> {
+        let sealed = value.strip_prefix(SEALED_PREFIX)?
+            .strip_prefix(self.key_id.as_str())?
+            .strip_prefix(':')?;
+        let sealed = URL_SAFE_NO_PAD.decode(sealed).ok()?;
+        if sealed.len() < NONCE_BYTES {
+            return None;
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_BYTES);
+        let payload = Payload { msg: ciphertext, aad: &aad(session_id, field) };
+        let plaintext = ChaCha20Poly1305::new(Key::from_slice(&self.key))
+            .decrypt(Nonce::from_slice(nonce), payload)
+            .ok()?;
+        String::from_utf8(plaintext).ok()
+    }
+
+    // Decrypt the `data` object of a serialized session in place. Values
+    // sealed under other keys, or redacted, are left as they are.
+    pub fn decrypt_session(&self, json: &mut Value) {
+        let Some(session_id) = json.get("id").and_then(Value::as_str).map(str::to_string) else { return };
+        if let Some(Value::Object(data)) = json.get_mut("data") {
+            for (field, value) in data.iter_mut() {
+                let plaintext = value.as_str().and_then(|sealed| self.open(&session_id, field, sealed));
+                if let Some(plaintext) = plaintext {
+                    *value = Value::String(plaintext);
+                }
+            }
+        }
+    }
+}
+
+fn parse_key(key: &str) -> Result<[u8; KEY_BYTES], RedisError> {
+    STANDARD.decode(key).ok()
+        .and_then(|key| key.try_into().ok())
+        .ok_or_else(|| RedisError::String(format!("Encryption key must be {} bytes, base64 encoded", KEY_BYTES)))
+}
+
+// Manage field-level encryption:
+// SESSION.ENCRYPTION KEY ADD tenant key_id key
+// SESSION.ENCRYPTION KEY DEL key_id [FORCE]
+// SESSION.ENCRYPTION KEY LIST
+// SESSION.ENCRYPTION FIELD ADD field [field ...]
+// SESSION.ENCRYPTION FIELD DEL field [field ...]
+// SESSION.ENCRYPTION FIELD LIST
+// Tenants are session apps, or `default` for sessions without one.
+#[tracing::instrument(name = "session.encryption", skip_all)]
+pub fn session_encryption(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let target = args.next_string()?.to_uppercase();
+    let subcommand = args.next_string()?.to_uppercase();
+
+    match (target.as_str(), subcommand.as_str()) {
+        ("KEY", "ADD") => {
+            let tenant = args.next_string()?;
+            let key_id = args.next_string()?;
+            let key = parse_key(&args.next_string()?)?;
+            args.done()?;
+            if key_id.is_empty() || key_id.contains(':') {
+                return Err(RedisError::String(format!("Invalid key id: {}", key_id)));
+            }
+
+            let mut keyring = init_keyring().write().map_err(|_| {
+                RedisError::String("Failed to acquire write lock".to_string())
+            })?;
+            if keyring.keys.contains_key(&key_id) {
+                return Err(RedisError::String(format!("Encryption key already exists: {}", key_id)));
+            }
+            // The newest key seals new values; older ones stay for reading
+            keyring.add(tenant, key_id, key);
+            Ok(RedisValue::SimpleStringStatic("OK"))
+        },
+        ("KEY", "DEL") => {
+            let key_id = args.next_string()?;
+            let force = match args.next() {
+                Some(option) if option.to_string_lossy().eq_ignore_ascii_case("FORCE") => true,
+                Some(option) => return Err(RedisError::String(format!("Unknown option: {}", option))),
+                None => false,
+            };
+            args.done()?;
+
+            // Values sealed under the key can't be read once it is gone
+            if !force {
+                let sealed = sealed_count(ctx, &key_id)?;
+                if sealed > 0 {
+                    return Err(RedisError::String(format!(
+                        "Encryption key {} still seals {} values; pass FORCE to delete it anyway", key_id, sealed,
+                    )));
+                }
+            }
+
+            let mut keyring = init_keyring().write().map_err(|_| {
+                RedisError::String("Failed to acquire write lock".to_string())
+            })?;
+            Ok(RedisValue::Integer(keyring.remove(&key_id) as i64))
+        },
+        ("KEY", "LIST") => {
+            args.done()?;
+
+            let keyring = init_keyring().read().map_err(|_| {
+                RedisError::String("Failed to acquire read lock".to_string())
+            })?;
+            let mut keys: Vec<(&String, &String)> = keyring.keys.iter().map(|(key_id, (tenant, _))| (tenant, key_id)).collect();
+            keys.sort();
+            Ok(RedisValue::Array(keys.into_iter()
+                .map(|(tenant, key_id)| RedisValue::Array(vec![
+                    RedisValue::BulkString(tenant.clone()),
+                    RedisValue::BulkString(key_id.clone()),
+                    RedisValue::Bool(keyring.current.get(tenant) == Some(key_id)),
+                ]))
+                .collect()))
+        },
+        ("FIELD", "ADD") | ("FIELD", "DEL") => {
+            let fields: Vec<String> = args.map(|field| field.to_string_lossy()).collect();
+            if fields.is_empty() {
+                return Err(RedisError::WrongArity);
+            }
+
+            let mut encrypted = init_encrypted_fields().write().map_err(|_| {
+                RedisError::String("Failed to acquire write lock".to_string())
+            })?;
+            let changed = if subcommand == "ADD" {
+                fields.into_iter().filter(|field| encrypted.insert(field.clone())).count()
+            } else {
+                fields.iter().filter(|field| encrypted.remove(*field)).count()
+            };
+            Ok(RedisValue::Integer(changed as i64))
+        },
+        ("FIELD", "LIST") => {
+            args.done()?;
+
+            let encrypted = init_encrypted_fields().read().map_err(|_| {
+                RedisError::String("Failed to acquire read lock".to_string())
+            })?;
+            Ok(RedisValue::Array(encrypted.iter().map(|field| RedisValue::BulkString(field.clone())).collect()))
+        },
+        _ => Err(RedisError::String(format!("Unknown SESSION.ENCRYPTION subcommand: {} {}", target, subcommand))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Give `tenant` the key `key_id`, encrypt `field` and return a session of the tenant.
+    // The keyring and field set are global, so each test uses its own names.
+    fn setup(tenant: &str, key_id: &str, field: &str) -> (Session, Decryptor) {
+        let key = [key_id.len() as u8; KEY_BYTES];
+        init_keyring().write().unwrap().add(tenant.to_string(), key_id.to_string(), key);
+        init_encrypted_fields().write().unwrap().insert(field.to_string());
+
+        let mut session = Session::new(format!("{}-session", tenant), "user:1".to_string(), 0);
+        session.app = Some(tenant.to_string());
+        (session, Decryptor { key_id: key_id.to_string(), key })
+    }
+
+    #[test]
+    fn seals_and_opens() {
+        let (session, decryptor) = setup("round-trip", "rt-1", "rt_card");
+        let sealed = seal(&session, "rt_card", "4111".to_string()).unwrap();
+        assert!(sealed.starts_with("enc:rt-1:"));
+        assert!(!sealed.contains("4111"));
+        assert_eq!(decryptor.open(&session.id, "rt_card", &sealed), Some("4111".to_string()));
+
+        // Fresh nonces make every seal different
+        assert_ne!(seal(&session, "rt_card", "4111".to_string()).unwrap(), sealed);
+    }
+
+    #[test]
+    fn seals_subtrees() {
+        let (session, decryptor) = setup("subtree", "st-1", "st_payment");
+        let sealed = seal(&session, "st_payment.card", "4111".to_string()).unwrap();
+        assert_eq!(decryptor.open(&session.id, "st_payment.card", &sealed), Some("4111".to_string()));
+        assert_eq!(seal(&session, "st_paymentx", "plain".to_string()).unwrap(), "plain");
+    }
+
+    #[test]
+    fn refuses_replayed_or_tampered_values() {
+        let (session, decryptor) = setup("tamper", "tp-1", "tp_card");
+        let sealed = seal(&session, "tp_card", "4111".to_string()).unwrap();
+
+        assert_eq!(decryptor.open(&session.id, "tp_other", &sealed), None);
+        assert_eq!(decryptor.open("another-session", "tp_card", &sealed), None);
+        let wrong_key = Decryptor { key_id: "tp-2".to_string(), key: decryptor.key };
+        assert_eq!(wrong_key.open(&session.id, "tp_card", &sealed), None);
+        let other_key = Decryptor { key_id: "tp-1".to_string(), key: [0; KEY_BYTES] };
+        assert_eq!(other_key.open(&session.id, "tp_card", &sealed), None);
+
+        let mut tampered = sealed.clone().into_bytes();
+        let last = tampered.len() - 1;
+        tampered[last] = if tampered[last] == b'A' { b'B' } else { b'A' };
+        assert_eq!(decryptor.open(&session.id, "tp_card", &String::from_utf8(tampered).unwrap()), None);
+        assert_eq!(decryptor.open(&session.id, "tp_card", "enc:tp-1:AAAA"), None);
+        assert_eq!(decryptor.open(&session.id, "tp_card", "enc:tp-1:not base64!"), None);
+        assert_eq!(decryptor.open(&session.id, "tp_card", "4111"), None);
+    }
+
+    #[test]
+    fn decrypts_serialized_sessions() {
+        let (session, decryptor) = setup("serialized", "sz-1", "sz_card");
+        let sealed = seal(&session, "sz_card", "4111".to_string()).unwrap();
+        let mut json = serde_json::json!({
+            "id": session.id,
+            "data": {"sz_card": sealed, "plain": "x", "redacted": "[REDACTED]"},
+        });
+        decryptor.decrypt_session(&mut json);
+        assert_eq!(json["data"], serde_json::json!({"sz_card": "4111", "plain": "x", "redacted": "[REDACTED]"}));
+    }
+
+    #[test]
+    fn refuses_tenants_without_a_key() {
+        let (_, _) = setup("keyed", "kd-1", "kd_card");
+        let mut session = Session::new("unkeyed-session".to_string(), "user:1".to_string(), 0);
+        session.app = Some("unkeyed".to_string());
+        assert!(seal(&session, "kd_card", "4111".to_string()).is_err());
+        assert_eq!(seal(&session, "kd_plain", "4111".to_string()).unwrap(), "4111");
+    }
+
+    #[test]
+    fn parses_keys() {
+        assert!(parse_key(&STANDARD.encode([7u8; KEY_BYTES])).is_ok());
+        assert!(parse_key(&STANDARD.encode([7u8; 16])).is_err());
+        assert!(parse_key("not base64").is_err());
+    }
+
+    #[test]
+    fn hands_over_to_the_newest_remaining_key() {
+        let mut keyring = Keyring::default();
+        keyring.add("acme".to_string(), "k1".to_string(), [1; KEY_BYTES]);
+        keyring.add("other".to_string(), "o1".to_string(), [2; KEY_BYTES]);
+        keyring.add("acme".to_string(), "k2".to_string(), [3; KEY_BYTES]);
+        keyring.add("acme".to_string(), "k3".to_string(), [4; KEY_BYTES]);
+        assert_eq!(keyring.current["acme"], "k3");
+
+        // Removing an older key leaves the current one alone
+        assert!(keyring.remove("k2"));
+        assert_eq!(keyring.current["acme"], "k3");
+        assert!(keyring.remove("k3"));
+        assert_eq!(keyring.current["acme"], "k1");
+        assert!(keyring.remove("k1"));
+        assert!(!keyring.current.contains_key("acme"));
+        assert_eq!(keyring.current["other"], "o1");
+        assert!(!keyring.remove("k1"));
+    }
+
+    #[test]
+    fn recognises_values_sealed_under_a_key() {
+        assert!(is_sealed_with("enc:k1:AAAA", "k1"));
+        assert!(!is_sealed_with("enc:k10:AAAA", "k1"));
+        assert!(!is_sealed_with("enc:k1", "k1"));
+        assert!(!is_sealed_with("k1:AAAA", "k1"));
+    }
+}
@@ -0,0 +1,167 @@
+use std::sync::atomic::{AtomicU64, AtomicU8, AtomicUsize, Ordering};
+use redis_module::{Context, RedisError, RedisValue};
+use session_core::{errors, events};
+
+use crate::retry::{self, BridgeOp};
+use crate::store::SessionStore;
+use crate::{binding, changes, init_sessions, unlink_user_key, webhooks};
+
+// Most sessions evicted per expiry sweep, so one sweep never stalls the server
+pub const EVICTION_BATCH: usize = 100;
+
+// Share of maxmemory (in percent) at which sessions start being evicted; 0 turns eviction off
+static EVICT_MEMORY_PERCENT: AtomicU64 = AtomicU64::new(0);
+static EVICTED: AtomicU64 = AtomicU64::new(0);
+
+// What a new session does once max_sessions sessions are live
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitPolicy {
+    Reject,
+    // Evict the least recently used session
+    EvictLru,
+    // Evict the session created first
+    EvictOldest,
+}
+
+impl LimitPolicy {
+    const ALL: [LimitPolicy; 3] = [LimitPolicy::Reject, LimitPolicy::EvictLru, LimitPolicy::EvictOldest];
+
+    pub fn parse(name: &str) -> Option<LimitPolicy> {
+        LimitPolicy::ALL.into_iter().find(|policy| policy.name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LimitPolicy::Reject => "reject",
+            LimitPolicy::EvictLru => "evict-lru",
+            LimitPolicy::EvictOldest => "evict-oldest",
+        }
+    }
+}
+
+// Most sessions live at once; 0 for no limit
+static MAX_SESSIONS: AtomicUsize = AtomicUsize::new(0);
+static LIMIT_POLICY: AtomicU8 = AtomicU8::new(LimitPolicy::Reject as u8);
+static LIMIT_REJECTIONS: AtomicU64 = AtomicU64::new(0);
+
+pub fn set_max_sessions(max: usize) {
+    MAX_SESSIONS.store(max, Ordering::Relaxed);
+}
+
+pub fn max_sessions() -> usize {
+    MAX_SESSIONS.load(Ordering::Relaxed)
+}
+
+pub fn set_limit_policy(policy: LimitPolicy) {
+    LIMIT_POLICY.store(policy as u8, Ordering::Relaxed);
+}
+
+pub fn limit_policy() -> LimitPolicy {
+    LimitPolicy::ALL[LIMIT_POLICY.load(Ordering::Relaxed) as usize]
+}
+
+// New sessions refused at max_sessions since the module was loaded
+pub fn limit_rejections() -> u64 {
+    LIMIT_REJECTIONS.load(Ordering::Relaxed)
+}
+
+pub fn set_evict_memory_percent(percent: u64) {
+    EVICT_MEMORY_PERCENT.store(percent, Ordering::Relaxed);
+}
+
+pub fn evict_memory_percent() -> u64 {
+    EVICT_MEMORY_PERCENT.load(Ordering::Relaxed)
+}
+
+// Sessions evicted since the module was loaded
+pub fn evicted() -> u64 {
+    EVICTED.load(Ordering::Relaxed)
+}
+
+// used_memory as a percentage of maxmemory, None without a maxmemory limit
+pub fn memory_percent(ctx: &Context) -> Option<u64> {
+    let info = match ctx.call("INFO", &["memory"]) {
+        Ok(RedisValue::BulkString(info)) | Ok(RedisValue::SimpleString(info)) => info,
+        _ => return None,
+    };
+    let field = |name: &str| info.lines().find_map(|line| {
+        line.strip_prefix(name)?.strip_prefix(':')?.trim().parse::<u64>().ok()
+    });
+    let maxmemory = field("maxmemory").filter(|maxmemory| *maxmemory > 0)?;
+    Some(field("used_memory")? * 100 / maxmemory)
+}
+
+// Evict a batch of sessions if memory use is past the threshold: LOW sessions
+// first, then NORMAL ones, least recently used first; HIGH sessions are never
+// evicted. Returns how many sessions were evicted.
+pub fn evict_under_pressure(ctx: &Context) -> usize {
+    let threshold = evict_memory_percent();
+    if threshold == 0 || memory_percent(ctx).is_none_or(|percent| percent < threshold) {
+        return 0;
+    }
+
+    let mut sessions_map = match init_sessions().write() {
+        Ok(sessions_map) => sessions_map,
+        Err(_) => return 0,
+    };
+    let victims = sessions_map.eviction_order(EVICTION_BATCH);
+    evict(ctx, &mut sessions_map, victims)
+}
+
+// Make room for one more session under max_sessions, evicting as the policy
+// says. Refuses the session with a MAXSESSIONS error under the reject policy,
+// or when only HIGH sessions are left to evict.
+pub fn make_room(ctx: &Context) -> Result<(), RedisError> {
+    let max = max_sessions();
+    if max == 0 {
+        return Ok(());
+    }
+
+    let mut sessions_map = init_sessions().write().map_err(|_| {
+        RedisError::String("Failed to acquire write lock".to_string())
+    })?;
+    make_room_in(ctx, &mut sessions_map)
+}
+
+// As make_room, for callers already holding the sessions lock
+pub fn make_room_in(ctx: &Context, sessions_map: &mut SessionStore) -> Result<(), RedisError> {
+    let max = max_sessions();
+    if max == 0 {
+        return Ok(());
+    }
+    let excess = (sessions_map.len() + 1).saturating_sub(max);
+    if excess == 0 {
+        return Ok(());
+    }
+    let victims = match limit_policy() {
+        LimitPolicy::Reject => Vec::new(),
+        LimitPolicy::EvictLru => sessions_map.eviction_order(excess),
+        LimitPolicy::EvictOldest => sessions_map.oldest_eviction_order(excess),
+    };
+    if victims.len() < excess {
+        LIMIT_REJECTIONS.fetch_add(1, Ordering::Relaxed);
+        return Err(RedisError::String(format!(
+            "{} {} sessions are live, the most allowed", errors::MAX_SESSIONS, sessions_map.len(),
+        )));
+    }
+    evict(ctx, sessions_map, victims);
+    Ok(())
+}
+
+// Remove sessions as evicted; returns how many were still there
+fn evict(ctx: &Context, sessions_map: &mut SessionStore, victims: Vec<String>) -> usize {
+    let mut evicted = 0;
+    for session_id in victims {
+        if let Some(session) = sessions_map.remove(&session_id) {
+            binding::forget(&session);
+            changes::record_deletion(&session_id);
+            webhooks::emit(events::EVICTED, &session_id, &session.user_key);
+            if let Err(err) = unlink_user_key(ctx, &session.user_key) {
+                retry::enqueue(ctx, &session_id, BridgeOp::Del { key: session.user_key.clone() }, err);
+            }
+            evicted += 1;
+        }
+    }
+    EVICTED.fetch_add(evicted as u64, Ordering::Relaxed);
+    evicted
+}
@@ -0,0 +1,607 @@
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use chrono::{DateTime, Utc};
+use redis_module::{Context, NextArg, RedisError, RedisResult, RedisString, RedisValue};
+use session_core::{events, ttl, Priority};
+
+use crate::retry::{self, BridgeOp};
+use crate::timers::EXPIRY_SWEEP;
+use crate::{activity, binding, changes, eviction, history, init_sessions, next_secs, spill, throttle, unlink_user_key, webhooks, writable_session, Session, SessionExt};
+
+// Channel used for warnings when none is configured
+const DEFAULT_WARNING_CHANNEL: &str = "session:expiring_soon";
+
+// Where expiring_soon events are delivered
+#[derive(Debug, Clone)]
+enum WarningSink {
+    // PUBLISH a JSON event to a pub/sub channel
+    Channel(String),
+    // XADD the event's fields to a stream
+    Stream(String),
+}
+
+// Inactivity warning settings; no lead time means warnings are off
+struct WarningConfig {
+    lead_secs: Option<u64>,
+    sink: WarningSink,
+}
+
+// A warning collected during a sweep, sent once the sessions lock is released
+struct Warning {
+    session_id: String,
+    user_key: String,
+    expires_at: DateTime<Utc>,
+}
+
+// How long expired sessions stay readable (flagged `expired`) before removal
+static GRACE_SECS: AtomicU64 = AtomicU64::new(0);
+
+// Idle timeout of sessions created without TTL or IDLE; 0 for none
+static IDLE_TIMEOUT_SECS: AtomicU64 = AtomicU64::new(0);
+
+// Longest any session lives from its creation, however active; 0 for no limit
+static MAX_LIFETIME_SECS: AtomicU64 = AtomicU64::new(0);
+
+static mut WARNING_CONFIG: Option<Mutex<WarningConfig>> = None;
+
+// Initialize the warning settings
+fn init_warning_config() -> &'static Mutex<WarningConfig> {
+    unsafe {
+        if WARNING_CONFIG.is_none() {
+            WARNING_CONFIG = Some(Mutex::new(WarningConfig {
+                lead_secs: None,
+                sink: WarningSink::Channel(DEFAULT_WARNING_CHANNEL.to_string()),
+            }));
+        }
+        WARNING_CONFIG.as_ref().unwrap()
+    }
+}
+
+// Deliver an expiring_soon event
+fn emit_warning(ctx: &Context, sink: &WarningSink, warning: &Warning) {
+    let expires_at = warning.expires_at.to_rfc3339();
+    let seconds_left = (warning.expires_at - Utc::now()).num_seconds().max(0).to_string();
+
+    let result = match sink {
+        WarningSink::Channel(channel) => {
+            let event = serde_json::json!({
+                "event": "expiring_soon",
+                "session_id": warning.session_id,
+                "user_key": warning.user_key,
+                "expires_at": expires_at,
+                "seconds_left": seconds_left,
+            });
+            ctx.call("PUBLISH", &[channel.as_str(), &event.to_string()])
+        },
+        WarningSink::Stream(key) => ctx.call("XADD", &[
+            key.as_str(), "*",
+            "event", "expiring_soon",
+            "session_id", &warning.session_id,
+            "user_key", &warning.user_key,
+            "expires_at", &expires_at,
+            "seconds_left", &seconds_left,
+        ]),
+    };
+
+    if let Err(err) = result {
+        ctx.log_warning(&format!("Failed to emit expiring_soon for session {}: {}", warning.session_id, err));
+    }
+}
+
+// Warn about sessions entering their warning window and delete expired ones
+// once their grace window is over
+fn sweep(ctx: &Context, _data: ()) {
+    let (lead_secs, sink) = match init_warning_config().lock() {
+        Ok(config) => (config.lead_secs, config.sink.clone()),
+        Err(_) => (None, WarningSink::Channel(DEFAULT_WARNING_CHANNEL.to_string())),
+    };
+
+    let now = Utc::now();
+    let grace_secs = GRACE_SECS.load(Ordering::Relaxed);
+    let mut warnings = Vec::new();
+    let (mut removed, mut store_size) = (0, 0);
+
+    if let Ok(mut sessions_map) = init_sessions().write() {
+        let mut expired = Vec::new();
+
+        // Only sessions inside the warning window (or already expired) need a look
+        let horizon = ttl::after(now, lead_secs.unwrap_or(0));
+
+        // Sessions that reach the maximum lifetime within the window, which
+        // only sessions older than the limit itself may not be capped at yet
+        let max_lifetime = max_lifetime_secs();
+        if max_lifetime > 0 {
+            let cutoff = ttl::before(horizon, max_lifetime);
+            for session_id in sessions_map.created_before(cutoff) {
+                if let Some(session) = sessions_map.get_mut(&session_id) {
+                    if session.cap_lifetime(max_lifetime) {
+                        session.mark_changed();
+                    }
+                }
+            }
+        }
+        for session_id in sessions_map.expiring_before(horizon) {
+            let session = match sessions_map.get(&session_id) {
+                Some(session) => session,
+                None => continue,
+            };
+            let expires_at = match session.expires_at {
+                Some(expires_at) => expires_at,
+                None => continue,
+            };
+            if ttl::after(expires_at, grace_secs) <= now {
+                expired.push(session_id);
+            } else if expires_at > now && !session.expiry_warned {
+                warnings.push(Warning {
+                    session_id: session.id.clone(),
+                    user_key: session.user_key.clone(),
+                    expires_at,
+                });
+            }
+        }
+
+        for warning in &warnings {
+            if let Some(session) = sessions_map.get_mut(&warning.session_id) {
+                session.expiry_warned = true;
+            }
+        }
+
+        removed = expired.len();
+        for session_id in expired {
+            if let Some(session) = sessions_map.remove(&session_id) {
+                binding::forget(&session);
+                changes::record_deletion(&session_id);
+                webhooks::emit(events::EXPIRED, &session_id, &session.user_key);
+                if let Err(err) = unlink_user_key(ctx, &session.user_key) {
+                    retry::enqueue(ctx, &session_id, BridgeOp::Del { key: session.user_key.clone() }, err);
+                }
+            }
+        }
+        store_size = sessions_map.len();
+    }
+    removed += eviction::evict_under_pressure(ctx);
+    spill::delete_orphans(ctx);
+    history::prune();
+    activity::prune();
+    throttle::prune();
+
+    for warning in &warnings {
+        emit_warning(ctx, &sink, warning);
+        webhooks::emit(events::EXPIRING_SOON, &warning.session_id, &warning.user_key);
+    }
+
+    // Come back sooner while sessions are expiring, back off while none are
+    ctx.create_timer(EXPIRY_SWEEP.next(removed + warnings.len(), store_size), sweep, ());
+}
+
+// Start the expiry sweeper at module load
+pub fn start(ctx: &Context) {
+    ctx.create_timer(EXPIRY_SWEEP.current(), sweep, ());
+}
+
+// Configure inactivity warnings:
+// SESSION.EXPIRY_WARNING SET seconds [CHANNEL channel | STREAM key]
+// SESSION.EXPIRY_WARNING OFF
+// SESSION.EXPIRY_WARNING GET
+#[tracing::instrument(name = "session.expiry_warning", skip_all)]
+pub fn session_expiry_warning(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let subcommand = args.next_string()?.to_uppercase();
+
+    let mut config = init_warning_config().lock().map_err(|_| {
+        RedisError::String("Failed to acquire warning config lock".to_string())
+    })?;
+
+    match subcommand.as_str() {
+        "SET" => {
+            let lead_secs = next_secs(&mut args, "Warning lead time")?;
+            if lead_secs == 0 {
+                return Err(RedisError::Str("Warning lead time must be positive"));
+            }
+
+            let mut sink = config.sink.clone();
+            if let Ok(option) = args.next_string() {
+                let target = args.next_string()?;
+                sink = match option.to_uppercase().as_str() {
+                    "CHANNEL" => WarningSink::Channel(target),
+                    "STREAM" => WarningSink::Stream(target),
+                    _ => return Err(RedisError::String(format!("Unknown option: {}", option))),
+                };
+            }
+            args.done()?;
+
+            config.lead_secs = Some(lead_secs);
+            config.sink = sink;
+            Ok(RedisValue::SimpleStringStatic("OK"))
+        },
+        "OFF" => {
+            args.done()?;
+            config.lead_secs = None;
+            Ok(RedisValue::SimpleStringStatic("OK"))
+        },
+        "GET" => {
+            args.done()?;
+            let (kind, target) = match &config.sink {
+                WarningSink::Channel(channel) => ("channel", channel.clone()),
+                WarningSink::Stream(key) => ("stream", key.clone()),
+            };
+            Ok(RedisValue::Array(vec![
+                RedisValue::SimpleStringStatic("seconds"),
+                RedisValue::Integer(config.lead_secs.unwrap_or(0) as i64),
+                RedisValue::SimpleStringStatic(kind),
+                RedisValue::BulkString(target),
+            ]))
+        },
+        _ => Err(RedisError::String(format!("Unknown SESSION.EXPIRY_WARNING subcommand: {}", subcommand))),
+    }
+}
+
+pub fn set_grace_secs(grace_secs: u64) {
+    GRACE_SECS.store(grace_secs, Ordering::Relaxed);
+}
+
+pub fn set_idle_timeout_secs(idle_secs: u64) {
+    IDLE_TIMEOUT_SECS.store(idle_secs, Ordering::Relaxed);
+}
+
+pub fn idle_timeout_secs() -> u64 {
+    IDLE_TIMEOUT_SECS.load(Ordering::Relaxed)
+}
+
+pub fn set_max_lifetime_secs(max_secs: u64) {
+    MAX_LIFETIME_SECS.store(max_secs, Ordering::Relaxed);
+}
+
+pub fn max_lifetime_secs() -> u64 {
+    MAX_LIFETIME_SECS.load(Ordering::Relaxed)
+}
+
+// Hold a session to the maximum lifetime, if there is one
+pub fn cap_lifetime(session: &mut Session) {
+    let max_secs = max_lifetime_secs();
+    if max_secs > 0 {
+        session.cap_lifetime(max_secs);
+    }
+}
+
+// Record an access made under a read lock, for a session with an idle timeout
+pub fn slide(session_id: &str) {
+    if let Ok(mut sessions_map) = init_sessions().write() {
+        if let Some(session) = sessions_map.get_mut(session_id) {
+            session.touch();
+        }
+    }
+}
+
+// Renew a session without reading it: SESSION.TOUCH session_id [ttl]
+// Records an access, which also slides an idle timeout, and with `ttl`
+// expires the session that many seconds from now. Replies with the seconds
+// left until it expires, -1 if it doesn't.
+#[tracing::instrument(name = "session.touch", skip_all)]
+pub fn session_touch(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1).peekable();
+    let session_id = args.next_string()?;
+    let ttl = match args.peek() {
+        Some(_) => match args.next_u64()? {
+            0 => return Err(RedisError::Str("TTL must be positive")),
+            secs => Some(ttl::check(secs, "TTL").map_err(RedisError::String)?),
+        },
+        None => None,
+    };
+    args.done()?;
+
+    let mut sessions_map = init_sessions().write().map_err(|_| {
+        RedisError::String("Failed to acquire write lock".to_string())
+    })?;
+    let session = writable_session(&mut sessions_map, &session_id)?;
+    activity::record(&session_id);
+    Ok(RedisValue::Integer(renew(session, ttl)))
+}
+
+// Record an access to a session and, with `ttl`, expire it that many seconds
+// from now. Returns the seconds left until it expires, -1 if it doesn't.
+fn renew(session: &mut Session, ttl: Option<u64>) -> i64 {
+    session.touch();
+    if let Some(secs) = ttl {
+        session.set_ttl(secs);
+        session.mark_changed();
+    }
+    session.expires_at.map_or(-1, |expires_at| (expires_at - Utc::now()).num_seconds().max(0))
+}
+
+// Pin a hard expiry on a session: SESSION.EXPIREAT session_id unix_ts
+// Unlike a TTL, later accesses and renewals never push expiry past it. A
+// time already past expires the session now, and the sweep then removes it
+// along with its user key in the custom hashmap.
+#[tracing::instrument(name = "session.expireat", skip_all)]
+pub fn session_expireat(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let session_id = args.next_string()?;
+    let unix_ts = args.next_i64()?;
+    args.done()?;
+    let deadline = DateTime::<Utc>::from_timestamp(unix_ts, 0)
+        .ok_or_else(|| RedisError::String(format!("Invalid timestamp: {}", unix_ts)))?;
+
+    let mut sessions_map = init_sessions().write().map_err(|_| {
+        RedisError::String("Failed to acquire write lock".to_string())
+    })?;
+    let session = writable_session(&mut sessions_map, &session_id)?;
+    session.set_deadline(deadline);
+    cap_lifetime(session);
+    session.mark_changed();
+
+    Ok(RedisValue::Integer(1))
+}
+
+// Make a session non-expiring: SESSION.PERSIST session_id
+// Removes its TTL, idle timeout and deadline, though a maximum lifetime still
+// holds. Replies 1 if that changed when it expires, 0 if it had no expiry.
+#[tracing::instrument(name = "session.persist", skip_all)]
+pub fn session_persist(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let session_id = args.next_string()?;
+    args.done()?;
+
+    let mut sessions_map = init_sessions().write().map_err(|_| {
+        RedisError::String("Failed to acquire write lock".to_string())
+    })?;
+    let session = writable_session(&mut sessions_map, &session_id)?;
+    let expires_at = session.expires_at;
+    session.persist();
+    cap_lifetime(session);
+    if session.expires_at == expires_at {
+        return Ok(RedisValue::Integer(0));
+    }
+    session.mark_changed();
+    Ok(RedisValue::Integer(1))
+}
+
+// Seconds until a session expires, as TTL replies: SESSION.TTL session_id
+// -1 if it has no expiry, -2 if it doesn't exist or has expired (even while
+// its grace window keeps it readable). Doesn't count as an access.
+#[tracing::instrument(name = "session.ttl", skip_all)]
+pub fn session_ttl(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let session_id = args.next_string()?;
+    args.done()?;
+
+    let sessions_map = init_sessions().read().map_err(|_| {
+        RedisError::String("Failed to acquire read lock".to_string())
+    })?;
+    let ttl = match sessions_map.get(&session_id) {
+        None => -2,
+        Some(session) if session.is_expired() => -2,
+        Some(session) => match session.expires_at {
+            None => -1,
+            // Whole seconds, rounded up
+            Some(expires_at) => ((expires_at - Utc::now()).num_milliseconds() + 999) / 1000,
+        },
+    };
+    Ok(RedisValue::Integer(ttl))
+}
+
+// Keep expired sessions readable for a while:
+// SESSION.EXPIRY_GRACE SET seconds
+// SESSION.EXPIRY_GRACE GET
+#[tracing::instrument(name = "session.expiry_grace", skip_all)]
+pub fn session_expiry_grace(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let subcommand = args.next_string()?.to_uppercase();
+
+    match subcommand.as_str() {
+        "SET" => {
+            let grace_secs = next_secs(&mut args, "Grace window")?;
+            args.done()?;
+            set_grace_secs(grace_secs);
+            Ok(RedisValue::SimpleStringStatic("OK"))
+        },
+        "GET" => {
+            args.done()?;
+            Ok(RedisValue::Integer(GRACE_SECS.load(Ordering::Relaxed) as i64))
+        },
+        _ => Err(RedisError::String(format!("Unknown SESSION.EXPIRY_GRACE subcommand: {}", subcommand))),
+    }
+}
+
+// Delete sessions idle for longer than `seconds`, optionally only one
+// application's: SESSION.EXPIRE_IDLE seconds [APP app] [LIMIT n]
+// HIGH priority sessions are never swept, and with LIMIT every idle LOW
+// session goes before any NORMAL one.
+#[tracing::instrument(name = "session.expire_idle", skip_all)]
+pub fn expire_idle_sessions(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let idle_secs = next_secs(&mut args, "Idle time")?;
+
+    let mut app = None;
+    let mut limit = usize::MAX;
+    while let Ok(option) = args.next_string() {
+        match option.to_uppercase().as_str() {
+            "APP" => app = Some(args.next_string()?),
+            "LIMIT" => limit = args.next_u64()? as usize,
+            _ => return Err(RedisError::String(format!("Unknown option: {}", option))),
+        }
+    }
+
+    let cutoff = ttl::before(Utc::now(), idle_secs);
+
+    let sessions = init_sessions();
+    let mut sessions_map = sessions.write().map_err(|_| {
+        RedisError::String("Failed to acquire write lock".to_string())
+    })?;
+
+    // Least recently used first within each priority
+    let mut idle: Vec<(Priority, String)> = sessions_map.idle_before(cutoff, app.as_deref())
+        .into_iter()
+        .filter_map(|session_id| {
+            let priority = sessions_map.get(&session_id)?.priority;
+            (priority != Priority::High).then_some((priority, session_id))
+        })
+        .collect();
+    idle.sort_by_key(|(priority, _)| *priority);
+
+    let mut removed = 0;
+    for (_, session_id) in idle.into_iter().take(limit) {
+        if let Some(session) = sessions_map.remove(&session_id) {
+            binding::forget(&session);
+            changes::record_deletion(&session_id);
+            webhooks::emit(events::EXPIRED, &session_id, &session.user_key);
+            if let Err(err) = unlink_user_key(ctx, &session.user_key) {
+                retry::enqueue(ctx, &session_id, BridgeOp::Del { key: session.user_key.clone() }, err);
+            }
+            removed += 1;
+        }
+    }
+
+    Ok(RedisValue::Integer(removed))
+}
+
+// Report which sessions a sweep would remove, without removing any:
+// SESSION.SIMULATE_EXPIRY [AFTER seconds] [GRACE seconds] [IDLE seconds] [EVICT_PERCENT percent] [LIMIT n]
+// The current grace window and eviction threshold apply unless GRACE or
+// EVICT_PERCENT give hypothetical ones; IDLE adds a SESSION.EXPIRE_IDLE cut,
+// and AFTER looks that far ahead. Eviction lists the next sweep's batch.
+#[tracing::instrument(name = "session.simulate_expiry", skip_all)]
+pub fn simulate_expiry(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+
+    let mut after_secs = 0;
+    let mut grace_secs = GRACE_SECS.load(Ordering::Relaxed);
+    let mut idle_secs = None;
+    let mut evict_percent = eviction::evict_memory_percent();
+    let mut limit = 100;
+    while let Ok(option) = args.next_string() {
+        match option.to_uppercase().as_str() {
+            "AFTER" => after_secs = next_secs(&mut args, "AFTER")?,
+            "GRACE" => grace_secs = next_secs(&mut args, "GRACE")?,
+            "IDLE" => idle_secs = Some(next_secs(&mut args, "IDLE")?),
+            "EVICT_PERCENT" => {
+                evict_percent = args.next_u64()?;
+                if evict_percent > 100 {
+                    return Err(RedisError::Str("EVICT_PERCENT must be at most 100"));
+                }
+            },
+            "LIMIT" => limit = args.next_u64()? as usize,
+            _ => return Err(RedisError::String(format!("Unknown option: {}", option))),
+        }
+    }
+
+    let at = ttl::after(Utc::now(), after_secs);
+    let memory_percent = eviction::memory_percent(ctx);
+
+    let sessions_map = init_sessions().read().map_err(|_| {
+        RedisError::String("Failed to acquire read lock".to_string())
+    })?;
+
+    // Past their expiry and grace window, soonest first
+    let expired: Vec<String> = sessions_map.expiring_before(ttl::before(at, grace_secs))
+        .into_iter()
+        .filter(|session_id| sessions_map.get(session_id).is_some_and(|session| {
+            session.expires_at.is_some_and(|expires_at| ttl::after(expires_at, grace_secs) <= at)
+        }))
+        .collect();
+    let mut removed: HashSet<&str> = expired.iter().map(String::as_str).collect();
+
+    // Idle past the cutoff, LOW before NORMAL, HIGH never
+    let mut idle: Vec<(Priority, String)> = match idle_secs {
+        Some(idle_secs) => sessions_map.idle_before(ttl::before(at, idle_secs), None)
+            .into_iter()
+            .filter(|session_id| !removed.contains(session_id.as_str()))
+            .filter_map(|session_id| {
+                let priority = sessions_map.get(&session_id)?.priority;
+                (priority != Priority::High).then_some((priority, session_id))
+            })
+            .collect(),
+        None => Vec::new(),
+    };
+    idle.sort_by_key(|(priority, _)| *priority);
+    removed.extend(idle.iter().map(|(_, session_id)| session_id.as_str()));
+
+    // The batch the next sweep would evict if memory use stays where it is
+    let evicted: Vec<String> = if evict_percent > 0 && memory_percent.is_some_and(|percent| percent >= evict_percent) {
+        sessions_map.eviction_order(eviction::EVICTION_BATCH + removed.len())
+            .into_iter()
+            .filter(|session_id| !removed.contains(session_id.as_str()))
+            .take(eviction::EVICTION_BATCH)
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    let sessions: Vec<RedisValue> = expired.iter().map(|session_id| (session_id, "expired"))
+        .chain(idle.iter().map(|(_, session_id)| (session_id, "idle")))
+        .chain(evicted.iter().map(|session_id| (session_id, "evicted")))
+        .take(limit)
+        .filter_map(|(session_id, reason)| {
+            let session = sessions_map.get(session_id)?;
+            Some(RedisValue::Array(vec![
+                RedisValue::BulkString(session_id.clone()),
+                RedisValue::BulkString(session.user_key.clone()),
+                RedisValue::SimpleStringStatic(reason),
+            ]))
+        })
+        .collect();
+
+    Ok(RedisValue::Array(vec![
+        RedisValue::SimpleStringStatic("at"),
+        RedisValue::BulkString(at.to_rfc3339()),
+        RedisValue::SimpleStringStatic("expired"),
+        RedisValue::Integer(expired.len() as i64),
+        RedisValue::SimpleStringStatic("idle"),
+        RedisValue::Integer(idle.len() as i64),
+        RedisValue::SimpleStringStatic("evicted"),
+        RedisValue::Integer(evicted.len() as i64),
+        RedisValue::SimpleStringStatic("memory_percent"),
+        memory_percent.map_or(RedisValue::Null, |percent| RedisValue::Integer(percent as i64)),
+        RedisValue::SimpleStringStatic("evict_percent"),
+        RedisValue::Integer(evict_percent as i64),
+        RedisValue::SimpleStringStatic("sessions"),
+        RedisValue::Array(sessions),
+    ]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeDelta;
+
+    fn session() -> Session {
+        Session::new("s1".to_string(), "user:1".to_string(), 0)
+    }
+
+    #[test]
+    fn renews_without_a_ttl() {
+        let mut session = session();
+        let before = session.last_accessed;
+        assert_eq!(renew(&mut session, None), -1);
+        assert!(session.last_accessed >= before);
+        assert_eq!(session.expires_at, None);
+        assert_eq!(session.change_seq, 0);
+    }
+
+    #[test]
+    fn resets_the_ttl() {
+        let mut session = session();
+        session.set_ttl(10);
+        let left = renew(&mut session, Some(600));
+        assert!((599..=600).contains(&left));
+        assert_ne!(session.change_seq, 0);
+        // Without a ttl the expiry stays where it was
+        assert!((599..=600).contains(&renew(&mut session, None)));
+    }
+
+    #[test]
+    fn slides_idle_timeouts() {
+        let mut session = session();
+        session.set_idle_timeout(300);
+        session.expires_at = Some(Utc::now() + TimeDelta::seconds(5));
+        assert!((299..=300).contains(&renew(&mut session, None)));
+    }
+
+    #[test]
+    fn keeps_deadlines() {
+        let mut session = session();
+        session.set_deadline(Utc::now() + TimeDelta::seconds(60));
+        assert!(renew(&mut session, Some(3600)) <= 60);
+    }
+}
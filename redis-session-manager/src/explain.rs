@@ -0,0 +1,196 @@
+use redis_module::{Context, NextArg, RedisError, RedisResult, RedisString, RedisValue};
+use session_core::capi::shard_of;
+
+use crate::{bridge, docs, expiry, init_sessions};
+
+// What a command names: nothing, a session, a user key, or two sessions
+enum Target {
+    None,
+    Session(usize),
+    UserKey(usize),
+    Sessions(usize, usize),
+}
+
+// How a command would touch the store, the custom hashmap and the indexes
+struct Plan {
+    // "read", "write" or "none": the lock taken on the session store
+    store_lock: &'static str,
+    target: Target,
+    // Bridge operations, in the order they would run
+    bridge: &'static [&'static str],
+    indexes: Vec<&'static str>,
+    // Per-session locks taken on top of the store lock
+    session_locks: bool,
+}
+
+impl Plan {
+    fn new(store_lock: &'static str, target: Target) -> Plan {
+        Plan { store_lock, target, bridge: &[], indexes: Vec::new(), session_locks: false }
+    }
+
+    fn bridge(mut self, bridge: &'static [&'static str]) -> Plan {
+        self.bridge = bridge;
+        self
+    }
+
+    fn indexes(mut self, indexes: &[&'static str]) -> Plan {
+        self.indexes.extend_from_slice(indexes);
+        self
+    }
+}
+
+// The value following an option such as APP or BY, if present
+fn option<'a>(args: &'a [String], name: &str) -> Option<&'a str> {
+    args.iter()
+        .position(|arg| arg.eq_ignore_ascii_case(name))
+        .and_then(|position| args.get(position + 1))
+        .map(String::as_str)
+}
+
+// Indexes a whole session is kept in, which removing or restoring it updates
+const SESSION_INDEXES: &[&str] = &["by_created", "by_last_accessed", "by_expiry", "by_app", "evict_by_created", "evict_by_last_accessed"];
+
+fn plan(command: &str, args: &[String]) -> Option<Plan> {
+    let app_index: &[&str] = if option(args, "APP").is_some() { &["by_app"] } else { &[] };
+    Some(match command {
+        "session.create" | "session.get_or_create" => {
+            let mut plan = Plan::new("write", Target::UserKey(0))
+                .bridge(&["get", "set if the key has no live session"])
+                .indexes(&["by_created", "by_last_accessed", "evict_by_created", "evict_by_last_accessed"])
+                .indexes(app_index);
+            let idle = option(args, "IDLE").map_or(expiry::idle_timeout_secs() > 0, |secs| secs != "0");
+            if option(args, "TTL").is_some() || idle {
+                plan = plan.indexes(&["by_expiry"]);
+            }
+            plan
+        },
+        "session.get" | "session.get_all_data" | "session.data_keys" | "session.field_type" | "session.ttl" | "session.activity" => Plan::new("read", Target::Session(0)),
+        "session.touch" | "session.get_data" | "session.add_data" | "session.set_data" | "session.patch" | "session.del_data" => {
+            Plan::new("write", Target::Session(0)).indexes(&["by_last_accessed", "evict_by_last_accessed"])
+        },
+        "session.set_meta" => Plan::new("write", Target::Session(0))
+            .indexes(&["by_last_accessed", "evict_by_created", "evict_by_last_accessed"]),
+        "session.expireat" | "session.persist" | "session.bind_ttl_to_key" => Plan::new("write", Target::Session(0)).indexes(&["by_expiry"]),
+        "session.delete" | "session.archive" => Plan::new("write", Target::Session(0)).bridge(&["del"]).indexes(SESSION_INDEXES),
+        "session.unarchive" => Plan::new("write", Target::Session(0)).bridge(&["set"]).indexes(SESSION_INDEXES),
+        "session.impersonate" => Plan::new("write", Target::Session(0))
+            .bridge(&["set"])
+            .indexes(&["by_created", "by_last_accessed", "by_expiry", "evict_by_created", "evict_by_last_accessed"]),
+        "session.compare" => Plan {
+            session_locks: true,
+            ..Plan::new("read", Target::Sessions(0, 1))
+        },
+        "session.list" => {
+            let sort_index = match option(args, "BY").map(str::to_lowercase).as_deref() {
+                Some("created") => "by_created",
+                Some("last_accessed") => "by_last_accessed",
+                Some("ttl") => "by_expiry",
+                _ => "none (unordered scan)",
+            };
+            Plan::new("read", Target::None).indexes(&[sort_index]).indexes(app_index)
+        },
+        "session.count" => Plan::new("read", Target::None).indexes(app_index),
+        "session.expire_idle" => Plan::new("write", Target::None)
+            .bridge(&["del per deleted session"])
+            .indexes(SESSION_INDEXES)
+            .indexes(app_index),
+        "session.digest" | "session.aggregate" | "session.info" => Plan::new("read", Target::None),
+        _ => return None,
+    })
+}
+
+// What the store knows about a session named by a command
+fn describe_session(reply: &mut Vec<RedisValue>, session_id: &str) -> Result<Option<String>, RedisError> {
+    let sessions_map = init_sessions().read().map_err(|_| {
+        RedisError::String("Failed to acquire read lock".to_string())
+    })?;
+    reply.push(RedisValue::SimpleStringStatic("session"));
+    reply.push(RedisValue::BulkString(session_id.to_string()));
+
+    let session = match sessions_map.get(session_id) {
+        Some(session) => session,
+        None => {
+            reply.push(RedisValue::SimpleStringStatic("session_exists"));
+            reply.push(RedisValue::Integer(0));
+            return Ok(None);
+        },
+    };
+    reply.extend([
+        RedisValue::SimpleStringStatic("session_exists"),
+        RedisValue::Integer(1),
+        RedisValue::SimpleStringStatic("session_expired"),
+        RedisValue::Integer(if session.is_expired() { 1 } else { 0 }),
+        // Serialization and paging costs grow with the number of data fields
+        RedisValue::SimpleStringStatic("data_fields"),
+        RedisValue::Integer(session.data.len() as i64),
+        RedisValue::SimpleStringStatic("priority"),
+        RedisValue::SimpleStringStatic(session.priority.as_str()),
+    ]);
+    Ok(Some(session.user_key.clone()))
+}
+
+// Report how a command would be routed, without running it:
+// SESSION.EXPLAIN command [arg ...]
+// Names the store lock it takes, the session it resolves to, the custom
+// hashmap key and shard involved, the bridge path its hashmap operations
+// would take, the indexes it reads or updates and its documented complexity.
+#[tracing::instrument(name = "session.explain", skip_all)]
+pub fn session_explain(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let command = args.next_string()?.to_lowercase();
+    let args: Vec<String> = args.map(|arg| arg.to_string_lossy()).collect();
+
+    let doc = docs::COMMANDS.iter()
+        .find(|doc| doc.name == command)
+        .ok_or_else(|| RedisError::String(format!("Unknown command: {}", command)))?;
+    let mut reply = vec![
+        RedisValue::SimpleStringStatic("command"),
+        RedisValue::SimpleStringStatic(doc.name),
+        RedisValue::SimpleStringStatic("complexity"),
+        doc.complexity.map_or(RedisValue::Null, RedisValue::SimpleStringStatic),
+    ];
+
+    let plan = match plan(&command, &args) {
+        Some(plan) => plan,
+        None => {
+            reply.push(RedisValue::SimpleStringStatic("plan"));
+            reply.push(RedisValue::SimpleStringStatic("not modelled"));
+            return Ok(RedisValue::Array(reply));
+        },
+    };
+    let arg = |index: usize| args.get(index).ok_or(RedisError::WrongArity);
+
+    reply.push(RedisValue::SimpleStringStatic("store_lock"));
+    reply.push(RedisValue::SimpleStringStatic(plan.store_lock));
+    reply.push(RedisValue::SimpleStringStatic("session_locks"));
+    reply.push(RedisValue::Integer(if plan.session_locks { 1 } else { 0 }));
+
+    let user_key = match plan.target {
+        Target::None => None,
+        Target::UserKey(index) => Some(arg(index)?.clone()),
+        Target::Session(index) => describe_session(&mut reply, arg(index)?)?,
+        Target::Sessions(left, right) => {
+            describe_session(&mut reply, arg(left)?)?;
+            describe_session(&mut reply, arg(right)?)?;
+            None
+        },
+    };
+    if let Some(user_key) = user_key {
+        reply.extend([
+            RedisValue::SimpleStringStatic("hashmap_key"),
+            RedisValue::BulkString(user_key.clone()),
+            RedisValue::SimpleStringStatic("hashmap_shard"),
+            RedisValue::Integer(shard_of(user_key.as_bytes()) as i64),
+        ]);
+    }
+
+    reply.push(RedisValue::SimpleStringStatic("bridge_ops"));
+    reply.push(RedisValue::Array(plan.bridge.iter().map(|op| RedisValue::SimpleStringStatic(op)).collect()));
+    if !plan.bridge.is_empty() {
+        reply.extend(bridge::explain_route());
+    }
+    reply.push(RedisValue::SimpleStringStatic("indexes"));
+    reply.push(RedisValue::Array(plan.indexes.into_iter().map(RedisValue::SimpleStringStatic).collect()));
+
+    Ok(RedisValue::Array(reply))
+}
@@ -0,0 +1,240 @@
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+use redis_module::{Context, NextArg, RedisError, RedisResult, RedisString, RedisValue};
+use session_core::errors;
+
+use crate::{init_sessions, DataValue, FieldType, Session};
+
+// What a write of another type than the field holds does
+#[derive(Clone, Copy, PartialEq)]
+pub enum Mode {
+    // The field takes the type of the write, as before types were tracked
+    Loose = 0,
+    // The write is refused
+    Strict = 1,
+    // The value is converted to the field's type, or the write refused if it can't be
+    Coerce = 2,
+}
+
+impl Mode {
+    pub fn parse(mode: &str) -> Option<Mode> {
+        match mode.to_ascii_lowercase().as_str() {
+            "loose" => Some(Mode::Loose),
+            "strict" => Some(Mode::Strict),
+            "coerce" => Some(Mode::Coerce),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Mode::Loose => "loose",
+            Mode::Strict => "strict",
+            Mode::Coerce => "coerce",
+        }
+    }
+}
+
+static MODE: AtomicU8 = AtomicU8::new(Mode::Loose as u8);
+
+// Writes refused for their type, and writes converted to the field's type
+static REJECTED: AtomicU64 = AtomicU64::new(0);
+static COERCED: AtomicU64 = AtomicU64::new(0);
+
+pub fn mode() -> Mode {
+    match MODE.load(Ordering::Relaxed) {
+        1 => Mode::Strict,
+        2 => Mode::Coerce,
+        _ => Mode::Loose,
+    }
+}
+
+pub fn set_mode(mode: Mode) {
+    MODE.store(mode as u8, Ordering::Relaxed);
+}
+
+// The type of a field, or None if the session has no such field
+pub fn field_type(session: &Session, field: &str) -> Option<FieldType> {
+    if !session.data.contains_key(field) {
+        return None;
+    }
+    Some(session.types.get(field).copied().unwrap_or_default())
+}
+
+// The text of a value read as the given type, if it can be
+fn coerce(value: &str, to: FieldType) -> Option<String> {
+    DataValue::parse(value, to).map(DataValue::into_text)
+}
+
+// A value written with SESSION.ADD_DATA ... TYPE, checked against its type
+pub fn typed(value: String, type_name: &str) -> Result<DataValue, RedisError> {
+    let field_type = match type_name.to_uppercase().as_str() {
+        "STRING" => return Ok(DataValue::String(value)),
+        "INT" => FieldType::Int,
+        "FLOAT" => FieldType::Float,
+        "BOOL" => FieldType::Boolean,
+        "JSON" => FieldType::Json,
+        _ => return Err(RedisError::String(format!("Unknown type: {}, expected STRING, INT, FLOAT, BOOL or JSON", type_name))),
+    };
+    DataValue::parse(&value, field_type)
+        .ok_or_else(|| RedisError::String(format!("Value is not a valid {}: {}", field_type.as_str(), value)))
+}
+
+// A value as the RESP type of its field: integers, doubles and booleans, and
+// text for strings and JSON documents
+pub fn reply(value: DataValue) -> RedisValue {
+    match value {
+        DataValue::String(string) => RedisValue::BulkString(string),
+        DataValue::Int(integer) => RedisValue::Integer(integer),
+        DataValue::Float(float) => RedisValue::Float(float),
+        DataValue::Bool(boolean) => RedisValue::Bool(boolean),
+        DataValue::Json(json) => RedisValue::BulkString(json.to_string()),
+    }
+}
+
+// Check a write of `value` as type `written` against the field's current
+// type, under the configured mode. Returns the value to store and its type.
+pub fn check(session: &Session, field: &str, value: String, written: FieldType) -> Result<(String, FieldType), RedisError> {
+    let current = match field_type(session, field) {
+        Some(current) if current != written => current,
+        _ => return Ok((value, written)),
+    };
+
+    let mismatch = || {
+        REJECTED.fetch_add(1, Ordering::Relaxed);
+        RedisError::String(format!(
+            "{} Field {} holds a {}, not a {}", errors::WRONG_TYPE, field, current.as_str(), written.as_str(),
+        ))
+    };
+    match mode() {
+        Mode::Loose => Ok((value, written)),
+        Mode::Strict => Err(mismatch()),
+        Mode::Coerce => match coerce(&value, current) {
+            Some(coerced) => {
+                COERCED.fetch_add(1, Ordering::Relaxed);
+                Ok((coerced, current))
+            },
+            None => Err(mismatch()),
+        },
+    }
+}
+
+// The type of a session's data field: SESSION.FIELD_TYPE session_id field
+// Replies string, int, float, boolean or json, or nil if the field isn't set.
+#[tracing::instrument(name = "session.field_type", skip_all)]
+pub fn session_field_type(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let session_id = args.next_string()?;
+    let field = args.next_string()?;
+    args.done()?;
+
+    let sessions_map = init_sessions().read().map_err(|_| {
+        RedisError::String("Failed to acquire read lock".to_string())
+    })?;
+    let session = sessions_map.get(&session_id)
+        .ok_or_else(|| RedisError::String(format!("Session not found: {}", session_id)))?;
+    Ok(field_type(session, &field).map_or(RedisValue::Null, |field_type| RedisValue::SimpleStringStatic(field_type.as_str())))
+}
+
+// Inspect or change how writes of another type are handled:
+// SESSION.FIELD_TYPES [MODE loose|strict|coerce]
+#[tracing::instrument(name = "session.field_types", skip_all)]
+pub fn session_field_types(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    if let Ok(option) = args.next_string() {
+        if !option.eq_ignore_ascii_case("MODE") {
+            return Err(RedisError::String(format!("Unknown option: {}", option)));
+        }
+        let value = args.next_string()?;
+        args.done()?;
+        let mode = Mode::parse(&value)
+            .ok_or_else(|| RedisError::String(format!("Invalid mode: {}, expected LOOSE, STRICT or COERCE", value)))?;
+        set_mode(mode);
+        return Ok(RedisValue::SimpleStringStatic("OK"));
+    }
+
+    Ok(RedisValue::Array(vec![
+        RedisValue::SimpleStringStatic("mode"),
+        RedisValue::SimpleStringStatic(mode().as_str()),
+        RedisValue::SimpleStringStatic("rejected"),
+        RedisValue::Integer(REJECTED.load(Ordering::Relaxed) as i64),
+        RedisValue::SimpleStringStatic("coerced"),
+        RedisValue::Integer(COERCED.load(Ordering::Relaxed) as i64),
+    ]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(fields: &[(&str, &str, FieldType)]) -> Session {
+        let mut session = Session::new("s1".to_string(), "user:1".to_string(), 0);
+        for (field, value, field_type) in fields {
+            session.set_field(field.to_string(), value.to_string(), *field_type);
+        }
+        session
+    }
+
+    #[test]
+    fn parses_typed_writes() {
+        assert_eq!(typed("7".to_string(), "int").unwrap(), DataValue::Int(7));
+        assert_eq!(typed("7".to_string(), "FLOAT").unwrap(), DataValue::Float(7.0));
+        assert_eq!(typed("false".to_string(), "Bool").unwrap(), DataValue::Bool(false));
+        assert_eq!(typed("[1]".to_string(), "json").unwrap(), DataValue::Json(serde_json::json!([1])));
+        assert_eq!(typed("not a number".to_string(), "string").unwrap(), DataValue::String("not a number".to_string()));
+
+        assert!(typed("7.5".to_string(), "int").is_err());
+        assert!(typed("maybe".to_string(), "bool").is_err());
+        assert!(typed("42".to_string(), "json").is_err());
+        assert!(typed("7".to_string(), "number").is_err());
+        assert!(Mode::parse("STRICT") == Some(Mode::Strict));
+        assert!(Mode::parse("lenient").is_none());
+    }
+
+    #[test]
+    fn coerces_between_types() {
+        assert_eq!(coerce(" 3 ", FieldType::Int), Some("3".to_string()));
+        assert_eq!(coerce("3", FieldType::Float), Some("3.0".to_string()));
+        assert_eq!(coerce("1", FieldType::Boolean), Some("true".to_string()));
+        assert_eq!(coerce("3", FieldType::String), Some("3".to_string()));
+        assert_eq!(coerce("3.5", FieldType::Int), None);
+        assert_eq!(coerce("{}x", FieldType::Json), None);
+    }
+
+    #[test]
+    fn tracks_field_types() {
+        let mut session = session(&[("qty", "2", FieldType::Int), ("name", "ann", FieldType::String)]);
+        assert_eq!(field_type(&session, "qty"), Some(FieldType::Int));
+        assert_eq!(field_type(&session, "name"), Some(FieldType::String));
+        assert_eq!(field_type(&session, "missing"), None);
+        // Strings are the default and aren't recorded
+        assert!(!session.types.contains_key("name"));
+
+        session.set_field("qty".to_string(), "two".to_string(), FieldType::String);
+        assert!(session.types.is_empty());
+        session.set_field("qty".to_string(), "2".to_string(), FieldType::Int);
+        assert_eq!(session.remove_fields(|field| field == "qty"), 1);
+        assert!(session.types.is_empty());
+        assert_eq!(session.remove_fields(|field| field == "qty"), 0);
+    }
+
+    // The mode is global, so every mode is checked in this one test
+    #[test]
+    fn checks_writes_under_each_mode() {
+        let session = session(&[("qty", "2", FieldType::Int)]);
+        let write = |value: &str, written| check(&session, "qty", value.to_string(), written);
+
+        set_mode(Mode::Strict);
+        assert_eq!(mode().as_str(), "strict");
+        assert_eq!(write("3", FieldType::Int).unwrap(), ("3".to_string(), FieldType::Int));
+        assert!(write("3", FieldType::String).is_err());
+        // New fields take any type
+        assert_eq!(check(&session, "name", "ann".to_string(), FieldType::String).unwrap(), ("ann".to_string(), FieldType::String));
+
+        set_mode(Mode::Coerce);
+        assert_eq!(write("3", FieldType::String).unwrap(), ("3".to_string(), FieldType::Int));
+        assert!(write("three", FieldType::String).is_err());
+
+        set_mode(Mode::Loose);
+        assert_eq!(write("three", FieldType::String).unwrap(), ("three".to_string(), FieldType::String));
+    }
+}
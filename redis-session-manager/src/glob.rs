@@ -0,0 +1,138 @@
+// Redis-style glob matching: `*`, `?`, `[abc]`, `[a-z]`, `[^a]` and `\` escapes
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    matches(&pattern, &text)
+}
+
+fn matches(pattern: &[char], text: &[char]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    // Where to resume after the last `*` if the rest fails to match
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        let step = match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+                continue;
+            },
+            Some('?') => Some(p + 1),
+            Some('[') => match_class(pattern, p, text[t]),
+            Some('\\') if p + 1 < pattern.len() => (pattern[p + 1] == text[t]).then_some(p + 2),
+            Some(&c) => (c == text[t]).then_some(p + 1),
+            None => None,
+        };
+
+        match step {
+            Some(next) => {
+                p = next;
+                t += 1;
+            },
+            None => match backtrack {
+                // Let the last `*` swallow one more character
+                Some((star, star_t)) => {
+                    backtrack = Some((star, star_t + 1));
+                    p = star + 1;
+                    t = star_t + 1;
+                },
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+// Match a `[...]` class starting at `start`; returns the index after it on a match
+fn match_class(pattern: &[char], start: usize, c: char) -> Option<usize> {
+    let mut i = start + 1;
+    let negated = matches!(pattern.get(i), Some('^') | Some('!'));
+    if negated {
+        i += 1;
+    }
+
+    let mut matched = false;
+    let mut first = true;
+    loop {
+        let current = match pattern.get(i) {
+            // An unterminated class matches nothing
+            None => return None,
+            Some(']') if !first => break,
+            Some('\\') if i + 1 < pattern.len() => {
+                i += 1;
+                pattern[i]
+            },
+            Some(&current) => current,
+        };
+        first = false;
+
+        if pattern.get(i + 1) == Some(&'-') && pattern.get(i + 2).is_some_and(|&end| end != ']') {
+            let end = pattern[i + 2];
+            let (low, high) = if current <= end { (current, end) } else { (end, current) };
+            matched |= (low..=high).contains(&c);
+            i += 3;
+        } else {
+            matched |= current == c;
+            i += 1;
+        }
+    }
+
+    (matched != negated).then_some(i + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_wildcards() {
+        assert!(glob_match("*", ""));
+        assert!(glob_match("*", "anything"));
+        assert!(glob_match("flag:*", "flag:"));
+        assert!(glob_match("flag:*", "flag:beta"));
+        assert!(!glob_match("flag:*", "flags:beta"));
+        assert!(glob_match("a*b*c", "a-b-b-c"));
+        assert!(!glob_match("a*b*c", "a-b-b-"));
+        assert!(glob_match("h?llo", "hello"));
+        assert!(!glob_match("h?llo", "hllo"));
+        assert!(glob_match("**x", "x"));
+    }
+
+    #[test]
+    fn matches_classes() {
+        assert!(glob_match("h[ae]llo", "hallo"));
+        assert!(!glob_match("h[ae]llo", "hillo"));
+        assert!(glob_match("[a-c]x", "bx"));
+        // Reversed ranges are read the right way round
+        assert!(glob_match("[c-a]x", "bx"));
+        assert!(glob_match("h[^e]llo", "hallo"));
+        assert!(!glob_match("h[^e]llo", "hello"));
+        assert!(glob_match("h[!e]llo", "hallo"));
+        // A `]` first in a class is a member, a `-` last is literal
+        assert!(glob_match("[]]", "]"));
+        assert!(glob_match("[a-]", "-"));
+    }
+
+    #[test]
+    fn escapes_special_characters() {
+        assert!(glob_match("a\\*b", "a*b"));
+        assert!(!glob_match("a\\*b", "axb"));
+        assert!(glob_match("\\?", "?"));
+        assert!(glob_match("[\\]]", "]"));
+        // A trailing backslash matches itself
+        assert!(glob_match("a\\", "a\\"));
+    }
+
+    #[test]
+    fn unterminated_classes_match_nothing() {
+        assert!(!glob_match("[abc", "a"));
+        assert!(!glob_match("x[", "x["));
+    }
+
+    #[test]
+    fn compares_characters_not_bytes() {
+        assert!(glob_match("caf?", "café"));
+        assert!(glob_match("[é]", "é"));
+    }
+}
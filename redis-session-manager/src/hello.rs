@@ -0,0 +1,120 @@
+use redis_module::{Context, RedisError, RedisResult, RedisString, RedisValue};
+use session_core::format;
+
+use crate::{bridge, changes, docs, encryption, eviction, expiry, fieldtypes, history, http, idempotency, impersonate, nonce, notify, replicate, retry, spill, throttle, webhooks, BUILD_INFO};
+
+// Version of the SESSION.HELLO reply itself
+const PROTOCOL: i64 = 1;
+
+// Capabilities of this module version, stable names clients can test for
+const CAPABILITIES: &[&str] = &[
+    "ttl",
+    "idle_timeout",
+    "hard_expiry",
+    "max_lifetime",
+    "max_sessions",
+    "persist",
+    "ttl_binding",
+    "idempotent_delete",
+    "activity",
+    "apps",
+    "priorities",
+    "templates",
+    "get_or_create",
+    "dotted_paths",
+    "patch",
+    "field_types",
+    "typed_values",
+    "export_since",
+    "digest",
+    "archive",
+    "impersonation",
+    "secrets",
+    "refresh_tokens",
+    "nonces",
+    "sensitive_fields",
+    "encryption",
+    "history",
+    "binding",
+    "lifecycle_events",
+    "standby_replication",
+    "format_migration",
+];
+
+// Versions of the reply formats clients parse, bumped on an incompatible change:
+// the SESSION.EXPORT reply, the SESSION.GET_OR_CREATE reply, and the error
+// codes of session_core::errors. The session JSON of SESSION.GET, SESSION.MGET
+// and exports is versioned by session_core::format.
+const FORMATS: &[(&str, i64)] = &[
+    ("export", 1),
+    ("get_or_create", 1),
+    ("error_codes", 1),
+];
+
+fn pairs(pairs: Vec<(&'static str, RedisValue)>) -> RedisValue {
+    RedisValue::Array(pairs.into_iter()
+        .flat_map(|(name, value)| [RedisValue::SimpleStringStatic(name), value])
+        .collect())
+}
+
+fn flag(enabled: bool) -> RedisValue {
+    RedisValue::Integer(enabled as i64)
+}
+
+// Describe what this module supports, for client libraries to feature-detect
+// at connect time: SESSION.HELLO
+// Replies with the reply protocol, module version, capabilities (including
+// compiled-in features), reply format versions, limits, which optional
+// subsystems are switched on, and the module's commands.
+#[tracing::instrument(name = "session.hello", skip_all)]
+pub fn session_hello(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    if args.len() != 1 {
+        return Err(RedisError::WrongArity);
+    }
+
+    let capabilities = CAPABILITIES.iter()
+        .chain(BUILD_INFO.features)
+        .map(|capability| RedisValue::SimpleStringStatic(capability))
+        .collect();
+    let formats = pairs(std::iter::once(("session_json", format::written() as i64))
+        .chain(FORMATS.iter().copied())
+        .map(|(name, version)| (name, RedisValue::Integer(version)))
+        .collect());
+    let limits = pairs(vec![
+        ("max_tombstones", RedisValue::Integer(changes::MAX_TOMBSTONES as i64)),
+        ("max_nonces_per_session", RedisValue::Integer(nonce::MAX_NONCES_PER_SESSION as i64)),
+        ("max_history_changes", RedisValue::Integer(history::MAX_CHANGES as i64)),
+        ("max_impersonation_ttl_secs", RedisValue::Integer(impersonate::IMPERSONATION_TTL_SECS as i64)),
+        ("max_ring_capacity", RedisValue::Integer(bridge::MAX_RING_CAPACITY as i64)),
+        ("max_delete_tokens", RedisValue::Integer(idempotency::MAX_DELETE_TOKENS as i64)),
+    ]);
+    let subsystems = pairs(vec![
+        ("http", flag(http::is_running())),
+        ("webhooks", flag(webhooks::count() > 0)),
+        ("events_channel", flag(notify::channel_enabled())),
+        ("replication", flag(replicate::is_active())),
+        ("throttle", flag(throttle::is_enabled())),
+        ("encryption", flag(encryption::is_enabled())),
+        ("history", flag(history::history_secs() > 0)),
+        ("spill", flag(spill::spill_threshold() > 0)),
+        ("eviction", flag(eviction::evict_memory_percent() > 0)),
+        ("max_sessions", flag(eviction::max_sessions() > 0)),
+        ("backpressure", flag(retry::backpressure_depth() > 0)),
+        ("idle_timeout", flag(expiry::idle_timeout_secs() > 0)),
+        ("max_lifetime", flag(expiry::max_lifetime_secs() > 0)),
+        ("field_types", RedisValue::SimpleStringStatic(fieldtypes::mode().as_str())),
+    ]);
+    let commands = docs::COMMANDS.iter().map(|doc| RedisValue::SimpleStringStatic(doc.name)).collect();
+
+    Ok(pairs(vec![
+        ("protocol", RedisValue::Integer(PROTOCOL)),
+        ("module", RedisValue::SimpleStringStatic(BUILD_INFO.module)),
+        ("version", RedisValue::SimpleStringStatic(BUILD_INFO.version)),
+        ("abi_version", RedisValue::Integer(BUILD_INFO.abi_version() as i64)),
+        ("capabilities", RedisValue::Array(capabilities)),
+        ("formats", formats),
+        ("limits", limits),
+        ("subsystems", subsystems),
+        ("commands", RedisValue::Array(commands)),
+    ]))
+}
@@ -0,0 +1,235 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use chrono::{DateTime, TimeZone, Utc};
+use redis_module::{Context, NextArg, RedisError, RedisResult, RedisString, RedisValue};
+use session_core::ttl;
+
+use crate::sensitive::Redactor;
+use crate::{spill, Session};
+
+// Most changes kept per session; older ones are folded into the base state
+pub const MAX_CHANGES: usize = 1000;
+
+// How far back (in seconds) session data can be read; 0 turns history off
+static HISTORY_SECS: AtomicU64 = AtomicU64::new(0);
+
+// Field updates of one change; None marks a removed field
+type Change = Vec<(String, Option<String>)>;
+
+// The data of one session over the retained window
+struct SessionHistory {
+    // Data as of `since`, the oldest time that can be read
+    base: HashMap<String, String>,
+    since: DateTime<Utc>,
+    changes: VecDeque<(DateTime<Utc>, Change)>,
+    // Data after the latest change, to diff the next one against
+    current: HashMap<String, String>,
+    deleted_at: Option<DateTime<Utc>>,
+}
+
+impl SessionHistory {
+    fn new(data: &HashMap<String, String>, now: DateTime<Utc>) -> SessionHistory {
+        SessionHistory {
+            base: data.clone(),
+            since: now,
+            changes: VecDeque::new(),
+            current: data.clone(),
+            deleted_at: None,
+        }
+    }
+
+    // Fold changes older than the window, and any beyond MAX_CHANGES, into the base
+    fn trim(&mut self, horizon: DateTime<Utc>) {
+        while let Some((at, _)) = self.changes.front() {
+            if *at >= horizon && self.changes.len() <= MAX_CHANGES {
+                break;
+            }
+            let (at, change) = self.changes.pop_front().unwrap();
+            apply(&mut self.base, &change);
+            self.since = at;
+        }
+    }
+}
+
+fn apply(data: &mut HashMap<String, String>, change: &Change) {
+    for (field, value) in change {
+        match value {
+            Some(value) => data.insert(field.clone(), value.clone()),
+            None => data.remove(field),
+        };
+    }
+}
+
+static mut HISTORY: Option<Mutex<HashMap<String, SessionHistory>>> = None;
+
+// Initialize the history store
+fn init_history() -> &'static Mutex<HashMap<String, SessionHistory>> {
+    unsafe {
+        if HISTORY.is_none() {
+            HISTORY = Some(Mutex::new(HashMap::new()));
+        }
+        HISTORY.as_ref().unwrap()
+    }
+}
+
+pub fn set_history_secs(secs: u64) {
+    HISTORY_SECS.store(secs, Ordering::Relaxed);
+}
+
+pub fn history_secs() -> u64 {
+    HISTORY_SECS.load(Ordering::Relaxed)
+}
+
+fn horizon(now: DateTime<Utc>) -> DateTime<Utc> {
+    ttl::before(now, history_secs())
+}
+
+// A session's data as history keeps it. Spilled fields hold the value last
+// written under their key, or the value recorded before when they weren't
+// rewritten; only a session that arrived already spilled records the key.
+fn values(session: &Session, current: Option<&HashMap<String, String>>) -> HashMap<String, String> {
+    session.data.iter()
+        .map(|(field, stored)| {
+            let value = if session.spilled.contains(field) {
+                spill::take_written(stored)
+                    .or_else(|| current.and_then(|current| current.get(field).cloned()))
+                    .unwrap_or_else(|| stored.clone())
+            } else {
+                stored.clone()
+            };
+            (field.clone(), value)
+        })
+        .collect()
+}
+
+// Record a session's data after it was created or changed
+pub fn record(session: &Session) {
+    if history_secs() == 0 {
+        return;
+    }
+    let now = Utc::now();
+    let Ok(mut history) = init_history().lock() else { return };
+
+    match history.get_mut(&session.id) {
+        // A session id reused after a deletion starts a new history
+        Some(entry) if entry.deleted_at.is_none() => {
+            let data = values(session, Some(&entry.current));
+            let mut change: Change = data.iter()
+                .filter(|(field, value)| entry.current.get(*field) != Some(*value))
+                .map(|(field, value)| (field.clone(), Some(value.clone())))
+                .collect();
+            change.extend(entry.current.keys()
+                .filter(|field| !data.contains_key(*field))
+                .map(|field| (field.clone(), None)));
+            if !change.is_empty() {
+                apply(&mut entry.current, &change);
+                entry.changes.push_back((now, change));
+            }
+            entry.trim(horizon(now));
+        },
+        _ => {
+            history.insert(session.id.clone(), SessionHistory::new(&values(session, None), now));
+        },
+    }
+}
+
+// Note that a session is gone, so reads past this point find nothing
+pub fn record_deletion(session_id: &str) {
+    if history_secs() == 0 {
+        return;
+    }
+    if let Ok(mut history) = init_history().lock() {
+        if let Some(entry) = history.get_mut(session_id) {
+            entry.deleted_at.get_or_insert_with(Utc::now);
+        }
+    }
+}
+
+// Forget deleted sessions whose deletion has left the window, and fold old
+// changes of the rest. Called by the expiry sweep.
+pub fn prune() {
+    let Ok(mut history) = init_history().lock() else { return };
+    if history_secs() == 0 {
+        history.clear();
+        return;
+    }
+    let horizon = horizon(Utc::now());
+    history.retain(|_, entry| entry.deleted_at.is_none_or(|deleted_at| deleted_at >= horizon));
+    for entry in history.values_mut() {
+        entry.trim(horizon);
+    }
+}
+
+// A session's data as of `at`, None if it was deleted by then
+fn data_at(session_id: &str, at: DateTime<Utc>) -> Result<Option<HashMap<String, String>>, RedisError> {
+    let history = init_history().lock().map_err(|_| {
+        RedisError::String("Failed to acquire history lock".to_string())
+    })?;
+    let entry = history.get(session_id)
+        .ok_or_else(|| RedisError::String(format!("No history for session: {}", session_id)))?;
+    if at < entry.since.max(horizon(Utc::now())) {
+        return Err(RedisError::String(format!(
+            "Timestamp is outside retained history for session {}, which starts at {}",
+            session_id, entry.since.max(horizon(Utc::now())).to_rfc3339(),
+        )));
+    }
+    if entry.deleted_at.is_some_and(|deleted_at| at >= deleted_at) {
+        return Ok(None);
+    }
+
+    let mut data = entry.base.clone();
+    for (_, change) in entry.changes.iter().take_while(|(changed_at, _)| *changed_at <= at) {
+        apply(&mut data, change);
+    }
+    Ok(Some(data))
+}
+
+// Unix seconds (fractions allowed) or an RFC 3339 time
+fn parse_timestamp(timestamp: &str) -> Result<DateTime<Utc>, RedisError> {
+    if let Ok(secs) = timestamp.parse::<f64>() {
+        let millis = (secs * 1000.0) as i64;
+        if let Some(at) = Utc.timestamp_millis_opt(millis).single() {
+            return Ok(at);
+        }
+    }
+    DateTime::parse_from_rfc3339(timestamp)
+        .map(|at| at.with_timezone(&Utc))
+        .map_err(|_| RedisError::String(format!("Invalid timestamp: {}", timestamp)))
+}
+
+// Read a session's data as it was at a past time:
+// SESSION.GET_AT session_id timestamp [REVEAL]
+// Replies with the data fields as a JSON object, or null if the session had
+// been deleted by then. Needs the history_secs module argument.
+#[tracing::instrument(name = "session.get_at", skip_all)]
+pub fn session_get_at(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let session_id = args.next_string()?;
+    let at = parse_timestamp(&args.next_string()?)?;
+    let reveal = match args.next_string() {
+        Ok(option) if option.eq_ignore_ascii_case("REVEAL") => true,
+        Ok(option) => return Err(RedisError::String(format!("Unknown option: {}", option))),
+        Err(_) => false,
+    };
+    args.done()?;
+
+    if history_secs() == 0 {
+        return Err(RedisError::Str("Session history is off; load the module with history_secs=<n>"));
+    }
+    if at > Utc::now() {
+        return Err(RedisError::Str("Timestamp is in the future"));
+    }
+
+    let redactor = Redactor::for_caller(ctx, reveal)?;
+    let data = match data_at(&session_id, at)? {
+        Some(data) => data,
+        None => return Ok(RedisValue::Null),
+    };
+    let sorted: BTreeMap<&str, &str> = data.iter()
+        .map(|(field, value)| (field.as_str(), redactor.value(field, value)))
+        .collect();
+    serde_json::to_string(&sorted)
+        .map(RedisValue::BulkString)
+        .map_err(|e| RedisError::String(format!("Failed to serialize session data: {}", e)))
+}
@@ -0,0 +1,174 @@
+use std::collections::HashMap;
+use redis_module::{Context, RedisError, RedisResult, RedisString};
+
+// Session operations that can run a hook
+#[cfg(feature = "wasm-hooks")]
+const HOOK_POINTS: &[&str] = &["on_create", "on_add_data"];
+
+// What a hook decided about an operation
+pub enum HookOutcome {
+    // Proceed, merging these fields into the session data
+    Allow(HashMap<String, String>),
+    #[cfg_attr(not(feature = "wasm-hooks"), allow(dead_code))]
+    Veto,
+}
+
+// Sandboxed hooks compiled to WebAssembly.
+//
+// A hook module must export `memory`, `alloc(len: i32) -> i32` and a
+// function named after its hook point taking `(ptr: i32, len: i32) -> i64`.
+// The module receives the event as JSON and returns -1 to veto, 0 to allow
+// unchanged, or `(ptr << 32) | len` pointing at a JSON object of string
+// fields to merge into the session data. Every call runs in a fresh
+// instance with a fuel budget, so hooks can't keep state or spin forever.
+#[cfg(feature = "wasm-hooks")]
+mod runtime {
+    use std::collections::HashMap;
+    use std::sync::RwLock;
+    use wasmi::{Config, Engine, Linker, Module, Store};
+
+    use super::HookOutcome;
+
+    // Instructions a single hook invocation may execute
+    const HOOK_FUEL: u64 = 1_000_000;
+
+    struct LoadedHook {
+        engine: Engine,
+        module: Module,
+    }
+
+    static mut HOOKS: Option<RwLock<HashMap<String, LoadedHook>>> = None;
+
+    // Initialize the loaded hooks table
+    fn init_hooks() -> &'static RwLock<HashMap<String, LoadedHook>> {
+        unsafe {
+            if HOOKS.is_none() {
+                HOOKS = Some(RwLock::new(HashMap::new()));
+            }
+            HOOKS.as_ref().unwrap()
+        }
+    }
+
+    pub fn load(point: &str, wasm: &[u8]) -> Result<(), String> {
+        let mut config = Config::default();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config);
+        let module = Module::new(&engine, wasm).map_err(|e| format!("Invalid hook module: {}", e))?;
+
+        if module.exports().all(|export| export.name() != point) {
+            return Err(format!("Hook module does not export {}", point));
+        }
+
+        let mut hooks = init_hooks().write().map_err(|_| "Failed to acquire write lock".to_string())?;
+        hooks.insert(point.to_string(), LoadedHook { engine, module });
+        Ok(())
+    }
+
+    pub fn unload(point: &str) -> bool {
+        init_hooks().write().map(|mut hooks| hooks.remove(point).is_some()).unwrap_or(false)
+    }
+
+    pub fn loaded() -> Vec<String> {
+        init_hooks().read().map(|hooks| hooks.keys().cloned().collect()).unwrap_or_default()
+    }
+
+    pub fn run(point: &str, event: &str) -> Result<HookOutcome, String> {
+        let hooks = init_hooks().read().map_err(|_| "Failed to acquire read lock".to_string())?;
+        let hook = match hooks.get(point) {
+            Some(hook) => hook,
+            None => return Ok(HookOutcome::Allow(HashMap::new())),
+        };
+
+        let mut store = Store::new(&hook.engine, ());
+        store.set_fuel(HOOK_FUEL).map_err(|e| e.to_string())?;
+
+        // No imports: the sandbox can only compute on the bytes we hand it
+        let linker = <Linker<()>>::new(&hook.engine);
+        let instance = linker.instantiate(&mut store, &hook.module)
+            .and_then(|pre| pre.start(&mut store))
+            .map_err(|e| format!("Failed to instantiate hook: {}", e))?;
+
+        let memory = instance.get_memory(&store, "memory").ok_or("Hook does not export memory")?;
+        let alloc = instance.get_typed_func::<i32, i32>(&store, "alloc").map_err(|e| e.to_string())?;
+        let func = instance.get_typed_func::<(i32, i32), i64>(&store, point).map_err(|e| e.to_string())?;
+
+        let input = event.as_bytes();
+        let ptr = alloc.call(&mut store, input.len() as i32).map_err(|e| format!("Hook alloc failed: {}", e))?;
+        memory.write(&mut store, ptr as usize, input).map_err(|e| e.to_string())?;
+
+        let result = func.call(&mut store, (ptr, input.len() as i32)).map_err(|e| format!("Hook failed: {}", e))?;
+        match result {
+            -1 => Ok(HookOutcome::Veto),
+            0 => Ok(HookOutcome::Allow(HashMap::new())),
+            packed if packed > 0 => {
+                let out_ptr = (packed >> 32) as usize;
+                let out_len = (packed & 0xffff_ffff) as usize;
+                let mut output = vec![0u8; out_len];
+                memory.read(&store, out_ptr, &mut output).map_err(|e| e.to_string())?;
+                let fields: HashMap<String, String> = serde_json::from_slice(&output)
+                    .map_err(|e| format!("Hook returned invalid JSON: {}", e))?;
+                Ok(HookOutcome::Allow(fields))
+            },
+            other => Err(format!("Hook returned invalid code {}", other)),
+        }
+    }
+}
+
+// Run the hook registered for a point, if any (always allows when hooks are compiled out)
+pub fn run_hook(point: &str, event: &serde_json::Value) -> Result<HookOutcome, RedisError> {
+    #[cfg(feature = "wasm-hooks")]
+    {
+        runtime::run(point, &event.to_string()).map_err(RedisError::String)
+    }
+    #[cfg(not(feature = "wasm-hooks"))]
+    {
+        let _ = (point, event);
+        Ok(HookOutcome::Allow(HashMap::new()))
+    }
+}
+
+// Manage sandboxed hooks:
+// SESSION.HOOK LOAD point wasm-bytes
+// SESSION.HOOK UNLOAD point
+// SESSION.HOOK LIST
+#[cfg(feature = "wasm-hooks")]
+#[tracing::instrument(name = "session.hook", skip_all)]
+pub fn session_hook(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    use redis_module::{NextArg, RedisValue};
+
+    let mut args = args.into_iter().skip(1);
+    let subcommand = args.next_string()?.to_uppercase();
+
+    match subcommand.as_str() {
+        "LOAD" => {
+            let point = args.next_string()?;
+            let wasm = args.next_arg()?;
+            args.done()?;
+
+            if !HOOK_POINTS.contains(&point.as_str()) {
+                return Err(RedisError::String(format!("Unknown hook point: {}", point)));
+            }
+
+            runtime::load(&point, wasm.as_slice()).map_err(RedisError::String)?;
+            Ok(RedisValue::SimpleStringStatic("OK"))
+        },
+        "UNLOAD" => {
+            let point = args.next_string()?;
+            args.done()?;
+            Ok(RedisValue::Integer(if runtime::unload(&point) { 1 } else { 0 }))
+        },
+        "LIST" => {
+            args.done()?;
+            let mut points = runtime::loaded();
+            points.sort();
+            Ok(RedisValue::Array(points.into_iter().map(RedisValue::BulkString).collect()))
+        },
+        _ => Err(RedisError::String(format!("Unknown SESSION.HOOK subcommand: {}", subcommand))),
+    }
+}
+
+#[cfg(not(feature = "wasm-hooks"))]
+#[tracing::instrument(name = "session.hook", skip_all)]
+pub fn session_hook(_ctx: &Context, _args: Vec<RedisString>) -> RedisResult {
+    Err(RedisError::Str("Hooks are not available: module built without the wasm-hooks feature"))
+}
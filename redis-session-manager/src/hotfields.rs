@@ -0,0 +1,68 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use redis_module::{Context, NextArg, RedisError, RedisResult, RedisString, RedisValue};
+
+// One field read in this many is counted; reported counts are scaled back up
+const SAMPLE_RATE: u64 = 16;
+
+// Distinct fields tracked. Past this, every count is halved and fields left
+// at zero are dropped, so rarely read fields make room and recent reads weigh more.
+const MAX_FIELDS: usize = 10_000;
+
+// Fields SESSION.HOTFIELDS lists unless TOP is given
+const DEFAULT_TOP: usize = 10;
+
+static READS: AtomicU64 = AtomicU64::new(0);
+
+static mut FIELD_READS: Option<Mutex<HashMap<String, u64>>> = None;
+
+// Initialize the sampled read counts
+fn init_field_reads() -> &'static Mutex<HashMap<String, u64>> {
+    unsafe {
+        if FIELD_READS.is_none() {
+            FIELD_READS = Some(Mutex::new(HashMap::new()));
+        }
+        FIELD_READS.as_ref().unwrap()
+    }
+}
+
+// Note a read of a data field, whichever session it was in
+pub fn record_read(field: &str) {
+    if !READS.fetch_add(1, Ordering::Relaxed).is_multiple_of(SAMPLE_RATE) {
+        return;
+    }
+    let Ok(mut counts) = init_field_reads().lock() else { return };
+    if !counts.contains_key(field) && counts.len() >= MAX_FIELDS {
+        counts.retain(|_, count| {
+            *count /= 2;
+            *count > 0
+        });
+    }
+    *counts.entry(field.to_string()).or_insert(0) += 1;
+}
+
+// The most read data fields across all sessions: SESSION.HOTFIELDS [TOP n]
+// Replies [field, estimated reads, ...], most read first. Counts are sampled,
+// so they are estimates in steps of the sample rate.
+#[tracing::instrument(name = "session.hotfields", skip_all)]
+pub fn session_hotfields(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let top = match args.next_string() {
+        Ok(option) if option.eq_ignore_ascii_case("TOP") => args.next_u64()? as usize,
+        Ok(option) => return Err(RedisError::String(format!("Unknown option: {}", option))),
+        Err(_) => DEFAULT_TOP,
+    };
+    args.done()?;
+
+    let counts = init_field_reads().lock().map_err(|_| {
+        RedisError::String("Failed to acquire field reads lock".to_string())
+    })?;
+    let mut hot: Vec<(&String, u64)> = counts.iter().map(|(field, count)| (field, *count)).collect();
+    hot.sort_unstable_by(|(a_field, a_count), (b_field, b_count)| b_count.cmp(a_count).then(a_field.cmp(b_field)));
+
+    Ok(RedisValue::Array(hot.into_iter()
+        .take(top)
+        .flat_map(|(field, count)| [RedisValue::BulkString(field.clone()), RedisValue::Integer((count * SAMPLE_RATE) as i64)])
+        .collect()))
+}
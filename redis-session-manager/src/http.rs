@@ -0,0 +1,208 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use crate::{bridge, init_sessions, locks, retry, watchdog};
+
+// How long a client may take to send its request
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Longest request head (request line plus headers) accepted
+const MAX_REQUEST_BYTES: usize = 8 * 1024;
+
+// One Prometheus sample
+pub struct Metric {
+    pub name: &'static str,
+    pub help: &'static str,
+    pub kind: MetricKind,
+    pub value: f64,
+}
+
+#[derive(Clone, Copy)]
+pub enum MetricKind {
+    Counter,
+    Gauge,
+}
+
+impl Metric {
+    pub fn counter(name: &'static str, help: &'static str, value: u64) -> Metric {
+        Metric { name, help, kind: MetricKind::Counter, value: value as f64 }
+    }
+
+    pub fn gauge(name: &'static str, help: &'static str, value: f64) -> Metric {
+        Metric { name, help, kind: MetricKind::Gauge, value }
+    }
+}
+
+struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    body: String,
+}
+
+impl Response {
+    fn text(status: &'static str, body: impl Into<String>) -> Response {
+        Response { status, content_type: "text/plain; charset=utf-8", body: body.into() }
+    }
+}
+
+// Set once the endpoint is listening
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+pub fn is_running() -> bool {
+    RUNNING.load(Ordering::Relaxed)
+}
+
+// Start the read-only status endpoint on its own thread. Every request must
+// carry `Authorization: Bearer <token>`.
+pub fn start(port: u16, token: String) -> Result<(), String> {
+    let listener = TcpListener::bind(("0.0.0.0", port))
+        .map_err(|e| format!("Failed to bind HTTP status endpoint on port {}: {}", port, e))?;
+
+    thread::Builder::new()
+        .name("session-http".to_string())
+        .spawn(move || {
+            // One connection at a time; the endpoint only serves small status replies
+            for stream in listener.incoming().flatten() {
+                let _ = serve(stream, &token);
+            }
+        })
+        .map(|_| RUNNING.store(true, Ordering::Relaxed))
+        .map_err(|e| format!("Failed to start HTTP status thread: {}", e))
+}
+
+fn serve(stream: TcpStream, token: &str) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+
+    let response = match read_request(&mut reader)? {
+        Some((method, path, authorization)) => {
+            if !authorized(authorization.as_deref(), token) {
+                Response::text("401 Unauthorized", "unauthorized\n")
+            } else if method != "GET" {
+                Response::text("405 Method Not Allowed", "method not allowed\n")
+            } else {
+                route(&path)
+            }
+        },
+        None => Response::text("400 Bad Request", "bad request\n"),
+    };
+
+    let mut stream = stream;
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.status, response.content_type, response.body.len(), response.body,
+    )?;
+    stream.flush()
+}
+
+// Method, path and Authorization header of a request; None if it is malformed
+fn read_request(reader: &mut impl BufRead) -> std::io::Result<Option<(String, String, Option<String>)>> {
+    let mut head_bytes = 0;
+    let mut lines = Vec::new();
+    loop {
+        let mut line = String::new();
+        let read = reader.read_line(&mut line)?;
+        head_bytes += read;
+        if read == 0 || head_bytes > MAX_REQUEST_BYTES {
+            return Ok(None);
+        }
+        let line = line.trim_end_matches(['\r', '\n']).to_string();
+        if line.is_empty() {
+            break;
+        }
+        lines.push(line);
+    }
+
+    let mut request_line = match lines.first() {
+        Some(line) => line.split_whitespace(),
+        None => return Ok(None),
+    };
+    let (method, target) = match (request_line.next(), request_line.next()) {
+        (Some(method), Some(target)) => (method.to_string(), target),
+        _ => return Ok(None),
+    };
+    // Query strings are ignored
+    let path = target.split('?').next().unwrap_or(target).to_string();
+
+    let authorization = lines[1..].iter()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("Authorization"))
+        .map(|(_, value)| value.trim().to_string());
+
+    Ok(Some((method, path, authorization)))
+}
+
+// Compare in constant time so response timing doesn't leak the token
+fn authorized(authorization: Option<&str>, token: &str) -> bool {
+    let presented = match authorization.and_then(|value| value.strip_prefix("Bearer ")) {
+        Some(presented) => presented.trim().as_bytes(),
+        None => return false,
+    };
+    let expected = token.as_bytes();
+    presented.len() == expected.len()
+        && presented.iter().zip(expected).fold(0u8, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+fn route(path: &str) -> Response {
+    match path {
+        "/healthz" => match init_sessions().read() {
+            Ok(_) => Response::text("200 OK", "ok\n"),
+            Err(_) => Response::text("503 Service Unavailable", "session store unavailable\n"),
+        },
+        "/sessions/count" => match session_counts() {
+            Some((count, expired)) => Response {
+                status: "200 OK",
+                content_type: "application/json",
+                body: format!("{{\"count\":{},\"expired\":{}}}\n", count, expired),
+            },
+            None => Response::text("503 Service Unavailable", "session store unavailable\n"),
+        },
+        "/metrics" => Response {
+            status: "200 OK",
+            content_type: "text/plain; version=0.0.4",
+            body: render_metrics(),
+        },
+        _ => Response::text("404 Not Found", "not found\n"),
+    }
+}
+
+// Live sessions, and how many of them are expired but still in their grace window
+fn session_counts() -> Option<(usize, usize)> {
+    let sessions_map = init_sessions().read().ok()?;
+    let expired = sessions_map.values().filter(|session| session.is_expired()).count();
+    Some((sessions_map.len(), expired))
+}
+
+// Prometheus text exposition format
+fn render_metrics() -> String {
+    let mut metrics = Vec::new();
+    if let Some((count, expired)) = session_counts() {
+        metrics.push(Metric::gauge("session_manager_sessions", "Sessions in the store", count as f64));
+        metrics.push(Metric::gauge(
+            "session_manager_sessions_expired",
+            "Expired sessions still readable in their grace window",
+            expired as f64,
+        ));
+    }
+    metrics.extend(bridge::metrics());
+    metrics.extend(watchdog::metrics());
+    metrics.extend(retry::metrics());
+    metrics.extend(locks::metrics());
+
+    let mut body = String::new();
+    for metric in metrics {
+        let kind = match metric.kind {
+            MetricKind::Counter => "counter",
+            MetricKind::Gauge => "gauge",
+        };
+        body.push_str(&format!(
+            "# HELP {name} {}\n# TYPE {name} {}\n{name} {}\n",
+            metric.help, kind, metric.value, name = metric.name,
+        ));
+    }
+    body
+}
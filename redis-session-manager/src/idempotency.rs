@@ -0,0 +1,64 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+// Completed deletes remembered by token; the least recently used is forgotten first
+pub const MAX_DELETE_TOKENS: usize = 10_000;
+
+// Session deleted under each token, with when the token was last used, and
+// tokens by last use so the least recently used is found without a scan
+#[derive(Default)]
+struct DeleteTokens {
+    by_token: HashMap<String, (String, u64)>,
+    by_use: BTreeMap<u64, String>,
+    next_use: u64,
+}
+
+impl DeleteTokens {
+    // Mark a token used now
+    fn touch(&mut self, token: &str) {
+        let Some((_, used)) = self.by_token.get_mut(token) else { return };
+        self.by_use.remove(used);
+        self.next_use += 1;
+        *used = self.next_use;
+        self.by_use.insert(self.next_use, token.to_string());
+    }
+}
+
+static mut DELETE_TOKENS: Option<Mutex<DeleteTokens>> = None;
+
+// Initialize the completed deletes
+fn init_tokens() -> &'static Mutex<DeleteTokens> {
+    unsafe {
+        if DELETE_TOKENS.is_none() {
+            DELETE_TOKENS = Some(Mutex::new(DeleteTokens::default()));
+        }
+        DELETE_TOKENS.as_ref().unwrap()
+    }
+}
+
+// The session a delete with this token removed, if it completed and is still remembered
+pub fn completed_delete(token: &str) -> Option<String> {
+    let mut tokens = init_tokens().lock().ok()?;
+    tokens.touch(token);
+    tokens.by_token.get(token).map(|(session_id, _)| session_id.clone())
+}
+
+// Remember that a delete with this token removed a session
+pub fn record_delete(token: String, session_id: String) {
+    let Ok(mut tokens) = init_tokens().lock() else { return };
+    tokens.next_use += 1;
+    let used = tokens.next_use;
+    if let Some((_, previous)) = tokens.by_token.insert(token.clone(), (session_id, used)) {
+        tokens.by_use.remove(&previous);
+    }
+    tokens.by_use.insert(used, token);
+    while tokens.by_token.len() > MAX_DELETE_TOKENS {
+        let Some((_, oldest)) = tokens.by_use.pop_first() else { break };
+        tokens.by_token.remove(&oldest);
+    }
+}
+
+// Completed deletes currently remembered
+pub fn remembered() -> usize {
+    init_tokens().lock().map_or(0, |tokens| tokens.by_token.len())
+}
@@ -0,0 +1,99 @@
+use chrono::Utc;
+use redis_module::{Context, NextArg, RedisError, RedisResult, RedisString, RedisValue};
+use session_core::{events, Impersonation};
+use uuid::Uuid;
+
+use crate::{bridge, changes, eviction, expiry, init_sessions, retry, spill, webhooks, Session};
+
+// Longest an impersonation session lives, and its TTL unless a shorter one is asked for
+pub const IMPERSONATION_TTL_SECS: u64 = 15 * 60;
+
+// Prefix of the custom hashmap keys that map to impersonation sessions
+const IMPERSONATION_KEY_PREFIX: &str = "impersonation:";
+
+// Record a span carrying both ids whenever an impersonation session is used,
+// so the trace of every command run through it names the admin behind it
+pub fn audit(session: &Session) {
+    if let Some(impersonation) = &session.impersonation {
+        tracing::info_span!(
+            "session.impersonation",
+            session_id = session.id.as_str(),
+            target_id = impersonation.target_id.as_str(),
+            admin_id = impersonation.admin_id.as_str(),
+        ).in_scope(|| ());
+    }
+}
+
+// Derive a short-lived session acting as another one:
+// SESSION.IMPERSONATE target_id admin_id [TTL seconds]
+// The new session starts with a copy of the target's data and app, and
+// expires after at most 15 minutes, never later than the target.
+#[tracing::instrument(name = "session.impersonate", skip_all)]
+pub fn session_impersonate(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let target_id = args.next_string()?;
+    let admin_id = args.next_string()?;
+
+    let mut ttl = IMPERSONATION_TTL_SECS;
+    if let Ok(option) = args.next_string() {
+        if !option.eq_ignore_ascii_case("TTL") {
+            return Err(RedisError::String(format!("Unknown option: {}", option)));
+        }
+        ttl = args.next_u64()?;
+        if ttl == 0 || ttl > IMPERSONATION_TTL_SECS {
+            return Err(RedisError::String(format!("TTL must be between 1 and {} seconds", IMPERSONATION_TTL_SECS)));
+        }
+    }
+    args.done()?;
+
+    if admin_id.is_empty() {
+        return Err(RedisError::Str("Admin id must not be empty"));
+    }
+
+    let (data, types, app, target_expires_at) = {
+        let sessions_map = init_sessions().read().map_err(|_| {
+            RedisError::String("Failed to acquire read lock".to_string())
+        })?;
+        let target = match sessions_map.get(&target_id) {
+            Some(target) if target.is_expired() => return Err(RedisError::String(format!("Session expired: {}", target_id))),
+            Some(target) => target,
+            None => return Err(RedisError::String(format!("Session not found: {}", target_id))),
+        };
+        // An admin acting as a user must not hand that identity on
+        if target.impersonation.is_some() {
+            return Err(RedisError::String(format!("Session {} is itself an impersonation", target_id)));
+        }
+        // Spilled values are copied in, as the target's keys go when it does
+        (spill::resolved_data(ctx, target), target.types.clone(), target.app.clone(), target.expires_at)
+    };
+
+    // Never outlive the target
+    if let Some(expires_at) = target_expires_at {
+        let remaining = (expires_at - Utc::now()).num_seconds().max(1) as u64;
+        ttl = ttl.min(remaining);
+    }
+
+    retry::check_backpressure()?;
+    eviction::make_room(ctx)?;
+
+    let session_id = Uuid::new_v4().to_string();
+    let key = format!("{}{}", IMPERSONATION_KEY_PREFIX, session_id);
+    bridge::set(ctx, &key, &session_id)?;
+
+    let mut session = Session::new(session_id.clone(), key, changes::next_seq());
+    session.data = data;
+    session.types = types;
+    session.app = app;
+    session.impersonation = Some(Impersonation { target_id, admin_id });
+    session.set_ttl(ttl);
+    expiry::cap_lifetime(&mut session);
+    audit(&session);
+
+    let mut sessions_map = init_sessions().write().map_err(|_| {
+        RedisError::String("Failed to acquire write lock".to_string())
+    })?;
+    webhooks::emit(events::CREATED, &session_id, &session.user_key);
+    sessions_map.insert(session_id.clone(), session);
+
+    Ok(RedisValue::SimpleString(format!("Impersonation created: {}", session_id)))
+}
@@ -1,282 +1,545 @@
 use std::collections::HashMap;
-use std::sync::RwLock;
-use redis_module::{Context, NextArg, RedisError, RedisResult, RedisString, RedisValue};
-use serde::{Serialize, Deserialize};
-use chrono::{DateTime, Utc};
+use std::sync::atomic::{AtomicU64, Ordering};
+use redis_module::{Context, NextArg, RedisError, RedisResult, RedisString, RedisValue, Status};
+use chrono::Utc;
+use module_tracing::TracedRwLock;
+use session_core::{events, format, ttl, value, BuildInfo, DataValue, Digest, FieldType, Priority, Session};
 use uuid::Uuid;
-use std::ffi::{CString, CStr};
-use std::os::raw::c_char;
 
-// Dynamic loading approach using libloading
-use libloading::{Library, Symbol};
+mod activity;
+mod aggregate;
+mod archive;
+mod bench;
+mod binding;
+mod bridge;
+mod changes;
+mod config;
+mod debug;
+mod docs;
+mod encryption;
+mod eviction;
+mod expiry;
+mod explain;
+mod fieldtypes;
+mod glob;
+mod hello;
+mod history;
+mod hooks;
+mod hotfields;
+mod idempotency;
+mod http;
+mod impersonate;
+mod locks;
+mod maintenance;
+mod migrate;
+mod nonce;
+mod notify;
+mod oncreate;
+mod paging;
+mod persistence;
+mod preload;
+mod refresh;
+mod replicate;
+mod retry;
+mod secrets;
+mod selftest;
+mod sensitive;
+mod shards;
+mod spill;
+mod store;
+mod templates;
+mod throttle;
+mod timers;
+mod tree;
+mod ttlsync;
+mod watchdog;
+mod webhooks;
 
-// Type aliases for our function signatures
-type SetFn = unsafe extern "C" fn(*const c_char, *const c_char) -> libc::c_int;
-type GetFn = unsafe extern "C" fn(*const c_char) -> *mut c_char;
-type DelFn = unsafe extern "C" fn(*const c_char) -> libc::c_int;
+use store::{SessionStore, SortKey};
 
-// Global variables to store our dynamically loaded functions
-static mut SET_FN: Option<Symbol<'static, SetFn>> = None;
-static mut GET_FN: Option<Symbol<'static, GetFn>> = None;
-static mut DEL_FN: Option<Symbol<'static, DelFn>> = None;
-static mut LIB_HANDLE: Option<Library> = None;
+// Remove a user key from the custom hashmap
+fn unlink_user_key(ctx: &Context, user_key: &str) -> Result<(), RedisError> {
+    bridge::del(ctx, user_key).map(|_| ())
+}
 
-// Initialize and load the custom hashmap library
-fn init_custom_hashmap_lib() -> Result<(), RedisError> {
-    unsafe {
-        if LIB_HANDLE.is_none() {
-            // Try to load the library
-            let lib = match Library::new("libredis_custom_hashmap.dylib") {
-                Ok(lib) => lib,
-                Err(e) => {
-                    // If we can't load the library, we'll fall back to Redis commands
-                    return Err(RedisError::String(format!("Failed to load custom hashmap library: {}", e)));
-                }
-            };
-            
-            // Get the symbols
-            let set_fn = match lib.get::<SetFn>(b"custom_hashmap_set") {
-                Ok(sym) => sym,
-                Err(e) => return Err(RedisError::String(format!("Failed to load custom_hashmap_set: {}", e))),
-            };
-                
-            let get_fn = match lib.get::<GetFn>(b"custom_hashmap_get") {
-                Ok(sym) => sym,
-                Err(e) => return Err(RedisError::String(format!("Failed to load custom_hashmap_get: {}", e))),
-            };
-                
-            let del_fn = match lib.get::<DelFn>(b"custom_hashmap_del") {
-                Ok(sym) => sym,
-                Err(e) => return Err(RedisError::String(format!("Failed to load custom_hashmap_del: {}", e))),
-            };
-                
-            // Need to use transmute for static lifetime, as these will live for the entire program
-            SET_FN = Some(std::mem::transmute(set_fn));
-            GET_FN = Some(std::mem::transmute(get_fn));
-            DEL_FN = Some(std::mem::transmute(del_fn));
-            
-            // Now we can store the library
-            LIB_HANDLE = Some(lib);
-        }
-    }
-    
-    Ok(())
+// Manager-side behaviour of the shared session type
+trait SessionExt {
+    fn mark_changed(&mut self);
+    fn cached_json(&self) -> Result<String, RedisError>;
 }
 
-// Helper function to get a value from the custom hashmap
-fn custom_get(key: &str) -> Option<String> {
-    // Try to initialize the custom hashmap library
-    if let Err(_) = init_custom_hashmap_lib() {
-        return None;
+impl SessionExt for Session {
+    // Stamp the session as modified (plain last_accessed bumps don't count)
+    fn mark_changed(&mut self) {
+        self.change_seq = changes::next_seq();
+        history::record(self);
     }
-    
-    unsafe {
-        let get_fn = match GET_FN.as_ref() {
-            Some(f) => f,
-            None => return None,
-        };
-        
-        let key_cstr = match CString::new(key) {
-            Ok(cstr) => cstr,
-            Err(_) => return None,
-        };
-        
-        let value_ptr = get_fn(key_cstr.as_ptr());
-        if value_ptr.is_null() {
-            return None;
+
+    // The session serialized as SESSION.GET returns it before redaction, with
+    // typed data as native JSON. Every change to a serialized field bumps
+    // change_seq or last_accessed, so the cached copy is reused until one of
+    // them moves.
+    fn cached_json(&self) -> Result<String, RedisError> {
+        let stamp = (self.change_seq, self.last_accessed);
+        let mut cache = self.json_cache.lock().map_err(|_| {
+            RedisError::String("Failed to acquire JSON cache lock".to_string())
+        })?;
+        if let Some((cached_stamp, json)) = cache.as_ref() {
+            if *cached_stamp == stamp {
+                JSON_CACHE_HITS.fetch_add(1, Ordering::Relaxed);
+                return Ok(json.clone());
+            }
         }
-        
-        let value_cstr = CStr::from_ptr(value_ptr);
-        let result = value_cstr.to_string_lossy().to_string();
-        
-        // Need to free the memory allocated by custom_hashmap_get
-        libc::free(value_ptr as *mut libc::c_void);
-        
-        Some(result)
+        JSON_CACHE_MISSES.fetch_add(1, Ordering::Relaxed);
+        let mut json = serde_json::to_value(self).map_err(|e| {
+            RedisError::String(format!("Failed to serialize session: {}", e))
+        })?;
+        value::type_session_json(&mut json);
+        let json = json.to_string();
+        *cache = Some((stamp, json.clone()));
+        Ok(json)
     }
 }
 
-// Helper function to set a value in the custom hashmap
-fn custom_set(key: &str, value: &str) -> bool {
-    // Try to initialize the custom hashmap library
-    if let Err(_) = init_custom_hashmap_lib() {
-        return false;
-    }
-    
+// This build, as SESSION.VERSION, MODULE LIST and the startup log report it
+const BUILD_INFO: BuildInfo = BuildInfo {
+    module: session_core::SESSION_MANAGER_MODULE,
+    version: env!("CARGO_PKG_VERSION"),
+    git_sha: env!("GIT_SHA"),
+    features: &[
+        #[cfg(feature = "wasm-hooks")]
+        "wasm-hooks",
+        #[cfg(debug_assertions)]
+        "debug",
+    ],
+};
+
+// SESSION.GET replies served from, and missing, a session's JSON cache
+static JSON_CACHE_HITS: AtomicU64 = AtomicU64::new(0);
+static JSON_CACHE_MISSES: AtomicU64 = AtomicU64::new(0);
+
+// Global sessions store
+static mut SESSIONS: Option<TracedRwLock<SessionStore>> = None;
+
+// Initialize the sessions store; waits for its lock are traced
+fn init_sessions() -> &'static TracedRwLock<SessionStore> {
     unsafe {
-        let set_fn = match SET_FN.as_ref() {
-            Some(f) => f,
-            None => return false,
-        };
-        
-        let key_cstr = match CString::new(key) {
-            Ok(cstr) => cstr,
-            Err(_) => return false,
-        };
-        
-        let value_cstr = match CString::new(value) {
-            Ok(cstr) => cstr,
-            Err(_) => return false,
-        };
-        
-        let result = set_fn(key_cstr.as_ptr(), value_cstr.as_ptr());
-        
-        result == 1
+        if SESSIONS.is_none() {
+            SESSIONS = Some(TracedRwLock::new("sessions", SessionStore::default()));
+        }
+        SESSIONS.as_ref().unwrap()
     }
 }
 
-// Helper function to delete a key from the custom hashmap
-fn custom_del(key: &str) -> bool {
-    // Try to initialize the custom hashmap library
-    if let Err(_) = init_custom_hashmap_lib() {
-        return false;
-    }
-    
-    unsafe {
-        let del_fn = match DEL_FN.as_ref() {
-            Some(f) => f,
-            None => return false,
-        };
-        
-        let key_cstr = match CString::new(key) {
-            Ok(cstr) => cstr,
-            Err(_) => return false,
-        };
-        
-        let result = del_fn(key_cstr.as_ptr());
-        
-        result == 1
+// A user key or data argument. Sessions are stored and exported as JSON, so
+// bytes that aren't UTF-8 are refused instead of being silently replaced.
+fn next_utf8(args: &mut impl Iterator<Item = RedisString>, what: &str) -> Result<String, RedisError> {
+    String::from_utf8(args.next_arg()?.as_slice().to_vec())
+        .map_err(|_| RedisError::String(format!("{} must be valid UTF-8", what)))
+}
+
+// A number of seconds, refused above session_core::ttl::MAX_SECS so the
+// times it moves can't overflow
+fn next_secs(args: &mut impl Iterator<Item = RedisString>, what: &str) -> Result<u64, RedisError> {
+    ttl::check(args.next_u64()?, what).map_err(RedisError::String)
+}
+
+// A session that may still be written to; expired sessions are read-only
+// for the rest of their grace window
+fn writable_session<'a>(sessions_map: &'a mut SessionStore, session_id: &str) -> Result<&'a mut Session, RedisError> {
+    match sessions_map.get_mut(session_id) {
+        Some(session) if session.is_expired() => Err(RedisError::String(format!("Session expired: {}", session_id))),
+        Some(session) => {
+            impersonate::audit(session);
+            Ok(session)
+        },
+        None => Err(RedisError::String(format!("Session not found: {}", session_id))),
     }
 }
 
-// Session structure
-#[derive(Debug, Serialize, Deserialize)]
-struct Session {
-    id: String,
-    user_key: String,
-    created_at: DateTime<Utc>,
-    last_accessed: DateTime<Utc>,
-    data: HashMap<String, String>,
+// Options shared by SESSION.CREATE and SESSION.GET_OR_CREATE
+#[derive(Default)]
+struct CreateOptions {
+    ttl: Option<u64>,
+    // Idle timeout; Some(0) opts out of the configured default
+    idle: Option<u64>,
+    app: Option<String>,
+    priority: Priority,
+    // New sessions start with a copy of the template's data
+    template: HashMap<String, String>,
 }
 
-// Global sessions store
-static mut SESSIONS: Option<RwLock<HashMap<String, Session>>> = None;
+impl CreateOptions {
+    // [TTL seconds | IDLE seconds] [APP app] [PRIORITY LOW|NORMAL|HIGH] [TEMPLATE name]
+    fn parse(args: &mut impl Iterator<Item = RedisString>) -> Result<CreateOptions, RedisError> {
+        let mut options = CreateOptions::default();
+        while let Ok(option) = args.next_string() {
+            match option.to_uppercase().as_str() {
+                "TTL" => {
+                    let secs = next_secs(args, "TTL")?;
+                    if secs == 0 {
+                        return Err(RedisError::Str("TTL must be positive"));
+                    }
+                    options.ttl = Some(secs);
+                },
+                "IDLE" => options.idle = Some(next_secs(args, "IDLE")?),
+                "APP" => options.app = Some(args.next_string()?),
+                "PRIORITY" => options.priority = parse_priority(&args.next_string()?)?,
+                "TEMPLATE" => options.template = templates::fields(&args.next_string()?)?,
+                _ => return Err(RedisError::String(format!("Unknown option: {}", option))),
+            }
+        }
+        if options.ttl.is_some() && options.idle.is_some_and(|secs| secs > 0) {
+            return Err(RedisError::Str("TTL and IDLE can't be combined"));
+        }
+        Ok(options)
+    }
 
-// Initialize the sessions store
-fn init_sessions() -> &'static RwLock<HashMap<String, Session>> {
-    unsafe {
-        if SESSIONS.is_none() {
-            SESSIONS = Some(RwLock::new(HashMap::new()));
+    // Give a new session its TTL or idle timeout. Without either option it
+    // gets the configured default idle timeout, if any. Either way it is held
+    // to the maximum lifetime.
+    fn apply_expiry(&self, session: &mut Session) {
+        if let Some(secs) = self.ttl {
+            session.set_ttl(secs);
+        } else {
+            match self.idle.unwrap_or_else(expiry::idle_timeout_secs) {
+                0 => {},
+                secs => session.set_idle_timeout(secs),
+            }
         }
-        SESSIONS.as_ref().unwrap()
+        expiry::cap_lifetime(session);
     }
 }
 
-// Create a new session
-fn create_session(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
-    let mut args = args.into_iter().skip(1);
-    let key = args.next_string()?;
-    
-    // Try to get the key from custom hashmap directly via FFI
-    match custom_get(&key) {
-        Some(session_id) => {
-            // Check if session exists
-            let sessions = init_sessions();
-            let mut sessions_map = sessions.write().map_err(|_| {
-                RedisError::String("Failed to acquire write lock".to_string())
-            })?;
-            
+// How a session was obtained for a user key
+enum Obtained {
+    Existing,
+    // The hashmap had the key but the store didn't have its session
+    Recreated,
+    Created,
+}
+
+// The live session of a user key, bumping its last access, or a new one
+fn obtain_session(ctx: &Context, key: String, mut options: CreateOptions) -> Result<(String, Obtained), RedisError> {
+    let app = options.app.take();
+    let template = std::mem::take(&mut options.template);
+    let priority = options.priority;
+
+    // Look up the key in the custom hashmap (FFI or command path, whichever is healthier)
+    if let Some(session_id) = bridge::get(ctx, &key)? {
+        // Check if session exists
+        let sessions = init_sessions();
+        let mut sessions_map = sessions.write().map_err(|_| {
+            RedisError::String("Failed to acquire write lock".to_string())
+        })?;
+        
+        match sessions_map.get_mut(&session_id) {
+            // An expired session still in its grace window is replaced below;
+            // it expires here rather than in the sweep, so say so as the sweep would
+            Some(session) if session.is_expired() => {
+                if let Some(session) = sessions_map.remove(&session_id) {
+                    binding::forget(&session);
+                    changes::record_deletion(&session_id);
+                    webhooks::emit(events::EXPIRED, &session_id, &session.user_key);
+                }
+            },
+            // Another application's session is never handed out
+            Some(session) if app.is_some() && session.app != app => {
+                return Err(RedisError::String(format!(
+                    "Session for key {} belongs to app {}", key, session.app.as_deref().unwrap_or("(none)"),
+                )));
+            },
             // Update the last accessed time if session exists
-            if let Some(session) = sessions_map.get_mut(&session_id) {
-                session.last_accessed = Utc::now();
-                Ok(RedisValue::SimpleString(format!("Session exists: {}", session_id)))
-            } else {
+            Some(session) => {
+                session.touch();
+                return Ok((session_id, Obtained::Existing));
+            },
+            None => {
                 // Create a new session if session ID exists in hashmap but not in our store
-                let session = Session {
-                    id: session_id.clone(),
-                    user_key: key,
-                    created_at: Utc::now(),
-                    last_accessed: Utc::now(),
-                    data: HashMap::new(),
-                };
-                
+                let mut session = Session::new(session_id.clone(), key, changes::next_seq());
+                session.app = app;
+                session.priority = priority;
+                session.data = template;
+                options.apply_expiry(&mut session);
+
+                webhooks::emit(events::CREATED, &session_id, &session.user_key);
+                let user_key = session.user_key.clone();
                 sessions_map.insert(session_id.clone(), session);
-                Ok(RedisValue::SimpleString(format!("Session recreated: {}", session_id)))
-            }
-        },
-        None => {
-            // If key doesn't exist, create a new session
-            // Generate a new session ID
-            let session_id = Uuid::new_v4().to_string();
-            
-            // Add key to custom hashmap with session_id as value directly
-            if !custom_set(&key, &session_id) {
-                // Fall back to Redis commands if direct call fails
-                match ctx.call("custom.set", &[&key, &session_id]) {
-                    Ok(_) => {},
-                    Err(err) => {
-                        return Err(RedisError::String(format!("Failed to call custom.set: {}", err)));
-                    }
-                }
-            }
-            
-            // Create a new session object
-            let session = Session {
-                id: session_id.clone(),
-                user_key: key,
-                created_at: Utc::now(),
-                last_accessed: Utc::now(),
-                data: HashMap::new(),
-            };
-            
-            // Store the session in our internal sessions store
-            let sessions = init_sessions();
-            let mut sessions_map = sessions.write().map_err(|_| {
-                RedisError::String("Failed to acquire write lock".to_string())
-            })?;
-            
-            sessions_map.insert(session_id.clone(), session);
-            
-            Ok(RedisValue::SimpleString(format!("Session created: {}", session_id)))
+                drop(sessions_map);
+                oncreate::run(ctx, &session_id, &user_key);
+                return Ok((session_id, Obtained::Recreated));
+            },
         }
     }
+
+    // If key doesn't exist, create a new session
+    retry::check_backpressure()?;
+    throttle::check_create(&key, throttle::client_ip(ctx).as_deref())?;
+
+    // Generate a new session ID
+    let session_id = Uuid::new_v4().to_string();
+
+    // Let an on_create hook veto the session or seed its data
+    let event = serde_json::json!({ "event": "create", "session_id": session_id, "user_key": key });
+    let seeded = match hooks::run_hook("on_create", &event)? {
+        hooks::HookOutcome::Veto => return Err(RedisError::Str("Session creation vetoed by hook")),
+        hooks::HookOutcome::Allow(fields) => fields,
+    };
+
+    eviction::make_room(ctx)?;
+
+    // Add key to custom hashmap with session_id as value
+    bridge::set(ctx, &key, &session_id)?;
+
+    // Create a new session object
+    let mut session = Session::new(session_id.clone(), key, changes::next_seq());
+    session.app = app;
+    session.priority = priority;
+    // Fields seeded by a hook win over the template's
+    session.data = template;
+    session.data.extend(seeded);
+    options.apply_expiry(&mut session);
+    
+    // Store the session in our internal sessions store
+    let sessions = init_sessions();
+    let mut sessions_map = sessions.write().map_err(|_| {
+        RedisError::String("Failed to acquire write lock".to_string())
+    })?;
+    
+    webhooks::emit(events::CREATED, &session_id, &session.user_key);
+    let user_key = session.user_key.clone();
+    sessions_map.insert(session_id.clone(), session);
+    drop(sessions_map);
+
+    // Bookkeeping configured with SESSION.ON_CREATE
+    oncreate::run(ctx, &session_id, &user_key);
+    
+    Ok((session_id, Obtained::Created))
 }
 
-// Get session by ID
-fn get_session(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+// Create a new session: SESSION.CREATE key [TTL seconds] [APP app] [TEMPLATE name]
+#[tracing::instrument(name = "session.create", skip_all)]
+fn create_session(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
     let mut args = args.into_iter().skip(1);
+    let key = next_utf8(&mut args, "User key")?;
+    let options = CreateOptions::parse(&mut args)?;
+
+    let (session_id, obtained) = obtain_session(ctx, key, options)?;
+    let status = match obtained {
+        Obtained::Existing => "Session exists",
+        Obtained::Recreated => "Session recreated",
+        Obtained::Created => "Session created",
+    };
+    Ok(RedisValue::SimpleString(format!("{}: {}", status, session_id)))
+}
+
+// Look up or create a session in one call:
+// SESSION.GET_OR_CREATE key [TTL seconds] [APP app] [PRIORITY LOW|NORMAL|HIGH] [TEMPLATE name]
+// Does what SESSION.CREATE does, then replies with the session as SESSION.GET
+// returns it and 1 if it was created, 0 if it already existed.
+#[tracing::instrument(name = "session.get_or_create", skip_all)]
+fn get_or_create_session(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let key = next_utf8(&mut args, "User key")?;
+    let options = CreateOptions::parse(&mut args)?;
+    let redactor = sensitive::Redactor::for_caller(ctx, false)?;
+
+    let (session_id, obtained) = obtain_session(ctx, key, options)?;
+
+    let sessions_map = init_sessions().read().map_err(|_| {
+        RedisError::String("Failed to acquire read lock".to_string())
+    })?;
+    let session = sessions_map.get(&session_id)
+        .ok_or_else(|| RedisError::String(format!("Session not found: {}", session_id)))?;
+    let json = finish_session_json(ctx, session, session_json(session, &redactor)?, None)?;
+    Ok(RedisValue::Array(vec![
+        RedisValue::BulkString(json),
+        RedisValue::Integer(!matches!(obtained, Obtained::Existing) as i64),
+    ]))
+}
+
+// Get session by ID:
+// SESSION.GET session_id [MAXAGE seconds] [REVEAL] [DECRYPT key_id] [LIMIT ... | CURSOR ...]
+#[tracing::instrument(name = "session.get", skip_all)]
+fn get_session(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1).peekable();
     let session_id = args.next_string()?;
+
+    // Only return sessions active within the last `seconds`
+    let mut max_age = None;
+    if args.peek().is_some_and(|option| option.to_string_lossy().eq_ignore_ascii_case("MAXAGE")) {
+        args.next();
+        max_age = Some(ttl::duration(next_secs(&mut args, "MAXAGE")?));
+    }
+    // Sensitive fields are redacted unless revealed
+    let mut reveal = false;
+    if args.peek().is_some_and(|option| option.to_string_lossy().eq_ignore_ascii_case("REVEAL")) {
+        args.next();
+        reveal = true;
+    }
+    // Encrypted fields stay ciphertext unless their key is given
+    let mut decrypt = None;
+    if args.peek().is_some_and(|option| option.to_string_lossy().eq_ignore_ascii_case("DECRYPT")) {
+        args.next();
+        decrypt = Some(args.next_string()?);
+    }
+    let page = paging::DataPage::parse(&mut args)?;
+    let redactor = sensitive::Redactor::for_caller(ctx, reveal)?;
+    let decryptor = decrypt.map(|key_id| encryption::Decryptor::for_caller(ctx, key_id)).transpose()?;
     
     let sessions = init_sessions();
     let sessions_map = sessions.read().map_err(|_| {
         RedisError::String("Failed to acquire read lock".to_string())
     })?;
     
-    match sessions_map.get(&session_id) {
+    let (json, sliding) = match sessions_map.get(&session_id) {
+        Some(session) if max_age.is_some_and(|max_age| Utc::now() - session.last_accessed > max_age) => {
+            return Ok(RedisValue::Null);
+        },
         Some(session) => {
-            let json = serde_json::to_string(session).map_err(|e| {
-                RedisError::String(format!("Failed to serialize session: {}", e))
-            })?;
-            Ok(RedisValue::BulkString(json.into()))
+            impersonate::audit(session);
+            let json = match page {
+                // Only the requested window of a large session's data is returned
+                Some(page) => paging::session_page_json(session, &page, &redactor)?,
+                None => session_json(session, &redactor)?,
+            };
+            let json = finish_session_json(ctx, session, json, decryptor.as_ref())?;
+            (json, session.idle_timeout.is_some())
         },
-        None => Ok(RedisValue::Null),
+        None => return Ok(RedisValue::Null),
+    };
+
+    // Reading a session with an idle timeout keeps it alive
+    drop(sessions_map);
+    if sliding {
+        expiry::slide(&session_id);
     }
+    Ok(RedisValue::BulkString(json.into()))
 }
 
-// List all sessions
-fn list_sessions(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
-    if args.len() != 1 {
+// A whole session as SESSION.GET and SESSION.MGET return it, before spilled values are fetched
+fn session_json(session: &Session, redactor: &sensitive::Redactor) -> Result<String, RedisError> {
+    // Hot path: nothing to redact or flag, so the cached JSON is the reply
+    if !session.is_expired() && !redactor.redacts_any(&session.data) {
+        return session.cached_json();
+    }
+    let mut json = serde_json::to_value(session).map_err(|e| {
+        RedisError::String(format!("Failed to serialize session: {}", e))
+    })?;
+    redactor.redact_session(&mut json);
+    value::type_session_json(&mut json);
+    // Let apps tell an expired session in its grace window from a live one
+    if session.is_expired() {
+        json["expired"] = serde_json::Value::Bool(true);
+    }
+    Ok(json.to_string())
+}
+
+// Fetch the spilled values of a serialized session, then decrypt what the
+// caller holds the key for, and type the values that brings back. Sessions
+// with neither are passed through untouched.
+fn finish_session_json(
+    ctx: &Context,
+    session: &Session,
+    json: String,
+    decryptor: Option<&encryption::Decryptor>,
+) -> Result<String, RedisError> {
+    if decryptor.is_none() && session.spilled.is_empty() {
+        return Ok(json);
+    }
+    let mut json: serde_json::Value = serde_json::from_str(&json).map_err(|e| {
+        RedisError::String(format!("Failed to serialize session: {}", e))
+    })?;
+    spill::resolve_session(ctx, session, &mut json);
+    if let Some(decryptor) = decryptor {
+        decryptor.decrypt_session(&mut json);
+    }
+    value::type_session_json(&mut json);
+    Ok(json.to_string())
+}
+
+// Fetch many sessions at once: SESSION.MGET session_id [session_id ...]
+// Replies with each session's JSON, or nil for unknown ids, in argument
+// order. All are read under one pass of the store's read lock, with
+// sensitive fields redacted.
+#[tracing::instrument(name = "session.mget", skip_all)]
+fn mget_sessions(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    if args.len() < 2 {
         return Err(RedisError::WrongArity);
     }
+    let redactor = sensitive::Redactor::for_caller(ctx, false)?;
+
+    let sessions_map = init_sessions().read().map_err(|_| {
+        RedisError::String("Failed to acquire read lock".to_string())
+    })?;
+
+    args.iter().skip(1)
+        .map(|session_id| match sessions_map.get(&session_id.to_string_lossy()) {
+            Some(session) => {
+                impersonate::audit(session);
+                let json = session_json(session, &redactor)?;
+                Ok(RedisValue::BulkString(finish_session_json(ctx, session, json, None)?))
+            },
+            None => Ok(RedisValue::Null),
+        })
+        .collect::<Result<_, _>>()
+        .map(RedisValue::Array)
+}
+
+// List all sessions:
+// SESSION.LIST [SORT BY created|last_accessed|ttl [ASC|DESC]] [LIMIT count] [APP app]
+#[tracing::instrument(name = "session.list", skip_all)]
+fn list_sessions(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1).peekable();
+
+    let mut sort = None;
+    let mut limit = usize::MAX;
+    let mut app = None;
+    while let Ok(option) = args.next_string() {
+        match option.to_uppercase().as_str() {
+            "SORT" => {
+                let by = args.next_string()?;
+                if !by.eq_ignore_ascii_case("BY") {
+                    return Err(RedisError::String(format!("Expected BY, got: {}", by)));
+                }
+                let field = args.next_string()?;
+                let key = match field.to_lowercase().as_str() {
+                    "created" => SortKey::Created,
+                    "last_accessed" => SortKey::LastAccessed,
+                    "ttl" => SortKey::Ttl,
+                    _ => return Err(RedisError::String(format!("Unknown sort field: {}", field))),
+                };
+                let mut descending = false;
+                if let Some(order) = args.peek().map(|order| order.to_string_lossy().to_uppercase()) {
+                    if order == "ASC" || order == "DESC" {
+                        args.next();
+                        descending = order == "DESC";
+                    }
+                }
+                sort = Some((key, descending));
+            },
+            "LIMIT" => limit = args.next_u64()? as usize,
+            "APP" => app = Some(args.next_string()?),
+            _ => return Err(RedisError::String(format!("Unknown option: {}", option))),
+        }
+    }
     
     let sessions = init_sessions();
     let sessions_map = sessions.read().map_err(|_| {
         RedisError::String("Failed to acquire read lock".to_string())
     })?;
+
+    // Sorted listings walk the maintained timestamp indexes instead of sorting every session
+    let selected: Vec<&Session> = match sort {
+        Some((key, descending)) => sessions_map.sorted(key, descending, limit, app.as_deref()),
+        None => match &app {
+            Some(app) => sessions_map.app_sessions(app).into_iter().take(limit).collect(),
+            None => sessions_map.values().take(limit).collect(),
+        },
+    };
     
-    let session_list: Vec<RedisValue> = sessions_map.keys()
-        .map(|id| {
-            let session = &sessions_map[id];
+    let session_list: Vec<RedisValue> = selected.into_iter()
+        .map(|session| {
             let output = format!("ID: {}, Key: {}, Created: {}", 
                 session.id, 
                 session.user_key,
@@ -288,33 +551,287 @@ fn list_sessions(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
     Ok(RedisValue::Array(session_list))
 }
 
-// Add data to a session
-fn add_session_data(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+// Count sessions, optionally only one application's: SESSION.COUNT [APP app]
+#[tracing::instrument(name = "session.count", skip_all)]
+fn count_sessions(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
     let mut args = args.into_iter().skip(1);
-    let session_id = args.next_string()?;
-    let data_key = args.next_string()?;
-    let data_value = args.next_string()?;
-    
+
+    let app = match args.next_string() {
+        Ok(option) if option.eq_ignore_ascii_case("APP") => Some(args.next_string()?),
+        Ok(option) => return Err(RedisError::String(format!("Unknown option: {}", option))),
+        Err(_) => None,
+    };
+    args.done()?;
+
     let sessions = init_sessions();
-    let mut sessions_map = sessions.write().map_err(|_| {
+    let sessions_map = sessions.read().map_err(|_| {
+        RedisError::String("Failed to acquire read lock".to_string())
+    })?;
+
+    Ok(RedisValue::Integer(sessions_map.count(app.as_deref()) as i64))
+}
+
+// Store size, retry queue backpressure and the current interval of each background timer
+#[tracing::instrument(name = "session.info", skip_all)]
+fn session_info(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    if args.len() != 1 {
+        return Err(RedisError::WrongArity);
+    }
+
+    let sessions = init_sessions().read().map_err(|_| {
+        RedisError::String("Failed to acquire read lock".to_string())
+    })?.len();
+
+    let mut info = vec![
+        RedisValue::SimpleStringStatic("sessions"),
+        RedisValue::Integer(sessions as i64),
+    ];
+    info.extend([
+        RedisValue::SimpleStringStatic("retry_queue_depth"),
+        RedisValue::Integer(retry::depth() as i64),
+        RedisValue::SimpleStringStatic("backpressure_depth"),
+        RedisValue::Integer(retry::backpressure_depth() as i64),
+        RedisValue::SimpleStringStatic("backpressure_rejections"),
+        RedisValue::Integer(retry::backpressure_rejections() as i64),
+        RedisValue::SimpleStringStatic("json_cache_hits"),
+        RedisValue::Integer(JSON_CACHE_HITS.load(Ordering::Relaxed) as i64),
+        RedisValue::SimpleStringStatic("json_cache_misses"),
+        RedisValue::Integer(JSON_CACHE_MISSES.load(Ordering::Relaxed) as i64),
+        RedisValue::SimpleStringStatic("evict_memory_percent"),
+        RedisValue::Integer(eviction::evict_memory_percent() as i64),
+        RedisValue::SimpleStringStatic("evicted_sessions"),
+        RedisValue::Integer(eviction::evicted() as i64),
+        RedisValue::SimpleStringStatic("max_sessions"),
+        RedisValue::Integer(eviction::max_sessions() as i64),
+        RedisValue::SimpleStringStatic("max_sessions_policy"),
+        RedisValue::SimpleStringStatic(eviction::limit_policy().name()),
+        RedisValue::SimpleStringStatic("max_sessions_rejections"),
+        RedisValue::Integer(eviction::limit_rejections() as i64),
+        RedisValue::SimpleStringStatic("history_secs"),
+        RedisValue::Integer(history::history_secs() as i64),
+        RedisValue::SimpleStringStatic("idle_timeout_secs"),
+        RedisValue::Integer(expiry::idle_timeout_secs() as i64),
+        RedisValue::SimpleStringStatic("max_lifetime_secs"),
+        RedisValue::Integer(expiry::max_lifetime_secs() as i64),
+        RedisValue::SimpleStringStatic("delete_tokens"),
+        RedisValue::Integer(idempotency::remembered() as i64),
+        RedisValue::SimpleStringStatic("ttl_sync_corrections"),
+        RedisValue::Integer(ttlsync::corrections() as i64),
+        RedisValue::SimpleStringStatic("activity_tracked"),
+        RedisValue::Integer(activity::tracked() as i64),
+    ]);
+    info.extend(spill::info());
+    info.extend(maintenance::info());
+    for timer in timers::ALL {
+        info.push(RedisValue::SimpleString(format!("{}_interval_ms", timer.name())));
+        info.push(RedisValue::Integer(timer.current().as_millis() as i64));
+    }
+    Ok(RedisValue::Array(info))
+}
+
+// Describe this build and the hashmap module it bridges to: SESSION.VERSION
+#[tracing::instrument(name = "session.version", skip_all)]
+fn session_version(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    if args.len() != 1 {
+        return Err(RedisError::WrongArity);
+    }
+
+    let hashmap_abi_version = bridge::hashmap_abi_version();
+    Ok(RedisValue::Array(vec![
+        RedisValue::SimpleStringStatic("module"),
+        RedisValue::SimpleStringStatic(BUILD_INFO.module),
+        RedisValue::SimpleStringStatic("version"),
+        RedisValue::SimpleStringStatic(BUILD_INFO.version),
+        RedisValue::SimpleStringStatic("git_sha"),
+        RedisValue::SimpleStringStatic(BUILD_INFO.git_sha),
+        RedisValue::SimpleStringStatic("features"),
+        RedisValue::Array(BUILD_INFO.features.iter().map(|feature| RedisValue::SimpleStringStatic(feature)).collect()),
+        RedisValue::SimpleStringStatic("abi_version"),
+        RedisValue::Integer(BUILD_INFO.abi_version() as i64),
+        RedisValue::SimpleStringStatic("hashmap_abi_version"),
+        hashmap_abi_version.map_or(RedisValue::Null, |abi_version| RedisValue::Integer(abi_version as i64)),
+        RedisValue::SimpleStringStatic("abi_compatible"),
+        RedisValue::Integer(hashmap_abi_version.is_none_or(|abi_version| abi_version == BUILD_INFO.abi_version()) as i64),
+        RedisValue::SimpleStringStatic("allow_abi_mismatch"),
+        RedisValue::Integer(bridge::allow_abi_mismatch() as i64),
+    ]))
+}
+
+// Fingerprint of every live session, to compare a primary, a replica and a backup:
+// SESSION.DIGEST replies [digest, hex, sessions, count]
+// Only what SESSION.EXPORT carries between stores is hashed: ids, user keys,
+// creation and expiry times, app, priority, impersonation and data. Access times, change
+// sequences and client bindings are local to each server and left out. Spilled
+// values are hashed as fetched, not as the key they are stored under.
+#[tracing::instrument(name = "session.digest", skip_all)]
+fn session_digest(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    if args.len() != 1 {
+        return Err(RedisError::WrongArity);
+    }
+
+    let sessions_map = init_sessions().read().map_err(|_| {
+        RedisError::String("Failed to acquire read lock".to_string())
+    })?;
+
+    let mut digest = Digest::default();
+    for session in sessions_map.values().filter(|session| !session.is_expired()) {
+        let (target_id, admin_id) = session.impersonation.as_ref()
+            .map_or(("", ""), |impersonation| (impersonation.target_id.as_str(), impersonation.admin_id.as_str()));
+        let created_at = session.created_at.timestamp_millis().to_string();
+        let expires_at = session.expires_at.map(|expires_at| expires_at.timestamp_millis().to_string()).unwrap_or_default();
+
+        let mut fields: Vec<&[u8]> = vec![
+            session.id.as_bytes(),
+            session.user_key.as_bytes(),
+            created_at.as_bytes(),
+            expires_at.as_bytes(),
+            session.app.as_deref().unwrap_or_default().as_bytes(),
+            session.priority.as_str().as_bytes(),
+            target_id.as_bytes(),
+            admin_id.as_bytes(),
+        ];
+        let resolved = spill::resolved_data(ctx, session);
+        let mut data: Vec<_> = resolved.iter().collect();
+        data.sort();
+        for (data_key, data_value) in data {
+            fields.push(data_key.as_bytes());
+            fields.push(data_value.as_bytes());
+        }
+        digest.add(&fields);
+    }
+
+    Ok(RedisValue::Array(vec![
+        RedisValue::SimpleStringStatic("digest"),
+        RedisValue::BulkString(digest.hex()),
+        RedisValue::SimpleStringStatic("sessions"),
+        RedisValue::Integer(digest.count() as i64),
+    ]))
+}
+
+fn parse_priority(priority: &str) -> Result<Priority, RedisError> {
+    Priority::parse(priority)
+        .ok_or_else(|| RedisError::String(format!("Invalid priority: {}, expected LOW, NORMAL or HIGH", priority)))
+}
+
+// Change a session's attributes other than its data:
+// SESSION.SET_META session_id PRIORITY LOW|NORMAL|HIGH
+#[tracing::instrument(name = "session.set_meta", skip_all)]
+fn set_session_meta(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let session_id = args.next_string()?;
+
+    let mut priority = None;
+    while let Ok(option) = args.next_string() {
+        match option.to_uppercase().as_str() {
+            "PRIORITY" => priority = Some(parse_priority(&args.next_string()?)?),
+            _ => return Err(RedisError::String(format!("Unknown option: {}", option))),
+        }
+    }
+    let priority = priority.ok_or(RedisError::WrongArity)?;
+
+    let mut sessions_map = init_sessions().write().map_err(|_| {
         RedisError::String("Failed to acquire write lock".to_string())
     })?;
-    
     match sessions_map.get_mut(&session_id) {
+        Some(session) if session.is_expired() => Err(RedisError::String(format!("Session expired: {}", session_id))),
         Some(session) => {
-            session.data.insert(data_key, data_value);
-            session.last_accessed = Utc::now();
+            session.priority = priority;
+            session.mark_changed();
             Ok(RedisValue::SimpleStringStatic("OK"))
         },
         None => Err(RedisError::String(format!("Session not found: {}", session_id))),
     }
 }
 
-// Get data from a session
-fn get_session_data(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+// Add data to a session:
+// SESSION.ADD_DATA session_id field value [TYPE STRING|INT|FLOAT|BOOL|JSON]
+// A typed value is checked and stored as its type, which SESSION.GET_DATA replies with.
+#[tracing::instrument(name = "session.add_data", skip_all)]
+fn add_session_data(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let session_id = args.next_string()?;
+    let data_key = next_utf8(&mut args, "Field")?;
+    let data_value = next_utf8(&mut args, "Value")?;
+    let data_value = match args.next_string() {
+        Ok(option) if option.eq_ignore_ascii_case("TYPE") => fieldtypes::typed(data_value, &args.next_string()?)?,
+        Ok(option) => return Err(RedisError::String(format!("Unknown option: {}", option))),
+        Err(_) => DataValue::String(data_value),
+    };
+    args.done()?;
+
+    write_session_field(ctx, session_id, data_key, data_value, false)
+}
+
+// Write one data field. Hierarchical writes (SESSION.SET_DATA) reject paths
+// that would turn an existing value into a subtree or the other way round.
+// Large values are spilled to native keys.
+fn write_session_field(
+    ctx: &Context,
+    session_id: String,
+    data_key: String,
+    data_value: DataValue,
+    hierarchical: bool,
+) -> RedisResult {
+    let data_type = data_value.field_type();
+    let data_value = data_value.into_text();
+
+    // Let an on_add_data hook veto the write or add derived fields
+    let event = serde_json::json!({ "event": "add_data", "session_id": session_id, "field": data_key, "value": data_value });
+    let derived = match hooks::run_hook("on_add_data", &event)? {
+        hooks::HookOutcome::Veto => return Err(RedisError::Str("Write vetoed by hook")),
+        hooks::HookOutcome::Allow(fields) => fields,
+    };
+
+    let sessions = init_sessions();
+    let mut sessions_map = sessions.write().map_err(|_| {
+        RedisError::String("Failed to acquire write lock".to_string())
+    })?;
+
+    let session = writable_session(&mut sessions_map, &session_id)?;
+    if hierarchical {
+        tree::check_conflict(&session.data, &data_key)?;
+    }
+    let (data_value, data_type) = fieldtypes::check(session, &data_key, data_value, data_type)?;
+    let derived = derived.into_iter()
+        .map(|(field, value)| {
+            let (value, field_type) = fieldtypes::check(session, &field, value, FieldType::String)?;
+            Ok((field, value, field_type))
+        })
+        .collect::<Result<Vec<_>, RedisError>>()?;
+
+    let data_value = encryption::seal(session, &data_key, data_value)?;
+    let data_value = spill::store(ctx, session, &data_key, data_value)?;
+    let derived = derived.into_iter()
+        .map(|(field, value, field_type)| {
+            let value = encryption::seal(session, &field, value)?;
+            Ok((field.clone(), spill::store(ctx, session, &field, value)?, field_type))
+        })
+        .collect::<Result<Vec<_>, RedisError>>()?;
+    session.set_field(data_key, data_value, data_type);
+    for (field, value, field_type) in derived {
+        session.set_field(field, value, field_type);
+    }
+    session.touch();
+    activity::record(&session_id);
+    session.mark_changed();
+    Ok(RedisValue::SimpleStringStatic("OK"))
+}
+
+// Get data from a session: SESSION.GET_DATA session_id field|path.* [REVEAL]
+// Sensitive fields are redacted from path.* replies unless revealed; a field
+// asked for by name is returned as is.
+#[tracing::instrument(name = "session.get_data", skip_all)]
+fn get_session_data(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
     let mut args = args.into_iter().skip(1);
     let session_id = args.next_string()?;
     let data_key = args.next_string()?;
+    let reveal = match args.next_string() {
+        Ok(option) if option.eq_ignore_ascii_case("REVEAL") => true,
+        Ok(option) => return Err(RedisError::String(format!("Unknown option: {}", option))),
+        Err(_) => false,
+    };
+    args.done()?;
+    let redactor = sensitive::Redactor::for_caller(ctx, reveal)?;
     
     let sessions = init_sessions();
     let mut sessions_map = sessions.write().map_err(|_| {
@@ -323,9 +840,28 @@ fn get_session_data(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
     
     match sessions_map.get_mut(&session_id) {
         Some(session) => {
-            session.last_accessed = Utc::now();
-            match session.data.get(&data_key) {
-                Some(value) => Ok(RedisValue::BulkString(value.clone().into())),
+            impersonate::audit(session);
+            session.touch();
+            activity::record(&session_id);
+            hotfields::record_read(&data_key);
+            // `path.*` (or `*`) returns the whole subtree as nested JSON,
+            // sensitive fields redacted
+            if let Some(prefix) = tree::subtree_prefix(&data_key) {
+                let resolved;
+                let data = if session.spilled.is_empty() && !redactor.redacts_any(&session.data) {
+                    &session.data
+                } else {
+                    resolved = redactor.redact_data(spill::resolved_data(ctx, session));
+                    &resolved
+                };
+                return Ok(match tree::subtree_json(data, &session.types, prefix) {
+                    Some(json) => RedisValue::BulkString(json),
+                    None => RedisValue::Null,
+                });
+            }
+            let field_type = session.types.get(&data_key).copied().unwrap_or_default();
+            match spill::value(ctx, session, &data_key) {
+                Some(value) => Ok(fieldtypes::reply(DataValue::read(&value, field_type))),
                 None => Ok(RedisValue::Null),
             }
         },
@@ -333,48 +869,426 @@ fn get_session_data(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
     }
 }
 
-// Delete a session
+// Names of a session's data fields, optionally filtered by a glob pattern:
+// SESSION.DATA_KEYS session_id [MATCH pattern]
+#[tracing::instrument(name = "session.data_keys", skip_all)]
+fn session_data_keys(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let session_id = args.next_string()?;
+
+    let mut pattern = None;
+    if let Ok(option) = args.next_string() {
+        if !option.eq_ignore_ascii_case("MATCH") {
+            return Err(RedisError::String(format!("Unknown option: {}", option)));
+        }
+        pattern = Some(args.next_string()?);
+    }
+    args.done()?;
+
+    let sessions = init_sessions();
+    let sessions_map = sessions.read().map_err(|_| {
+        RedisError::String("Failed to acquire read lock".to_string())
+    })?;
+
+    let session = sessions_map.get(&session_id)
+        .ok_or_else(|| RedisError::String(format!("Session not found: {}", session_id)))?;
+
+    let mut fields: Vec<&String> = session.data.keys()
+        .filter(|field| pattern.as_ref().is_none_or(|pattern| glob::glob_match(pattern, field)))
+        .collect();
+    fields.sort();
+
+    Ok(RedisValue::Array(fields.into_iter().map(|field| RedisValue::BulkString(field.clone())).collect()))
+}
+
+// Field-level diff of two sessions' data
+#[tracing::instrument(name = "session.compare", skip_all)]
+fn compare_sessions(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let left_id = args.next_string()?;
+    let right_id = args.next_string()?;
+    args.done()?;
+
+    let _locks = locks::lock_sessions(&[&left_id, &right_id], locks::DEFAULT_LOCK_TIMEOUT)?;
+
+    let sessions = init_sessions();
+    let sessions_map = sessions.read().map_err(|_| {
+        RedisError::String("Failed to acquire read lock".to_string())
+    })?;
+
+    let left = sessions_map.get(&left_id)
+        .ok_or_else(|| RedisError::String(format!("Session not found: {}", left_id)))?;
+    let right = sessions_map.get(&right_id)
+        .ok_or_else(|| RedisError::String(format!("Session not found: {}", right_id)))?;
+    // Spilled values are compared by content, not by the key holding them
+    let (left, right) = (spill::resolved_data(ctx, left), spill::resolved_data(ctx, right));
+
+    let mut fields: Vec<&String> = left.keys().chain(right.keys()).collect();
+    fields.sort();
+    fields.dedup();
+
+    let as_value = |value: Option<&String>| match value {
+        Some(value) => RedisValue::BulkString(value.clone()),
+        None => RedisValue::Null,
+    };
+
+    // Each entry is [field, added|removed|changed, left value, right value]
+    let diff: Vec<RedisValue> = fields.into_iter()
+        .filter_map(|field| {
+            let left_value = left.get(field);
+            let right_value = right.get(field);
+            let change = match (left_value, right_value) {
+                (None, Some(_)) => "added",
+                (Some(_), None) => "removed",
+                (Some(a), Some(b)) if a != b => "changed",
+                _ => return None,
+            };
+            Some(RedisValue::Array(vec![
+                RedisValue::BulkString(field.clone()),
+                RedisValue::SimpleStringStatic(change),
+                as_value(left_value),
+                as_value(right_value),
+            ]))
+        })
+        .collect();
+
+    Ok(RedisValue::Array(diff))
+}
+
+// Delete a session: SESSION.DELETE session_id [TOKEN token]
+// Replies 1 once deleted and 0 for an unknown session. Retrying a delete
+// that completed with the same TOKEN replies ALREADY_DELETED instead, with
+// none of its side effects repeated.
+#[tracing::instrument(name = "session.delete", skip_all)]
 fn delete_session(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
     let mut args = args.into_iter().skip(1);
     let session_id = args.next_string()?;
+    let token = match args.next_string() {
+        Ok(option) if option.eq_ignore_ascii_case("TOKEN") => Some(args.next_string()?),
+        Ok(option) => return Err(RedisError::String(format!("Unknown option: {}", option))),
+        Err(_) => None,
+    };
+    args.done()?;
+
+    if let Some(token) = &token {
+        match idempotency::completed_delete(token) {
+            Some(deleted) if deleted == session_id => return Ok(RedisValue::SimpleStringStatic("ALREADY_DELETED")),
+            Some(deleted) => return Err(RedisError::String(format!("Token {} already deleted session {}", token, deleted))),
+            None => {},
+        }
+    }
     
     let sessions = init_sessions();
     let mut sessions_map = sessions.write().map_err(|_| {
         RedisError::String("Failed to acquire write lock".to_string())
     })?;
     
+    // Unlink the user key before the store changes, so a failed unlink
+    // leaves the session exactly as it was and a retry starts over
+    let user_key = match sessions_map.get(&session_id) {
+        Some(session) => session.user_key.clone(),
+        None => return Ok(RedisValue::Integer(0)),
+    };
+    unlink_user_key(ctx, &user_key)?;
+
     if let Some(session) = sessions_map.remove(&session_id) {
-        // Try to remove from custom hashmap directly via FFI
-        if !custom_del(&session.user_key) {
-            // Fall back to Redis commands if direct call fails
-            match ctx.call("custom.del", &[&session.user_key]) {
-                Ok(_) => {},
-                Err(err) => {
-                    // Re-add the session since we failed to remove from custom hashmap
-                    sessions_map.insert(session_id.clone(), session);
-                    return Err(RedisError::String(format!("Failed to call custom.del: {}", err)));
-                }
-            }
+        binding::forget(&session);
+        changes::record_deletion(&session_id);
+        webhooks::emit(events::DELETED, &session_id, &session.user_key);
+    }
+    if let Some(token) = token {
+        idempotency::record_delete(token, session_id);
+    }
+    Ok(RedisValue::Integer(1))
+}
+
+// Inspect recorded spans: SESSION.TRACE RECENT|EXPORT|LEVEL|STATUS ...
+fn session_trace(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    module_tracing::trace_command("SESSION.TRACE", args)
+}
+
+// Options given as name=value arguments after the module path
+#[derive(Default)]
+struct ModuleArgs {
+    // preload=<path to sessions.ndjson>
+    preload_path: Option<String>,
+    // http_port=<port> and http_token=<token> enable the HTTP status endpoint
+    http_port: Option<u16>,
+    http_token: Option<String>,
+    // backpressure_depth=<n>: retry queue depth at which new sessions are refused, 0 for never
+    backpressure_depth: Option<usize>,
+    // evict_memory_percent=<n>: share of maxmemory at which sessions are evicted, 0 for never
+    evict_memory_percent: Option<u64>,
+    // max_sessions=<n>: most sessions live at once, 0 for no limit
+    max_sessions: Option<usize>,
+    // max_sessions_policy=reject|evict-lru|evict-oldest: what a new session does at max_sessions
+    max_sessions_policy: Option<eviction::LimitPolicy>,
+    // history_secs=<n>: how far back SESSION.GET_AT can read session data, 0 for no history
+    history_secs: Option<u64>,
+    // allow_abi_mismatch=yes|no: bridge to a hashmap module built against another C API version
+    allow_abi_mismatch: bool,
+    // spill_threshold=<bytes>: data values at least this large are stored in native keys, 0 for never
+    spill_threshold: Option<u64>,
+    // fallback_get=, fallback_set=, fallback_del=<command>: names the bridge's command path calls
+    fallback_get: Option<String>,
+    fallback_set: Option<String>,
+    fallback_del: Option<String>,
+    // idle_timeout=<secs>: sessions created without TTL or IDLE expire after this long without access, 0 for never
+    idle_timeout: Option<u64>,
+    // max_lifetime=<secs>: no session lives longer than this from its creation, however active, 0 for no limit
+    max_lifetime: Option<u64>,
+    // field_types=loose|strict|coerce: what a data write of another type than the field holds does
+    field_types: Option<fieldtypes::Mode>,
+    // session_format=<version>: format sessions are written in, the previous one while older nodes still read them
+    session_format: Option<u32>,
+    // events_channel=<channel>: pub/sub channel lifecycle events are published to
+    events_channel: Option<String>,
+    // config=<path to TOML file>: settings applied after the other arguments, reloadable with SESSION.CONFIG RELOAD
+    config_path: Option<String>,
+}
+
+fn parse_module_args(args: &[RedisString]) -> Result<ModuleArgs, String> {
+    let mut parsed = ModuleArgs::default();
+    for arg in args {
+        let arg = arg.to_string_lossy();
+        let (name, value) = arg.split_once('=')
+            .ok_or_else(|| format!("Unknown module argument: {}", arg))?;
+        match name.to_lowercase().as_str() {
+            "preload" => parsed.preload_path = Some(value.to_string()),
+            "http_port" => {
+                let port = value.parse().map_err(|_| format!("Invalid http_port: {}", value))?;
+                parsed.http_port = Some(port);
+            },
+            "http_token" => parsed.http_token = Some(value.to_string()),
+            "backpressure_depth" => {
+                let depth = value.parse().map_err(|_| format!("Invalid backpressure_depth: {}", value))?;
+                parsed.backpressure_depth = Some(depth);
+            },
+            "evict_memory_percent" => {
+                let percent = value.parse().ok().filter(|percent| *percent <= 100)
+                    .ok_or_else(|| format!("Invalid evict_memory_percent: {}", value))?;
+                parsed.evict_memory_percent = Some(percent);
+            },
+            "max_sessions" => {
+                let max = value.parse().map_err(|_| format!("Invalid max_sessions: {}", value))?;
+                parsed.max_sessions = Some(max);
+            },
+            "max_sessions_policy" => {
+                let policy = eviction::LimitPolicy::parse(value)
+                    .ok_or_else(|| format!("Invalid max_sessions_policy: {}, expected reject, evict-lru or evict-oldest", value))?;
+                parsed.max_sessions_policy = Some(policy);
+            },
+            "history_secs" => {
+                let secs = value.parse().map_err(|_| format!("Invalid history_secs: {}", value))?;
+                let secs = ttl::check(secs, "history_secs")?;
+                parsed.history_secs = Some(secs);
+            },
+            "allow_abi_mismatch" => {
+                parsed.allow_abi_mismatch = match value.to_lowercase().as_str() {
+                    "yes" => true,
+                    "no" => false,
+                    _ => return Err(format!("Invalid allow_abi_mismatch: {}, expected yes or no", value)),
+                };
+            },
+            "spill_threshold" => {
+                let bytes = value.parse().map_err(|_| format!("Invalid spill_threshold: {}", value))?;
+                parsed.spill_threshold = Some(bytes);
+            },
+            "fallback_get" => parsed.fallback_get = Some(value.to_string()),
+            "fallback_set" => parsed.fallback_set = Some(value.to_string()),
+            "fallback_del" => parsed.fallback_del = Some(value.to_string()),
+            "idle_timeout" => {
+                let secs = value.parse().map_err(|_| format!("Invalid idle_timeout: {}", value))?;
+                let secs = ttl::check(secs, "idle_timeout")?;
+                parsed.idle_timeout = Some(secs);
+            },
+            "max_lifetime" => {
+                let secs = value.parse().map_err(|_| format!("Invalid max_lifetime: {}", value))?;
+                let secs = ttl::check(secs, "max_lifetime")?;
+                parsed.max_lifetime = Some(secs);
+            },
+            "field_types" => {
+                let mode = fieldtypes::Mode::parse(value)
+                    .ok_or_else(|| format!("Invalid field_types: {}, expected loose, strict or coerce", value))?;
+                parsed.field_types = Some(mode);
+            },
+            "session_format" => {
+                let version = value.parse().map_err(|_| format!("Invalid session_format: {}", value))?;
+                format::check(version)?;
+                parsed.session_format = Some(version);
+            },
+            "events_channel" => parsed.events_channel = Some(value.to_string()),
+            "config" => parsed.config_path = Some(value.to_string()),
+            _ => return Err(format!("Unknown module argument: {}", arg)),
         }
-        
-        Ok(RedisValue::Integer(1))
-    } else {
-        Ok(RedisValue::Integer(0))
     }
+    if parsed.http_port.is_some() && parsed.http_token.as_deref().is_none_or(str::is_empty) {
+        return Err("http_port needs a non-empty http_token".to_string());
+    }
+    Ok(parsed)
+}
+
+// Module load hook
+fn init(ctx: &Context, args: &[RedisString]) -> Status {
+    let args = match parse_module_args(args) {
+        Ok(args) => args,
+        Err(err) => {
+            ctx.log_warning(&err);
+            return Status::Err;
+        },
+    };
+
+    ctx.log_notice(&format!("Loading {}", BUILD_INFO));
+    module_tracing::init();
+    command_docs::register(ctx, docs::COMMANDS);
+    bridge::set_allow_abi_mismatch(args.allow_abi_mismatch);
+    bridge::set_fallback_commands(args.fallback_get, args.fallback_set, args.fallback_del);
+    if let Some(depth) = args.backpressure_depth {
+        retry::set_backpressure_depth(depth);
+    }
+    if let Some(percent) = args.evict_memory_percent {
+        eviction::set_evict_memory_percent(percent);
+    }
+    if let Some(max) = args.max_sessions {
+        eviction::set_max_sessions(max);
+    }
+    if let Some(policy) = args.max_sessions_policy {
+        eviction::set_limit_policy(policy);
+    }
+    if let Some(secs) = args.history_secs {
+        history::set_history_secs(secs);
+    }
+    if let Some(bytes) = args.spill_threshold {
+        spill::set_spill_threshold(bytes);
+    }
+    if let Some(secs) = args.idle_timeout {
+        expiry::set_idle_timeout_secs(secs);
+    }
+    if let Some(secs) = args.max_lifetime {
+        expiry::set_max_lifetime_secs(secs);
+    }
+    if let Some(mode) = args.field_types {
+        fieldtypes::set_mode(mode);
+    }
+    if let Some(version) = args.session_format {
+        if let Err(err) = format::set_written(version) {
+            ctx.log_warning(&err);
+            return Status::Err;
+        }
+    }
+    if let Some(channel) = args.events_channel {
+        notify::set_channel(channel);
+    }
+    // The config file wins over arguments that set the same thing
+    if let Some(path) = args.config_path {
+        if let Err(err) = config::load_at_start(path) {
+            ctx.log_warning(&err);
+            return Status::Err;
+        }
+    }
+    bridge::start(ctx);
+
+    // Warm the store before the module serves its first command
+    if let Some(path) = args.preload_path {
+        if preload::load(ctx, &path) == Status::Err {
+            return Status::Err;
+        }
+    }
+
+    if let (Some(port), Some(token)) = (args.http_port, args.http_token) {
+        if let Err(err) = http::start(port, token) {
+            ctx.log_warning(&err);
+            return Status::Err;
+        }
+    }
+
+    if let Err(err) = webhooks::start() {
+        ctx.log_warning(&err);
+        return Status::Err;
+    }
+
+    expiry::start(ctx);
+    retry::start(ctx);
+    replicate::start(ctx);
+    ttlsync::start(ctx);
+    notify::start(ctx);
+    binding::subscribe_client_events(ctx)
 }
 
 // Redis module initialization
 redis_module::redis_module! {
     name: "session_manager",
-    version: 1,
+    version: session_core::version::module_version(env!("CARGO_PKG_VERSION")),
     allocator: (redis_module::alloc::RedisAlloc, redis_module::alloc::RedisAlloc),
     data_types: [],
+    init: init,
     commands: [
         ["session.create", create_session, "write", 1, 1, 1],
+        ["session.get_or_create", get_or_create_session, "write", 1, 1, 1],
         ["session.get", get_session, "readonly", 1, 1, 1],
+        ["session.mget", mget_sessions, "readonly", 1, -1, 1],
+        ["session.get_at", history::session_get_at, "readonly", 1, 1, 1],
+        ["session.impersonate", impersonate::session_impersonate, "write", 1, 1, 1],
         ["session.list", list_sessions, "readonly", 0, 0, 0],
+        ["session.count", count_sessions, "readonly", 0, 0, 0],
+        ["session.info", session_info, "readonly", 0, 0, 0],
+        ["session.digest", session_digest, "readonly", 0, 0, 0],
+        ["session.version", session_version, "readonly", 0, 0, 0],
+        ["session.hello", hello::session_hello, "readonly", 0, 0, 0],
+        ["session.explain", explain::session_explain, "readonly", 0, 0, 0],
+        ["session.aggregate", aggregate::session_aggregate, "readonly", 0, 0, 0],
+        ["session.touch", expiry::session_touch, "write", 1, 1, 1],
+        ["session.expireat", expiry::session_expireat, "write", 1, 1, 1],
+        ["session.persist", expiry::session_persist, "write", 1, 1, 1],
+        ["session.ttl", expiry::session_ttl, "readonly", 1, 1, 1],
+        ["session.bind_ttl_to_key", ttlsync::session_bind_ttl_to_key, "write", 1, 1, 1],
+        ["session.expire_idle", expiry::expire_idle_sessions, "write", 0, 0, 0],
+        ["session.simulate_expiry", expiry::simulate_expiry, "readonly", 0, 0, 0],
         ["session.add_data", add_session_data, "write", 1, 1, 1],
         ["session.get_data", get_session_data, "readonly", 1, 1, 1],
+        ["session.hotfields", hotfields::session_hotfields, "readonly", 0, 0, 0],
+        ["session.activity", activity::session_activity, "readonly", 1, 1, 1],
+        ["session.set_data", tree::set_session_data, "write", 1, 1, 1],
+        ["session.patch", tree::patch_session_data, "write", 1, 1, 1],
+        ["session.set_meta", set_session_meta, "write", 1, 1, 1],
+        ["session.del_data", tree::del_session_data, "write", 1, 1, 1],
+        ["session.get_all_data", paging::get_all_session_data, "readonly", 1, 1, 1],
+        ["session.data_keys", session_data_keys, "readonly", 1, 1, 1],
+        ["session.field_type", fieldtypes::session_field_type, "readonly", 1, 1, 1],
+        ["session.field_types", fieldtypes::session_field_types, "admin", 0, 0, 0],
         ["session.delete", delete_session, "write", 1, 1, 1],
+        ["session.archive", archive::archive_session, "write", 1, 1, 1],
+        ["session.unarchive", archive::unarchive_session, "write", 1, 1, 1],
+        ["session.compare", compare_sessions, "readonly", 1, 2, 1],
+        ["session.secret", secrets::session_secret, "write", 2, 2, 1],
+        ["session.refresh", refresh::session_refresh, "write", 0, 0, 0],
+        ["session.nonce", nonce::session_nonce, "write", 2, 2, 1],
+        ["session.on_create", oncreate::session_on_create, "admin", 0, 0, 0],
+        ["session.template", templates::session_template, "admin", 0, 0, 0],
+        ["session.sensitive", sensitive::session_sensitive, "admin", 0, 0, 0],
+        ["session.encryption", encryption::session_encryption, "admin", 0, 0, 0],
+        ["session.bridge", bridge::session_bridge, "admin", 0, 0, 0],
+        ["session.expiry_warning", expiry::session_expiry_warning, "admin", 0, 0, 0],
+        ["session.webhook", webhooks::session_webhook, "admin", 0, 0, 0],
+        ["session.events", notify::session_events, "admin", 0, 0, 0],
+        ["session.throttle_create", throttle::session_throttle_create, "admin", 0, 0, 0],
+        ["session.maintenance", maintenance::session_maintenance, "admin", 0, 0, 0],
+        ["session.flush_persistence", persistence::session_flush_persistence, "admin", 0, 0, 0],
+        ["session.expiry_grace", expiry::session_expiry_grace, "admin", 0, 0, 0],
+        ["session.export", changes::export_sessions, "readonly", 0, 0, 0],
+        ["session.replicate_to", replicate::session_replicate_to, "admin", 0, 0, 0],
+        ["session.replica_apply", replicate::session_replica_apply, "admin write", 0, 0, 0],
+        ["session.migrate_format", migrate::session_migrate_format, "admin", 0, 0, 0],
+        ["session.dlq", retry::session_dlq, "admin", 0, 0, 0],
+        ["session.lockstats", locks::lock_stats, "readonly", 0, 0, 0],
+        ["session.shards", shards::session_shards, "admin", 0, 0, 0],
+        ["session.config", config::session_config, "admin", 0, 0, 0],
+        ["session.hook", hooks::session_hook, "admin", 0, 0, 0],
+        ["session.bench", bench::session_bench, "admin", 0, 0, 0],
+        ["session.selftest", selftest::session_selftest, "admin", 0, 0, 0],
+        ["session.debug", debug::session_debug, "admin", 0, 0, 0],
+        ["session.bind", binding::bind_session, "write", 1, 1, 1],
+        ["session.unbind", binding::unbind_session, "write", 1, 1, 1],
+        ["session.trace", session_trace, "admin", 0, 0, 0],
     ],
 }
@@ -0,0 +1,139 @@
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+use redis_module::{Context, RedisError, RedisResult, RedisString, RedisValue};
+
+use crate::http::Metric;
+
+// Default time a multi-session operation waits for its locks
+pub const DEFAULT_LOCK_TIMEOUT: Duration = Duration::from_millis(100);
+
+// Logical per-session locks for operations spanning several sessions.
+// Locks are always taken in session id order, so two multi-session
+// operations can never wait on each other in a cycle.
+struct LockTable {
+    held: Mutex<HashSet<String>>,
+    released: Condvar,
+}
+
+// Contention metrics
+static ACQUISITIONS: AtomicU64 = AtomicU64::new(0);
+static CONTENDED: AtomicU64 = AtomicU64::new(0);
+static TIMEOUTS: AtomicU64 = AtomicU64::new(0);
+static WAIT_MICROS: AtomicU64 = AtomicU64::new(0);
+
+static mut LOCK_TABLE: Option<LockTable> = None;
+
+// Initialize the lock table
+fn init_lock_table() -> &'static LockTable {
+    unsafe {
+        if LOCK_TABLE.is_none() {
+            LOCK_TABLE = Some(LockTable {
+                held: Mutex::new(HashSet::new()),
+                released: Condvar::new(),
+            });
+        }
+        LOCK_TABLE.as_ref().unwrap()
+    }
+}
+
+// Locks held on a set of sessions, released on drop
+pub struct SessionLocks {
+    ids: Vec<String>,
+}
+
+impl Drop for SessionLocks {
+    fn drop(&mut self) {
+        release(&self.ids);
+    }
+}
+
+fn release(ids: &[String]) {
+    let table = init_lock_table();
+    if let Ok(mut held) = table.held.lock() {
+        for id in ids {
+            held.remove(id);
+        }
+    }
+    table.released.notify_all();
+}
+
+// Lock several sessions, in id order, giving up after `timeout`
+#[tracing::instrument(name = "lock_wait", level = "debug", skip_all, fields(lock = "session_locks", sessions = ids.len()))]
+pub fn lock_sessions(ids: &[&str], timeout: Duration) -> Result<SessionLocks, RedisError> {
+    let mut ordered: Vec<String> = ids.iter().map(|id| id.to_string()).collect();
+    ordered.sort();
+    ordered.dedup();
+
+    let table = init_lock_table();
+    let deadline = Instant::now() + timeout;
+    let mut acquired: Vec<String> = Vec::with_capacity(ordered.len());
+    let mut waited = false;
+    let start = Instant::now();
+
+    let mut held = table.held.lock().map_err(|_| {
+        RedisError::String("Failed to acquire lock table".to_string())
+    })?;
+
+    for id in ordered {
+        while held.contains(&id) {
+            waited = true;
+            let now = Instant::now();
+            if now >= deadline {
+                drop(held);
+                release(&acquired);
+                TIMEOUTS.fetch_add(1, Ordering::Relaxed);
+                return Err(RedisError::String(format!("Timed out waiting for lock on session {}", id)));
+            }
+            held = table.released.wait_timeout(held, deadline - now)
+                .map_err(|_| RedisError::String("Failed to acquire lock table".to_string()))?
+                .0;
+        }
+        held.insert(id.clone());
+        acquired.push(id);
+    }
+
+    ACQUISITIONS.fetch_add(1, Ordering::Relaxed);
+    if waited {
+        CONTENDED.fetch_add(1, Ordering::Relaxed);
+        WAIT_MICROS.fetch_add(start.elapsed().as_micros() as u64, Ordering::Relaxed);
+    }
+
+    Ok(SessionLocks { ids: acquired })
+}
+
+// Lock metrics for the HTTP status endpoint
+pub fn metrics() -> Vec<Metric> {
+    let held = init_lock_table().held.lock().map(|held| held.len()).unwrap_or(0);
+    vec![
+        Metric::counter("session_manager_lock_acquisitions_total", "Multi-session lock acquisitions", ACQUISITIONS.load(Ordering::Relaxed)),
+        Metric::counter("session_manager_lock_contended_total", "Acquisitions that had to wait", CONTENDED.load(Ordering::Relaxed)),
+        Metric::counter("session_manager_lock_timeouts_total", "Acquisitions that timed out", TIMEOUTS.load(Ordering::Relaxed)),
+        Metric::counter("session_manager_lock_wait_us_total", "Time spent waiting for session locks", WAIT_MICROS.load(Ordering::Relaxed)),
+        Metric::gauge("session_manager_locks_held", "Session locks currently held", held as f64),
+    ]
+}
+
+// Report multi-session lock metrics: SESSION.LOCKSTATS
+#[tracing::instrument(name = "session.lockstats", skip_all)]
+pub fn lock_stats(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    if args.len() != 1 {
+        return Err(RedisError::WrongArity);
+    }
+
+    let held = init_lock_table().held.lock().map(|held| held.len()).unwrap_or(0);
+
+    Ok(RedisValue::Array(vec![
+        RedisValue::SimpleStringStatic("acquisitions"),
+        RedisValue::Integer(ACQUISITIONS.load(Ordering::Relaxed) as i64),
+        RedisValue::SimpleStringStatic("contended"),
+        RedisValue::Integer(CONTENDED.load(Ordering::Relaxed) as i64),
+        RedisValue::SimpleStringStatic("timeouts"),
+        RedisValue::Integer(TIMEOUTS.load(Ordering::Relaxed) as i64),
+        RedisValue::SimpleStringStatic("wait_us"),
+        RedisValue::Integer(WAIT_MICROS.load(Ordering::Relaxed) as i64),
+        RedisValue::SimpleStringStatic("held"),
+        RedisValue::Integer(held as i64),
+    ]))
+}
@@ -0,0 +1,279 @@
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use chrono::{DateTime, NaiveTime, Utc};
+use redis_module::{Context, NextArg, RedisError, RedisResult, RedisString, RedisValue};
+
+use crate::{bridge, history, init_sessions, refresh, throttle};
+
+// User keys looked up per bridge call while reconciling
+const RECONCILE_BATCH: usize = 100;
+
+// A maintenance job the scheduler can run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Task {
+    // Drop expired nonces and refresh tokens, stale history and throttle counts
+    Gc,
+    // Give back memory the store no longer needs
+    Compact,
+    // Restore missing user key mappings of live sessions in the custom hashmap
+    Reconcile,
+}
+
+const TASKS: [Task; 3] = [Task::Gc, Task::Compact, Task::Reconcile];
+
+impl Task {
+    fn as_str(self) -> &'static str {
+        match self {
+            Task::Gc => "gc",
+            Task::Compact => "compact",
+            Task::Reconcile => "reconcile",
+        }
+    }
+
+    fn parse(name: &str) -> Result<Task, RedisError> {
+        TASKS.into_iter()
+            .find(|task| name.eq_ignore_ascii_case(task.as_str()))
+            .ok_or_else(|| RedisError::String(format!("Unknown maintenance task: {}", name)))
+    }
+
+    // Run the task, returning how many items it removed or repaired
+    fn run(self, ctx: &Context) -> Result<usize, RedisError> {
+        match self {
+            Task::Gc => gc(),
+            Task::Compact => compact(),
+            Task::Reconcile => reconcile(ctx),
+        }
+    }
+}
+
+// Outcome of a task's latest run
+struct LastRun {
+    at: DateTime<Utc>,
+    duration: Duration,
+    result: Result<usize, String>,
+}
+
+#[derive(Default)]
+struct Maintenance {
+    // Daily start time (UTC) and the tasks run then, in order
+    schedule: Option<(NaiveTime, Vec<Task>)>,
+    last_runs: [Option<LastRun>; 3],
+}
+
+static mut MAINTENANCE: Option<Mutex<Maintenance>> = None;
+
+// Bumped on every schedule change, so timers set for an older schedule do nothing
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+// Initialize the maintenance state
+fn init_maintenance() -> &'static Mutex<Maintenance> {
+    unsafe {
+        if MAINTENANCE.is_none() {
+            MAINTENANCE = Some(Mutex::new(Maintenance::default()));
+        }
+        MAINTENANCE.as_ref().unwrap()
+    }
+}
+
+fn gc() -> Result<usize, RedisError> {
+    let nonces = {
+        let mut sessions_map = init_sessions().write().map_err(|_| {
+            RedisError::String("Failed to acquire write lock".to_string())
+        })?;
+        sessions_map.prune_nonces(Utc::now())
+    };
+    history::prune();
+    throttle::prune();
+    Ok(nonces + refresh::prune()?)
+}
+
+fn compact() -> Result<usize, RedisError> {
+    let mut sessions_map = init_sessions().write().map_err(|_| {
+        RedisError::String("Failed to acquire write lock".to_string())
+    })?;
+    sessions_map.compact();
+    Ok(sessions_map.len())
+}
+
+// Map user keys of live sessions that lost their hashmap entry back to their
+// session. Keys mapped to another session are left alone.
+fn reconcile(ctx: &Context) -> Result<usize, RedisError> {
+    let live: Vec<(String, String)> = {
+        let sessions_map = init_sessions().read().map_err(|_| {
+            RedisError::String("Failed to acquire read lock".to_string())
+        })?;
+        sessions_map.values()
+            .filter(|session| !session.is_expired())
+            .map(|session| (session.user_key.clone(), session.id.clone()))
+            .collect()
+    };
+
+    let mut repaired = 0;
+    for batch in live.chunks(RECONCILE_BATCH) {
+        let keys: Vec<&str> = batch.iter().map(|(user_key, _)| user_key.as_str()).collect();
+        for ((user_key, session_id), mapped) in batch.iter().zip(bridge::mget(ctx, &keys)?) {
+            if mapped.is_none() {
+                bridge::set(ctx, user_key, session_id)?;
+                repaired += 1;
+            }
+        }
+    }
+    Ok(repaired)
+}
+
+fn run_tasks(ctx: &Context, tasks: &[Task]) {
+    for task in tasks {
+        let started = Instant::now();
+        let result = task.run(ctx).map_err(|err| err.to_string());
+        if let Err(err) = &result {
+            ctx.log_warning(&format!("Maintenance task {} failed: {}", task.as_str(), err));
+        }
+        let last_run = LastRun { at: Utc::now(), duration: started.elapsed(), result };
+        if let Ok(mut maintenance) = init_maintenance().lock() {
+            let index = TASKS.iter().position(|known| known == task).unwrap();
+            maintenance.last_runs[index] = Some(last_run);
+        }
+    }
+}
+
+// Time until `at` (UTC) is next reached
+fn until_next(at: NaiveTime, now: DateTime<Utc>) -> Duration {
+    let today = now.date_naive().and_time(at).and_utc();
+    let next = if today > now { today } else { today + chrono::Duration::days(1) };
+    (next - now).to_std().unwrap_or_default()
+}
+
+fn arm(ctx: &Context, at: NaiveTime, generation: u64) {
+    ctx.create_timer(until_next(at, Utc::now()), window, generation);
+}
+
+// Timer callback running the scheduled tasks, then waiting for the next day's window
+fn window(ctx: &Context, generation: u64) {
+    if generation != GENERATION.load(Ordering::Relaxed) {
+        return;
+    }
+    let (at, tasks) = match init_maintenance().lock() {
+        Ok(maintenance) => match &maintenance.schedule {
+            Some((at, tasks)) => (*at, tasks.clone()),
+            None => return,
+        },
+        Err(_) => return,
+    };
+    run_tasks(ctx, &tasks);
+    arm(ctx, at, generation);
+}
+
+// "HH:MM" in UTC
+fn parse_time(time: &str) -> Result<NaiveTime, RedisError> {
+    NaiveTime::parse_from_str(time, "%H:%M")
+        .map_err(|_| RedisError::String(format!("Invalid time: {}, expected HH:MM", time)))
+}
+
+// Comma-separated task names, each at most once
+fn parse_tasks(list: &str) -> Result<Vec<Task>, RedisError> {
+    let mut tasks = Vec::new();
+    for name in list.split(',') {
+        let task = Task::parse(name.trim())?;
+        if !tasks.contains(&task) {
+            tasks.push(task);
+        }
+    }
+    Ok(tasks)
+}
+
+fn parse_tasks_option(args: &mut impl Iterator<Item = RedisString>) -> Result<Option<Vec<Task>>, RedisError> {
+    match args.next_string() {
+        Ok(option) if option.eq_ignore_ascii_case("TASKS") => Ok(Some(parse_tasks(&args.next_string()?)?)),
+        Ok(option) => Err(RedisError::String(format!("Unknown option: {}", option))),
+        Err(_) => Ok(None),
+    }
+}
+
+// The schedule and each task's latest run, as flat name/value pairs for
+// SESSION.INFO and SESSION.MAINTENANCE STATUS
+pub fn info() -> Vec<RedisValue> {
+    let Ok(maintenance) = init_maintenance().lock() else { return Vec::new() };
+    let schedule = match &maintenance.schedule {
+        Some((at, tasks)) => {
+            let names: Vec<&str> = tasks.iter().map(|task| task.as_str()).collect();
+            format!("{} {}", at.format("%H:%M"), names.join(","))
+        },
+        None => "off".to_string(),
+    };
+
+    let mut info = vec![
+        RedisValue::SimpleStringStatic("maintenance_schedule"),
+        RedisValue::BulkString(schedule),
+    ];
+    for (task, last_run) in TASKS.iter().zip(&maintenance.last_runs) {
+        let (at, duration_ms, status, items) = match last_run {
+            Some(run) => (
+                run.at.to_rfc3339(),
+                run.duration.as_millis() as i64,
+                run.result.as_ref().map_or_else(|err| format!("error: {}", err), |_| "ok".to_string()),
+                *run.result.as_ref().unwrap_or(&0) as i64,
+            ),
+            None => (String::new(), 0, "never".to_string(), 0),
+        };
+        info.extend([
+            RedisValue::SimpleString(format!("maintenance_{}_last_run", task.as_str())),
+            RedisValue::BulkString(at),
+            RedisValue::SimpleString(format!("maintenance_{}_duration_ms", task.as_str())),
+            RedisValue::Integer(duration_ms),
+            RedisValue::SimpleString(format!("maintenance_{}_status", task.as_str())),
+            RedisValue::BulkString(status),
+            RedisValue::SimpleString(format!("maintenance_{}_items", task.as_str())),
+            RedisValue::Integer(items),
+        ]);
+    }
+    info
+}
+
+// Run maintenance jobs in a daily low-traffic window:
+// SESSION.MAINTENANCE SCHEDULE HH:MM TASKS task[,task...]
+// SESSION.MAINTENANCE OFF
+// SESSION.MAINTENANCE RUN [TASKS task[,task...]]
+// SESSION.MAINTENANCE STATUS
+// Tasks are gc, compact and reconcile; times are UTC.
+#[tracing::instrument(name = "session.maintenance", skip_all)]
+pub fn session_maintenance(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let subcommand = args.next_string()?.to_uppercase();
+
+    match subcommand.as_str() {
+        "SCHEDULE" => {
+            let at = parse_time(&args.next_string()?)?;
+            let tasks = parse_tasks_option(&mut args)?.ok_or(RedisError::Str("TASKS is required"))?;
+            args.done()?;
+
+            let mut maintenance = init_maintenance().lock().map_err(|_| {
+                RedisError::String("Failed to acquire maintenance lock".to_string())
+            })?;
+            maintenance.schedule = Some((at, tasks));
+            let generation = GENERATION.fetch_add(1, Ordering::Relaxed) + 1;
+            arm(ctx, at, generation);
+            Ok(RedisValue::SimpleStringStatic("OK"))
+        },
+        "OFF" => {
+            args.done()?;
+            let mut maintenance = init_maintenance().lock().map_err(|_| {
+                RedisError::String("Failed to acquire maintenance lock".to_string())
+            })?;
+            maintenance.schedule = None;
+            GENERATION.fetch_add(1, Ordering::Relaxed);
+            Ok(RedisValue::SimpleStringStatic("OK"))
+        },
+        "RUN" => {
+            let tasks = parse_tasks_option(&mut args)?.unwrap_or_else(|| TASKS.to_vec());
+            args.done()?;
+            run_tasks(ctx, &tasks);
+            Ok(RedisValue::Array(info()))
+        },
+        "STATUS" => {
+            args.done()?;
+            Ok(RedisValue::Array(info()))
+        },
+        _ => Err(RedisError::String(format!("Unknown SESSION.MAINTENANCE subcommand: {}", subcommand))),
+    }
+}
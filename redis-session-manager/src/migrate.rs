@@ -0,0 +1,53 @@
+use redis_module::{Context, NextArg, RedisError, RedisResult, RedisString, RedisValue};
+use session_core::format;
+
+use crate::{archive, init_sessions, SessionExt};
+
+// Rewrite stored sessions in another format: SESSION.MIGRATE_FORMAT [version]
+// New sessions are written in `version` (by default the configured one)
+// from now on; every session in memory and every archive in another format
+// is rewritten in it. Rewritten sessions count as changed, so exports and
+// the standby pick them up. Replies with the version, how many sessions and
+// archives were rewritten, and how many archives couldn't be read.
+#[tracing::instrument(name = "session.migrate_format", skip_all)]
+pub fn session_migrate_format(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let version = match args.next_u64() {
+        Ok(version) => version as u32,
+        Err(_) => format::written(),
+    };
+    args.done()?;
+    format::set_written(version).map_err(RedisError::String)?;
+
+    let sessions = {
+        let mut sessions_map = init_sessions().write().map_err(|_| {
+            RedisError::String("Failed to acquire write lock".to_string())
+        })?;
+        let stale: Vec<String> = sessions_map.values()
+            .filter(|session| session.format_version != version)
+            .map(|session| session.id.clone())
+            .collect();
+        for session_id in &stale {
+            if let Some(session) = sessions_map.get_mut(session_id) {
+                session.format_version = version;
+                session.mark_changed();
+            }
+        }
+        stale.len()
+    };
+    let (archives, unreadable) = archive::migrate_archives(ctx, version)?;
+    ctx.log_notice(&format!(
+        "Migrated {} sessions and {} archives to session format {}", sessions, archives, version,
+    ));
+
+    Ok(RedisValue::Array(vec![
+        RedisValue::SimpleStringStatic("format_version"),
+        RedisValue::Integer(version as i64),
+        RedisValue::SimpleStringStatic("sessions"),
+        RedisValue::Integer(sessions as i64),
+        RedisValue::SimpleStringStatic("archives"),
+        RedisValue::Integer(archives as i64),
+        RedisValue::SimpleStringStatic("unreadable_archives"),
+        RedisValue::Integer(unreadable as i64),
+    ]))
+}
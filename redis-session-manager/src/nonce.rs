@@ -0,0 +1,66 @@
+use redis_module::{Context, NextArg, RedisError, RedisResult, RedisString, RedisValue};
+use session_core::ttl;
+
+use crate::{init_sessions, writable_session};
+
+// How long a nonce is remembered when no EX is given
+const DEFAULT_NONCE_TTL_SECS: u64 = 300;
+
+// Longest EX, a year: replay windows are minutes, and larger values would
+// overflow the expiry time
+pub const MAX_NONCE_TTL_SECS: u64 = 365 * 24 * 60 * 60;
+
+// Live nonces a single session may hold, so a client can't grow it unbounded
+pub const MAX_NONCES_PER_SESSION: usize = 10_000;
+
+// Track request nonces per session for replay protection:
+// SESSION.NONCE CHECK session_id nonce [EX seconds]
+// Replies 1 and records the nonce the first time it is seen, 0 while it is
+// still remembered.
+#[tracing::instrument(name = "session.nonce", skip_all)]
+pub fn session_nonce(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let subcommand = args.next_string()?.to_uppercase();
+
+    match subcommand.as_str() {
+        "CHECK" => {
+            let session_id = args.next_string()?;
+            let nonce = args.next_string()?;
+
+            let mut ttl_secs = DEFAULT_NONCE_TTL_SECS;
+            if let Ok(option) = args.next_string() {
+                if !option.eq_ignore_ascii_case("EX") {
+                    return Err(RedisError::String(format!("Unknown option: {}", option)));
+                }
+                ttl_secs = args.next_u64()?;
+                if ttl_secs == 0 || ttl_secs > MAX_NONCE_TTL_SECS {
+                    return Err(RedisError::String(format!("EX must be between 1 and {} seconds", MAX_NONCE_TTL_SECS)));
+                }
+            }
+            args.done()?;
+
+            let sessions = init_sessions();
+            let mut sessions_map = sessions.write().map_err(|_| {
+                RedisError::String("Failed to acquire write lock".to_string())
+            })?;
+
+            let session = writable_session(&mut sessions_map, &session_id)?;
+            session.touch();
+            let now = session.last_accessed;
+
+            // Forget nonces whose window has passed
+            session.nonces.retain(|_, expires_at| *expires_at > now);
+
+            if session.nonces.contains_key(&nonce) {
+                return Ok(RedisValue::Integer(0));
+            }
+            if session.nonces.len() >= MAX_NONCES_PER_SESSION {
+                return Err(RedisError::String(format!("Too many live nonces for session: {}", session_id)));
+            }
+
+            session.nonces.insert(nonce, ttl::after(now, ttl_secs));
+            Ok(RedisValue::Integer(1))
+        },
+        _ => Err(RedisError::String(format!("Unknown SESSION.NONCE subcommand: {}", subcommand))),
+    }
+}
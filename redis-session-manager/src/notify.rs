@@ -0,0 +1,185 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use redis_module::{Context, NextArg, NotifyEvent, RedisError, RedisResult, RedisString, RedisValue};
+use session_core::events::{self, SessionEvent};
+
+use crate::timers::NOTIFY;
+
+// Events waiting to be published; events beyond this are dropped
+const MAX_PENDING: usize = 10_000;
+
+// Where lifecycle events are published, and which
+struct NotifyConfig {
+    // PUBLISH the event JSON to this channel; None for no channel
+    channel: Option<String>,
+    // Fire a keyspace event `session.<event>` on the session's user key
+    keyspace: bool,
+    // Events to publish; empty means all of them
+    events: Vec<String>,
+}
+
+static PUBLISHED: AtomicU64 = AtomicU64::new(0);
+static DROPPED: AtomicU64 = AtomicU64::new(0);
+
+static mut NOTIFY_CONFIG: Option<Mutex<NotifyConfig>> = None;
+static mut PENDING: Option<Mutex<VecDeque<SessionEvent>>> = None;
+
+// Initialize the notification settings
+fn init_config() -> &'static Mutex<NotifyConfig> {
+    unsafe {
+        if NOTIFY_CONFIG.is_none() {
+            NOTIFY_CONFIG = Some(Mutex::new(NotifyConfig {
+                channel: None,
+                keyspace: true,
+                events: Vec::new(),
+            }));
+        }
+        NOTIFY_CONFIG.as_ref().unwrap()
+    }
+}
+
+// Initialize the events waiting to be published
+fn init_pending() -> &'static Mutex<VecDeque<SessionEvent>> {
+    unsafe {
+        if PENDING.is_none() {
+            PENDING = Some(Mutex::new(VecDeque::new()));
+        }
+        PENDING.as_ref().unwrap()
+    }
+}
+
+// Publish lifecycle events to a channel from module load
+pub fn set_channel(channel: String) {
+    if let Ok(mut config) = init_config().lock() {
+        config.channel = Some(channel);
+    }
+}
+
+// Whether events are published to a channel
+pub fn channel_enabled() -> bool {
+    init_config().lock().is_ok_and(|config| config.channel.is_some())
+}
+
+// Queue an event for publishing. Called with the sessions lock held and
+// often without a context, so the notify timer publishes it.
+pub fn queue(event: &'static str, session_id: &str, user_key: &str) {
+    let wanted = init_config().lock().is_ok_and(|config| {
+        (config.channel.is_some() || config.keyspace)
+            && (config.events.is_empty() || config.events.iter().any(|subscribed| subscribed == event))
+    });
+    if !wanted {
+        return;
+    }
+
+    let Ok(mut pending) = init_pending().lock() else { return };
+    if pending.len() >= MAX_PENDING {
+        DROPPED.fetch_add(1, Ordering::Relaxed);
+        return;
+    }
+    pending.push_back(SessionEvent::new(event, session_id, user_key));
+}
+
+// Publish the queued events, in the order they happened
+fn publish(ctx: &Context, _data: ()) {
+    let batch: Vec<SessionEvent> = match init_pending().lock() {
+        Ok(mut pending) => pending.drain(..).collect(),
+        Err(_) => Vec::new(),
+    };
+    let (channel, keyspace) = match init_config().lock() {
+        Ok(config) => (config.channel.clone(), config.keyspace),
+        Err(_) => (None, false),
+    };
+
+    for event in &batch {
+        if keyspace {
+            let key = ctx.create_string(event.user_key.as_str());
+            ctx.notify_keyspace_event(NotifyEvent::GENERIC, &format!("session.{}", event.event), &key);
+        }
+        if let Some(channel) = &channel {
+            let body = match serde_json::to_string(event) {
+                Ok(body) => body,
+                Err(_) => continue,
+            };
+            if let Err(err) = ctx.call("PUBLISH", &[channel.as_str(), &body]) {
+                ctx.log_warning(&format!("Failed to publish {} for session {}: {}", event.event, event.session_id, err));
+                continue;
+            }
+        }
+        PUBLISHED.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // Come back sooner while sessions come and go, back off while none do
+    ctx.create_timer(NOTIFY.next(batch.len(), 0), publish, ());
+}
+
+// Start publishing lifecycle events at module load
+pub fn start(ctx: &Context) {
+    ctx.create_timer(NOTIFY.current(), publish, ());
+}
+
+// Configure lifecycle event notifications:
+// SESSION.EVENTS CHANNEL channel|OFF
+// SESSION.EVENTS KEYSPACE ON|OFF
+// SESSION.EVENTS FILTER [event [event ...]]
+// SESSION.EVENTS GET
+// FILTER without events publishes all of them again.
+#[tracing::instrument(name = "session.events", skip_all)]
+pub fn session_events(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let subcommand = args.next_string()?.to_uppercase();
+
+    let mut config = init_config().lock().map_err(|_| {
+        RedisError::String("Failed to acquire notification config lock".to_string())
+    })?;
+
+    match subcommand.as_str() {
+        "CHANNEL" => {
+            let channel = args.next_string()?;
+            args.done()?;
+            config.channel = if channel.eq_ignore_ascii_case("OFF") { None } else { Some(channel) };
+            Ok(RedisValue::SimpleStringStatic("OK"))
+        },
+        "KEYSPACE" => {
+            let option = args.next_string()?;
+            args.done()?;
+            config.keyspace = match option.to_uppercase().as_str() {
+                "ON" => true,
+                "OFF" => false,
+                _ => return Err(RedisError::String(format!("Unknown option: {}", option))),
+            };
+            Ok(RedisValue::SimpleStringStatic("OK"))
+        },
+        "FILTER" => {
+            let mut filter = Vec::new();
+            for event in args {
+                let event = event.to_string_lossy().to_lowercase();
+                if !events::ALL.contains(&event.as_str()) {
+                    return Err(RedisError::String(format!("Unknown event: {}, expected one of {}", event, events::ALL.join(", "))));
+                }
+                filter.push(event);
+            }
+            config.events = filter;
+            Ok(RedisValue::SimpleStringStatic("OK"))
+        },
+        "GET" => {
+            args.done()?;
+            let pending = init_pending().lock().map_or(0, |pending| pending.len());
+            Ok(RedisValue::Array(vec![
+                RedisValue::SimpleStringStatic("channel"),
+                config.channel.clone().map_or(RedisValue::Null, RedisValue::BulkString),
+                RedisValue::SimpleStringStatic("keyspace"),
+                RedisValue::Integer(config.keyspace as i64),
+                RedisValue::SimpleStringStatic("events"),
+                RedisValue::Array(config.events.iter().map(|event| RedisValue::BulkString(event.clone())).collect()),
+                RedisValue::SimpleStringStatic("pending"),
+                RedisValue::Integer(pending as i64),
+                RedisValue::SimpleStringStatic("published"),
+                RedisValue::Integer(PUBLISHED.load(Ordering::Relaxed) as i64),
+                RedisValue::SimpleStringStatic("dropped"),
+                RedisValue::Integer(DROPPED.load(Ordering::Relaxed) as i64),
+            ]))
+        },
+        _ => Err(RedisError::String(format!("Unknown SESSION.EVENTS subcommand: {}", subcommand))),
+    }
+}
@@ -0,0 +1,103 @@
+use std::sync::RwLock;
+use redis_module::{Context, NextArg, RedisError, RedisResult, RedisString, RedisValue};
+
+// Commands run after every session creation, as argument lists with {id}
+// and {key} placeholders
+static mut ON_CREATE: Option<RwLock<Vec<Vec<String>>>> = None;
+
+// Initialize the creation command list
+fn init_on_create() -> &'static RwLock<Vec<Vec<String>>> {
+    unsafe {
+        if ON_CREATE.is_none() {
+            ON_CREATE = Some(RwLock::new(Vec::new()));
+        }
+        ON_CREATE.as_ref().unwrap()
+    }
+}
+
+fn expand(arg: &str, session_id: &str, user_key: &str) -> String {
+    arg.replace("{id}", session_id).replace("{key}", user_key)
+}
+
+// Run the configured commands for a new session, in order. They run inside
+// SESSION.CREATE, so no other client's command runs in between; the first
+// failure is logged and the rest are skipped, leaving the session in place.
+pub fn run(ctx: &Context, session_id: &str, user_key: &str) {
+    let commands = match init_on_create().read() {
+        Ok(commands) => commands.clone(),
+        Err(_) => return,
+    };
+    for command in commands {
+        let args: Vec<String> = command[1..].iter().map(|arg| expand(arg, session_id, user_key)).collect();
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        if let Err(err) = ctx.call(&command[0], &args[..]) {
+            ctx.log_warning(&format!("SESSION.ON_CREATE command {} failed for session {}: {}", command[0], session_id, err));
+            return;
+        }
+    }
+}
+
+// Manage commands run when a session is created:
+// SESSION.ON_CREATE ADD command [arg ...]
+// SESSION.ON_CREATE DEL index
+// SESSION.ON_CREATE LIST
+// SESSION.ON_CREATE CLEAR
+// {id} and {key} in arguments are replaced with the session id and user key.
+#[tracing::instrument(name = "session.on_create", skip_all)]
+pub fn session_on_create(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let subcommand = args.next_string()?.to_uppercase();
+
+    match subcommand.as_str() {
+        "ADD" => {
+            let command: Vec<String> = args.map(|arg| arg.to_string_lossy()).collect();
+            let Some(name) = command.first() else {
+                return Err(RedisError::WrongArity);
+            };
+            // A session command here could create sessions from inside SESSION.CREATE
+            if name.to_lowercase().starts_with("session.") {
+                return Err(RedisError::String(format!("Session commands can't run on creation: {}", name)));
+            }
+
+            let mut commands = init_on_create().write().map_err(|_| {
+                RedisError::String("Failed to acquire write lock".to_string())
+            })?;
+            commands.push(command);
+            Ok(RedisValue::Integer(commands.len() as i64 - 1))
+        },
+        "DEL" => {
+            let index = args.next_u64()? as usize;
+            args.done()?;
+
+            let mut commands = init_on_create().write().map_err(|_| {
+                RedisError::String("Failed to acquire write lock".to_string())
+            })?;
+            if index >= commands.len() {
+                return Ok(RedisValue::Integer(0));
+            }
+            commands.remove(index);
+            Ok(RedisValue::Integer(1))
+        },
+        "LIST" => {
+            args.done()?;
+
+            let commands = init_on_create().read().map_err(|_| {
+                RedisError::String("Failed to acquire read lock".to_string())
+            })?;
+            Ok(RedisValue::Array(commands.iter()
+                .map(|command| RedisValue::Array(command.iter().map(|arg| RedisValue::BulkString(arg.clone())).collect()))
+                .collect()))
+        },
+        "CLEAR" => {
+            args.done()?;
+
+            let mut commands = init_on_create().write().map_err(|_| {
+                RedisError::String("Failed to acquire write lock".to_string())
+            })?;
+            let cleared = commands.len();
+            commands.clear();
+            Ok(RedisValue::Integer(cleared as i64))
+        },
+        _ => Err(RedisError::String(format!("Unknown SESSION.ON_CREATE subcommand: {}", subcommand))),
+    }
+}
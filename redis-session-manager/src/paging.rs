@@ -0,0 +1,152 @@
+use std::collections::HashMap;
+use redis_module::{Context, NextArg, RedisError, RedisResult, RedisString, RedisValue};
+use serde_json::{Map, Value};
+use session_core::value;
+
+use crate::sensitive::{Redactor, REDACTED};
+use crate::{activity, init_sessions, spill, Session};
+
+// Page size used by CURSOR mode when COUNT is not given
+const DEFAULT_CURSOR_COUNT: usize = 100;
+
+// A window over a session's data fields, in field-name order
+pub struct DataPage {
+    offset: usize,
+    count: usize,
+    // CURSOR mode replies carry the cursor for the next page
+    cursor: bool,
+}
+
+impl DataPage {
+    // Parse `LIMIT offset count` or `CURSOR cursor [COUNT n]` from the remaining arguments
+    pub fn parse<I: Iterator<Item = RedisString>>(args: &mut I) -> Result<Option<DataPage>, RedisError> {
+        let option = match args.next() {
+            Some(option) => option.to_string_lossy().to_uppercase(),
+            None => return Ok(None),
+        };
+
+        let page = match option.as_str() {
+            "LIMIT" => {
+                let offset = args.next_u64()? as usize;
+                let count = args.next_u64()? as usize;
+                DataPage { offset, count, cursor: false }
+            },
+            "CURSOR" => {
+                let offset = args.next_u64()? as usize;
+                let mut count = DEFAULT_CURSOR_COUNT;
+                if let Some(option) = args.next() {
+                    if !option.to_string_lossy().eq_ignore_ascii_case("COUNT") {
+                        return Err(RedisError::String(format!("Unknown option: {}", option)));
+                    }
+                    count = args.next_u64()? as usize;
+                    if count == 0 {
+                        return Err(RedisError::Str("COUNT must be positive"));
+                    }
+                }
+                DataPage { offset, count, cursor: true }
+            },
+            _ => return Err(RedisError::String(format!("Unknown option: {}", option))),
+        };
+
+        args.done()?;
+        Ok(Some(page))
+    }
+
+    // Select this page's fields; the cursor is 0 once the last field has been returned
+    fn select<'a>(&self, data: &'a HashMap<String, String>) -> (Vec<(&'a String, &'a String)>, usize) {
+        let mut fields: Vec<(&String, &String)> = data.iter().collect();
+        fields.sort_unstable_by(|a, b| a.0.cmp(b.0));
+
+        let end = self.offset.saturating_add(self.count).min(fields.len());
+        let next_cursor = if end < fields.len() { end } else { 0 };
+        let page = fields.into_iter().skip(self.offset).take(end.saturating_sub(self.offset)).collect();
+
+        (page, next_cursor)
+    }
+}
+
+// Serialize a session with only the requested page of data, sensitive values
+// redacted. Everything else is serialized as SESSION.GET has it, so the two
+// replies can't drift apart; `data_total` and, in CURSOR mode, `next_cursor`
+// are added.
+pub fn session_page_json(session: &Session, page: &DataPage, redactor: &Redactor) -> Result<String, RedisError> {
+    let (fields, next_cursor) = page.select(&session.data);
+    let window: Map<String, Value> = fields.into_iter()
+        .map(|(field, value)| (field.clone(), Value::String(value.clone())))
+        .collect();
+
+    let mut json = serde_json::to_value(session).map_err(|e| {
+        RedisError::String(format!("Failed to serialize session: {}", e))
+    })?;
+    json["data"] = Value::Object(window);
+    json["data_total"] = Value::from(session.data.len());
+    if page.cursor {
+        json["next_cursor"] = Value::from(next_cursor);
+    }
+    // Only present while the session is in its post-expiry grace window
+    if session.is_expired() {
+        json["expired"] = Value::Bool(true);
+    }
+    redactor.redact_session(&mut json);
+    value::type_session_json(&mut json);
+    Ok(json.to_string())
+}
+
+// Get a session's data as a flat field/value array:
+// SESSION.GET_ALL_DATA session_id [REVEAL] [LIMIT offset count | CURSOR cursor [COUNT n]]
+// Sensitive fields are redacted unless revealed, as in SESSION.GET.
+#[tracing::instrument(name = "session.get_all_data", skip_all)]
+pub fn get_all_session_data(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1).peekable();
+    let session_id = args.next_string()?;
+    let mut reveal = false;
+    if args.peek().is_some_and(|option| option.to_string_lossy().eq_ignore_ascii_case("REVEAL")) {
+        args.next();
+        reveal = true;
+    }
+    let page = DataPage::parse(&mut args)?;
+    let redactor = Redactor::for_caller(ctx, reveal)?;
+
+    let sessions = init_sessions();
+    let mut sessions_map = sessions.write().map_err(|_| {
+        RedisError::String("Failed to acquire write lock".to_string())
+    })?;
+
+    let session = sessions_map.get_mut(&session_id)
+        .ok_or_else(|| RedisError::String(format!("Session not found: {}", session_id)))?;
+    session.touch();
+    activity::record(&session_id);
+
+    let session = &*session;
+    let flatten = |fields: Vec<(&String, &String)>| -> Vec<RedisValue> {
+        fields.into_iter()
+            .flat_map(|(field, _)| [
+                RedisValue::BulkString(field.clone()),
+                if redactor.is_sensitive(field) {
+                    RedisValue::BulkString(REDACTED.to_string())
+                } else {
+                    spill::value(ctx, session, field).map_or(RedisValue::Null, RedisValue::BulkString)
+                },
+            ])
+            .collect()
+    };
+
+    match page {
+        Some(page) => {
+            let (fields, next_cursor) = page.select(&session.data);
+            if page.cursor {
+                Ok(RedisValue::Array(vec![
+                    RedisValue::BulkString(next_cursor.to_string()),
+                    RedisValue::Array(flatten(fields)),
+                ]))
+            } else {
+                Ok(RedisValue::Array(flatten(fields)))
+            }
+        },
+        None => {
+            let mut fields: Vec<(&String, &String)> = session.data.iter().collect();
+            fields.sort_unstable_by(|a, b| a.0.cmp(b.0));
+            Ok(RedisValue::Array(flatten(fields)))
+        },
+    }
+}
@@ -0,0 +1,77 @@
+use std::time::{Duration, Instant};
+use redis_module::{BlockedClient, Context, NextArg, RedisError, RedisResult, RedisString, RedisValue, ThreadSafeContext};
+use session_core::errors;
+
+// How long SESSION.FLUSH_PERSISTENCE waits unless TIMEOUT is given
+const DEFAULT_TIMEOUT_MS: u64 = 5000;
+
+// How often a waiting client checks whether the mirror has caught up
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+// A client waiting for the mirror to apply every write queued before it asked
+struct Waiter {
+    blocked_client: BlockedClient,
+    target: i64,
+    deadline: Option<Instant>,
+}
+
+// (rules, queued, flushed) from CUSTOM.MIRROR PROGRESS
+fn progress(ctx: &Context) -> Result<(i64, i64, i64), RedisError> {
+    let reply = match ctx.call("custom.mirror", &["PROGRESS"])? {
+        RedisValue::Array(reply) => reply,
+        other => return Err(RedisError::String(format!("Unexpected reply from CUSTOM.MIRROR PROGRESS: {:?}", other))),
+    };
+    let mut counts = reply.into_iter().filter_map(|value| match value {
+        RedisValue::Integer(count) => Some(count),
+        _ => None,
+    });
+    match (counts.next(), counts.next(), counts.next()) {
+        (Some(rules), Some(queued), Some(flushed)) => Ok((rules, queued, flushed)),
+        _ => Err(RedisError::Str("Unexpected reply from CUSTOM.MIRROR PROGRESS")),
+    }
+}
+
+// Timer callback answering the waiter once the mirror has caught up or time ran out
+fn poll(ctx: &Context, waiter: Waiter) {
+    let result = match progress(ctx) {
+        Ok((_, _, flushed)) if flushed >= waiter.target => Ok(RedisValue::SimpleStringStatic("OK")),
+        Ok(_) if waiter.deadline.is_some_and(|deadline| Instant::now() >= deadline) => {
+            Err(RedisError::String(format!("{} Pending writes were not flushed in time", errors::TIMEOUT)))
+        },
+        Ok(_) => {
+            ctx.create_timer(POLL_INTERVAL, poll, waiter);
+            return;
+        },
+        Err(err) => Err(err),
+    };
+    ThreadSafeContext::with_blocked_client(waiter.blocked_client).reply(result);
+}
+
+// Wait until writes mirrored to the keyspace have caught up:
+// SESSION.FLUSH_PERSISTENCE [TIMEOUT ms]
+// Blocks until every write queued by CUSTOM.MIRROR rules before the call has
+// been applied to the keyspace, then replies OK; TIMEOUT 0 waits indefinitely.
+// Whether applied writes are on disk is up to the server's AOF settings.
+#[tracing::instrument(name = "session.flush_persistence", skip_all)]
+pub fn session_flush_persistence(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let timeout_ms = match args.next_string() {
+        Ok(option) if option.eq_ignore_ascii_case("TIMEOUT") => args.next_u64()?,
+        Ok(option) => return Err(RedisError::String(format!("Unknown option: {}", option))),
+        Err(_) => DEFAULT_TIMEOUT_MS,
+    };
+    args.done()?;
+
+    let (rules, queued, flushed) = progress(ctx)?;
+    if rules == 0 {
+        return Err(RedisError::Str("Write-behind persistence is off: no CUSTOM.MIRROR rules"));
+    }
+    if flushed >= queued {
+        return Ok(RedisValue::SimpleStringStatic("OK"));
+    }
+
+    let deadline = (timeout_ms > 0).then(|| Instant::now() + Duration::from_millis(timeout_ms));
+    let waiter = Waiter { blocked_client: ctx.block_client(), target: queued, deadline };
+    ctx.create_timer(POLL_INTERVAL, poll, waiter);
+    Ok(RedisValue::NoReply)
+}
@@ -0,0 +1,70 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use redis_module::{Context, Status};
+
+use crate::{bridge, init_sessions, Session, SessionExt};
+
+// Import sessions from a newline-delimited JSON file at module load, one
+// session per line in the SESSION.GET / SESSION.EXPORT format. Lines may
+// also carry a `secrets` object of Argon2 hashes. Expired sessions and
+// malformed lines are skipped; a later line for the same session id wins.
+pub fn load(ctx: &Context, path: &str) -> Status {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) => {
+            ctx.log_warning(&format!("Failed to open preload file {}: {}", path, e));
+            return Status::Err;
+        },
+    };
+
+    let sessions = init_sessions();
+    let mut sessions_map = match sessions.write() {
+        Ok(sessions_map) => sessions_map,
+        Err(_) => {
+            ctx.log_warning("Failed to acquire write lock for preload");
+            return Status::Err;
+        },
+    };
+
+    let (mut loaded, mut skipped) = (0, 0);
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let line = match line {
+            Ok(line) => line,
+            Err(e) => {
+                ctx.log_warning(&format!("Failed to read preload file {}: {}", path, e));
+                return Status::Err;
+            },
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let mut session: Session = match serde_json::from_str(&line) {
+            Ok(session) => session,
+            Err(e) => {
+                ctx.log_warning(&format!("Skipping preload line {}: {}", index + 1, e));
+                skipped += 1;
+                continue;
+            },
+        };
+        if session.is_expired() {
+            skipped += 1;
+            continue;
+        }
+
+        // Client ids from the old server mean nothing here
+        session.bound_client = None;
+        session.mark_changed();
+
+        if let Err(err) = bridge::set(ctx, &session.user_key, &session.id) {
+            ctx.log_warning(&format!("Skipping preloaded session {}: {}", session.id, err));
+            skipped += 1;
+            continue;
+        }
+        sessions_map.insert(session.id.clone(), session);
+        loaded += 1;
+    }
+
+    ctx.log_notice(&format!("Preloaded {} sessions from {} ({} skipped)", loaded, path, skipped));
+    Status::Ok
+}
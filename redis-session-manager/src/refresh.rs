@@ -0,0 +1,297 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+use std::thread;
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use chrono::{DateTime, Utc};
+use redis_module::{Context, NextArg, RedisError, RedisResult, RedisString, RedisValue, ThreadSafeContext};
+use session_core::{events, ttl};
+use uuid::Uuid;
+
+use crate::secrets::{hash_secret, verify_secret};
+use crate::{binding, bridge, changes, eviction, init_sessions, retry, webhooks, Session, SessionExt};
+
+// Session lifetime set by an exchange unless ACCESS_TTL is given
+const DEFAULT_ACCESS_TTL_SECS: u64 = 60 * 60;
+
+// Reply for unknown, expired, already exchanged or mistyped tokens alike
+const INVALID_TOKEN: &str = "Invalid or expired refresh token";
+
+// Random bytes in the secret half of a token
+const SECRET_BYTES: usize = 32;
+
+// A refresh token, stored by id with only a hash of its secret
+struct RefreshToken {
+    hash: String,
+    session_id: String,
+    user_key: String,
+    app: Option<String>,
+    // Lifetime of the token, restarted by every rotation
+    ttl_secs: u64,
+    // Lifetime given to the session on every exchange
+    access_ttl_secs: u64,
+    expires_at: DateTime<Utc>,
+}
+
+static mut REFRESH_TOKENS: Option<RwLock<HashMap<String, RefreshToken>>> = None;
+
+// Initialize the refresh token store
+fn init_tokens() -> &'static RwLock<HashMap<String, RefreshToken>> {
+    unsafe {
+        if REFRESH_TOKENS.is_none() {
+            REFRESH_TOKENS = Some(RwLock::new(HashMap::new()));
+        }
+        REFRESH_TOKENS.as_ref().unwrap()
+    }
+}
+
+// Forget tokens that ran out while nobody exchanged them; returns how many
+pub fn prune() -> Result<usize, RedisError> {
+    let mut tokens = init_tokens().write().map_err(|_| {
+        RedisError::String("Failed to acquire write lock".to_string())
+    })?;
+    let (before, now) = (tokens.len(), Utc::now());
+    tokens.retain(|_, token| token.expires_at > now);
+    Ok(before - tokens.len())
+}
+
+// Seconds, or a number with an s, m, h or d suffix such as 30d
+fn parse_duration(duration: &str) -> Result<u64, RedisError> {
+    let (number, unit) = match duration.char_indices().last() {
+        Some((index, unit)) if unit.is_ascii_alphabetic() => (&duration[..index], unit.to_ascii_lowercase()),
+        _ => (duration, 's'),
+    };
+    let multiplier = match unit {
+        's' => 1,
+        'm' => 60,
+        'h' => 60 * 60,
+        'd' => 24 * 60 * 60,
+        _ => return Err(RedisError::String(format!("Invalid duration: {}", duration))),
+    };
+    match number.parse::<u64>().ok().filter(|value| *value > 0).and_then(|value| value.checked_mul(multiplier)) {
+        Some(secs) => ttl::check(secs, "Duration").map_err(RedisError::String),
+        None => Err(RedisError::String(format!("Invalid duration: {}", duration))),
+    }
+}
+
+// A new token as (id, secret); clients see `<id>.<secret>`
+fn mint() -> (String, String) {
+    let mut secret = [0u8; SECRET_BYTES];
+    OsRng.fill_bytes(&mut secret);
+    (Uuid::new_v4().simple().to_string(), URL_SAFE_NO_PAD.encode(secret))
+}
+
+fn split_token(token: &str) -> Result<(&str, &str), RedisError> {
+    token.split_once('.').ok_or(RedisError::Str(INVALID_TOKEN))
+}
+
+// Extend the session a token belongs to, or start a new one for its user key
+// if that session is gone. Runs with the server lock held.
+fn refresh_session(ctx: &Context, token: &RefreshToken) -> Result<String, RedisError> {
+    {
+        let mut sessions_map = init_sessions().write().map_err(|_| {
+            RedisError::String("Failed to acquire write lock".to_string())
+        })?;
+        match sessions_map.get_mut(&token.session_id) {
+            Some(session) if !session.is_expired() => {
+                session.set_ttl(token.access_ttl_secs);
+                session.last_accessed = Utc::now();
+                session.mark_changed();
+                return Ok(session.id.clone());
+            },
+            // An expired session still in its grace window is replaced
+            Some(_) => {
+                if let Some(session) = sessions_map.remove(&token.session_id) {
+                    binding::forget(&session);
+                    changes::record_deletion(&token.session_id);
+                }
+            },
+            None => {},
+        }
+    }
+
+    // The user may have started another session in the meantime; adopt it
+    if let Some(current) = bridge::get(ctx, &token.user_key)? {
+        let mut sessions_map = init_sessions().write().map_err(|_| {
+            RedisError::String("Failed to acquire write lock".to_string())
+        })?;
+        if let Some(session) = sessions_map.get_mut(&current) {
+            if !session.is_expired() && session.app == token.app {
+                session.set_ttl(token.access_ttl_secs);
+                session.last_accessed = Utc::now();
+                session.mark_changed();
+                return Ok(current);
+            }
+        }
+    }
+
+    retry::check_backpressure()?;
+    eviction::make_room(ctx)?;
+    let session_id = Uuid::new_v4().to_string();
+    bridge::set(ctx, &token.user_key, &session_id)?;
+
+    let mut session = Session::new(session_id.clone(), token.user_key.clone(), changes::next_seq());
+    session.app = token.app.clone();
+    session.set_ttl(token.access_ttl_secs);
+
+    let mut sessions_map = init_sessions().write().map_err(|_| {
+        RedisError::String("Failed to acquire write lock".to_string())
+    })?;
+    webhooks::emit(events::CREATED, &session_id, &session.user_key);
+    sessions_map.insert(session_id.clone(), session);
+    Ok(session_id)
+}
+
+// Swap a verified token for a new one and refresh its session. Fails if the
+// token was exchanged or revoked while its secret was being verified.
+fn rotate(ctx: &Context, token_id: &str, verified_hash: &str, new_id: String, new_hash: String) -> Result<(String, String), RedisError> {
+    let mut token = {
+        let mut tokens = init_tokens().write().map_err(|_| {
+            RedisError::String("Failed to acquire write lock".to_string())
+        })?;
+        match tokens.get(token_id) {
+            Some(token) if token.hash == verified_hash && token.expires_at > Utc::now() => {},
+            _ => return Err(RedisError::Str(INVALID_TOKEN)),
+        }
+        tokens.remove(token_id).unwrap()
+    };
+
+    token.session_id = match refresh_session(ctx, &token) {
+        Ok(session_id) => session_id,
+        Err(err) => {
+            // Keep the old token usable so the client can try again
+            if let Ok(mut tokens) = init_tokens().write() {
+                tokens.insert(token_id.to_string(), token);
+            }
+            return Err(err);
+        },
+    };
+    token.hash = new_hash;
+    token.expires_at = ttl::after(Utc::now(), token.ttl_secs);
+    let session_id = token.session_id.clone();
+
+    let mut tokens = init_tokens().write().map_err(|_| {
+        RedisError::String("Failed to acquire write lock".to_string())
+    })?;
+    tokens.insert(new_id.clone(), token);
+    Ok((session_id, new_id))
+}
+
+// Pair sessions with long-lived, single-use refresh tokens:
+// SESSION.REFRESH CREATE session_id TTL duration [ACCESS_TTL duration]
+// SESSION.REFRESH EXCHANGE token
+// SESSION.REFRESH REVOKE token
+// Durations are seconds or take an s, m, h or d suffix, e.g. TTL 30d.
+#[tracing::instrument(name = "session.refresh", skip_all)]
+pub fn session_refresh(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let subcommand = args.next_string()?.to_uppercase();
+
+    match subcommand.as_str() {
+        "CREATE" => {
+            let session_id = args.next_string()?;
+            let mut ttl_secs = None;
+            let mut access_ttl_secs = DEFAULT_ACCESS_TTL_SECS;
+            while let Ok(option) = args.next_string() {
+                match option.to_uppercase().as_str() {
+                    "TTL" => ttl_secs = Some(parse_duration(&args.next_string()?)?),
+                    "ACCESS_TTL" => access_ttl_secs = parse_duration(&args.next_string()?)?,
+                    _ => return Err(RedisError::String(format!("Unknown option: {}", option))),
+                }
+            }
+            let ttl_secs = ttl_secs.ok_or(RedisError::Str("TTL is required"))?;
+
+            let (user_key, app) = {
+                let sessions_map = init_sessions().read().map_err(|_| {
+                    RedisError::String("Failed to acquire read lock".to_string())
+                })?;
+                match sessions_map.get(&session_id) {
+                    Some(session) if session.is_expired() => return Err(RedisError::String(format!("Session expired: {}", session_id))),
+                    Some(session) if session.impersonation.is_some() => {
+                        return Err(RedisError::String(format!("Session {} is an impersonation", session_id)));
+                    },
+                    Some(session) => (session.user_key.clone(), session.app.clone()),
+                    None => return Err(RedisError::String(format!("Session not found: {}", session_id))),
+                }
+            };
+
+            // Argon2 is deliberately slow, so hash on a worker thread instead of the event loop
+            let blocked_client = ctx.block_client();
+            thread::spawn(move || {
+                let thread_ctx = ThreadSafeContext::with_blocked_client(blocked_client);
+                let (token_id, secret) = mint();
+                let result = hash_secret(&secret).and_then(|hash| {
+                    let mut tokens = init_tokens().write().map_err(|_| {
+                        RedisError::String("Failed to acquire write lock".to_string())
+                    })?;
+                    // Forget tokens that ran out while nobody exchanged them
+                    let now = Utc::now();
+                    tokens.retain(|_, token| token.expires_at > now);
+                    tokens.insert(token_id.clone(), RefreshToken {
+                        hash,
+                        session_id,
+                        user_key,
+                        app,
+                        ttl_secs,
+                        access_ttl_secs,
+                        expires_at: ttl::after(now, ttl_secs),
+                    });
+                    Ok(RedisValue::BulkString(format!("{}.{}", token_id, secret)))
+                });
+                thread_ctx.reply(result);
+            });
+
+            Ok(RedisValue::NoReply)
+        },
+        "EXCHANGE" => {
+            let token = args.next_string()?;
+            args.done()?;
+
+            let (token_id, secret) = split_token(&token)?;
+            let (token_id, secret) = (token_id.to_string(), secret.to_string());
+            let hash = {
+                let tokens = init_tokens().read().map_err(|_| {
+                    RedisError::String("Failed to acquire read lock".to_string())
+                })?;
+                match tokens.get(&token_id) {
+                    Some(stored) if stored.expires_at > Utc::now() => stored.hash.clone(),
+                    _ => return Err(RedisError::Str(INVALID_TOKEN)),
+                }
+            };
+
+            let blocked_client = ctx.block_client();
+            thread::spawn(move || {
+                let thread_ctx = ThreadSafeContext::with_blocked_client(blocked_client);
+                if !verify_secret(&hash, &secret) {
+                    thread_ctx.reply(Err(RedisError::Str(INVALID_TOKEN)));
+                    return;
+                }
+                let (new_id, new_secret) = mint();
+                let result = hash_secret(&new_secret).and_then(|new_hash| {
+                    // Rotate under the server lock so the session work is atomic with commands
+                    let ctx = thread_ctx.lock();
+                    rotate(&ctx, &token_id, &hash, new_id, new_hash)
+                }).map(|(session_id, new_id)| RedisValue::Array(vec![
+                    RedisValue::BulkString(session_id),
+                    RedisValue::BulkString(format!("{}.{}", new_id, new_secret)),
+                ]));
+                thread_ctx.reply(result);
+            });
+
+            Ok(RedisValue::NoReply)
+        },
+        "REVOKE" => {
+            let token = args.next_string()?;
+            args.done()?;
+
+            // Revoking only needs the token id
+            let (token_id, _) = split_token(&token)?;
+            let mut tokens = init_tokens().write().map_err(|_| {
+                RedisError::String("Failed to acquire write lock".to_string())
+            })?;
+            Ok(RedisValue::Integer(if tokens.remove(token_id).is_some() { 1 } else { 0 }))
+        },
+        _ => Err(RedisError::String(format!("Unknown SESSION.REFRESH subcommand: {}", subcommand))),
+    }
+}
@@ -0,0 +1,470 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::Duration;
+use redis_module::{Context, NextArg, RedisError, RedisResult, RedisString, RedisValue};
+
+use crate::timers::REPLICATE;
+use crate::{binding, bridge, changes, init_sessions, unlink_user_key, Session, SessionExt};
+
+// Changes waiting to be sent; a tick that finds the queue full leaves the
+// rest for the next tick
+const QUEUE_CAPACITY: usize = 10_000;
+
+// Most changes written to the standby before reading their replies
+const PIPELINE: usize = 256;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(1);
+const IO_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Reconnect delay after the first failure, doubled per failure up to the maximum
+const BACKOFF_START: Duration = Duration::from_millis(100);
+const BACKOFF_MAX: Duration = Duration::from_secs(30);
+
+// A change to apply on the standby, as SESSION.REPLICA_APPLY arguments
+enum Change {
+    // Drop every session, ahead of a full snapshot
+    Reset,
+    Upsert(String),
+    Delete(String),
+}
+
+const APPLY: &[u8] = b"SESSION.REPLICA_APPLY";
+
+impl Change {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            Change::Reset => encode(out, &[APPLY, b"RESET"]),
+            Change::Upsert(json) => encode(out, &[APPLY, b"UPSERT", json.as_bytes()]),
+            Change::Delete(id) => encode(out, &[APPLY, b"DEL", id.as_bytes()]),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Stats {
+    connected: AtomicBool,
+    // Set when the standby is replaced or turned off, so its thread exits
+    stopped: AtomicBool,
+    queued: AtomicU64,
+    sent: AtomicU64,
+    // Changes the standby answered with an error
+    failed: AtomicU64,
+    // Full snapshots sent, the first one included
+    snapshots: AtomicU64,
+    reconnects: AtomicU64,
+    last_error: Mutex<Option<String>>,
+}
+
+impl Stats {
+    fn fail(&self, err: String) {
+        self.connected.store(false, Ordering::Relaxed);
+        if let Ok(mut last_error) = self.last_error.lock() {
+            *last_error = Some(err);
+        }
+    }
+}
+
+// The standby session changes are streamed to
+struct Standby {
+    address: String,
+    sender: SyncSender<Change>,
+    // Set once the reset starting the first full snapshot is queued
+    synced: AtomicBool,
+    // Change sequence everything up to which has been queued
+    cursor: AtomicU64,
+    stats: Arc<Stats>,
+}
+
+static mut STANDBY: Option<RwLock<Option<Standby>>> = None;
+
+// Initialize the standby slot
+fn init_standby() -> &'static RwLock<Option<Standby>> {
+    unsafe {
+        if STANDBY.is_none() {
+            STANDBY = Some(RwLock::new(None));
+        }
+        STANDBY.as_ref().unwrap()
+    }
+}
+
+// Whether changes are being streamed to a standby
+pub fn is_active() -> bool {
+    init_standby().read().is_ok_and(|standby| standby.is_some())
+}
+
+// Queue the changes made since the last tick, oldest first. A standby that
+// fell behind the retained change log is reset and sent a full snapshot.
+fn ship(ctx: &Context) -> usize {
+    let standby = match init_standby().read() {
+        Ok(standby) => standby,
+        Err(_) => return 0,
+    };
+    let Some(standby) = standby.as_ref() else { return 0 };
+
+    let synced = standby.synced.load(Ordering::Relaxed);
+    let mut since = standby.cursor.load(Ordering::Relaxed);
+    if synced && !changes::is_retained(since) {
+        ctx.log_warning(&format!("Standby {} fell behind the change log, sending a full snapshot", standby.address));
+    }
+    let full = !synced || !changes::is_retained(since);
+    if full {
+        since = 0;
+    }
+    // Secrets aren't part of a session's JSON, but the standby needs them for step-up checks
+    let changes = match changes::collect(ctx, since, |session, json| {
+        if session.secrets.is_empty() {
+            return;
+        }
+        if let (Some(json), Ok(secrets)) = (json.as_object_mut(), serde_json::to_value(&session.secrets)) {
+            json.insert("secrets".to_string(), secrets);
+        }
+    }) {
+        Ok(changes) => changes,
+        Err(err) => {
+            standby.stats.fail(err.to_string());
+            return 0;
+        },
+    };
+
+    let mut pending: Vec<(u64, Change)> = changes.changed.into_iter()
+        .map(|(seq, json)| (seq, Change::Upsert(json.to_string())))
+        .chain(changes.deleted.into_iter().map(|(seq, id)| (seq, Change::Delete(id))))
+        .collect();
+    pending.sort_by_key(|(seq, _)| *seq);
+    if full {
+        pending.insert(0, (0, Change::Reset));
+    }
+
+    // Advance the cursor only past what was queued, so a full queue resumes where it stopped
+    let mut queued = 0;
+    for (seq, change) in pending {
+        match standby.sender.try_send(change) {
+            Ok(()) => {
+                if full && queued == 0 {
+                    standby.synced.store(true, Ordering::Relaxed);
+                    standby.stats.snapshots.fetch_add(1, Ordering::Relaxed);
+                }
+                queued += 1;
+                standby.cursor.store(seq.max(since), Ordering::Relaxed);
+            },
+            Err(TrySendError::Full(_)) | Err(TrySendError::Disconnected(_)) => {
+                standby.stats.queued.fetch_add(queued as u64, Ordering::Relaxed);
+                return queued;
+            },
+        }
+    }
+    standby.cursor.store(changes.cursor.max(since), Ordering::Relaxed);
+    standby.stats.queued.fetch_add(queued as u64, Ordering::Relaxed);
+    queued
+}
+
+fn tick(ctx: &Context, _data: ()) {
+    let queued = ship(ctx);
+    ctx.create_timer(REPLICATE.next(queued, 0), tick, ());
+}
+
+// Start the replication timer at module load; it idles until SESSION.REPLICATE_TO
+pub fn start(ctx: &Context) {
+    ctx.create_timer(REPLICATE.current(), tick, ());
+}
+
+// A command as a RESP array of bulk strings
+fn encode(out: &mut Vec<u8>, args: &[&[u8]]) {
+    out.extend_from_slice(format!("*{}\r\n", args.len()).as_bytes());
+    for arg in args {
+        out.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+        out.extend_from_slice(arg);
+        out.extend_from_slice(b"\r\n");
+    }
+}
+
+// Reads the one-line replies SESSION.REPLICA_APPLY and AUTH give
+struct Connection {
+    stream: TcpStream,
+    reader: BufReader<TcpStream>,
+}
+
+impl Connection {
+    fn open(address: &str, password: Option<&str>) -> Result<Connection, String> {
+        let socket_addr = address.to_socket_addrs()
+            .map_err(|e| format!("Failed to resolve {}: {}", address, e))?
+            .next()
+            .ok_or_else(|| format!("Failed to resolve {}", address))?;
+        let stream = TcpStream::connect_timeout(&socket_addr, CONNECT_TIMEOUT)
+            .map_err(|e| format!("Failed to connect to {}: {}", address, e))?;
+        stream.set_read_timeout(Some(IO_TIMEOUT)).map_err(|e| e.to_string())?;
+        stream.set_write_timeout(Some(IO_TIMEOUT)).map_err(|e| e.to_string())?;
+        let reader = BufReader::new(stream.try_clone().map_err(|e| e.to_string())?);
+
+        let mut connection = Connection { stream, reader };
+        if let Some(password) = password {
+            let mut auth = Vec::new();
+            encode(&mut auth, &[b"AUTH", password.as_bytes()]);
+            connection.send(&auth)?;
+            if let Some(err) = connection.read_reply()? {
+                return Err(format!("AUTH failed: {}", err));
+            }
+        }
+        Ok(connection)
+    }
+
+    fn send(&mut self, commands: &[u8]) -> Result<(), String> {
+        self.stream.write_all(commands).map_err(|e| format!("Failed to send: {}", e))
+    }
+
+    // The error of an error reply, None for any other reply
+    fn read_reply(&mut self) -> Result<Option<String>, String> {
+        let mut line = String::new();
+        match self.reader.read_line(&mut line) {
+            Ok(0) => Err("Connection closed".to_string()),
+            Ok(_) => Ok(line.strip_prefix('-').map(|err| err.trim_end().to_string())),
+            Err(e) => Err(format!("Failed to read reply: {}", e)),
+        }
+    }
+}
+
+// Send a batch and read one reply per change
+fn send_batch(connection: &mut Connection, batch: &[Change], stats: &Stats) -> Result<(), String> {
+    let mut commands = Vec::new();
+    for change in batch {
+        change.encode(&mut commands);
+    }
+    connection.send(&commands)?;
+
+    for _ in batch {
+        if let Some(err) = connection.read_reply()? {
+            stats.failed.fetch_add(1, Ordering::Relaxed);
+            if let Ok(mut last_error) = stats.last_error.lock() {
+                *last_error = Some(err);
+            }
+        }
+    }
+    stats.sent.fetch_add(batch.len() as u64, Ordering::Relaxed);
+    Ok(())
+}
+
+// Replication thread: send queued changes in order, reconnecting with
+// backoff. A batch that fails mid-way is sent again in full, which is safe
+// because every change replaces or removes a whole session.
+fn run(address: String, password: Option<String>, receiver: Receiver<Change>, stats: Arc<Stats>) {
+    let mut connection = None;
+    let mut backoff = BACKOFF_START;
+
+    while let Ok(first) = receiver.recv() {
+        let mut batch = vec![first];
+        batch.extend(receiver.try_iter().take(PIPELINE - 1));
+
+        loop {
+            if stats.stopped.load(Ordering::Relaxed) {
+                return;
+            }
+            if connection.is_none() {
+                match Connection::open(&address, password.as_deref()) {
+                    Ok(opened) => {
+                        connection = Some(opened);
+                        stats.connected.store(true, Ordering::Relaxed);
+                        backoff = BACKOFF_START;
+                    },
+                    Err(err) => {
+                        stats.fail(err);
+                        thread::sleep(backoff);
+                        backoff = (backoff * 2).min(BACKOFF_MAX);
+                        continue;
+                    },
+                }
+            }
+
+            let Some(open) = connection.as_mut() else { continue };
+            match send_batch(open, &batch, &stats) {
+                Ok(()) => break,
+                Err(err) => {
+                    connection = None;
+                    stats.reconnects.fetch_add(1, Ordering::Relaxed);
+                    stats.fail(err);
+                },
+            }
+        }
+    }
+}
+
+// Stream session changes to a standby Redis running this module:
+// SESSION.REPLICATE_TO host:port [AUTH password]
+// SESSION.REPLICATE_TO OFF
+// SESSION.REPLICATE_TO STATUS
+// The standby is first reset and sent every session, then each change as the
+// replication timer finds it.
+#[tracing::instrument(name = "session.replicate_to", skip_all)]
+pub fn session_replicate_to(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let target = args.next_string()?;
+
+    match target.to_uppercase().as_str() {
+        "OFF" => {
+            args.done()?;
+            let mut standby = init_standby().write().map_err(|_| {
+                RedisError::String("Failed to acquire write lock".to_string())
+            })?;
+            if let Some(standby) = standby.take() {
+                standby.stats.stopped.store(true, Ordering::Relaxed);
+            }
+            Ok(RedisValue::SimpleStringStatic("OK"))
+        },
+        "STATUS" => {
+            args.done()?;
+            let standby = init_standby().read().map_err(|_| {
+                RedisError::String("Failed to acquire read lock".to_string())
+            })?;
+            let Some(standby) = standby.as_ref() else {
+                return Ok(RedisValue::Array(vec![
+                    RedisValue::SimpleStringStatic("address"),
+                    RedisValue::Null,
+                ]));
+            };
+            let stats = &standby.stats;
+            let last_error = stats.last_error.lock().ok().and_then(|last_error| last_error.clone());
+            Ok(RedisValue::Array(vec![
+                RedisValue::SimpleStringStatic("address"),
+                RedisValue::BulkString(standby.address.clone()),
+                RedisValue::SimpleStringStatic("connected"),
+                RedisValue::Integer(stats.connected.load(Ordering::Relaxed) as i64),
+                RedisValue::SimpleStringStatic("cursor"),
+                RedisValue::Integer(standby.cursor.load(Ordering::Relaxed) as i64),
+                RedisValue::SimpleStringStatic("queued"),
+                RedisValue::Integer(stats.queued.load(Ordering::Relaxed) as i64),
+                RedisValue::SimpleStringStatic("sent"),
+                RedisValue::Integer(stats.sent.load(Ordering::Relaxed) as i64),
+                RedisValue::SimpleStringStatic("failed"),
+                RedisValue::Integer(stats.failed.load(Ordering::Relaxed) as i64),
+                RedisValue::SimpleStringStatic("snapshots"),
+                RedisValue::Integer(stats.snapshots.load(Ordering::Relaxed) as i64),
+                RedisValue::SimpleStringStatic("reconnects"),
+                RedisValue::Integer(stats.reconnects.load(Ordering::Relaxed) as i64),
+                RedisValue::SimpleStringStatic("last_error"),
+                last_error.map_or(RedisValue::Null, RedisValue::BulkString),
+            ]))
+        },
+        _ => {
+            if !target.contains(':') {
+                return Err(RedisError::String(format!("Invalid address: {}, expected host:port", target)));
+            }
+            let mut password = None;
+            while let Ok(option) = args.next_string() {
+                match option.to_uppercase().as_str() {
+                    "AUTH" => password = Some(args.next_string()?),
+                    _ => return Err(RedisError::String(format!("Unknown option: {}", option))),
+                }
+            }
+
+            let mut standby = init_standby().write().map_err(|_| {
+                RedisError::String("Failed to acquire write lock".to_string())
+            })?;
+            let (sender, receiver) = mpsc::sync_channel(QUEUE_CAPACITY);
+            let stats = Arc::new(Stats::default());
+            let thread_stats = stats.clone();
+            let address = target.clone();
+            thread::Builder::new()
+                .name("session-replicate".to_string())
+                .spawn(move || run(address, password, receiver, thread_stats))
+                .map_err(|e| RedisError::String(format!("Failed to start replication thread: {}", e)))?;
+
+            // The previous standby's thread exits once its queue is dropped
+            let next = Standby { address: target, sender, synced: AtomicBool::new(false), cursor: AtomicU64::new(0), stats };
+            if let Some(previous) = standby.replace(next) {
+                previous.stats.stopped.store(true, Ordering::Relaxed);
+            }
+            Ok(RedisValue::SimpleStringStatic("OK"))
+        },
+    }
+}
+
+// Apply a change streamed from a primary, on the standby:
+// SESSION.REPLICA_APPLY UPSERT session-json
+// SESSION.REPLICA_APPLY DEL session-id
+// SESSION.REPLICA_APPLY RESET
+// Creation hooks, webhooks and throttles don't run: the primary already did.
+#[tracing::instrument(name = "session.replica_apply", skip_all)]
+pub fn session_replica_apply(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let subcommand = args.next_string()?.to_uppercase();
+
+    let sessions = init_sessions();
+    match subcommand.as_str() {
+        "UPSERT" => {
+            let json = args.next_string()?;
+            args.done()?;
+            let mut session: Session = serde_json::from_str(&json).map_err(|e| {
+                RedisError::String(format!("Invalid session JSON: {}", e))
+            })?;
+            // Client ids from the primary mean nothing here
+            session.bound_client = None;
+            session.mark_changed();
+
+            let mut sessions_map = sessions.write().map_err(|_| {
+                RedisError::String("Failed to acquire write lock".to_string())
+            })?;
+            let previous_key = sessions_map.get(&session.id)
+                .map(|previous| previous.user_key.clone())
+                .filter(|previous_key| *previous_key != session.user_key);
+            if let Some(previous_key) = previous_key {
+                unlink_user_key(ctx, &previous_key)?;
+            }
+            bridge::set(ctx, &session.user_key, &session.id)?;
+            sessions_map.insert(session.id.clone(), session);
+            Ok(RedisValue::SimpleStringStatic("OK"))
+        },
+        "DEL" => {
+            let session_id = args.next_string()?;
+            args.done()?;
+
+            let mut sessions_map = sessions.write().map_err(|_| {
+                RedisError::String("Failed to acquire write lock".to_string())
+            })?;
+            // Unlink first, so a failed unlink leaves the session in place to retry
+            let Some(user_key) = sessions_map.get(&session_id).map(|session| session.user_key.clone()) else {
+                return Ok(RedisValue::Integer(0));
+            };
+            unlink_user_key(ctx, &user_key)?;
+            if let Some(session) = sessions_map.remove(&session_id) {
+                binding::forget(&session);
+                changes::record_deletion(&session_id);
+            }
+            Ok(RedisValue::Integer(1))
+        },
+        "RESET" => {
+            args.done()?;
+
+            let mut sessions_map = sessions.write().map_err(|_| {
+                RedisError::String("Failed to acquire write lock".to_string())
+            })?;
+            let sessions: Vec<(String, String)> = sessions_map.values()
+                .map(|session| (session.id.clone(), session.user_key.clone()))
+                .collect();
+            // Sessions whose key can't be unlinked stay, so the stream's next
+            // RESET finds them again; the rest are removed either way
+            let (mut removed, mut failed, mut first_error) = (0, 0, None);
+            for (session_id, user_key) in &sessions {
+                if let Err(err) = unlink_user_key(ctx, user_key) {
+                    failed += 1;
+                    first_error.get_or_insert(err);
+                    continue;
+                }
+                if let Some(session) = sessions_map.remove(session_id) {
+                    binding::forget(&session);
+                    changes::record_deletion(session_id);
+                    removed += 1;
+                }
+            }
+            if let Some(err) = first_error {
+                return Err(RedisError::String(format!(
+                    "Failed to unlink {} of {} sessions, which were kept: {}", failed, sessions.len(), err,
+                )));
+            }
+            Ok(RedisValue::Integer(removed))
+        },
+        _ => Err(RedisError::String(format!("Unknown SESSION.REPLICA_APPLY subcommand: {}", subcommand))),
+    }
+}
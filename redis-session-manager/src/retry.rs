@@ -0,0 +1,313 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use chrono::{DateTime, Utc};
+use redis_module::{Context, NextArg, RedisError, RedisResult, RedisString, RedisValue};
+use session_core::errors;
+
+use crate::bridge;
+use crate::http::Metric;
+use crate::timers::RETRY;
+
+// Attempts (including the original one) before a write is dead-lettered
+const MAX_ATTEMPTS: u32 = 5;
+
+// Delay before the first retry; doubles with every further attempt
+const BASE_BACKOFF: Duration = Duration::from_secs(1);
+
+// Queued retries at which new sessions are refused, unless set with backpressure_depth=
+const DEFAULT_BACKPRESSURE_DEPTH: usize = 10_000;
+
+// 0 turns backpressure off
+static BACKPRESSURE_DEPTH: AtomicUsize = AtomicUsize::new(DEFAULT_BACKPRESSURE_DEPTH);
+static BACKPRESSURE_REJECTIONS: AtomicU64 = AtomicU64::new(0);
+
+// A custom hashmap write that has to happen for sessions to stay consistent
+#[derive(Debug, Clone)]
+pub enum BridgeOp {
+    Del { key: String },
+}
+
+impl BridgeOp {
+    // The custom hashmap key the write is about
+    fn key(&self) -> &str {
+        match self {
+            BridgeOp::Del { key } => key,
+        }
+    }
+
+    fn run(&self, ctx: &Context, session_id: &str) -> Result<(), RedisError> {
+        let current = bridge::get(ctx, self.key())?;
+        self.apply(ctx, session_id, current)
+    }
+
+    // Perform the write given the key's current value
+    fn apply(&self, ctx: &Context, session_id: &str, current: Option<String>) -> Result<(), RedisError> {
+        match self {
+            // The key may have been claimed by a newer session in the meantime
+            BridgeOp::Del { key } => match current {
+                Some(current) if current == session_id => bridge::del(ctx, key).map(|_| ()),
+                _ => Ok(()),
+            },
+        }
+    }
+
+    fn describe(&self) -> Vec<RedisValue> {
+        match self {
+            BridgeOp::Del { key } => vec![
+                RedisValue::SimpleStringStatic("del"),
+                RedisValue::BulkString(key.clone()),
+            ],
+        }
+    }
+}
+
+// A failed write, waiting for a retry or parked in the dead-letter store
+struct FailedWrite {
+    session_id: String,
+    op: BridgeOp,
+    attempts: u32,
+    last_error: String,
+    first_failed_at: DateTime<Utc>,
+    next_attempt: Instant,
+}
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+static mut RETRY_QUEUE: Option<Mutex<BTreeMap<u64, FailedWrite>>> = None;
+static mut DEAD_LETTERS: Option<Mutex<BTreeMap<u64, FailedWrite>>> = None;
+
+// Initialize the retry queue
+fn init_retry_queue() -> &'static Mutex<BTreeMap<u64, FailedWrite>> {
+    unsafe {
+        if RETRY_QUEUE.is_none() {
+            RETRY_QUEUE = Some(Mutex::new(BTreeMap::new()));
+        }
+        RETRY_QUEUE.as_ref().unwrap()
+    }
+}
+
+// Initialize the dead-letter store
+fn init_dead_letters() -> &'static Mutex<BTreeMap<u64, FailedWrite>> {
+    unsafe {
+        if DEAD_LETTERS.is_none() {
+            DEAD_LETTERS = Some(Mutex::new(BTreeMap::new()));
+        }
+        DEAD_LETTERS.as_ref().unwrap()
+    }
+}
+
+fn backoff(attempts: u32) -> Duration {
+    BASE_BACKOFF * 2u32.saturating_pow(attempts.saturating_sub(1))
+}
+
+// Queue a write that failed outside of a client command for retrying
+pub fn enqueue(ctx: &Context, session_id: &str, op: BridgeOp, err: RedisError) {
+    ctx.log_warning(&format!("Bridge write for session {} failed, will retry: {}", session_id, err));
+
+    let write = FailedWrite {
+        session_id: session_id.to_string(),
+        op,
+        attempts: 1,
+        last_error: err.to_string(),
+        first_failed_at: Utc::now(),
+        next_attempt: Instant::now() + backoff(1),
+    };
+    if let Ok(mut queue) = init_retry_queue().lock() {
+        queue.insert(NEXT_ID.fetch_add(1, Ordering::Relaxed), write);
+    }
+}
+
+// Retry due writes; writes that keep failing move to the dead-letter store
+fn process(ctx: &Context, _data: ()) {
+    let now = Instant::now();
+    let due: Vec<(u64, FailedWrite)> = match init_retry_queue().lock() {
+        Ok(mut queue) => {
+            let ids: Vec<u64> = queue.iter()
+                .filter(|(_, write)| write.next_attempt <= now)
+                .map(|(id, _)| *id)
+                .collect();
+            ids.into_iter().filter_map(|id| queue.remove(&id).map(|write| (id, write))).collect()
+        },
+        Err(_) => Vec::new(),
+    };
+
+    // Fetch the current values of all due keys in one go; per-key lookups if that fails
+    let attempted = due.len();
+    let keys: Vec<&str> = due.iter().map(|(_, write)| write.op.key()).collect();
+    let current = if keys.is_empty() { None } else { bridge::mget(ctx, &keys).ok() };
+
+    for (index, (id, mut write)) in due.into_iter().enumerate() {
+        let result = match &current {
+            Some(values) => write.op.apply(ctx, &write.session_id, values[index].clone()),
+            None => write.op.run(ctx, &write.session_id),
+        };
+        let err = match result {
+            Ok(()) => continue,
+            Err(err) => err,
+        };
+
+        write.attempts += 1;
+        write.last_error = err.to_string();
+
+        if write.attempts >= MAX_ATTEMPTS {
+            ctx.log_warning(&format!(
+                "Bridge write for session {} failed {} times, moved to the dead-letter store: {}",
+                write.session_id, write.attempts, err,
+            ));
+            if let Ok(mut dead) = init_dead_letters().lock() {
+                dead.insert(id, write);
+            }
+        } else {
+            write.next_attempt = Instant::now() + backoff(write.attempts);
+            if let Ok(mut queue) = init_retry_queue().lock() {
+                queue.insert(id, write);
+            }
+        }
+    }
+
+    ctx.create_timer(RETRY.next(attempted, depth()), process, ());
+}
+
+// Failed bridge writes waiting to be retried
+pub fn depth() -> usize {
+    init_retry_queue().lock().map(|queue| queue.len()).unwrap_or(0)
+}
+
+pub fn set_backpressure_depth(depth: usize) {
+    BACKPRESSURE_DEPTH.store(depth, Ordering::Relaxed);
+}
+
+pub fn backpressure_depth() -> usize {
+    BACKPRESSURE_DEPTH.load(Ordering::Relaxed)
+}
+
+pub fn backpressure_rejections() -> u64 {
+    BACKPRESSURE_REJECTIONS.load(Ordering::Relaxed)
+}
+
+// Refuse a new bridge write while the retry queue is at its limit, so clients
+// back off before the queue grows beyond what the retry timer can drain
+pub fn check_backpressure() -> Result<(), RedisError> {
+    let limit = backpressure_depth();
+    if limit == 0 {
+        return Ok(());
+    }
+    let depth = depth();
+    if depth < limit {
+        return Ok(());
+    }
+    BACKPRESSURE_REJECTIONS.fetch_add(1, Ordering::Relaxed);
+    Err(RedisError::String(format!(
+        "{} {} bridge writes are waiting to be retried, try again later", errors::BUSY_SESSION, depth,
+    )))
+}
+
+// Start the retry timer at module load
+pub fn start(ctx: &Context) {
+    ctx.create_timer(RETRY.current(), process, ());
+}
+
+// Parse `id` or `ALL`
+fn parse_target(target: &str) -> Result<Option<u64>, RedisError> {
+    if target.eq_ignore_ascii_case("ALL") {
+        return Ok(None);
+    }
+    target.parse().map(Some).map_err(|_| RedisError::String(format!("Invalid dead letter id: {}", target)))
+}
+
+// Queue sizes for the HTTP status endpoint
+pub fn metrics() -> Vec<Metric> {
+    let queued = depth();
+    let dead = init_dead_letters().lock().map(|dead| dead.len()).unwrap_or(0);
+    vec![
+        Metric::gauge("session_manager_retry_queued", "Failed bridge writes waiting to be retried", queued as f64),
+        Metric::gauge("session_manager_dead_letters", "Bridge writes that ran out of retries", dead as f64),
+        Metric::counter(
+            "session_manager_backpressure_rejections_total",
+            "New sessions refused with BUSYSESSION while the retry queue was full",
+            backpressure_rejections(),
+        ),
+    ]
+}
+
+// Inspect and act on dead-lettered writes:
+// SESSION.DLQ LIST
+// SESSION.DLQ RETRY id|ALL
+// SESSION.DLQ PURGE id|ALL
+#[tracing::instrument(name = "session.dlq", skip_all)]
+pub fn session_dlq(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let subcommand = args.next_string()?.to_uppercase();
+
+    let mut dead = init_dead_letters().lock().map_err(|_| {
+        RedisError::String("Failed to acquire dead-letter lock".to_string())
+    })?;
+
+    match subcommand.as_str() {
+        "LIST" => {
+            args.done()?;
+            let entries = dead.iter()
+                .map(|(id, write)| RedisValue::Array(vec![
+                    RedisValue::SimpleStringStatic("id"),
+                    RedisValue::Integer(*id as i64),
+                    RedisValue::SimpleStringStatic("session_id"),
+                    RedisValue::BulkString(write.session_id.clone()),
+                    RedisValue::SimpleStringStatic("op"),
+                    RedisValue::Array(write.op.describe()),
+                    RedisValue::SimpleStringStatic("attempts"),
+                    RedisValue::Integer(write.attempts as i64),
+                    RedisValue::SimpleStringStatic("last_error"),
+                    RedisValue::BulkString(write.last_error.clone()),
+                    RedisValue::SimpleStringStatic("first_failed_at"),
+                    RedisValue::BulkString(write.first_failed_at.to_rfc3339()),
+                ]))
+                .collect();
+            Ok(RedisValue::Array(entries))
+        },
+        "RETRY" => {
+            let target = parse_target(&args.next_string()?)?;
+            args.done()?;
+
+            let ids: Vec<u64> = match target {
+                Some(id) if dead.contains_key(&id) => vec![id],
+                Some(id) => return Err(RedisError::String(format!("No such dead letter: {}", id))),
+                None => dead.keys().copied().collect(),
+            };
+
+            // Retry right away; writes that fail again stay dead-lettered
+            let mut succeeded = 0;
+            for id in ids {
+                if let Some(write) = dead.get_mut(&id) {
+                    match write.op.run(ctx, &write.session_id) {
+                        Ok(()) => {
+                            dead.remove(&id);
+                            succeeded += 1;
+                        },
+                        Err(err) => {
+                            write.attempts += 1;
+                            write.last_error = err.to_string();
+                        },
+                    }
+                }
+            }
+            Ok(RedisValue::Integer(succeeded))
+        },
+        "PURGE" => {
+            let target = parse_target(&args.next_string()?)?;
+            args.done()?;
+
+            let purged = match target {
+                Some(id) => dead.remove(&id).map_or(0, |_| 1),
+                None => {
+                    let count = dead.len();
+                    dead.clear();
+                    count
+                },
+            };
+            Ok(RedisValue::Integer(purged as i64))
+        },
+        _ => Err(RedisError::String(format!("Unknown SESSION.DLQ subcommand: {}", subcommand))),
+    }
+}
@@ -0,0 +1,113 @@
+use std::thread;
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::SaltString;
+use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
+use redis_module::{Context, NextArg, RedisError, RedisResult, RedisString, RedisValue, ThreadSafeContext};
+
+use crate::{init_sessions, writable_session, SessionExt};
+
+// Hash a secret into a PHC string (argon2id, random salt)
+pub fn hash_secret(plaintext: &str) -> Result<String, RedisError> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(plaintext.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| RedisError::String(format!("Failed to hash secret: {}", e)))
+}
+
+// Check a candidate against a stored PHC string
+pub fn verify_secret(hash: &str, candidate: &str) -> bool {
+    match PasswordHash::new(hash) {
+        Ok(parsed) => Argon2::default().verify_password(candidate.as_bytes(), &parsed).is_ok(),
+        Err(_) => false,
+    }
+}
+
+// Store the hash of a secret on a session (runs off the main thread)
+fn store_secret(session_id: &str, name: String, plaintext: &str) -> RedisResult {
+    let hash = hash_secret(plaintext)?;
+
+    let sessions = init_sessions();
+    let mut sessions_map = sessions.write().map_err(|_| {
+        RedisError::String("Failed to acquire write lock".to_string())
+    })?;
+
+    // The session may have been deleted (or expired) while we were hashing
+    let session = writable_session(&mut sessions_map, session_id)?;
+    session.secrets.insert(name, hash);
+    session.touch();
+    session.mark_changed();
+    Ok(RedisValue::SimpleStringStatic("OK"))
+}
+
+// Manage hashed secrets tied to a session:
+// SESSION.SECRET SET session_id name plaintext
+// SESSION.SECRET VERIFY session_id name candidate
+// SESSION.SECRET DEL session_id name
+#[tracing::instrument(name = "session.secret", skip_all)]
+pub fn session_secret(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let subcommand = args.next_string()?.to_uppercase();
+    let session_id = args.next_string()?;
+    let name = args.next_string()?;
+
+    match subcommand.as_str() {
+        "SET" => {
+            let plaintext = args.next_string()?;
+            args.done()?;
+
+            // Argon2 is deliberately slow, so hash on a worker thread instead of the event loop
+            let blocked_client = ctx.block_client();
+            thread::spawn(move || {
+                let thread_ctx = ThreadSafeContext::with_blocked_client(blocked_client);
+                thread_ctx.reply(store_secret(&session_id, name, &plaintext));
+            });
+
+            Ok(RedisValue::NoReply)
+        },
+        "VERIFY" => {
+            let candidate = args.next_string()?;
+            args.done()?;
+
+            let hash = {
+                let sessions = init_sessions();
+                let mut sessions_map = sessions.write().map_err(|_| {
+                    RedisError::String("Failed to acquire write lock".to_string())
+                })?;
+
+                let session = writable_session(&mut sessions_map, &session_id)?;
+                session.touch();
+
+                match session.secrets.get(&name) {
+                    Some(hash) => hash.clone(),
+                    None => return Ok(RedisValue::Integer(0)),
+                }
+            };
+
+            let blocked_client = ctx.block_client();
+            thread::spawn(move || {
+                let thread_ctx = ThreadSafeContext::with_blocked_client(blocked_client);
+                let matched = verify_secret(&hash, &candidate);
+                thread_ctx.reply(Ok(RedisValue::Integer(if matched { 1 } else { 0 })));
+            });
+
+            Ok(RedisValue::NoReply)
+        },
+        "DEL" => {
+            args.done()?;
+
+            let sessions = init_sessions();
+            let mut sessions_map = sessions.write().map_err(|_| {
+                RedisError::String("Failed to acquire write lock".to_string())
+            })?;
+
+            let session = writable_session(&mut sessions_map, &session_id)?;
+            let removed = session.secrets.remove(&name).is_some();
+            if removed {
+                session.mark_changed();
+            }
+            Ok(RedisValue::Integer(if removed { 1 } else { 0 }))
+        },
+        _ => Err(RedisError::String(format!("Unknown SESSION.SECRET subcommand: {}", subcommand))),
+    }
+}
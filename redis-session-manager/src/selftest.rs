@@ -0,0 +1,88 @@
+use std::time::Duration;
+use redis_module::{Context, RedisError, RedisResult, RedisString, RedisValue};
+use uuid::Uuid;
+
+use crate::{bridge, changes, init_sessions, Session};
+
+// Prefix of the throwaway keys written by the checks
+const SELFTEST_PREFIX: &str = "session:selftest:";
+
+// How long the persistence check's native key lives if its cleanup fails
+const SELFTEST_KEY_TTL_MS: &str = "10000";
+
+// Insert a session into the store, read it back and remove it again
+fn check_store(session_id: &str) -> Result<(), RedisError> {
+    let sessions = init_sessions();
+    let mut sessions_map = sessions.write().map_err(|_| {
+        RedisError::String("Failed to acquire write lock".to_string())
+    })?;
+
+    sessions_map.insert(session_id.to_string(), Session::new(session_id.to_string(), session_id.to_string(), changes::next_seq()));
+    let found = sessions_map.get(session_id).is_some_and(|session| session.user_key == session_id);
+    sessions_map.remove(session_id);
+
+    if found {
+        Ok(())
+    } else {
+        Err(RedisError::Str("Session was not found after it was inserted"))
+    }
+}
+
+fn noop(_ctx: &Context, _data: ()) {}
+
+// Register a timer with the server and cancel it before it fires
+fn check_timer(ctx: &Context) -> Result<(), RedisError> {
+    let timer_id = ctx.create_timer(Duration::from_secs(60), noop, ());
+    ctx.stop_timer::<()>(timer_id)
+}
+
+// Write a native key, read it back and delete it, exercising the keyspace
+// that archives and the fallback key map are persisted in
+fn check_persistence(ctx: &Context, key: &str) -> Result<(), RedisError> {
+    ctx.call("SET", &[key, key, "PX", SELFTEST_KEY_TTL_MS])?;
+    let read = ctx.call("GET", &[key]);
+    ctx.call("DEL", &[key])?;
+    match read? {
+        RedisValue::BulkString(value) | RedisValue::SimpleString(value) if value == key => Ok(()),
+        RedisValue::StringBuffer(value) if value == key.as_bytes() => Ok(()),
+        other => Err(RedisError::String(format!("Unexpected read back: {:?}", other))),
+    }
+}
+
+// Exercise every layer a session depends on and report each one:
+// SESSION.SELFTEST
+// Replies with `status` (pass or fail) followed by one entry per check,
+// each `ok` or the error it hit. Nothing is left behind either way.
+#[tracing::instrument(name = "session.selftest", skip_all)]
+pub fn session_selftest(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    if args.len() != 1 {
+        return Err(RedisError::WrongArity);
+    }
+
+    let key = format!("{}{}", SELFTEST_PREFIX, Uuid::new_v4());
+    let checks: Vec<(&'static str, Result<(), RedisError>)> = vec![
+        ("store", check_store(&key)),
+        ("ffi", bridge::check_ffi(&key)),
+        ("command", bridge::check_command(ctx, &key)),
+        ("timer", check_timer(ctx)),
+        ("persistence", check_persistence(ctx, &key)),
+    ];
+
+    let passed = checks.iter().all(|(_, result)| result.is_ok());
+    if !passed {
+        ctx.log_warning("SESSION.SELFTEST failed");
+    }
+
+    let mut report = vec![
+        RedisValue::SimpleStringStatic("status"),
+        RedisValue::SimpleStringStatic(if passed { "pass" } else { "fail" }),
+    ];
+    for (name, result) in checks {
+        report.push(RedisValue::SimpleStringStatic(name));
+        report.push(match result {
+            Ok(()) => RedisValue::SimpleStringStatic("ok"),
+            Err(err) => RedisValue::BulkString(err.to_string()),
+        });
+    }
+    Ok(RedisValue::Array(report))
+}
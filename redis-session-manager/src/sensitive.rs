@@ -0,0 +1,133 @@
+use std::collections::{BTreeSet, HashMap};
+use std::sync::RwLock;
+use redis_module::{AclPermissions, Context, NextArg, RedisError, RedisResult, RedisString, RedisValue};
+use serde_json::Value;
+use session_core::errors;
+
+use crate::tree;
+
+// Stands in for the value of a sensitive field
+pub const REDACTED: &str = "[REDACTED]";
+
+// REVEAL is allowed for users with read access to this key pattern
+const REVEAL_ACL_KEY: &str = "session:sensitive";
+
+// Field names (or dotted subtree paths) whose values are redacted in replies
+static mut SENSITIVE_FIELDS: Option<RwLock<BTreeSet<String>>> = None;
+
+// Initialize the sensitive field set
+fn init_sensitive_fields() -> &'static RwLock<BTreeSet<String>> {
+    unsafe {
+        if SENSITIVE_FIELDS.is_none() {
+            SENSITIVE_FIELDS = Some(RwLock::new(BTreeSet::new()));
+        }
+        SENSITIVE_FIELDS.as_ref().unwrap()
+    }
+}
+
+// Decides per field whether its value may be shown
+pub struct Redactor {
+    fields: BTreeSet<String>,
+}
+
+impl Redactor {
+    // Sensitive fields are redacted unless the caller asked for REVEAL and is allowed to
+    pub fn for_caller(ctx: &Context, reveal: bool) -> Result<Redactor, RedisError> {
+        if reveal {
+            check_reveal(ctx)?;
+            return Ok(Redactor { fields: BTreeSet::new() });
+        }
+        let fields = init_sensitive_fields().read().map_err(|_| {
+            RedisError::String("Failed to acquire read lock".to_string())
+        })?;
+        Ok(Redactor { fields: fields.clone() })
+    }
+
+    pub fn is_sensitive(&self, field: &str) -> bool {
+        self.fields.iter().any(|sensitive| field == sensitive || tree::in_subtree(field, sensitive))
+    }
+
+    // The value to reply with for a field
+    pub fn value<'a>(&self, field: &str, value: &'a str) -> &'a str {
+        if self.is_sensitive(field) { REDACTED } else { value }
+    }
+
+    // Whether any of a session's fields would be redacted
+    pub fn redacts_any(&self, data: &HashMap<String, String>) -> bool {
+        !self.fields.is_empty() && data.keys().any(|field| self.is_sensitive(field))
+    }
+
+    // A copy of session data with sensitive values redacted
+    pub fn redact_data(&self, mut data: HashMap<String, String>) -> HashMap<String, String> {
+        for (field, value) in data.iter_mut() {
+            if self.is_sensitive(field) {
+                *value = REDACTED.to_string();
+            }
+        }
+        data
+    }
+
+    // Redact the `data` object of a serialized session in place
+    pub fn redact_session(&self, json: &mut Value) {
+        if self.fields.is_empty() {
+            return;
+        }
+        if let Some(Value::Object(data)) = json.get_mut("data") {
+            for (field, value) in data.iter_mut() {
+                if self.is_sensitive(field) {
+                    *value = Value::String(REDACTED.to_string());
+                }
+            }
+        }
+    }
+}
+
+// The current user must be able to read REVEAL_ACL_KEY, e.g. `%R~session:sensitive`
+fn check_reveal(ctx: &Context) -> Result<(), RedisError> {
+    let user = ctx.get_current_user();
+    let key = ctx.create_string(REVEAL_ACL_KEY);
+    ctx.acl_check_key_permission(&user, &key, &AclPermissions::ACCESS).map_err(|_| {
+        RedisError::String(format!("{} REVEAL requires read access to the key {}", errors::NO_PERMISSION, REVEAL_ACL_KEY))
+    })
+}
+
+// Manage the fields redacted from SESSION.GET, SESSION.EXPORT, SESSION.GET_ALL_DATA
+// and SESSION.GET_DATA path.* replies:
+// SESSION.SENSITIVE ADD field [field ...]
+// SESSION.SENSITIVE DEL field [field ...]
+// SESSION.SENSITIVE LIST
+#[tracing::instrument(name = "session.sensitive", skip_all)]
+pub fn session_sensitive(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let subcommand = args.next_string()?.to_uppercase();
+
+    match subcommand.as_str() {
+        "ADD" | "DEL" => {
+            let fields: Vec<String> = args.map(|field| field.to_string_lossy()).collect();
+            if fields.is_empty() {
+                return Err(RedisError::WrongArity);
+            }
+
+            let mut sensitive = init_sensitive_fields().write().map_err(|_| {
+                RedisError::String("Failed to acquire write lock".to_string())
+            })?;
+            let changed = if subcommand == "ADD" {
+                fields.into_iter().filter(|field| sensitive.insert(field.clone())).count()
+            } else {
+                fields.iter().filter(|field| sensitive.remove(*field)).count()
+            };
+
+            Ok(RedisValue::Integer(changed as i64))
+        },
+        "LIST" => {
+            args.done()?;
+
+            let sensitive = init_sensitive_fields().read().map_err(|_| {
+                RedisError::String("Failed to acquire read lock".to_string())
+            })?;
+
+            Ok(RedisValue::Array(sensitive.iter().map(|field| RedisValue::BulkString(field.clone())).collect()))
+        },
+        _ => Err(RedisError::String(format!("Unknown SESSION.SENSITIVE subcommand: {}", subcommand))),
+    }
+}
@@ -0,0 +1,13 @@
+use redis_module::{Context, RedisResult, RedisString};
+
+// Shard statistics and rebalancing of the hashmap holding user key mappings:
+// SESSION.SHARDS [STATS]
+// SESSION.SHARDS CONFIG count
+// SESSION.SHARDS REBALANCE
+// A pass-through to CUSTOM.SHARDS, so operators can tune the shard count
+// from the same place as the rest of the session manager's diagnostics.
+#[tracing::instrument(name = "session.shards", skip_all)]
+pub fn session_shards(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let args: Vec<&[u8]> = args.iter().skip(1).map(|arg| arg.as_slice()).collect();
+    ctx.call("custom.shards", &args[..])
+}
@@ -0,0 +1,218 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use redis_module::{Context, RedisError, RedisValue};
+use serde_json::Value;
+
+use crate::{history, Session};
+
+// Prefix of the native keys spilled values are stored under: session:spill:<session id>:<field>
+const SPILL_KEY_PREFIX: &str = "session:spill:";
+
+// Values of at least this many bytes are moved out of the session; 0 keeps every value inline
+static SPILL_THRESHOLD: AtomicU64 = AtomicU64::new(0);
+static SPILLED: AtomicU64 = AtomicU64::new(0);
+static FETCHES: AtomicU64 = AtomicU64::new(0);
+
+// Keys of spilled values whose field or session is gone, deleted by the next expiry sweep
+static mut ORPHANS: Option<Mutex<Vec<String>>> = None;
+
+// Initialize the orphaned key queue
+fn init_orphans() -> &'static Mutex<Vec<String>> {
+    unsafe {
+        if ORPHANS.is_none() {
+            ORPHANS = Some(Mutex::new(Vec::new()));
+        }
+        ORPHANS.as_ref().unwrap()
+    }
+}
+
+// Values spilled since history last recorded them, by key, so history keeps
+// the value rather than the key
+static mut WRITTEN: Option<Mutex<HashMap<String, String>>> = None;
+
+// Initialize the values waiting for history
+fn init_written() -> &'static Mutex<HashMap<String, String>> {
+    unsafe {
+        if WRITTEN.is_none() {
+            WRITTEN = Some(Mutex::new(HashMap::new()));
+        }
+        WRITTEN.as_ref().unwrap()
+    }
+}
+
+pub fn set_spill_threshold(bytes: u64) {
+    SPILL_THRESHOLD.store(bytes, Ordering::Relaxed);
+}
+
+pub fn spill_threshold() -> u64 {
+    SPILL_THRESHOLD.load(Ordering::Relaxed)
+}
+
+fn spill_key(session_id: &str, field: &str) -> String {
+    format!("{}{}:{}", SPILL_KEY_PREFIX, session_id, field)
+}
+
+fn orphan(keys: impl IntoIterator<Item = String>) {
+    if let Ok(mut orphans) = init_orphans().lock() {
+        orphans.extend(keys);
+    }
+}
+
+// What to keep in the session for a field being written: the value itself
+// when it's small, otherwise the key it has been stored under
+pub fn store(ctx: &Context, session: &mut Session, field: &str, value: String) -> Result<String, RedisError> {
+    let mut stored = store_all(ctx, session, vec![(field.to_string(), value)])?;
+    Ok(stored.pop().unwrap_or_default())
+}
+
+// As `store` for several writes at once, in order. Large values are written
+// with one MSET, so either all of them are stored or, on an error, none is
+// and the session is left as it was.
+pub fn store_all(ctx: &Context, session: &mut Session, writes: Vec<(String, String)>) -> Result<Vec<String>, RedisError> {
+    let threshold = spill_threshold();
+    let spills = |value: &str| threshold > 0 && value.len() as u64 >= threshold;
+
+    let spilled: Vec<String> = writes.iter()
+        .filter(|(_, value)| spills(value))
+        .flat_map(|(field, value)| [spill_key(&session.id, field), value.clone()])
+        .collect();
+    if !spilled.is_empty() {
+        let args: Vec<&str> = spilled.iter().map(String::as_str).collect();
+        ctx.call("MSET", args.as_slice())
+            .map_err(|e| RedisError::String(format!("Failed to write spilled values: {}", e)))?;
+    }
+
+    let fields: Vec<String> = writes.iter().map(|(field, _)| field.clone()).collect();
+    let stored = writes.into_iter()
+        .map(|(field, value)| {
+            let key = spill_key(&session.id, &field);
+            if !spills(&value) {
+                // A value that shrank comes back inline and its old key goes
+                if session.spilled.remove(&field) {
+                    orphan([key]);
+                }
+                return value;
+            }
+            session.spilled.insert(field);
+            SPILLED.fetch_add(1, Ordering::Relaxed);
+            if history::history_secs() > 0 {
+                if let Ok(mut written) = init_written().lock() {
+                    written.insert(key.clone(), value);
+                }
+            }
+            key
+        })
+        .collect();
+
+    // A key orphaned by an earlier write that is in use again must survive the next sweep
+    let live: Vec<String> = fields.iter()
+        .filter(|field| session.spilled.contains(*field))
+        .map(|field| spill_key(&session.id, field))
+        .collect();
+    if !live.is_empty() {
+        if let Ok(mut orphans) = init_orphans().lock() {
+            orphans.retain(|key| !live.contains(key));
+        }
+    }
+    Ok(stored)
+}
+
+// The value last spilled under a key, if history hasn't taken it yet
+pub fn take_written(key: &str) -> Option<String> {
+    init_written().lock().ok()?.remove(key)
+}
+
+// Forget spilled values of fields no longer in the session's data
+pub fn prune(session: &mut Session) {
+    let gone: Vec<String> = session.spilled.iter()
+        .filter(|field| !session.data.contains_key(*field))
+        .cloned()
+        .collect();
+    for field in &gone {
+        session.spilled.remove(field);
+    }
+    orphan(gone.iter().map(|field| spill_key(&session.id, field)));
+}
+
+// A session left the store; its spilled values go with it
+pub fn forget(session: &Session) {
+    orphan(session.spilled.iter().map(|field| spill_key(&session.id, field)));
+}
+
+// A session was replaced under its id; only the spilled values the new one
+// no longer refers to go
+pub fn forget_replaced(previous: &Session, session: &Session) {
+    orphan(previous.spilled.iter()
+        .filter(|field| !session.spilled.contains(*field))
+        .map(|field| spill_key(&previous.id, field)));
+}
+
+// Delete the keys of values whose session or field is gone
+pub fn delete_orphans(ctx: &Context) {
+    let keys = match init_orphans().lock() {
+        Ok(mut orphans) => std::mem::take(&mut *orphans),
+        Err(_) => return,
+    };
+    for key in keys {
+        if let Err(err) = ctx.call("DEL", &[key.as_str()]) {
+            ctx.log_warning(&format!("Failed to delete {}: {}", key, err));
+        }
+    }
+}
+
+fn fetch(ctx: &Context, key: &str) -> Option<String> {
+    FETCHES.fetch_add(1, Ordering::Relaxed);
+    match ctx.call("GET", &[key]) {
+        Ok(RedisValue::BulkString(value)) | Ok(RedisValue::SimpleString(value)) => Some(value),
+        Ok(RedisValue::StringBuffer(value)) => String::from_utf8(value).ok(),
+        _ => None,
+    }
+}
+
+// The value of a data field as clients see it: fetched from its key when
+// spilled, None when the field is unset or the key has gone
+pub fn value(ctx: &Context, session: &Session, field: &str) -> Option<String> {
+    let stored = session.data.get(field)?;
+    if session.spilled.contains(field) {
+        fetch(ctx, stored)
+    } else {
+        Some(stored.clone())
+    }
+}
+
+// The session's data with every spilled value fetched, for copies that
+// outlive the session's keys such as archives and impersonations
+pub fn resolved_data(ctx: &Context, session: &Session) -> HashMap<String, String> {
+    session.data.keys()
+        .filter_map(|field| Some((field.clone(), value(ctx, session, field)?)))
+        .collect()
+}
+
+// Put spilled values back into the `data` object of a serialized session.
+// Entries that no longer hold the key, such as redacted ones, stay as they are.
+pub fn resolve_session(ctx: &Context, session: &Session, json: &mut Value) {
+    if let Some(json) = json.as_object_mut() {
+        json.remove("spilled");
+    }
+    if let Some(Value::Object(data)) = json.get_mut("data") {
+        for field in &session.spilled {
+            let Some(entry) = data.get_mut(field) else { continue };
+            if entry.as_str() == session.data.get(field).map(String::as_str) {
+                *entry = value(ctx, session, field).map_or(Value::Null, Value::String);
+            }
+        }
+    }
+}
+
+// Tiering settings and counters as flat name/value pairs for SESSION.INFO
+pub fn info() -> Vec<RedisValue> {
+    vec![
+        RedisValue::SimpleStringStatic("spill_threshold"),
+        RedisValue::Integer(spill_threshold() as i64),
+        RedisValue::SimpleStringStatic("spilled_values"),
+        RedisValue::Integer(SPILLED.load(Ordering::Relaxed) as i64),
+        RedisValue::SimpleStringStatic("spill_fetches"),
+        RedisValue::Integer(FETCHES.load(Ordering::Relaxed) as i64),
+    ]
+}
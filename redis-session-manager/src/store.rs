@@ -0,0 +1,390 @@
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Mutex;
+use chrono::{DateTime, Utc};
+
+use session_core::Priority;
+
+use crate::{history, spill, Session};
+
+// Ordered (timestamp, session id) index
+type TimeIndex = BTreeSet<(DateTime<Utc>, String)>;
+
+// Ordered (priority, timestamp, session id) index of the sessions eviction may
+// pick, so LOW sessions come before NORMAL ones and HIGH ones aren't there
+type EvictionIndex = BTreeSet<(Priority, DateTime<Utc>, String)>;
+
+// What a session handed out by get_mut was indexed under: last_accessed,
+// expires_at and priority
+type Indexed = (DateTime<Utc>, Option<DateTime<Utc>>, Priority);
+
+fn evictable(priority: Priority) -> bool {
+    priority != Priority::High
+}
+
+// Ordered indexes over session timestamps, plus session ids by app
+#[derive(Default)]
+struct SessionIndexes {
+    by_created: TimeIndex,
+    by_last_accessed: TimeIndex,
+    // Only sessions with an expiry
+    by_expiry: TimeIndex,
+    // Only evictable sessions
+    evict_by_created: EvictionIndex,
+    evict_by_last_accessed: EvictionIndex,
+    // Only sessions created with an app; a session's app never changes
+    by_app: HashMap<String, BTreeSet<String>>,
+    // Sessions handed out by get_mut, with what they were indexed under
+    pending: HashMap<String, Indexed>,
+}
+
+impl SessionIndexes {
+    fn add(&mut self, session: &Session) {
+        self.by_created.insert((session.created_at, session.id.clone()));
+        self.by_last_accessed.insert((session.last_accessed, session.id.clone()));
+        if let Some(expires_at) = session.expires_at {
+            self.by_expiry.insert((expires_at, session.id.clone()));
+        }
+        if evictable(session.priority) {
+            self.evict_by_created.insert((session.priority, session.created_at, session.id.clone()));
+            self.evict_by_last_accessed.insert((session.priority, session.last_accessed, session.id.clone()));
+        }
+        if let Some(app) = &session.app {
+            self.by_app.entry(app.clone()).or_default().insert(session.id.clone());
+        }
+    }
+
+    fn remove(&mut self, session: &Session) {
+        self.by_created.remove(&(session.created_at, session.id.clone()));
+        self.by_last_accessed.remove(&(session.last_accessed, session.id.clone()));
+        if let Some(expires_at) = session.expires_at {
+            self.by_expiry.remove(&(expires_at, session.id.clone()));
+        }
+        self.evict_by_created.remove(&(session.priority, session.created_at, session.id.clone()));
+        self.evict_by_last_accessed.remove(&(session.priority, session.last_accessed, session.id.clone()));
+        if let Some(app) = &session.app {
+            if let Some(ids) = self.by_app.get_mut(app) {
+                ids.remove(&session.id);
+                if ids.is_empty() {
+                    self.by_app.remove(app);
+                }
+            }
+        }
+    }
+
+    // Whether a session belongs to `app`; no app matches every session
+    fn in_app(&self, id: &str, app: Option<&str>) -> bool {
+        app.is_none_or(|app| self.by_app.get(app).is_some_and(|ids| ids.contains(id)))
+    }
+
+    // Re-index sessions that may have been modified since get_mut handed them out
+    fn reconcile(&mut self, sessions: &HashMap<String, Session>) {
+        for (id, (last_accessed, expires_at, priority)) in std::mem::take(&mut self.pending) {
+            let session = match sessions.get(&id) {
+                Some(session) => session,
+                None => continue,
+            };
+            if session.last_accessed != last_accessed {
+                self.by_last_accessed.remove(&(last_accessed, id.clone()));
+                self.by_last_accessed.insert((session.last_accessed, id.clone()));
+            }
+            if session.last_accessed != last_accessed || session.priority != priority {
+                self.evict_by_last_accessed.remove(&(priority, last_accessed, id.clone()));
+                if evictable(session.priority) {
+                    self.evict_by_last_accessed.insert((session.priority, session.last_accessed, id.clone()));
+                }
+            }
+            if session.priority != priority {
+                self.evict_by_created.remove(&(priority, session.created_at, id.clone()));
+                if evictable(session.priority) {
+                    self.evict_by_created.insert((session.priority, session.created_at, id.clone()));
+                }
+            }
+            if session.expires_at != expires_at {
+                if let Some(expires_at) = expires_at {
+                    self.by_expiry.remove(&(expires_at, id.clone()));
+                }
+                if let Some(expires_at) = session.expires_at {
+                    self.by_expiry.insert((expires_at, id));
+                }
+            }
+        }
+    }
+}
+
+// The ids of the first `limit` entries of an eviction index
+fn first_ids(index: &EvictionIndex, limit: usize) -> Vec<String> {
+    index.iter().take(limit).map(|(_, _, id)| id.clone()).collect()
+}
+
+// Orderings offered by SESSION.LIST SORT BY
+#[derive(Debug, Clone, Copy)]
+pub enum SortKey {
+    Created,
+    LastAccessed,
+    Ttl,
+}
+
+// The sessions map plus ordered indexes on created_at, last_accessed and
+// expires_at, and an index of sessions by app. Sessions modified through get_mut are re-indexed lazily, on
+// the next insert, remove or index lookup, so callers can keep mutating
+// sessions in place.
+#[derive(Default)]
+pub struct SessionStore {
+    sessions: HashMap<String, Session>,
+    // Behind a mutex so lookups under the store's read lock can reconcile
+    indexes: Mutex<SessionIndexes>,
+}
+
+impl SessionStore {
+    fn indexes_mut(&mut self) -> &mut SessionIndexes {
+        self.indexes.get_mut().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Session> {
+        self.sessions.get(id)
+    }
+
+    pub fn get_mut(&mut self, id: &str) -> Option<&mut Session> {
+        let session = self.sessions.get(id)?;
+        let indexed = (session.last_accessed, session.expires_at, session.priority);
+        self.indexes_mut().pending.entry(id.to_string()).or_insert(indexed);
+        self.sessions.get_mut(id)
+    }
+
+    pub fn contains_key(&self, id: &str) -> bool {
+        self.sessions.contains_key(id)
+    }
+
+    pub fn insert(&mut self, id: String, session: Session) -> Option<Session> {
+        // Spilled values the new session still refers to must survive it replacing the old one
+        let previous = self.take(&id);
+        if let Some(previous) = &previous {
+            spill::forget_replaced(previous, &session);
+        }
+        self.indexes_mut().add(&session);
+        history::record(&session);
+        self.sessions.insert(id, session);
+        previous
+    }
+
+    pub fn remove(&mut self, id: &str) -> Option<Session> {
+        let session = self.take(id)?;
+        spill::forget(&session);
+        Some(session)
+    }
+
+    // Remove a session from the map and the indexes, leaving its spilled values
+    fn take(&mut self, id: &str) -> Option<Session> {
+        let sessions = &self.sessions;
+        let indexes = self.indexes.get_mut().unwrap_or_else(|poisoned| poisoned.into_inner());
+        indexes.reconcile(sessions);
+
+        let session = self.sessions.remove(id)?;
+        self.indexes_mut().remove(&session);
+        Some(session)
+    }
+
+    pub fn len(&self) -> usize {
+        self.sessions.len()
+    }
+
+    // Forget nonces whose window has passed; returns how many were dropped.
+    // Nonces aren't indexed, so sessions are changed in place.
+    pub fn prune_nonces(&mut self, now: DateTime<Utc>) -> usize {
+        let mut pruned = 0;
+        for session in self.sessions.values_mut() {
+            let before = session.nonces.len();
+            session.nonces.retain(|_, expires_at| *expires_at > now);
+            pruned += before - session.nonces.len();
+        }
+        pruned
+    }
+
+    // Give back memory held by the map, the indexes and each session's data
+    // after mass deletions
+    pub fn compact(&mut self) {
+        for session in self.sessions.values_mut() {
+            session.data.shrink_to_fit();
+            session.nonces.shrink_to_fit();
+        }
+        self.sessions.shrink_to_fit();
+        let indexes = self.indexes_mut();
+        indexes.by_app.shrink_to_fit();
+        indexes.pending.shrink_to_fit();
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &Session> {
+        self.sessions.values()
+    }
+
+    // Sessions created with `app`
+    pub fn app_sessions(&self, app: &str) -> Vec<&Session> {
+        let ids: Vec<String> = self.with_indexes(|indexes| {
+            indexes.by_app.get(app).map(|ids| ids.iter().cloned().collect()).unwrap_or_default()
+        });
+        ids.iter().filter_map(|id| self.sessions.get(id)).collect()
+    }
+
+    // Number of sessions, or of sessions created with `app`
+    pub fn count(&self, app: Option<&str>) -> usize {
+        match app {
+            Some(app) => self.with_indexes(|indexes| indexes.by_app.get(app).map(|ids| ids.len()).unwrap_or(0)),
+            None => self.sessions.len(),
+        }
+    }
+
+    // Read the indexes after reconciling them
+    fn with_indexes<T>(&self, select: impl FnOnce(&SessionIndexes) -> T) -> T {
+        let mut indexes = self.indexes.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        indexes.reconcile(&self.sessions);
+        select(&indexes)
+    }
+
+    // Up to `limit` sessions in index order, only those of `app` if given;
+    // sessions without an expiry sort last by TTL
+    pub fn sorted(&self, key: SortKey, descending: bool, limit: usize, app: Option<&str>) -> Vec<&Session> {
+        let ids: Vec<String> = self.with_indexes(|indexes| {
+            let index = match key {
+                SortKey::Created => &indexes.by_created,
+                SortKey::LastAccessed => &indexes.by_last_accessed,
+                SortKey::Ttl => &indexes.by_expiry,
+            };
+            let entries: Box<dyn Iterator<Item = &(DateTime<Utc>, String)>> = if descending {
+                Box::new(index.iter().rev())
+            } else {
+                Box::new(index.iter())
+            };
+            entries
+                .filter(|(_, id)| indexes.in_app(id, app))
+                .take(limit)
+                .map(|(_, id)| id.clone())
+                .collect()
+        });
+
+        let mut sorted: Vec<&Session> = ids.iter().filter_map(|id| self.sessions.get(id)).collect();
+
+        if let SortKey::Ttl = key {
+            if sorted.len() < limit {
+                let mut persistent: Vec<&Session> = self.sessions.values()
+                    .filter(|session| session.expires_at.is_none())
+                    .filter(|session| app.is_none_or(|app| session.app.as_deref() == Some(app)))
+                    .collect();
+                persistent.sort_unstable_by(|a, b| a.id.cmp(&b.id));
+                let remaining = limit - sorted.len();
+                sorted.extend(persistent.into_iter().take(remaining));
+            }
+        }
+
+        sorted
+    }
+
+    // Ids of sessions last accessed before `cutoff`, only those of `app` if given
+    pub fn idle_before(&self, cutoff: DateTime<Utc>, app: Option<&str>) -> Vec<String> {
+        self.with_indexes(|indexes| {
+            indexes.by_last_accessed.iter()
+                .take_while(|(last_accessed, _)| *last_accessed < cutoff)
+                .filter(|(_, id)| indexes.in_app(id, app))
+                .map(|(_, id)| id.clone())
+                .collect()
+        })
+    }
+
+    // Ids of sessions created before `cutoff`, oldest first
+    pub fn created_before(&self, cutoff: DateTime<Utc>) -> Vec<String> {
+        self.with_indexes(|indexes| {
+            indexes.by_created.iter()
+                .take_while(|(created_at, _)| *created_at < cutoff)
+                .map(|(_, id)| id.clone())
+                .collect()
+        })
+    }
+
+    // Up to `limit` ids of sessions to evict, least recently used first: every
+    // LOW session before any NORMAL one, and HIGH sessions never
+    pub fn eviction_order(&self, limit: usize) -> Vec<String> {
+        self.with_indexes(|indexes| first_ids(&indexes.evict_by_last_accessed, limit))
+    }
+
+    // As eviction_order, but oldest first
+    pub fn oldest_eviction_order(&self, limit: usize) -> Vec<String> {
+        self.with_indexes(|indexes| first_ids(&indexes.evict_by_created, limit))
+    }
+
+    // Ids of sessions expiring at or before `horizon`, soonest first
+    pub fn expiring_before(&self, horizon: DateTime<Utc>) -> Vec<String> {
+        self.with_indexes(|indexes| {
+            indexes.by_expiry.iter()
+                .take_while(|(expires_at, _)| *expires_at <= horizon)
+                .map(|(_, id)| id.clone())
+                .collect()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeDelta;
+
+    // A store of sessions created a minute apart, in the given order, each
+    // last accessed `accessed` minutes after the first was created
+    fn store(sessions: &[(&str, Priority, i64)]) -> SessionStore {
+        let start = Utc::now() - TimeDelta::hours(1);
+        let mut store = SessionStore::default();
+        for (minute, (id, priority, accessed)) in sessions.iter().enumerate() {
+            let mut session = Session::new(id.to_string(), format!("user:{}", id), 0);
+            session.created_at = start + TimeDelta::minutes(minute as i64);
+            session.last_accessed = start + TimeDelta::minutes(*accessed);
+            session.priority = *priority;
+            store.insert(id.to_string(), session);
+        }
+        store
+    }
+
+    #[test]
+    fn evicts_low_before_normal_and_never_high() {
+        let store = store(&[
+            ("normal-old", Priority::Normal, 10),
+            ("high", Priority::High, 0),
+            ("low-recent", Priority::Low, 30),
+            ("normal-recent", Priority::Normal, 20),
+            ("low-old", Priority::Low, 5),
+        ]);
+        assert_eq!(store.eviction_order(10), ["low-old", "low-recent", "normal-old", "normal-recent"]);
+        assert_eq!(store.oldest_eviction_order(10), ["low-recent", "low-old", "normal-old", "normal-recent"]);
+        assert_eq!(store.eviction_order(1), ["low-old"]);
+        assert!(store.eviction_order(0).is_empty());
+    }
+
+    #[test]
+    fn follows_accesses_and_priority_changes() {
+        let mut store = store(&[
+            ("a", Priority::Normal, 1),
+            ("b", Priority::Normal, 2),
+            ("c", Priority::Normal, 3),
+        ]);
+        store.get_mut("a").unwrap().last_accessed = Utc::now();
+        assert_eq!(store.eviction_order(3), ["b", "c", "a"]);
+
+        store.get_mut("c").unwrap().priority = Priority::Low;
+        store.get_mut("b").unwrap().priority = Priority::High;
+        assert_eq!(store.eviction_order(3), ["c", "a"]);
+        assert_eq!(store.oldest_eviction_order(3), ["c", "a"]);
+
+        store.get_mut("b").unwrap().priority = Priority::Normal;
+        assert_eq!(store.oldest_eviction_order(3), ["c", "a", "b"]);
+    }
+
+    #[test]
+    fn forgets_removed_and_replaced_sessions() {
+        let mut store = store(&[("a", Priority::Low, 1), ("b", Priority::Normal, 2)]);
+        store.remove("a");
+        assert_eq!(store.eviction_order(3), ["b"]);
+
+        let mut replacement = Session::new("b".to_string(), "user:b".to_string(), 0);
+        replacement.priority = Priority::High;
+        store.insert("b".to_string(), replacement);
+        assert!(store.eviction_order(3).is_empty());
+        assert!(store.oldest_eviction_order(3).is_empty());
+    }
+}
@@ -0,0 +1,111 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::RwLock;
+use redis_module::{Context, NextArg, RedisError, RedisResult, RedisString, RedisValue};
+use serde_json::Value;
+
+use crate::tree;
+
+// Named sets of data fields copied into new sessions by SESSION.CREATE ... TEMPLATE
+static mut TEMPLATES: Option<RwLock<BTreeMap<String, HashMap<String, String>>>> = None;
+
+// Initialize the template store
+fn init_templates() -> &'static RwLock<BTreeMap<String, HashMap<String, String>>> {
+    unsafe {
+        if TEMPLATES.is_none() {
+            TEMPLATES = Some(RwLock::new(BTreeMap::new()));
+        }
+        TEMPLATES.as_ref().unwrap()
+    }
+}
+
+// The data fields of a template
+pub fn fields(name: &str) -> Result<HashMap<String, String>, RedisError> {
+    let templates = init_templates().read().map_err(|_| {
+        RedisError::String("Failed to acquire read lock".to_string())
+    })?;
+    templates.get(name)
+        .cloned()
+        .ok_or_else(|| RedisError::String(format!("Template not found: {}", name)))
+}
+
+// A flat JSON object of strings, numbers and booleans; numbers and booleans are stored as text
+fn parse_fields(json: &str) -> Result<HashMap<String, String>, RedisError> {
+    let object = match serde_json::from_str(json) {
+        Ok(Value::Object(object)) => object,
+        Ok(_) => return Err(RedisError::Str("Template must be a JSON object")),
+        Err(e) => return Err(RedisError::String(format!("Invalid template JSON: {}", e))),
+    };
+
+    let mut fields = HashMap::new();
+    for (field, value) in object {
+        let value = match value {
+            Value::String(value) => value,
+            Value::Number(number) => number.to_string(),
+            Value::Bool(flag) => flag.to_string(),
+            _ => return Err(RedisError::String(format!("Template field {} must be a string, number or boolean", field))),
+        };
+        tree::check_conflict(&fields, &field)?;
+        fields.insert(field, value);
+    }
+    Ok(fields)
+}
+
+fn fields_json(fields: &HashMap<String, String>) -> String {
+    let sorted: BTreeMap<&String, &String> = fields.iter().collect();
+    serde_json::to_string(&sorted).unwrap_or_default()
+}
+
+// Manage session templates:
+// SESSION.TEMPLATE SET name json
+// SESSION.TEMPLATE GET name
+// SESSION.TEMPLATE DEL name
+// SESSION.TEMPLATE LIST
+#[tracing::instrument(name = "session.template", skip_all)]
+pub fn session_template(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let subcommand = args.next_string()?.to_uppercase();
+
+    match subcommand.as_str() {
+        "SET" => {
+            let name = args.next_string()?;
+            let fields = parse_fields(&args.next_string()?)?;
+            args.done()?;
+
+            let mut templates = init_templates().write().map_err(|_| {
+                RedisError::String("Failed to acquire write lock".to_string())
+            })?;
+            templates.insert(name, fields);
+            Ok(RedisValue::SimpleStringStatic("OK"))
+        },
+        "GET" => {
+            let name = args.next_string()?;
+            args.done()?;
+
+            let templates = init_templates().read().map_err(|_| {
+                RedisError::String("Failed to acquire read lock".to_string())
+            })?;
+            Ok(match templates.get(&name) {
+                Some(fields) => RedisValue::BulkString(fields_json(fields)),
+                None => RedisValue::Null,
+            })
+        },
+        "DEL" => {
+            let name = args.next_string()?;
+            args.done()?;
+
+            let mut templates = init_templates().write().map_err(|_| {
+                RedisError::String("Failed to acquire write lock".to_string())
+            })?;
+            Ok(RedisValue::Integer(if templates.remove(&name).is_some() { 1 } else { 0 }))
+        },
+        "LIST" => {
+            args.done()?;
+
+            let templates = init_templates().read().map_err(|_| {
+                RedisError::String("Failed to acquire read lock".to_string())
+            })?;
+            Ok(RedisValue::Array(templates.keys().map(|name| RedisValue::BulkString(name.clone())).collect()))
+        },
+        _ => Err(RedisError::String(format!("Unknown SESSION.TEMPLATE subcommand: {}", subcommand))),
+    }
+}
@@ -0,0 +1,230 @@
+use std::collections::{HashMap, VecDeque};
+use std::ffi::CStr;
+use std::os::raw::c_void;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use redis_module::{raw, Context, NextArg, RedisError, RedisResult, RedisString, RedisValue};
+use session_core::errors;
+
+// At most `limit` new sessions per sliding window of `window`
+#[derive(Clone, Copy)]
+struct Limit {
+    limit: usize,
+    window: Duration,
+}
+
+// Creation times within the window, per user key or client IP
+type CreationLog = HashMap<String, VecDeque<Instant>>;
+
+#[derive(Default)]
+struct Throttle {
+    user: Option<Limit>,
+    ip: Option<Limit>,
+    by_user: CreationLog,
+    by_ip: CreationLog,
+    rejected_user: u64,
+    rejected_ip: u64,
+}
+
+static mut THROTTLE: Option<Mutex<Throttle>> = None;
+
+// Initialize the throttle state
+fn init_throttle() -> &'static Mutex<Throttle> {
+    unsafe {
+        if THROTTLE.is_none() {
+            THROTTLE = Some(Mutex::new(Throttle::default()));
+        }
+        THROTTLE.as_ref().unwrap()
+    }
+}
+
+// How long until another creation fits into the window, None if one fits now
+fn retry_after(log: Option<&VecDeque<Instant>>, limit: Limit, now: Instant) -> Option<Duration> {
+    let log = log?;
+    let in_window = log.iter().filter(|created| now.duration_since(**created) < limit.window).count();
+    if in_window < limit.limit {
+        return None;
+    }
+    // The oldest creation still in the window has to leave it first
+    let oldest = log[log.len() - limit.limit];
+    Some(limit.window.saturating_sub(now.duration_since(oldest)))
+}
+
+fn record(log: &mut CreationLog, key: &str, limit: Limit, now: Instant) {
+    let entries = log.entry(key.to_string()).or_default();
+    entries.push_back(now);
+    while entries.len() > limit.limit {
+        entries.pop_front();
+    }
+}
+
+fn throttled(what: &str, key: &str, wait: Duration) -> RedisError {
+    RedisError::String(format!(
+        "{} Too many sessions created for {} {}, retry after {} seconds",
+        errors::THROTTLED, what, key, wait.as_secs() + u64::from(wait.subsec_nanos() > 0),
+    ))
+}
+
+// The IP address the calling client connected from, if the server reports one
+pub fn client_ip(ctx: &Context) -> Option<String> {
+    let get_client_info = unsafe { raw::RedisModule_GetClientInfoById? };
+    let mut info: raw::RedisModuleClientInfoV1 = unsafe { std::mem::zeroed() };
+    info.version = 1;
+    let res = unsafe { get_client_info(&mut info as *mut _ as *mut c_void, ctx.get_client_id()) };
+    if res != raw::REDISMODULE_OK as i32 {
+        return None;
+    }
+    let addr = unsafe { CStr::from_ptr(info.addr.as_ptr()) }.to_string_lossy().into_owned();
+    (!addr.is_empty()).then_some(addr)
+}
+
+// Count a new session against the creation limits, or refuse it with a
+// THROTTLED error saying when to try again. Nothing is counted when refused.
+pub fn check_create(user_key: &str, ip: Option<&str>) -> Result<(), RedisError> {
+    let mut throttle = init_throttle().lock().map_err(|_| {
+        RedisError::String("Failed to acquire throttle lock".to_string())
+    })?;
+    let now = Instant::now();
+
+    if let Some(limit) = throttle.user {
+        if let Some(wait) = retry_after(throttle.by_user.get(user_key), limit, now) {
+            throttle.rejected_user += 1;
+            return Err(throttled("user key", user_key, wait));
+        }
+    }
+    if let (Some(limit), Some(ip)) = (throttle.ip, ip) {
+        if let Some(wait) = retry_after(throttle.by_ip.get(ip), limit, now) {
+            throttle.rejected_ip += 1;
+            return Err(throttled("client IP", ip, wait));
+        }
+    }
+
+    let throttle = &mut *throttle;
+    if let Some(limit) = throttle.user {
+        record(&mut throttle.by_user, user_key, limit, now);
+    }
+    if let (Some(limit), Some(ip)) = (throttle.ip, ip) {
+        record(&mut throttle.by_ip, ip, limit, now);
+    }
+    Ok(())
+}
+
+// Whether either creation limit is set
+pub fn is_enabled() -> bool {
+    init_throttle().lock().is_ok_and(|throttle| throttle.user.is_some() || throttle.ip.is_some())
+}
+
+// Forget user keys and IPs with no creation left in their window. Called by the expiry sweep.
+pub fn prune() {
+    let Ok(mut throttle) = init_throttle().lock() else { return };
+    let now = Instant::now();
+    let throttle = &mut *throttle;
+    for (log, limit) in [(&mut throttle.by_user, throttle.user), (&mut throttle.by_ip, throttle.ip)] {
+        match limit {
+            Some(limit) => log.retain(|_, entries| {
+                entries.back().is_some_and(|created| now.duration_since(*created) < limit.window)
+            }),
+            None => log.clear(),
+        }
+    }
+}
+
+fn parse_limit(args: &mut impl Iterator<Item = RedisString>) -> Result<Limit, RedisError> {
+    let limit = args.next_u64()?;
+    let window_secs = args.next_u64()?;
+    if limit == 0 || window_secs == 0 {
+        return Err(RedisError::Str("limit and window must be positive"));
+    }
+    Ok(Limit { limit: limit as usize, window: Duration::from_secs(window_secs) })
+}
+
+// Limit creations per user key (or per client IP) to `limit` per `window_secs`
+pub fn set_limit(by_ip: bool, limit: u64, window_secs: u64) -> Result<(), RedisError> {
+    if limit == 0 || window_secs == 0 {
+        return Err(RedisError::Str("limit and window must be positive"));
+    }
+    let mut throttle = init_throttle().lock().map_err(|_| {
+        RedisError::String("Failed to acquire throttle lock".to_string())
+    })?;
+    let limit = Some(Limit { limit: limit as usize, window: Duration::from_secs(window_secs) });
+    if by_ip {
+        throttle.ip = limit;
+    } else {
+        throttle.user = limit;
+    }
+    Ok(())
+}
+
+fn limit_reply(limit: Option<Limit>) -> (RedisValue, RedisValue) {
+    match limit {
+        Some(limit) => (RedisValue::Integer(limit.limit as i64), RedisValue::Integer(limit.window.as_secs() as i64)),
+        None => (RedisValue::Integer(0), RedisValue::Integer(0)),
+    }
+}
+
+// Rate-limit session creation:
+// SESSION.THROTTLE_CREATE USER limit window_seconds
+// SESSION.THROTTLE_CREATE IP limit window_seconds
+// SESSION.THROTTLE_CREATE OFF USER|IP
+// SESSION.THROTTLE_CREATE STATUS
+#[tracing::instrument(name = "session.throttle_create", skip_all)]
+pub fn session_throttle_create(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let subcommand = args.next_string()?.to_uppercase();
+    let mut throttle = init_throttle().lock().map_err(|_| {
+        RedisError::String("Failed to acquire throttle lock".to_string())
+    })?;
+
+    match subcommand.as_str() {
+        "USER" | "IP" => {
+            let limit = parse_limit(&mut args)?;
+            args.done()?;
+            if subcommand == "USER" {
+                throttle.user = Some(limit);
+            } else {
+                throttle.ip = Some(limit);
+            }
+            Ok(RedisValue::SimpleStringStatic("OK"))
+        },
+        "OFF" => {
+            let scope = args.next_string()?.to_uppercase();
+            args.done()?;
+            match scope.as_str() {
+                "USER" => {
+                    throttle.user = None;
+                    throttle.by_user.clear();
+                },
+                "IP" => {
+                    throttle.ip = None;
+                    throttle.by_ip.clear();
+                },
+                _ => return Err(RedisError::String(format!("Unknown throttle scope: {}", scope))),
+            }
+            Ok(RedisValue::SimpleStringStatic("OK"))
+        },
+        "STATUS" => {
+            args.done()?;
+            let (user_limit, user_window) = limit_reply(throttle.user);
+            let (ip_limit, ip_window) = limit_reply(throttle.ip);
+            Ok(RedisValue::Array(vec![
+                RedisValue::SimpleStringStatic("user_limit"),
+                user_limit,
+                RedisValue::SimpleStringStatic("user_window_secs"),
+                user_window,
+                RedisValue::SimpleStringStatic("ip_limit"),
+                ip_limit,
+                RedisValue::SimpleStringStatic("ip_window_secs"),
+                ip_window,
+                RedisValue::SimpleStringStatic("tracked_user_keys"),
+                RedisValue::Integer(throttle.by_user.len() as i64),
+                RedisValue::SimpleStringStatic("tracked_ips"),
+                RedisValue::Integer(throttle.by_ip.len() as i64),
+                RedisValue::SimpleStringStatic("rejected_user"),
+                RedisValue::Integer(throttle.rejected_user as i64),
+                RedisValue::SimpleStringStatic("rejected_ip"),
+                RedisValue::Integer(throttle.rejected_ip as i64),
+            ]))
+        },
+        _ => Err(RedisError::String(format!("Unknown SESSION.THROTTLE_CREATE subcommand: {}", subcommand))),
+    }
+}
@@ -0,0 +1,65 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+// Each item of backlog beyond this many lowers an idle timer's ceiling one step
+const BACKLOG_STEP: u64 = 1_000;
+
+// A background timer interval that tunes itself: it halves whenever a run
+// found work and grows by half while runs come up empty. The idle ceiling
+// shrinks as the backlog the timer watches (e.g. the store) grows, so a
+// large store is never left unswept for long.
+pub struct AdaptiveInterval {
+    name: &'static str,
+    current_ms: AtomicU64,
+    min_ms: u64,
+    max_ms: u64,
+}
+
+impl AdaptiveInterval {
+    const fn new(name: &'static str, min_ms: u64, initial_ms: u64, max_ms: u64) -> AdaptiveInterval {
+        AdaptiveInterval { name, current_ms: AtomicU64::new(initial_ms), min_ms, max_ms }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn current(&self) -> Duration {
+        Duration::from_millis(self.current_ms.load(Ordering::Relaxed))
+    }
+
+    // Record how much a run did and how large its backlog is; returns the
+    // delay until the next run
+    pub fn next(&self, work: usize, backlog: usize) -> Duration {
+        let current = self.current_ms.load(Ordering::Relaxed);
+        let ceiling = (self.max_ms / (1 + backlog as u64 / BACKLOG_STEP)).max(self.min_ms);
+
+        let next = if work > 0 {
+            current / 2
+        } else {
+            current + current / 2
+        }
+        .clamp(self.min_ms, ceiling);
+
+        self.current_ms.store(next, Ordering::Relaxed);
+        Duration::from_millis(next)
+    }
+}
+
+// Expired-session removal and expiry warnings
+pub static EXPIRY_SWEEP: AdaptiveInterval = AdaptiveInterval::new("expiry_sweep", 100, 1_000, 5_000);
+// Failed bridge write retries
+pub static RETRY: AdaptiveInterval = AdaptiveInterval::new("retry", 100, 500, 5_000);
+// Promotion of native key mappings back into the hashmap
+pub static PROMOTE: AdaptiveInterval = AdaptiveInterval::new("promote", 250, 1_000, 10_000);
+
+// Shipping of session changes to a standby
+pub static REPLICATE: AdaptiveInterval = AdaptiveInterval::new("replicate", 50, 200, 2_000);
+
+// Reconciliation of session expiries with the TTLs of their bound native keys
+pub static TTL_SYNC: AdaptiveInterval = AdaptiveInterval::new("ttl_sync", 250, 1_000, 10_000);
+
+// Publishing of lifecycle events to pub/sub and keyspace notifications
+pub static NOTIFY: AdaptiveInterval = AdaptiveInterval::new("notify", 10, 100, 1_000);
+
+pub static ALL: [&AdaptiveInterval; 6] = [&EXPIRY_SWEEP, &RETRY, &PROMOTE, &REPLICATE, &TTL_SYNC, &NOTIFY];
@@ -0,0 +1,400 @@
+use std::collections::HashMap;
+use redis_module::{Context, NextArg, RedisError, RedisResult, RedisString, RedisValue};
+use serde_json::{Map, Value};
+
+use crate::{activity, encryption, fieldtypes, hooks, init_sessions, next_utf8, spill, writable_session, write_session_field, DataValue, FieldType, SessionExt};
+
+// Separates the segments of a dotted field path (cart.items.0.sku)
+const SEPARATOR: char = '.';
+
+// `cart.*` selects the subtree under `cart`; `*` selects everything
+pub fn subtree_prefix(field: &str) -> Option<&str> {
+    if field == "*" {
+        Some("")
+    } else {
+        field.strip_suffix(".*")
+    }
+}
+
+// Whether a stored field lies strictly below `prefix`
+pub fn in_subtree(field: &str, prefix: &str) -> bool {
+    prefix.is_empty() || field.strip_prefix(prefix).is_some_and(|rest| rest.starts_with(SEPARATOR))
+}
+
+fn validate_path(path: &str) -> Result<(), RedisError> {
+    if path.split(SEPARATOR).any(|segment| segment.is_empty() || segment == "*") {
+        return Err(RedisError::String(format!("Invalid field path: {}", path)));
+    }
+    Ok(())
+}
+
+// Reject a path that would nest under an existing value or replace an existing subtree
+pub fn check_conflict(data: &HashMap<String, String>, path: &str) -> Result<(), RedisError> {
+    for (end, _) in path.match_indices(SEPARATOR) {
+        if data.contains_key(&path[..end]) {
+            return Err(RedisError::String(format!("Field {} holds a value, not a subtree", &path[..end])));
+        }
+    }
+    if data.keys().any(|field| in_subtree(field, path)) {
+        return Err(RedisError::String(format!("Field {} holds a subtree, not a value", path)));
+    }
+    Ok(())
+}
+
+// Turn objects keyed 0..n into arrays, recursively
+fn into_arrays(value: Value) -> Value {
+    match value {
+        Value::Object(map) => {
+            let is_array = !map.is_empty()
+                && (0..map.len()).all(|index| map.contains_key(&index.to_string()));
+            if is_array {
+                let mut map = map;
+                Value::Array((0..map.len())
+                    .map(|index| into_arrays(map.remove(&index.to_string()).unwrap_or(Value::Null)))
+                    .collect())
+            } else {
+                Value::Object(map.into_iter().map(|(key, value)| (key, into_arrays(value))).collect())
+            }
+        },
+        other => other,
+    }
+}
+
+// Nested JSON for the fields under `prefix`, or None if there are none.
+// Typed fields keep their JSON type.
+pub fn subtree_json(data: &HashMap<String, String>, types: &HashMap<String, FieldType>, prefix: &str) -> Option<String> {
+    let mut fields: Vec<(&String, &String)> = data.iter()
+        .filter(|(field, _)| in_subtree(field, prefix))
+        .collect();
+    if fields.is_empty() {
+        return None;
+    }
+    // Deeper paths are applied last, so they win over flat values written with ADD_DATA
+    fields.sort_unstable_by(|a, b| a.0.cmp(b.0));
+
+    let mut root = Map::new();
+    for (field, value) in fields {
+        let relative = if prefix.is_empty() { field.as_str() } else { &field[prefix.len() + 1..] };
+        let mut segments: Vec<&str> = relative.split(SEPARATOR).collect();
+        let leaf = segments.pop().unwrap_or_default();
+
+        let mut node = &mut root;
+        for segment in segments {
+            let child = node.entry(segment.to_string()).or_insert_with(|| Value::Object(Map::new()));
+            if !child.is_object() {
+                *child = Value::Object(Map::new());
+            }
+            node = child.as_object_mut().unwrap();
+        }
+        let field_type = types.get(field).copied().unwrap_or_default();
+        node.insert(leaf.to_string(), DataValue::read(value, field_type).to_json());
+    }
+
+    Some(into_arrays(Value::Object(root)).to_string())
+}
+
+// One step of a merge patch, applied to the flat data map in order
+#[derive(Debug, PartialEq)]
+enum PatchOp {
+    // Remove the value at a path and everything below it
+    Delete(String),
+    // Remove the value at a path, which is becoming a subtree
+    DeleteLeaf(String),
+    Set(String, String, FieldType),
+}
+
+// Translate the patch for the subtree at `path` into steps (RFC 7396):
+// null removes, objects merge into the subtree, anything else replaces it.
+// Arrays become subtrees keyed 0..n, except an empty one, which is stored as
+// a JSON value since it has no fields to hold it. Numbers and booleans are
+// stored as text that keeps their type. An empty object merges nothing, so
+// unlike RFC 7396 it doesn't turn a plain value into `{}`: it removes it.
+fn patch_ops(path: String, patch: Value, ops: &mut Vec<PatchOp>) -> Result<(), RedisError> {
+    let child = |key: &str| if path.is_empty() { key.to_string() } else { format!("{}{}{}", path, SEPARATOR, key) };
+    match patch {
+        Value::Null => ops.push(PatchOp::Delete(path)),
+        Value::Object(members) => {
+            if !path.is_empty() {
+                ops.push(PatchOp::DeleteLeaf(path.clone()));
+            }
+            for (key, value) in members {
+                if key.contains(SEPARATOR) {
+                    return Err(RedisError::String(format!("Invalid field name in patch: {}", key)));
+                }
+                let field = child(&key);
+                validate_path(&field)?;
+                patch_ops(field, value, ops)?;
+            }
+        },
+        Value::Array(items) if items.is_empty() => {
+            ops.push(PatchOp::Delete(path.clone()));
+            ops.push(PatchOp::Set(path, DataValue::Json(Value::Array(items)).into_text(), FieldType::Json));
+        },
+        Value::Array(items) => {
+            ops.push(PatchOp::Delete(path.clone()));
+            for (index, item) in items.into_iter().enumerate() {
+                patch_ops(child(&index.to_string()), item, ops)?;
+            }
+        },
+        value => {
+            let value = DataValue::from_json(value);
+            let field_type = value.field_type();
+            ops.push(PatchOp::Delete(path.clone()));
+            ops.push(PatchOp::Set(path, value.into_text(), field_type));
+        },
+    }
+    Ok(())
+}
+
+// Apply a JSON merge patch (RFC 7396) to a session's data in one step:
+// SESSION.PATCH session_id patch
+// The patch is a JSON object addressing nested data by dotted path, e.g.
+// {"cart":{"qty":2},"promo":null} sets cart.qty and removes promo with its subtree.
+#[tracing::instrument(name = "session.patch", skip_all)]
+pub fn patch_session_data(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let session_id = args.next_string()?;
+    let patch = next_utf8(&mut args, "Patch")?;
+    args.done()?;
+
+    let patch: Value = serde_json::from_str(&patch)
+        .map_err(|e| RedisError::String(format!("Invalid JSON patch: {}", e)))?;
+    if !patch.is_object() {
+        return Err(RedisError::Str("Patch must be a JSON object"));
+    }
+    let mut ops = Vec::new();
+    patch_ops(String::new(), patch, &mut ops)?;
+
+    // Let an on_add_data hook veto any of the writes or add derived fields
+    let mut derived = HashMap::new();
+    for op in &ops {
+        if let PatchOp::Set(field, value, _) = op {
+            let event = serde_json::json!({ "event": "add_data", "session_id": session_id, "field": field, "value": value });
+            match hooks::run_hook("on_add_data", &event)? {
+                hooks::HookOutcome::Veto => return Err(RedisError::String(format!("Write of {} vetoed by hook", field))),
+                hooks::HookOutcome::Allow(fields) => derived.extend(fields),
+            }
+        }
+    }
+
+    let mut sessions_map = init_sessions().write().map_err(|_| {
+        RedisError::String("Failed to acquire write lock".to_string())
+    })?;
+    let session = writable_session(&mut sessions_map, &session_id)?;
+
+    // Check types and encrypt before touching the data, so a refused write or
+    // a missing key leaves the session as it was
+    let mut writes = Vec::new();
+    for op in ops {
+        writes.push(match op {
+            PatchOp::Set(field, value, field_type) => {
+                let (value, field_type) = fieldtypes::check(session, &field, value, field_type)?;
+                let value = encryption::seal(session, &field, value)?;
+                PatchOp::Set(field, value, field_type)
+            },
+            other => other,
+        });
+    }
+    let derived = derived.into_iter()
+        .map(|(field, value)| {
+            let (value, field_type) = fieldtypes::check(session, &field, value, FieldType::String)?;
+            Ok((field.clone(), encryption::seal(session, &field, value)?, field_type))
+        })
+        .collect::<Result<Vec<_>, RedisError>>()?;
+
+    // Store large values next, all at once, so a failed write does too
+    let values = writes.iter()
+        .filter_map(|op| match op {
+            PatchOp::Set(field, value, _) => Some((field.clone(), value.clone())),
+            _ => None,
+        })
+        .chain(derived.iter().map(|(field, value, _)| (field.clone(), value.clone())))
+        .collect();
+    let mut stored = spill::store_all(ctx, session, values)?.into_iter();
+
+    // Nothing below can fail
+    for op in writes {
+        match op {
+            PatchOp::Delete(path) => {
+                session.remove_fields(|field| field == path || in_subtree(field, &path));
+            },
+            PatchOp::DeleteLeaf(path) => {
+                session.remove_fields(|field| field == path);
+            },
+            PatchOp::Set(field, _, field_type) => {
+                session.set_field(field, stored.next().unwrap_or_default(), field_type);
+            },
+        }
+    }
+    for (field, _, field_type) in derived {
+        session.set_field(field, stored.next().unwrap_or_default(), field_type);
+    }
+    spill::prune(session);
+    session.touch();
+    activity::record(&session_id);
+    session.mark_changed();
+    Ok(RedisValue::SimpleStringStatic("OK"))
+}
+
+// Set a value at a dotted path: SESSION.SET_DATA session_id path value
+#[tracing::instrument(name = "session.set_data", skip_all)]
+pub fn set_session_data(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let session_id = args.next_string()?;
+    let path = next_utf8(&mut args, "Path")?;
+    let value = next_utf8(&mut args, "Value")?;
+    args.done()?;
+
+    validate_path(&path)?;
+    write_session_field(ctx, session_id, path, DataValue::String(value), true)
+}
+
+// Delete a field or a whole subtree: SESSION.DEL_DATA session_id field|path.*
+#[tracing::instrument(name = "session.del_data", skip_all)]
+pub fn del_session_data(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let session_id = args.next_string()?;
+    let field = args.next_string()?;
+    args.done()?;
+
+    let sessions = init_sessions();
+    let mut sessions_map = sessions.write().map_err(|_| {
+        RedisError::String("Failed to acquire write lock".to_string())
+    })?;
+
+    let session = writable_session(&mut sessions_map, &session_id)?;
+
+    let removed = match subtree_prefix(&field) {
+        Some(prefix) => session.remove_fields(|key| in_subtree(key, prefix)),
+        None => session.remove_fields(|key| key == field),
+    };
+    spill::prune(session);
+
+    session.touch();
+    activity::record(&session_id);
+    if removed > 0 {
+        session.mark_changed();
+    }
+
+    Ok(RedisValue::Integer(removed as i64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn data(fields: &[(&str, &str)]) -> HashMap<String, String> {
+        fields.iter().map(|(field, value)| (field.to_string(), value.to_string())).collect()
+    }
+
+    fn ops(patch: Value) -> Result<Vec<PatchOp>, RedisError> {
+        let mut ops = Vec::new();
+        patch_ops(String::new(), patch, &mut ops).map(|_| ops)
+    }
+
+    #[test]
+    fn selects_subtrees() {
+        assert_eq!(subtree_prefix("*"), Some(""));
+        assert_eq!(subtree_prefix("cart.*"), Some("cart"));
+        assert_eq!(subtree_prefix("cart"), None);
+        assert_eq!(subtree_prefix("cart*"), None);
+
+        assert!(in_subtree("cart.qty", "cart"));
+        assert!(in_subtree("cart.items.0", "cart"));
+        assert!(in_subtree("anything", ""));
+        assert!(!in_subtree("cart", "cart"));
+        assert!(!in_subtree("cartography.x", "cart"));
+    }
+
+    #[test]
+    fn validates_paths() {
+        assert!(validate_path("cart.items.0.sku").is_ok());
+        assert!(validate_path("").is_err());
+        assert!(validate_path("cart.").is_err());
+        assert!(validate_path(".cart").is_err());
+        assert!(validate_path("cart..qty").is_err());
+        assert!(validate_path("cart.*").is_err());
+    }
+
+    #[test]
+    fn refuses_conflicting_paths() {
+        let data = data(&[("cart", "full"), ("user.name", "ann")]);
+        assert!(check_conflict(&data, "cart.qty").is_err());
+        assert!(check_conflict(&data, "user").is_err());
+        assert!(check_conflict(&data, "user.age").is_ok());
+        assert!(check_conflict(&data, "cart").is_ok());
+        assert!(check_conflict(&data, "username").is_ok());
+    }
+
+    #[test]
+    fn builds_nested_json() {
+        let data = data(&[
+            ("cart.items.0.sku", "A"),
+            ("cart.items.1.sku", "B"),
+            ("cart.qty", "2"),
+            ("cart.open", "true"),
+            ("other", "x"),
+        ]);
+        let types = HashMap::from([
+            ("cart.qty".to_string(), FieldType::Int),
+            ("cart.open".to_string(), FieldType::Boolean),
+        ]);
+
+        let cart: Value = serde_json::from_str(&subtree_json(&data, &types, "cart").unwrap()).unwrap();
+        assert_eq!(cart, json!({"items": [{"sku": "A"}, {"sku": "B"}], "qty": 2, "open": true}));
+        let all: Value = serde_json::from_str(&subtree_json(&data, &types, "").unwrap()).unwrap();
+        assert_eq!(all["other"], json!("x"));
+        assert_eq!(subtree_json(&data, &types, "missing"), None);
+        assert_eq!(subtree_json(&data, &types, "other"), None);
+    }
+
+    #[test]
+    fn keeps_sparse_indexes_as_objects() {
+        assert_eq!(into_arrays(json!({"0": "a", "2": "c"})), json!({"0": "a", "2": "c"}));
+        assert_eq!(into_arrays(json!({"1": "b", "0": {"0": 1}})), json!([[1], "b"]));
+        assert_eq!(into_arrays(json!({})), json!({}));
+    }
+
+    #[test]
+    fn translates_merge_patches() {
+        assert_eq!(ops(json!({"promo": null, "cart": {"qty": 2}})).unwrap(), vec![
+            PatchOp::DeleteLeaf("cart".to_string()),
+            PatchOp::Delete("cart.qty".to_string()),
+            PatchOp::Set("cart.qty".to_string(), "2".to_string(), FieldType::Int),
+            PatchOp::Delete("promo".to_string()),
+        ]);
+        assert_eq!(ops(json!({"tags": ["a", 1.5]})).unwrap(), vec![
+            PatchOp::Delete("tags".to_string()),
+            PatchOp::Delete("tags.0".to_string()),
+            PatchOp::Set("tags.0".to_string(), "a".to_string(), FieldType::String),
+            PatchOp::Delete("tags.1".to_string()),
+            PatchOp::Set("tags.1".to_string(), "1.5".to_string(), FieldType::Float),
+        ]);
+        assert_eq!(ops(json!({"ok": false})).unwrap(), vec![
+            PatchOp::Delete("ok".to_string()),
+            PatchOp::Set("ok".to_string(), "false".to_string(), FieldType::Boolean),
+        ]);
+        assert!(ops(json!({})).unwrap().is_empty());
+    }
+
+    #[test]
+    fn keeps_empty_arrays() {
+        assert_eq!(ops(json!({"tags": []})).unwrap(), vec![
+            PatchOp::Delete("tags".to_string()),
+            PatchOp::Set("tags".to_string(), "[]".to_string(), FieldType::Json),
+        ]);
+
+        let data = data(&[("cart.tags", "[]")]);
+        let types = HashMap::from([("cart.tags".to_string(), FieldType::Json)]);
+        let cart: Value = serde_json::from_str(&subtree_json(&data, &types, "cart").unwrap()).unwrap();
+        assert_eq!(cart, json!({"tags": []}));
+    }
+
+    #[test]
+    fn refuses_invalid_patch_keys() {
+        assert!(ops(json!({"cart.qty": 2})).is_err());
+        assert!(ops(json!({"cart": {"": 2}})).is_err());
+        assert!(ops(json!({"*": 1})).is_err());
+    }
+}
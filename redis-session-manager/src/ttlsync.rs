@@ -0,0 +1,187 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use chrono::{DateTime, Utc};
+use redis_module::{Context, NextArg, RedisError, RedisResult, RedisString, RedisValue};
+use session_core::{TtlBinding, TtlSource};
+
+use crate::timers::TTL_SYNC;
+use crate::{expiry, init_sessions, writable_session, SessionExt};
+
+// Expiries this close together count as in step; PTTL and the clock move while we compare
+const TOLERANCE_MS: i64 = 1_000;
+
+static CORRECTIONS: AtomicU64 = AtomicU64::new(0);
+
+// Expiries moved by reconciliation since the module was loaded, on either side
+pub fn corrections() -> u64 {
+    CORRECTIONS.load(Ordering::Relaxed)
+}
+
+// A bound session as it was when the sync started
+struct Bound {
+    session_id: String,
+    binding: TtlBinding,
+    expires_at: Option<DateTime<Utc>>,
+    deadline: Option<DateTime<Utc>>,
+}
+
+// When a native key expires: None if it doesn't exist, Some(None) if it never does
+fn key_expiry(ctx: &Context, key: &str, now: DateTime<Utc>) -> Result<Option<Option<DateTime<Utc>>>, RedisError> {
+    match ctx.call("PTTL", &[key])? {
+        RedisValue::Integer(-2) => Ok(None),
+        RedisValue::Integer(-1) => Ok(Some(None)),
+        RedisValue::Integer(ms) => Ok(Some(Some(now + chrono::Duration::milliseconds(ms)))),
+        other => Err(RedisError::String(format!("Unexpected PTTL reply: {:?}", other))),
+    }
+}
+
+fn drifted(a: Option<DateTime<Utc>>, b: Option<DateTime<Utc>>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => (a - b).num_milliseconds().abs() > TOLERANCE_MS,
+        (None, None) => false,
+        _ => true,
+    }
+}
+
+// Bring each session and its key back in step. Keys are read and written with
+// the sessions lock released; sessions the key decides for are updated after.
+// Returns how many expiries were moved.
+fn sync(ctx: &Context, bound: Vec<Bound>) -> usize {
+    let now = Utc::now();
+    let mut updates = Vec::new();
+    let mut moved = 0;
+
+    for Bound { session_id, binding, expires_at, deadline } in bound {
+        let key_expires_at = match key_expiry(ctx, &binding.key, now) {
+            Ok(key_expires_at) => key_expires_at,
+            Err(err) => {
+                ctx.log_warning(&format!("Failed to read TTL of {} for session {}: {}", binding.key, session_id, err));
+                continue;
+            },
+        };
+        match binding.source {
+            TtlSource::Key => {
+                // A missing key ends the session; a deadline still caps it
+                let wanted = match key_expires_at {
+                    None => Some(now),
+                    Some(key_expires_at) => match (key_expires_at, deadline) {
+                        (Some(at), Some(deadline)) => Some(at.min(deadline)),
+                        (at, deadline) => at.or(deadline),
+                    },
+                };
+                if drifted(expires_at, wanted) {
+                    updates.push((session_id, binding, wanted));
+                }
+            },
+            TtlSource::Session => {
+                // There is nothing to expire until the key is written again
+                let Some(key_expires_at) = key_expires_at else { continue };
+                if !drifted(key_expires_at, expires_at) {
+                    continue;
+                }
+                let result = match expires_at {
+                    Some(at) => ctx.call("PEXPIREAT", &[binding.key.as_str(), &at.timestamp_millis().to_string()]),
+                    None => ctx.call("PERSIST", &[binding.key.as_str()]),
+                };
+                match result {
+                    Ok(_) => moved += 1,
+                    Err(err) => ctx.log_warning(&format!("Failed to sync TTL of {} to session {}: {}", binding.key, session_id, err)),
+                }
+            },
+        }
+    }
+
+    if !updates.is_empty() {
+        if let Ok(mut sessions_map) = init_sessions().write() {
+            for (session_id, binding, wanted) in updates {
+                let Some(session) = sessions_map.get_mut(&session_id) else { continue };
+                // Rebound or unbound while the key was read
+                if session.ttl_binding.as_ref() != Some(&binding) {
+                    continue;
+                }
+                session.expires_at = wanted;
+                session.expiry_warned = false;
+                expiry::cap_lifetime(session);
+                session.mark_changed();
+                moved += 1;
+            }
+        }
+    }
+
+    CORRECTIONS.fetch_add(moved as u64, Ordering::Relaxed);
+    moved
+}
+
+fn reconcile(ctx: &Context, _data: ()) {
+    let bound: Vec<Bound> = match init_sessions().read() {
+        Ok(sessions_map) => sessions_map.values()
+            .filter_map(|session| session.ttl_binding.clone().map(|binding| Bound {
+                session_id: session.id.clone(),
+                binding,
+                expires_at: session.expires_at,
+                deadline: session.deadline,
+            }))
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    let count = bound.len();
+    let moved = sync(ctx, bound);
+
+    // Come back sooner while expiries drift, back off while they agree
+    ctx.create_timer(TTL_SYNC.next(moved, count), reconcile, ());
+}
+
+// Start TTL reconciliation at module load
+pub fn start(ctx: &Context) {
+    ctx.create_timer(TTL_SYNC.current(), reconcile, ());
+}
+
+// Keep a session's expiry in step with a native key's TTL:
+// SESSION.BIND_TTL_TO_KEY session_id [key [FROM_KEY|FROM_SESSION]]
+// FROM_KEY (the default) copies the key's TTL onto the session, so EXPIRE on
+// the key reaches the session; FROM_SESSION copies the session's expiry onto
+// the key. Both are brought in step at once and then whenever they drift.
+// Without a key the binding is removed: replies 1 if there was one, else 0.
+#[tracing::instrument(name = "session.bind_ttl_to_key", skip_all)]
+pub fn session_bind_ttl_to_key(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let session_id = args.next_string()?;
+    let key = args.next_string().ok();
+    let source = match args.next_string() {
+        Ok(option) if option.eq_ignore_ascii_case("FROM_KEY") => TtlSource::Key,
+        Ok(option) if option.eq_ignore_ascii_case("FROM_SESSION") => TtlSource::Session,
+        Ok(option) => return Err(RedisError::String(format!("Unknown option: {}", option))),
+        Err(_) => TtlSource::Key,
+    };
+    args.done()?;
+
+    let mut sessions_map = init_sessions().write().map_err(|_| {
+        RedisError::String("Failed to acquire write lock".to_string())
+    })?;
+    let session = writable_session(&mut sessions_map, &session_id)?;
+
+    let Some(key) = key else {
+        let unbound = session.ttl_binding.take().is_some();
+        if unbound {
+            session.mark_changed();
+        }
+        return Ok(RedisValue::Integer(if unbound { 1 } else { 0 }));
+    };
+
+    let binding = TtlBinding { key, source };
+    if source == TtlSource::Key {
+        // Accesses must not slide an expiry the key decides
+        session.idle_timeout = None;
+    }
+    session.ttl_binding = Some(binding.clone());
+    session.mark_changed();
+    let bound = Bound {
+        session_id,
+        binding,
+        expires_at: session.expires_at,
+        deadline: session.deadline,
+    };
+    drop(sessions_map);
+
+    sync(ctx, vec![bound]);
+    Ok(RedisValue::SimpleStringStatic("OK"))
+}
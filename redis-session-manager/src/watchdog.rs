@@ -0,0 +1,224 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+use chrono::{DateTime, Utc};
+use redis_module::{RedisError, RedisValue};
+
+use crate::http::Metric;
+
+// Timeout events kept for SESSION.BRIDGE EVENTS
+const RECENT_EVENTS: usize = 32;
+
+// Bridge operation deadline in milliseconds; 0 runs FFI calls inline with no deadline
+static TIMEOUT_MS: AtomicU64 = AtomicU64::new(0);
+
+static TIMEOUTS: AtomicU64 = AtomicU64::new(0);
+static SLOW_CALLS: AtomicU64 = AtomicU64::new(0);
+
+// The worker is running a call, and whether a caller gave up waiting on it
+static RUNNING: AtomicBool = AtomicBool::new(false);
+static ABANDONED: AtomicBool = AtomicBool::new(false);
+
+// Operation of the last call a caller gave up waiting on
+static ABANDONED_OP: Mutex<Option<&'static str>> = Mutex::new(None);
+
+// A call that timed out is still running on the worker
+pub fn worker_stuck() -> bool {
+    ABANDONED.load(Ordering::Relaxed) && RUNNING.load(Ordering::Relaxed)
+}
+
+// The operation of the timed-out call still running on the worker, if any
+pub fn stuck_op() -> Option<&'static str> {
+    if !worker_stuck() {
+        return None;
+    }
+    ABANDONED_OP.lock().ok().and_then(|op| *op)
+}
+
+type Job = Box<dyn FnOnce() + Send>;
+
+// Thread FFI calls run on while a timeout is configured, started on first use
+static WORKER: Mutex<Option<Sender<Job>>> = Mutex::new(None);
+
+// A bridge operation that overran the deadline
+struct Event {
+    op: &'static str,
+    path: &'static str,
+    elapsed_ms: u64,
+    // FFI calls are abandoned; command calls can only be reported afterwards
+    aborted: bool,
+    at: DateTime<Utc>,
+}
+
+static EVENTS: Mutex<VecDeque<Event>> = Mutex::new(VecDeque::new());
+
+fn record(op: &'static str, path: &'static str, elapsed: Duration, aborted: bool) {
+    if let Ok(mut events) = EVENTS.lock() {
+        if events.len() == RECENT_EVENTS {
+            events.pop_front();
+        }
+        events.push_back(Event {
+            op,
+            path,
+            elapsed_ms: elapsed.as_millis() as u64,
+            aborted,
+            at: Utc::now(),
+        });
+    }
+}
+
+fn submit(job: Job) -> Result<(), RedisError> {
+    let mut worker = WORKER.lock().map_err(|_| {
+        RedisError::String("Failed to acquire bridge worker lock".to_string())
+    })?;
+
+    if worker.is_none() {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        thread::Builder::new()
+            .name("session-bridge-ffi".to_string())
+            .spawn(move || {
+                for job in receiver {
+                    job();
+                }
+            })
+            .map_err(|e| RedisError::String(format!("Failed to start bridge worker: {}", e)))?;
+        *worker = Some(sender);
+    }
+
+    if let Some(Err(_)) = worker.as_ref().map(|sender| sender.send(job)) {
+        // The worker died; start a fresh one next time
+        *worker = None;
+        return Err(RedisError::Str("Bridge worker exited"));
+    }
+    Ok(())
+}
+
+// Run an FFI call under the configured deadline. The call runs on a worker
+// thread so a hung hashmap can't stall the event loop; while a timed-out
+// call is still stuck there, further FFI calls fail straight away and the
+// router falls back to commands.
+pub fn run_ffi<T: Send + 'static>(
+    op: &'static str,
+    call: impl FnOnce() -> Result<T, RedisError> + Send + 'static,
+) -> Result<T, RedisError> {
+    let timeout_ms = TIMEOUT_MS.load(Ordering::Relaxed);
+    if timeout_ms == 0 {
+        return call();
+    }
+    if worker_stuck() {
+        return Err(RedisError::String(format!("FFI {} skipped: an earlier call is still stuck", op)));
+    }
+
+    let start = Instant::now();
+    let (sender, receiver) = mpsc::channel();
+    submit(Box::new(move || {
+        RUNNING.store(true, Ordering::Relaxed);
+        let result = call();
+        RUNNING.store(false, Ordering::Relaxed);
+        ABANDONED.store(false, Ordering::Relaxed);
+        let _ = sender.send(result);
+    }))?;
+
+    match receiver.recv_timeout(Duration::from_millis(timeout_ms)) {
+        Ok(result) => result,
+        Err(RecvTimeoutError::Timeout) => {
+            if let Ok(mut abandoned) = ABANDONED_OP.lock() {
+                *abandoned = Some(op);
+            }
+            ABANDONED.store(true, Ordering::Relaxed);
+            TIMEOUTS.fetch_add(1, Ordering::Relaxed);
+            record(op, "ffi", start.elapsed(), true);
+            Err(RedisError::String(format!("FFI {} timed out after {}ms", op, timeout_ms)))
+        },
+        Err(RecvTimeoutError::Disconnected) => Err(RedisError::Str("Bridge worker exited")),
+    }
+}
+
+// Account for a command call that took longer than the deadline
+pub fn check_call(op: &'static str, elapsed: Duration) {
+    let timeout_ms = TIMEOUT_MS.load(Ordering::Relaxed);
+    if timeout_ms > 0 && elapsed > Duration::from_millis(timeout_ms) {
+        SLOW_CALLS.fetch_add(1, Ordering::Relaxed);
+        record(op, "call", elapsed, false);
+    }
+}
+
+pub fn timeout_ms() -> u64 {
+    TIMEOUT_MS.load(Ordering::Relaxed)
+}
+
+pub fn set_timeout(timeout_ms: u64) {
+    TIMEOUT_MS.store(timeout_ms, Ordering::Relaxed);
+}
+
+// Fields for SESSION.BRIDGE STATUS
+pub fn status() -> Vec<RedisValue> {
+    vec![
+        RedisValue::SimpleStringStatic("timeout_ms"),
+        RedisValue::Integer(TIMEOUT_MS.load(Ordering::Relaxed) as i64),
+        RedisValue::SimpleStringStatic("timeouts"),
+        RedisValue::Integer(TIMEOUTS.load(Ordering::Relaxed) as i64),
+        RedisValue::SimpleStringStatic("slow_calls"),
+        RedisValue::Integer(SLOW_CALLS.load(Ordering::Relaxed) as i64),
+        RedisValue::SimpleStringStatic("worker_stuck"),
+        RedisValue::Integer(if worker_stuck() { 1 } else { 0 }),
+    ]
+}
+
+// Deadline metrics for the HTTP status endpoint
+pub fn metrics() -> Vec<Metric> {
+    vec![
+        Metric::counter("session_manager_bridge_timeouts_total", "FFI calls abandoned at the deadline", TIMEOUTS.load(Ordering::Relaxed)),
+        Metric::counter("session_manager_bridge_slow_calls_total", "Command calls that overran the deadline", SLOW_CALLS.load(Ordering::Relaxed)),
+        Metric::gauge("session_manager_bridge_worker_stuck", "Whether a timed-out FFI call is still running", if worker_stuck() { 1.0 } else { 0.0 }),
+    ]
+}
+
+// Recent overruns, newest first, for SESSION.BRIDGE EVENTS
+pub fn events() -> Vec<RedisValue> {
+    let events = match EVENTS.lock() {
+        Ok(events) => events,
+        Err(_) => return Vec::new(),
+    };
+    events.iter().rev()
+        .map(|event| RedisValue::Array(vec![
+            RedisValue::SimpleStringStatic("op"),
+            RedisValue::SimpleStringStatic(event.op),
+            RedisValue::SimpleStringStatic("path"),
+            RedisValue::SimpleStringStatic(event.path),
+            RedisValue::SimpleStringStatic("elapsed_ms"),
+            RedisValue::Integer(event.elapsed_ms as i64),
+            RedisValue::SimpleStringStatic("aborted"),
+            RedisValue::Integer(if event.aborted { 1 } else { 0 }),
+            RedisValue::SimpleStringStatic("at"),
+            RedisValue::BulkString(event.at.to_rfc3339()),
+        ]))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn abandons_calls_past_the_deadline() {
+        set_timeout(20);
+        let slow = run_ffi("set", || {
+            thread::sleep(Duration::from_millis(200));
+            Ok(())
+        });
+        assert!(slow.is_err());
+        assert_eq!(stuck_op(), Some("set"));
+        // Further calls are skipped while the abandoned one runs
+        assert!(run_ffi("get", || Ok(1)).is_err());
+
+        thread::sleep(Duration::from_millis(300));
+        assert_eq!(stuck_op(), None);
+        assert_eq!(run_ffi("get", || Ok(1)).unwrap(), 1);
+        assert!(!events().is_empty());
+        set_timeout(0);
+    }
+}
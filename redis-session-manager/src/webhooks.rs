@@ -0,0 +1,373 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Condvar, Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+use redis_module::{Context, NextArg, RedisError, RedisResult, RedisString, RedisValue};
+use session_core::events::{self, SessionEvent};
+
+use crate::notify;
+
+// Attempts (including the first one) before a delivery is dead-lettered
+const MAX_ATTEMPTS: u32 = 5;
+
+// Delay before the first retry; doubles with every further attempt
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(1);
+
+// Deliveries waiting to be sent; events beyond this are dropped
+const MAX_PENDING: usize = 10_000;
+
+// Dead-lettered deliveries kept for inspection; the oldest are dropped first
+const MAX_DEAD_LETTERS: usize = 1_000;
+
+// Connect, write and read timeout of a single POST
+const HTTP_TIMEOUT: Duration = Duration::from_secs(5);
+
+// A configured webhook
+struct Webhook {
+    url: String,
+    // Events to deliver; empty means all of them
+    events: Vec<String>,
+}
+
+// An event on its way to one webhook
+struct Delivery {
+    webhook: String,
+    url: String,
+    event: &'static str,
+    body: String,
+    attempts: u32,
+    next_attempt: Instant,
+    last_error: Option<String>,
+}
+
+// Deliveries waiting to be sent or retried, and those that ran out of attempts
+#[derive(Default)]
+struct Queue {
+    pending: VecDeque<Delivery>,
+    dead: VecDeque<Delivery>,
+}
+
+static DELIVERED: AtomicU64 = AtomicU64::new(0);
+static FAILED_ATTEMPTS: AtomicU64 = AtomicU64::new(0);
+static DEAD_LETTERED: AtomicU64 = AtomicU64::new(0);
+static DROPPED: AtomicU64 = AtomicU64::new(0);
+
+static mut WEBHOOKS: Option<RwLock<BTreeMap<String, Webhook>>> = None;
+static mut QUEUE: Option<(Mutex<Queue>, Condvar)> = None;
+
+// Initialize the webhook table
+fn init_webhooks() -> &'static RwLock<BTreeMap<String, Webhook>> {
+    unsafe {
+        if WEBHOOKS.is_none() {
+            WEBHOOKS = Some(RwLock::new(BTreeMap::new()));
+        }
+        WEBHOOKS.as_ref().unwrap()
+    }
+}
+
+// Initialize the delivery queue and the condition variable the dispatcher waits on
+fn init_queue() -> &'static (Mutex<Queue>, Condvar) {
+    unsafe {
+        if QUEUE.is_none() {
+            QUEUE = Some((Mutex::new(Queue::default()), Condvar::new()));
+        }
+        QUEUE.as_ref().unwrap()
+    }
+}
+
+// Split an http:// URL into (host:port, path)
+fn parse_url(url: &str) -> Result<(String, String), String> {
+    let rest = url.strip_prefix("http://")
+        .ok_or_else(|| format!("Only http:// webhook URLs are supported: {}", url))?;
+    let (authority, path) = match rest.find('/') {
+        Some(slash) => (&rest[..slash], &rest[slash..]),
+        None => (rest, "/"),
+    };
+    if authority.is_empty() {
+        return Err(format!("Webhook URL has no host: {}", url));
+    }
+    let address = if authority.contains(':') { authority.to_string() } else { format!("{}:80", authority) };
+    Ok((address, path.to_string()))
+}
+
+// POST a JSON body; any 2xx status counts as delivered
+fn post(url: &str, event: &str, body: &str) -> Result<(), String> {
+    let (address, path) = parse_url(url)?;
+    let socket_addr = address.to_socket_addrs()
+        .map_err(|e| format!("Failed to resolve {}: {}", address, e))?
+        .next()
+        .ok_or_else(|| format!("Failed to resolve {}", address))?;
+
+    let mut stream = TcpStream::connect_timeout(&socket_addr, HTTP_TIMEOUT)
+        .map_err(|e| format!("Failed to connect to {}: {}", address, e))?;
+    stream.set_read_timeout(Some(HTTP_TIMEOUT)).map_err(|e| e.to_string())?;
+    stream.set_write_timeout(Some(HTTP_TIMEOUT)).map_err(|e| e.to_string())?;
+
+    let host = address.split(':').next().unwrap_or_default();
+    let request = format!(
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nX-Session-Event: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        path, host, event, body.len(), body,
+    );
+    stream.write_all(request.as_bytes()).map_err(|e| format!("Failed to send to {}: {}", address, e))?;
+
+    let mut status_line = String::new();
+    BufReader::new(stream).read_line(&mut status_line)
+        .map_err(|e| format!("Failed to read response from {}: {}", address, e))?;
+    match status_line.split_whitespace().nth(1) {
+        Some(status) if status.starts_with('2') => Ok(()),
+        Some(status) => Err(format!("{} replied {}", address, status)),
+        None => Err(format!("{} sent no status line", address)),
+    }
+}
+
+// Webhooks configured
+pub fn count() -> usize {
+    init_webhooks().read().map_or(0, |webhooks| webhooks.len())
+}
+
+// Queue an event for every webhook subscribed to it. Called with the sessions
+// lock held, so it only copies the event; the dispatcher thread sends it.
+pub fn emit(event: &'static str, session_id: &str, user_key: &str) {
+    notify::queue(event, session_id, user_key);
+
+    let webhooks = match init_webhooks().read() {
+        Ok(webhooks) if !webhooks.is_empty() => webhooks,
+        _ => return,
+    };
+
+    let body = match serde_json::to_string(&SessionEvent::new(event, session_id, user_key)) {
+        Ok(body) => body,
+        Err(_) => return,
+    };
+
+    let (queue, wakeup) = init_queue();
+    let mut queue = match queue.lock() {
+        Ok(queue) => queue,
+        Err(_) => return,
+    };
+    for (name, webhook) in webhooks.iter() {
+        if !webhook.events.is_empty() && !webhook.events.iter().any(|subscribed| subscribed == event) {
+            continue;
+        }
+        if queue.pending.len() >= MAX_PENDING {
+            DROPPED.fetch_add(1, Ordering::Relaxed);
+            continue;
+        }
+        queue.pending.push_back(Delivery {
+            webhook: name.clone(),
+            url: webhook.url.clone(),
+            event,
+            body: body.clone(),
+            attempts: 0,
+            next_attempt: Instant::now(),
+            last_error: None,
+        });
+    }
+    wakeup.notify_one();
+}
+
+// Dispatcher loop: send due deliveries one at a time, outside the queue lock
+fn dispatch() {
+    let (queue, wakeup) = init_queue();
+    loop {
+        let mut delivery = {
+            let mut guard = match queue.lock() {
+                Ok(guard) => guard,
+                Err(_) => return,
+            };
+            loop {
+                let now = Instant::now();
+                if let Some(position) = guard.pending.iter().position(|delivery| delivery.next_attempt <= now) {
+                    break guard.pending.remove(position).unwrap();
+                }
+                // Sleep until the next retry is due or a new event arrives
+                let wait = guard.pending.iter()
+                    .map(|delivery| delivery.next_attempt.saturating_duration_since(now))
+                    .min()
+                    .unwrap_or(Duration::from_secs(60));
+                guard = match wakeup.wait_timeout(guard, wait) {
+                    Ok((guard, _)) => guard,
+                    Err(_) => return,
+                };
+            }
+        };
+
+        // Deliveries for a webhook removed since they were queued are dropped
+        let still_configured = init_webhooks().read()
+            .is_ok_and(|webhooks| webhooks.contains_key(&delivery.webhook));
+        if !still_configured {
+            continue;
+        }
+
+        delivery.attempts += 1;
+        let result = post(&delivery.url, delivery.event, &delivery.body);
+
+        let mut guard = match queue.lock() {
+            Ok(guard) => guard,
+            Err(_) => return,
+        };
+        match result {
+            Ok(()) => {
+                DELIVERED.fetch_add(1, Ordering::Relaxed);
+            },
+            Err(err) => {
+                FAILED_ATTEMPTS.fetch_add(1, Ordering::Relaxed);
+                delivery.last_error = Some(err);
+                if delivery.attempts >= MAX_ATTEMPTS {
+                    DEAD_LETTERED.fetch_add(1, Ordering::Relaxed);
+                    guard.dead.push_back(delivery);
+                    if guard.dead.len() > MAX_DEAD_LETTERS {
+                        guard.dead.pop_front();
+                    }
+                } else {
+                    delivery.next_attempt = Instant::now() + RETRY_BASE_DELAY * 2u32.pow(delivery.attempts - 1);
+                    guard.pending.push_back(delivery);
+                }
+            },
+        }
+    }
+}
+
+// Start the dispatcher thread at module load; it idles until a webhook is added
+pub fn start() -> Result<(), String> {
+    thread::Builder::new()
+        .name("session-webhooks".to_string())
+        .spawn(dispatch)
+        .map(|_| ())
+        .map_err(|e| format!("Failed to start webhook dispatcher thread: {}", e))
+}
+
+// Manage webhooks for session lifecycle events:
+// SESSION.WEBHOOK ADD name url [EVENTS event [event ...]]
+// SESSION.WEBHOOK DEL name
+// SESSION.WEBHOOK LIST
+// SESSION.WEBHOOK STATS
+// SESSION.WEBHOOK DEADLETTERS
+// SESSION.WEBHOOK REDELIVER
+// SESSION.WEBHOOK PURGE
+#[tracing::instrument(name = "session.webhook", skip_all)]
+pub fn session_webhook(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let subcommand = args.next_string()?.to_uppercase();
+
+    match subcommand.as_str() {
+        "ADD" => {
+            let name = args.next_string()?;
+            let url = args.next_string()?;
+            parse_url(&url).map_err(RedisError::String)?;
+
+            let mut events = Vec::new();
+            if let Ok(option) = args.next_string() {
+                if !option.eq_ignore_ascii_case("EVENTS") {
+                    return Err(RedisError::String(format!("Unknown option: {}", option)));
+                }
+                while let Ok(event) = args.next_string() {
+                    let event = event.to_lowercase();
+                    if !events::ALL.contains(&event.as_str()) {
+                        return Err(RedisError::String(format!("Unknown event: {}, expected one of {}", event, events::ALL.join(", "))));
+                    }
+                    events.push(event);
+                }
+                if events.is_empty() {
+                    return Err(RedisError::WrongArity);
+                }
+            }
+
+            let mut webhooks = init_webhooks().write().map_err(|_| {
+                RedisError::String("Failed to acquire write lock".to_string())
+            })?;
+            webhooks.insert(name, Webhook { url, events });
+            Ok(RedisValue::SimpleStringStatic("OK"))
+        },
+        "DEL" => {
+            let name = args.next_string()?;
+            args.done()?;
+
+            let mut webhooks = init_webhooks().write().map_err(|_| {
+                RedisError::String("Failed to acquire write lock".to_string())
+            })?;
+            Ok(RedisValue::Integer(if webhooks.remove(&name).is_some() { 1 } else { 0 }))
+        },
+        "LIST" => {
+            args.done()?;
+
+            let webhooks = init_webhooks().read().map_err(|_| {
+                RedisError::String("Failed to acquire read lock".to_string())
+            })?;
+            Ok(RedisValue::Array(webhooks.iter()
+                .map(|(name, webhook)| RedisValue::Array(vec![
+                    RedisValue::BulkString(name.clone()),
+                    RedisValue::BulkString(webhook.url.clone()),
+                    RedisValue::Array(webhook.events.iter().map(|event| RedisValue::BulkString(event.clone())).collect()),
+                ]))
+                .collect()))
+        },
+        "STATS" => {
+            args.done()?;
+
+            let (pending, dead) = init_queue().0.lock()
+                .map(|queue| (queue.pending.len(), queue.dead.len()))
+                .map_err(|_| RedisError::String("Failed to acquire webhook queue lock".to_string()))?;
+            Ok(RedisValue::Array(vec![
+                RedisValue::SimpleStringStatic("pending"),
+                RedisValue::Integer(pending as i64),
+                RedisValue::SimpleStringStatic("dead_letters"),
+                RedisValue::Integer(dead as i64),
+                RedisValue::SimpleStringStatic("delivered"),
+                RedisValue::Integer(DELIVERED.load(Ordering::Relaxed) as i64),
+                RedisValue::SimpleStringStatic("failed_attempts"),
+                RedisValue::Integer(FAILED_ATTEMPTS.load(Ordering::Relaxed) as i64),
+                RedisValue::SimpleStringStatic("dead_lettered"),
+                RedisValue::Integer(DEAD_LETTERED.load(Ordering::Relaxed) as i64),
+                RedisValue::SimpleStringStatic("dropped"),
+                RedisValue::Integer(DROPPED.load(Ordering::Relaxed) as i64),
+            ]))
+        },
+        "DEADLETTERS" => {
+            args.done()?;
+
+            let queue = init_queue().0.lock().map_err(|_| {
+                RedisError::String("Failed to acquire webhook queue lock".to_string())
+            })?;
+            Ok(RedisValue::Array(queue.dead.iter()
+                .map(|delivery| RedisValue::Array(vec![
+                    RedisValue::BulkString(delivery.webhook.clone()),
+                    RedisValue::BulkString(delivery.event.to_string()),
+                    RedisValue::BulkString(delivery.body.clone()),
+                    RedisValue::Integer(delivery.attempts as i64),
+                    RedisValue::BulkString(delivery.last_error.clone().unwrap_or_default()),
+                ]))
+                .collect()))
+        },
+        "REDELIVER" => {
+            args.done()?;
+
+            let (queue, wakeup) = init_queue();
+            let mut queue = queue.lock().map_err(|_| {
+                RedisError::String("Failed to acquire webhook queue lock".to_string())
+            })?;
+            let count = queue.dead.len();
+            while let Some(mut delivery) = queue.dead.pop_front() {
+                delivery.attempts = 0;
+                delivery.next_attempt = Instant::now();
+                queue.pending.push_back(delivery);
+            }
+            wakeup.notify_one();
+            Ok(RedisValue::Integer(count as i64))
+        },
+        "PURGE" => {
+            args.done()?;
+
+            let mut queue = init_queue().0.lock().map_err(|_| {
+                RedisError::String("Failed to acquire webhook queue lock".to_string())
+            })?;
+            let count = queue.dead.len();
+            queue.dead.clear();
+            Ok(RedisValue::Integer(count as i64))
+        },
+        _ => Err(RedisError::String(format!("Unknown SESSION.WEBHOOK subcommand: {}", subcommand))),
+    }
+}
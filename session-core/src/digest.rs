@@ -0,0 +1,40 @@
+//! Order-independent content digests, for comparing a store on a primary, a
+//! replica and a backup without caring in what order each holds its entries.
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// Running digest of a set of entries. Each entry is hashed on its own with
+/// 64-bit FNV-1a and the hashes are summed, so the result depends only on
+/// which entries were added, never on the order they were added in.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Digest {
+    sum: u64,
+    count: u64,
+}
+
+impl Digest {
+    /// Add one entry made of the given fields. Every field is length-prefixed,
+    /// so `["ab", "c"]` and `["a", "bc"]` hash differently.
+    pub fn add(&mut self, fields: &[&[u8]]) {
+        let mut hash = FNV_OFFSET;
+        for field in fields {
+            for byte in (field.len() as u64).to_le_bytes().iter().chain(field.iter()) {
+                hash ^= *byte as u64;
+                hash = hash.wrapping_mul(FNV_PRIME);
+            }
+        }
+        self.sum = self.sum.wrapping_add(hash);
+        self.count += 1;
+    }
+
+    /// Number of entries added
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// The digest as 16 hex digits
+    pub fn hex(&self) -> String {
+        format!("{:016x}", self.sum)
+    }
+}
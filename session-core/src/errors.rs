@@ -0,0 +1,26 @@
+//! Error codes the modules reply with. Codes are the first word of an error
+//! reply, so clients can match on them.
+
+/// Returned by a custom hashmap C API delete refused by a protected prefix
+pub use custom_hashmap_sys::PROTECTED;
+
+/// Returned by a batched custom hashmap C API call whose struct layout doesn't match
+pub use custom_hashmap_sys::WIRE_MISMATCH;
+
+/// New sessions are refused while too many bridge writes wait to be retried
+pub const BUSY_SESSION: &str = "BUSYSESSION";
+
+/// A new session is refused because max_sessions are live and the policy doesn't evict
+pub const MAX_SESSIONS: &str = "MAXSESSIONS";
+
+/// A new session is refused by a SESSION.THROTTLE_CREATE creation limit
+pub const THROTTLED: &str = "THROTTLED";
+
+/// The caller lacks the ACL permission an option needs, e.g. REVEAL
+pub const NO_PERMISSION: &str = "NOPERM";
+
+/// A SESSION.FLUSH_PERSISTENCE barrier wasn't reached within its timeout
+pub const TIMEOUT: &str = "TIMEOUT";
+
+/// A data write would change a field's type under SESSION.FIELD_TYPES STRICT or COERCE
+pub const WRONG_TYPE: &str = "WRONGTYPE";
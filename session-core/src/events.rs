@@ -0,0 +1,35 @@
+//! Session lifecycle events as delivered to webhooks.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+pub const CREATED: &str = "created";
+pub const DELETED: &str = "deleted";
+pub const EXPIRED: &str = "expired";
+pub const ARCHIVED: &str = "archived";
+pub const UNARCHIVED: &str = "unarchived";
+pub const EXPIRING_SOON: &str = "expiring_soon";
+pub const EVICTED: &str = "evicted";
+
+/// Every lifecycle event name
+pub const ALL: &[&str] = &[CREATED, DELETED, EXPIRED, ARCHIVED, UNARCHIVED, EXPIRING_SOON, EVICTED];
+
+/// JSON body of a lifecycle event
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionEvent {
+    pub event: String,
+    pub session_id: String,
+    pub user_key: String,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl SessionEvent {
+    pub fn new(event: &str, session_id: &str, user_key: &str) -> Self {
+        SessionEvent {
+            event: event.to_string(),
+            session_id: session_id.to_string(),
+            user_key: user_key.to_string(),
+            timestamp: Utc::now(),
+        }
+    }
+}
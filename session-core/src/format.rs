@@ -0,0 +1,59 @@
+//! Versions of the session JSON format. Each session carries the version it
+//! is serialized in, and a build reads its own version and the one before,
+//! so modules of two releases and the snapshots and archives they wrote can
+//! be mixed during a rolling upgrade.
+//!
+//! Version 1 is the layout from before versioning, which has no
+//! `format_version` field. Version 2 adds it.
+
+use std::sync::atomic::{AtomicU32, Ordering};
+use serde::{Deserialize, Deserializer};
+
+/// The newest format this build reads and writes
+pub const CURRENT: u32 = 2;
+
+/// The oldest format this build reads
+pub const OLDEST: u32 = CURRENT - 1;
+
+/// The format of sessions serialized before versioning
+pub const UNVERSIONED: u32 = 1;
+
+// Format new sessions are written in
+static WRITTEN: AtomicU32 = AtomicU32::new(CURRENT);
+
+/// The format new sessions are written in
+pub fn written() -> u32 {
+    WRITTEN.load(Ordering::Relaxed)
+}
+
+/// Write new sessions in `version`, e.g. the previous one while nodes of
+/// the older release still read them
+pub fn set_written(version: u32) -> Result<(), String> {
+    check(version)?;
+    WRITTEN.store(version, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Whether this build reads and writes `version`
+pub fn check(version: u32) -> Result<(), String> {
+    if (OLDEST..=CURRENT).contains(&version) {
+        Ok(())
+    } else {
+        Err(format!("Unsupported session format version {}, expected {} to {}", version, OLDEST, CURRENT))
+    }
+}
+
+pub(crate) fn unversioned() -> u32 {
+    UNVERSIONED
+}
+
+pub(crate) fn is_unversioned(version: &u32) -> bool {
+    *version == UNVERSIONED
+}
+
+// Refuse a session in a format this build can't read
+pub(crate) fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+    let version = u32::deserialize(deserializer)?;
+    check(version).map_err(serde::de::Error::custom)?;
+    Ok(version)
+}
@@ -0,0 +1,27 @@
+//! Types the session manager and the custom hashmap module agree on.
+//!
+//! Both modules, and tools that read what they produce, depend on this crate
+//! instead of keeping their own copies, so a change to the session layout,
+//! an event payload or an error code shows up as a compile error on every
+//! side rather than as drift between them.
+
+pub mod digest;
+pub mod errors;
+pub mod events;
+pub mod format;
+pub mod session;
+pub mod ttl;
+pub mod value;
+pub mod version;
+
+pub use digest::Digest;
+pub use session::{FieldType, Impersonation, JsonStamp, Priority, Session, TtlBinding, TtlSource};
+pub use value::DataValue;
+pub use version::BuildInfo;
+
+/// Declarations of the custom hashmap's C API
+pub use custom_hashmap_sys as capi;
+
+/// Name the session manager writes to the custom hashmap as, for
+/// `CUSTOM.PROTECT prefix MODE owner:session_manager`
+pub const SESSION_MANAGER_MODULE: &str = "session_manager";
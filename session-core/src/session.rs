@@ -0,0 +1,288 @@
+//! The session record kept by the session manager. Its JSON form is what
+//! SESSION.GET and SESSION.EXPORT reply with and what preload files and
+//! archives contain.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{format, ttl};
+
+/// What a cached session JSON was serialized from: (change_seq, last_accessed)
+pub type JsonStamp = (u64, DateTime<Utc>);
+
+/// Who an impersonation session acts for, and who is acting
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Impersonation {
+    pub target_id: String,
+    pub admin_id: String,
+}
+
+/// Which side of a SESSION.BIND_TTL_TO_KEY binding decides the expiry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TtlSource {
+    /// The native key's TTL is copied onto the session
+    Key,
+    /// The session's expiry is copied onto the native key
+    Session,
+}
+
+/// A native key whose TTL is kept in step with the session's expiry
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TtlBinding {
+    pub key: String,
+    pub source: TtlSource,
+}
+
+/// How readily a session is given up when the store is under pressure.
+/// Priority never changes when a session expires by its TTL.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    /// Evicted and idle-swept before anything else
+    Low,
+    #[default]
+    Normal,
+    /// Never evicted or idle-swept, e.g. service-account sessions
+    High,
+}
+
+impl Priority {
+    /// Parse LOW, NORMAL or HIGH, in any case
+    pub fn parse(priority: &str) -> Option<Priority> {
+        match priority.to_ascii_lowercase().as_str() {
+            "low" => Some(Priority::Low),
+            "normal" => Some(Priority::Normal),
+            "high" => Some(Priority::High),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Priority::Low => "low",
+            Priority::Normal => "normal",
+            Priority::High => "high",
+        }
+    }
+}
+
+/// The type a data field was written as. Values are always stored as text,
+/// read back as their type with [`crate::DataValue`]. JSON numbers and
+/// booleans written with SESSION.PATCH keep their type, as do values written
+/// with SESSION.ADD_DATA ... TYPE.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldType {
+    #[default]
+    String,
+    Int,
+    /// Also read from `number`, which sessions written before integers and
+    /// floats were told apart use
+    #[serde(alias = "number")]
+    Float,
+    Boolean,
+    /// A JSON object or array
+    Json,
+}
+
+impl FieldType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FieldType::String => "string",
+            FieldType::Int => "int",
+            FieldType::Float => "float",
+            FieldType::Boolean => "boolean",
+            FieldType::Json => "json",
+        }
+    }
+}
+
+/// Session structure
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Session {
+    /// Format the session is serialized in; see [`crate::format`]
+    #[serde(default = "format::unversioned", skip_serializing_if = "format::is_unversioned", deserialize_with = "format::deserialize")]
+    pub format_version: u32,
+    pub id: String,
+    pub user_key: String,
+    pub created_at: DateTime<Utc>,
+    pub last_accessed: DateTime<Utc>,
+    /// Data fields as stored text, read as their type from `types`. Values
+    /// stay text because encryption and tiering put ciphertext or a key in a
+    /// value's place; write them with [`Session::set_field`] and remove them
+    /// with [`Session::remove_fields`] so `types` keeps up.
+    #[serde(deserialize_with = "crate::value::data_from_json")]
+    pub data: HashMap<String, String>,
+    /// Client the session is bound to via SESSION.BIND, if any
+    #[serde(default)]
+    pub bound_client: Option<u64>,
+    /// Set when the bound client disconnected and the session was kept
+    #[serde(default)]
+    pub idle: bool,
+    /// Argon2 hashes of step-up secrets; never included in replies
+    #[serde(default, skip_serializing)]
+    pub secrets: HashMap<String, String>,
+    /// Global change sequence of the last mutation, used by SESSION.EXPORT SINCE
+    #[serde(default)]
+    pub change_seq: u64,
+    /// When the session expires, if it was created with a TTL
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Hard expiry set with SESSION.EXPIREAT; no TTL or access moves
+    /// `expires_at` past it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deadline: Option<DateTime<Utc>>,
+    /// Seconds without access after which the session expires; every access
+    /// moves `expires_at` this far ahead again
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idle_timeout: Option<u64>,
+    /// Native key whose TTL is kept in step with `expires_at`, set with SESSION.BIND_TTL_TO_KEY
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl_binding: Option<TtlBinding>,
+    /// Whether the expiring_soon warning has been sent for the current expiry
+    #[serde(skip)]
+    pub expiry_warned: bool,
+    /// Request nonces seen for replay protection, with when each may be forgotten
+    #[serde(default, skip_serializing)]
+    pub nonces: HashMap<String, DateTime<Utc>>,
+    /// Application that owns the session, set once at creation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub app: Option<String>,
+    /// Eviction and idle sweep class, set at creation or with SESSION.SET_META
+    #[serde(default)]
+    pub priority: Priority,
+    /// Set on sessions created by SESSION.IMPERSONATE
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub impersonation: Option<Impersonation>,
+    /// Data fields whose values were too large to keep inline; their `data`
+    /// entry holds the native key the value is stored under instead
+    #[serde(default, skip_serializing_if = "HashSet::is_empty")]
+    pub spilled: HashSet<String>,
+    /// Types of data fields not written as strings; fields left out are strings
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub types: HashMap<String, FieldType>,
+    /// Serialized JSON as of (change_seq, last_accessed), reused by SESSION.GET until either moves
+    #[serde(skip)]
+    pub json_cache: Mutex<Option<(JsonStamp, String)>>,
+}
+
+impl Session {
+    /// A new session, stamped with the given change sequence
+    pub fn new(id: String, user_key: String, change_seq: u64) -> Self {
+        let now = Utc::now();
+        Session {
+            format_version: format::written(),
+            id,
+            user_key,
+            created_at: now,
+            last_accessed: now,
+            data: HashMap::new(),
+            bound_client: None,
+            idle: false,
+            secrets: HashMap::new(),
+            change_seq,
+            expires_at: None,
+            deadline: None,
+            idle_timeout: None,
+            ttl_binding: None,
+            expiry_warned: false,
+            nonces: HashMap::new(),
+            app: None,
+            priority: Priority::Normal,
+            impersonation: None,
+            spilled: HashSet::new(),
+            types: HashMap::new(),
+            json_cache: Mutex::new(None),
+        }
+    }
+
+    /// Expire the session `ttl_secs` from now, or at its deadline if that's sooner
+    pub fn set_ttl(&mut self, ttl_secs: u64) {
+        let expires_at = ttl::after(Utc::now(), ttl_secs);
+        self.expires_at = Some(self.deadline.map_or(expires_at, |deadline| expires_at.min(deadline)));
+        self.expiry_warned = false;
+    }
+
+    /// Remove every expiry: TTL, idle timeout and deadline
+    pub fn persist(&mut self) {
+        self.expires_at = None;
+        self.deadline = None;
+        self.idle_timeout = None;
+        self.expiry_warned = false;
+    }
+
+    /// Never let the session outlive `max_secs` from its creation. Returns
+    /// whether that moved its deadline.
+    pub fn cap_lifetime(&mut self, max_secs: u64) -> bool {
+        let cap = ttl::after(self.created_at, max_secs);
+        if self.deadline.is_some_and(|deadline| deadline <= cap) {
+            return false;
+        }
+        self.deadline = Some(cap);
+        if self.expires_at.is_none_or(|expires_at| expires_at > cap) {
+            self.expires_at = Some(cap);
+            self.expiry_warned = false;
+        }
+        true
+    }
+
+    /// Expire the session at `deadline` whatever its activity. A session with
+    /// an idle timeout still expires sooner if it goes idle first.
+    pub fn set_deadline(&mut self, deadline: DateTime<Utc>) {
+        self.deadline = Some(deadline);
+        match self.idle_timeout {
+            Some(idle_secs) => self.set_ttl(idle_secs),
+            None => {
+                self.expires_at = Some(deadline);
+                self.expiry_warned = false;
+            },
+        }
+    }
+
+    /// Expire the session after `idle_secs` without access, starting now
+    pub fn set_idle_timeout(&mut self, idle_secs: u64) {
+        self.idle_timeout = Some(idle_secs);
+        self.set_ttl(idle_secs);
+    }
+
+    /// Record an access. A session with an idle timeout expires that long
+    /// from now, unless it has already expired.
+    pub fn touch(&mut self) {
+        self.last_accessed = Utc::now();
+        if let Some(idle_secs) = self.idle_timeout {
+            if !self.is_expired() {
+                self.set_ttl(idle_secs);
+            }
+        }
+    }
+
+    /// Set a data field to the text of a value of `field_type`
+    pub fn set_field(&mut self, field: String, value: String, field_type: FieldType) {
+        if field_type == FieldType::String {
+            self.types.remove(&field);
+        } else {
+            self.types.insert(field.clone(), field_type);
+        }
+        self.data.insert(field, value);
+    }
+
+    /// Remove the data fields `remove` picks, with their types. Returns how
+    /// many were removed.
+    pub fn remove_fields(&mut self, mut remove: impl FnMut(&str) -> bool) -> usize {
+        let before = self.data.len();
+        self.data.retain(|field, _| !remove(field));
+        if !self.types.is_empty() {
+            let data = &self.data;
+            self.types.retain(|field, _| data.contains_key(field));
+        }
+        before - self.data.len()
+    }
+
+    /// Past its expiry but not yet removed, i.e. inside the grace window
+    pub fn is_expired(&self) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= Utc::now())
+    }
+}
@@ -0,0 +1,56 @@
+//! Bounds on the seconds that commands and settings take as durations.
+//!
+//! `chrono` panics when a duration or a time moved by one is out of range,
+//! which inside a module takes the whole server down. Commands refuse
+//! seconds above [`MAX_SECS`] with [`check`]; the helpers below saturate
+//! instead of panicking for values that slipped past that.
+
+use chrono::{DateTime, TimeDelta, Utc};
+
+/// The longest TTL, timeout or window accepted: 100 years
+pub const MAX_SECS: u64 = 100 * 365 * 24 * 60 * 60;
+
+/// `secs` if it is at most [`MAX_SECS`], else an error naming `what`
+pub fn check(secs: u64, what: &str) -> Result<u64, String> {
+    if secs > MAX_SECS {
+        return Err(format!("{} must be at most {} seconds", what, MAX_SECS));
+    }
+    Ok(secs)
+}
+
+/// `secs` as a duration, capped at [`MAX_SECS`]
+pub fn duration(secs: u64) -> TimeDelta {
+    TimeDelta::try_seconds(secs.min(MAX_SECS) as i64).unwrap_or(TimeDelta::MAX)
+}
+
+/// `secs` after `at`, or the latest representable time
+pub fn after(at: DateTime<Utc>, secs: u64) -> DateTime<Utc> {
+    at.checked_add_signed(duration(secs)).unwrap_or(DateTime::<Utc>::MAX_UTC)
+}
+
+/// `secs` before `at`, or the earliest representable time
+pub fn before(at: DateTime<Utc>, secs: u64) -> DateTime<Utc> {
+    at.checked_sub_signed(duration(secs)).unwrap_or(DateTime::<Utc>::MIN_UTC)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_bounds() {
+        assert_eq!(check(0, "TTL"), Ok(0));
+        assert_eq!(check(MAX_SECS, "TTL"), Ok(MAX_SECS));
+        assert_eq!(check(MAX_SECS + 1, "TTL"), Err(format!("TTL must be at most {} seconds", MAX_SECS)));
+    }
+
+    #[test]
+    fn saturates_instead_of_panicking() {
+        let now = Utc::now();
+        assert_eq!(duration(u64::MAX), duration(MAX_SECS));
+        assert_eq!(after(now, 60) - now, TimeDelta::seconds(60));
+        assert_eq!(before(now, 60), now - TimeDelta::seconds(60));
+        assert_eq!(after(DateTime::<Utc>::MAX_UTC, 1), DateTime::<Utc>::MAX_UTC);
+        assert_eq!(before(DateTime::<Utc>::MIN_UTC, 1), DateTime::<Utc>::MIN_UTC);
+    }
+}
@@ -0,0 +1,218 @@
+//! Typed data values.
+//!
+//! Session data is stored as text, so encryption and tiering can put
+//! ciphertext or a key in a value's place; a field's [`FieldType`] says how
+//! to read the text back. A [`DataValue`] is a value read as its type, which
+//! commands parse writes into and build replies and JSON from.
+
+use std::collections::HashMap;
+use serde::{Deserialize, Deserializer};
+use serde_json::{Number, Value};
+
+use crate::FieldType;
+
+/// A data field's value as the type it was written as
+#[derive(Debug, Clone, PartialEq)]
+pub enum DataValue {
+    String(String),
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    /// A JSON object or array
+    Json(Value),
+}
+
+impl DataValue {
+    /// Text read as `field_type`, or None if it isn't a valid one. Integers
+    /// and floats don't mix: `3.5` is no int, while `3` reads as the float 3.0.
+    pub fn parse(text: &str, field_type: FieldType) -> Option<DataValue> {
+        match field_type {
+            FieldType::String => Some(DataValue::String(text.to_string())),
+            FieldType::Int => text.trim().parse().ok().map(DataValue::Int),
+            FieldType::Float => text.trim().parse::<f64>().ok()
+                .filter(|float| float.is_finite())
+                .map(DataValue::Float),
+            FieldType::Boolean => match text.trim().to_ascii_lowercase().as_str() {
+                "true" | "1" => Some(DataValue::Bool(true)),
+                "false" | "0" => Some(DataValue::Bool(false)),
+                _ => None,
+            },
+            FieldType::Json => serde_json::from_str::<Value>(text).ok()
+                .filter(|json| json.is_object() || json.is_array())
+                .map(DataValue::Json),
+        }
+    }
+
+    /// Stored text as `field_type`, or as a string when it doesn't read as
+    /// one, such as the ciphertext of an encrypted field
+    pub fn read(text: &str, field_type: FieldType) -> DataValue {
+        DataValue::parse(text, field_type).unwrap_or_else(|| DataValue::String(text.to_string()))
+    }
+
+    /// A native JSON value: strings, integers, other numbers and booleans
+    /// take their own type, anything else is a JSON document
+    pub fn from_json(json: Value) -> DataValue {
+        match json {
+            Value::String(string) => DataValue::String(string),
+            Value::Bool(boolean) => DataValue::Bool(boolean),
+            Value::Number(number) => match (number.as_i64(), number.as_f64()) {
+                (Some(integer), _) => DataValue::Int(integer),
+                (None, Some(float)) => DataValue::Float(float),
+                (None, None) => DataValue::Json(Value::Number(number)),
+            },
+            json => DataValue::Json(json),
+        }
+    }
+
+    pub fn field_type(&self) -> FieldType {
+        match self {
+            DataValue::String(_) => FieldType::String,
+            DataValue::Int(_) => FieldType::Int,
+            DataValue::Float(_) => FieldType::Float,
+            DataValue::Bool(_) => FieldType::Boolean,
+            DataValue::Json(_) => FieldType::Json,
+        }
+    }
+
+    /// The text the value is stored as. A float keeps its fraction (`3.0`,
+    /// not `3`) and a document is compact.
+    pub fn into_text(self) -> String {
+        match self {
+            DataValue::String(string) => string,
+            DataValue::Int(integer) => integer.to_string(),
+            DataValue::Float(float) => Number::from_f64(float).map_or_else(|| float.to_string(), |number| number.to_string()),
+            DataValue::Bool(boolean) => boolean.to_string(),
+            DataValue::Json(json) => json.to_string(),
+        }
+    }
+
+    /// The value as native JSON
+    pub fn to_json(&self) -> Value {
+        match self {
+            DataValue::String(string) => Value::String(string.clone()),
+            DataValue::Int(integer) => Value::from(*integer),
+            DataValue::Float(float) => Number::from_f64(*float).map_or(Value::Null, Value::Number),
+            DataValue::Bool(boolean) => Value::Bool(*boolean),
+            DataValue::Json(json) => json.clone(),
+        }
+    }
+}
+
+/// Turn the `data` entries of a serialized session into native JSON by the
+/// session's `types`. Entries that don't read as their type, such as redacted
+/// or still encrypted ones, and entries already typed are left as they are.
+pub fn type_session_json(json: &mut Value) {
+    let Some(types) = json.get("types") else { return };
+    let Ok(types) = serde_json::from_value::<HashMap<String, FieldType>>(types.clone()) else { return };
+    let Some(Value::Object(data)) = json.get_mut("data") else { return };
+    for (field, field_type) in types {
+        let Some(entry) = data.get_mut(&field) else { continue };
+        let typed = match entry {
+            Value::String(text) => DataValue::parse(text, field_type),
+            _ => None,
+        };
+        if let Some(typed) = typed {
+            *entry = typed.to_json();
+        }
+    }
+}
+
+/// Deserialize session data written either as text or as native JSON by
+/// [`type_session_json`], storing every value as text
+pub fn data_from_json<'de, D: Deserializer<'de>>(deserializer: D) -> Result<HashMap<String, String>, D::Error> {
+    let data = HashMap::<String, Value>::deserialize(deserializer)?;
+    Ok(data.into_iter()
+        .map(|(field, value)| (field, DataValue::from_json(value).into_text()))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn parses_each_type() {
+        assert_eq!(DataValue::parse("abc", FieldType::String), Some(DataValue::String("abc".to_string())));
+        assert_eq!(DataValue::parse(" 42 ", FieldType::Int), Some(DataValue::Int(42)));
+        assert_eq!(DataValue::parse("2.5", FieldType::Float), Some(DataValue::Float(2.5)));
+        assert_eq!(DataValue::parse("TRUE", FieldType::Boolean), Some(DataValue::Bool(true)));
+        assert_eq!(DataValue::parse("0", FieldType::Boolean), Some(DataValue::Bool(false)));
+        assert_eq!(DataValue::parse("{\"a\":[1]}", FieldType::Json), Some(DataValue::Json(json!({"a": [1]}))));
+    }
+
+    #[test]
+    fn refuses_invalid_text() {
+        assert_eq!(DataValue::parse("3.5", FieldType::Int), None);
+        assert_eq!(DataValue::parse("9223372036854775808", FieldType::Int), None);
+        assert_eq!(DataValue::parse("NaN", FieldType::Float), None);
+        assert_eq!(DataValue::parse("inf", FieldType::Float), None);
+        assert_eq!(DataValue::parse("yes", FieldType::Boolean), None);
+        assert_eq!(DataValue::parse("{", FieldType::Json), None);
+        assert_eq!(DataValue::parse("\"scalar\"", FieldType::Json), None);
+        assert_eq!(DataValue::read("enc:v1:abc", FieldType::Int), DataValue::String("enc:v1:abc".to_string()));
+    }
+
+    #[test]
+    fn keeps_ints_and_floats_apart() {
+        assert_eq!(DataValue::from_json(json!(3)), DataValue::Int(3));
+        assert_eq!(DataValue::from_json(json!(3.0)), DataValue::Float(3.0));
+        assert_eq!(DataValue::parse("3", FieldType::Float), Some(DataValue::Float(3.0)));
+        assert_eq!(DataValue::Float(3.0).into_text(), "3.0");
+        assert_eq!(DataValue::Int(3).into_text(), "3");
+        assert_eq!(DataValue::Float(3.0).to_json().to_string(), "3.0");
+        assert_eq!(DataValue::Int(3).to_json().to_string(), "3");
+    }
+
+    #[test]
+    fn text_round_trips() {
+        for value in [
+            DataValue::String("3".to_string()),
+            DataValue::Int(-7),
+            DataValue::Float(0.1),
+            DataValue::Float(1e300),
+            DataValue::Bool(false),
+            DataValue::Json(json!([{"sku": "ABC"}])),
+        ] {
+            let field_type = value.field_type();
+            assert_eq!(DataValue::parse(&value.clone().into_text(), field_type), Some(value));
+        }
+    }
+
+    #[test]
+    fn types_serialized_sessions() {
+        let mut session = json!({
+            "data": {"n": "3", "f": "3.0", "b": "true", "j": "{\"a\":1}", "s": "3", "secret": "[REDACTED]"},
+            "types": {"n": "int", "f": "float", "b": "boolean", "j": "json", "secret": "int"},
+        });
+        type_session_json(&mut session);
+        assert_eq!(session["data"], json!({"n": 3, "f": 3.0, "b": true, "j": {"a": 1}, "s": "3", "secret": "[REDACTED]"}));
+        assert!(session["data"]["f"].is_f64());
+
+        // Typed JSON reads back as the same text
+        let data: HashMap<String, String> = data_from_json(session["data"].clone()).unwrap();
+        assert_eq!(data["n"], "3");
+        assert_eq!(data["f"], "3.0");
+        assert_eq!(data["b"], "true");
+        assert_eq!(data["j"], "{\"a\":1}");
+        assert_eq!(data["s"], "3");
+    }
+
+    #[test]
+    fn sessions_round_trip_through_typed_json() {
+        let mut session = crate::Session::new("s1".to_string(), "user:1".to_string(), 0);
+        session.set_field("qty".to_string(), "2".to_string(), FieldType::Int);
+        session.set_field("price".to_string(), "9.0".to_string(), FieldType::Float);
+        session.set_field("gift".to_string(), "false".to_string(), FieldType::Boolean);
+        session.set_field("cart".to_string(), "[{\"sku\":\"ABC\"}]".to_string(), FieldType::Json);
+        session.set_field("code".to_string(), "007".to_string(), FieldType::String);
+
+        let mut json = serde_json::to_value(&session).unwrap();
+        type_session_json(&mut json);
+        assert_eq!(json["data"], json!({"qty": 2, "price": 9.0, "gift": false, "cart": [{"sku": "ABC"}], "code": "007"}));
+
+        let restored: crate::Session = serde_json::from_value(json).unwrap();
+        assert_eq!(restored.data, session.data);
+        assert_eq!(restored.types, session.types);
+    }
+}
@@ -0,0 +1,54 @@
+//! Build metadata both modules report through `CUSTOM.VERSION`,
+//! `SESSION.VERSION`, their startup log line and `MODULE LIST`.
+
+use std::fmt;
+
+use crate::capi::ABI_VERSION;
+
+/// What a module build is: its version, commit and compiled-in features
+#[derive(Debug, Clone, Copy)]
+pub struct BuildInfo {
+    /// Name the module registers under
+    pub module: &'static str,
+    /// Semantic version of the crate
+    pub version: &'static str,
+    /// Commit the module was built from, `unknown` outside a git checkout
+    pub git_sha: &'static str,
+    /// Cargo features and build profile flags the module was built with
+    pub features: &'static [&'static str],
+}
+
+impl BuildInfo {
+    /// Version of the custom hashmap C API this build speaks
+    pub fn abi_version(&self) -> u32 {
+        ABI_VERSION
+    }
+}
+
+impl fmt::Display for BuildInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} ({}), C API ABI {}", self.module, self.version, self.git_sha, ABI_VERSION)?;
+        if !self.features.is_empty() {
+            write!(f, ", features: {}", self.features.join(","))?;
+        }
+        Ok(())
+    }
+}
+
+/// A `major.minor.patch` version as the integer `MODULE LIST` shows,
+/// `major * 10000 + minor * 100 + patch` like Redis' own modules, so
+/// 1.2.3 is listed as 10203. Anything after the patch number is ignored.
+pub const fn module_version(semver: &str) -> i32 {
+    let bytes = semver.as_bytes();
+    let mut parts = [0i32; 3];
+    let (mut part, mut i) = (0, 0);
+    while i < bytes.len() && part < 3 {
+        match bytes[i] {
+            b'.' => part += 1,
+            digit @ b'0'..=b'9' => parts[part] = parts[part] * 10 + (digit - b'0') as i32,
+            _ => break,
+        }
+        i += 1;
+    }
+    parts[0] * 10000 + parts[1] * 100 + parts[2]
+}